@@ -0,0 +1,42 @@
+/* C interface to engiffen, built as a shared library with
+ *
+ *     cargo rustc --release --lib --crate-type cdylib --features capi
+ *
+ * Functions returning int return 0 on success and -1 on failure, after which
+ * engiffen_last_error describes what went wrong. */
+
+#ifndef ENGIFFEN_H
+#define ENGIFFEN_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct EngiffenContext EngiffenContext;
+
+/* Creates a context that encodes at 10 frames per second. */
+EngiffenContext *engiffen_new(void);
+void engiffen_free(EngiffenContext *ctx);
+
+int engiffen_set_fps(EngiffenContext *ctx, double fps);
+
+/* Copies width * height RGBA pixels, four bytes each, row by row. */
+int engiffen_push_rgba(EngiffenContext *ctx, const uint8_t *pixels, uint32_t width, uint32_t height);
+
+/* Encodes and clears the pushed frames. Free the buffer with
+ * engiffen_free_buffer. */
+int engiffen_finish_to_buffer(EngiffenContext *ctx, uint8_t **out, size_t *out_len);
+void engiffen_free_buffer(uint8_t *buf, size_t len);
+int engiffen_finish_to_file(EngiffenContext *ctx, const char *path);
+
+/* The last failure's message, owned by ctx, or NULL. */
+const char *engiffen_last_error(const EngiffenContext *ctx);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,120 @@
+//! Per-pixel alpha handling applied to frames before quantization.
+
+use super::{ChromaKey, Image};
+
+/// 4x4 Bayer matrix, used as a repeating grid of dithering thresholds.
+pub const BAYER: [[u8; 4]; 4] = [
+    [ 0,  8,  2, 10],
+    [12,  4, 14,  6],
+    [ 3, 11,  1,  9],
+    [15,  7, 13,  5],
+];
+
+/// Reduces every pixel's alpha to fully opaque or fully transparent using
+/// ordered dithering, so that the proportion of opaque pixels in an area
+/// matches its average alpha.
+pub fn dither(img: &mut Image) {
+    let width = img.width as usize;
+    for (n, px) in img.pixels.iter_mut().enumerate() {
+        let (x, y) = (n % width, n / width);
+        // Thresholds are spread evenly over 8..=248, so alpha 0 is always
+        // transparent and alpha 255 is always opaque.
+        let threshold = BAYER[y % 4][x % 4] * 16 + 8;
+        px[3] = if px[3] >= threshold { 255 } else { 0 };
+    }
+}
+
+/// Blends every pixel's color over `color` in proportion to its alpha,
+/// leaving the alpha channel untouched.
+pub fn matte(img: &mut Image, color: [u8; 3]) {
+    for px in img.pixels.iter_mut() {
+        let alpha = px[3] as u32;
+        for c in 0..3 {
+            px[c] = ((px[c] as u32 * alpha + color[c] as u32 * (255 - alpha) + 127) / 255) as u8;
+        }
+    }
+}
+
+/// Composites `img` over `background` with the "over" operator. Both images
+/// must have the same dimensions.
+pub fn composite_over(img: &mut Image, background: &Image) {
+    for (px, bg) in img.pixels.iter_mut().zip(&background.pixels) {
+        let fg_alpha = px[3] as u32;
+        // Background alpha, scaled by how much of it the frame lets through.
+        let bg_weight = bg[3] as u32 * (255 - fg_alpha) / 255;
+        let out_alpha = fg_alpha + bg_weight;
+        if out_alpha == 0 {
+            *px = [0, 0, 0, 0];
+            continue;
+        }
+        for c in 0..3 {
+            px[c] = ((px[c] as u32 * fg_alpha + bg[c] as u32 * bg_weight + out_alpha / 2) / out_alpha) as u8;
+        }
+        px[3] = out_alpha as u8;
+    }
+}
+
+/// Makes every pixel within the key's tolerance of its color transparent.
+pub fn chroma_key(img: &mut Image, key: ChromaKey) {
+    for px in img.pixels.iter_mut() {
+        let matches = px[..3].iter().zip(&key.color).all(|(&c, &k)| {
+            (c as i16 - k as i16).abs() <= key.tolerance as i16
+        });
+        if matches {
+            px[3] = 0;
+        }
+    }
+}
+
+/// Makes every pixel fully opaque, keeping its color.
+pub fn flatten(img: &mut Image) {
+    for px in img.pixels.iter_mut() {
+        px[3] = 255;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{chroma_key, composite_over, dither, matte};
+    use super::super::{ChromaKey, Image};
+    use fixtures::solid;
+
+    #[test]
+    fn test_dither_coverage() {
+        let mut img = solid(4, 4, [0, 0, 0, 64]);
+        dither(&mut img);
+        let opaque = img.pixels.iter().filter(|px| px[3] == 255).count();
+        assert!(img.pixels.iter().all(|px| px[3] == 0 || px[3] == 255));
+        assert_eq!(opaque, 4);
+    }
+
+    #[test]
+    fn test_dither_keeps_extremes() {
+        let mut img = Image { pixels: vec![[0, 0, 0, 0], [0, 0, 0, 255]], width: 2, height: 1 };
+        dither(&mut img);
+        assert_eq!(img.pixels, vec![[0, 0, 0, 0], [0, 0, 0, 255]]);
+    }
+
+    #[test]
+    fn test_matte() {
+        let mut img = Image { pixels: vec![[255, 0, 0, 255], [255, 0, 0, 128], [255, 0, 0, 0]], width: 3, height: 1 };
+        matte(&mut img, [0, 0, 255]);
+        assert_eq!(img.pixels, vec![[255, 0, 0, 255], [128, 0, 127, 128], [0, 0, 255, 0]]);
+    }
+
+    #[test]
+    fn test_composite_over() {
+        let mut img = Image { pixels: vec![[255, 0, 0, 255], [255, 0, 0, 0], [255, 0, 0, 0]], width: 3, height: 1 };
+        let background = Image { pixels: vec![[0, 255, 0, 255], [0, 255, 0, 255], [0, 255, 0, 0]], width: 3, height: 1 };
+        composite_over(&mut img, &background);
+        assert_eq!(img.pixels, vec![[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 0, 0]]);
+    }
+
+    #[test]
+    fn test_chroma_key() {
+        let mut img = Image { pixels: vec![[0, 255, 0, 255], [20, 240, 10, 255], [40, 255, 0, 255]], width: 3, height: 1 };
+        chroma_key(&mut img, ChromaKey { color: [0, 255, 0], tolerance: 20 });
+        let alphas: Vec<_> = img.pixels.iter().map(|px| px[3]).collect();
+        assert_eq!(alphas, vec![0, 0, 255]);
+    }
+}
@@ -0,0 +1,259 @@
+//! Decoding animated PNGs back into frames.
+//!
+//! Each frame's data is rewrapped as a standalone PNG for the image crate to
+//! decode, so every bit depth and color type a PNG can have works, then
+//! composited onto the canvas the way its `fcTL` chunk says.
+
+use std::io::Read;
+use image::{self, ImageError, ImageFormat};
+use super::{Error, Image};
+
+const SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+/// The CRC32 PNG chunks are checked with.
+pub fn crc32<'a, I: Iterator<Item = &'a u8>>(bytes: I) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn format_error<S: Into<String>>(msg: S) -> Error {
+    Error::ImageLoad(ImageError::FormatError(msg.into()))
+}
+
+fn u32_at(data: &[u8], at: usize) -> Result<u32, Error> {
+    data.get(at..at + 4)
+        .map(|b| (b[0] as u32) << 24 | (b[1] as u32) << 16 | (b[2] as u32) << 8 | b[3] as u32)
+        .ok_or_else(|| format_error("png chunk is too short"))
+}
+
+fn u16_at(data: &[u8], at: usize) -> Result<u16, Error> {
+    data.get(at..at + 2)
+        .map(|b| (b[0] as u16) << 8 | b[1] as u16)
+        .ok_or_else(|| format_error("png chunk is too short"))
+}
+
+/// A frame's region and timing, from its `fcTL` chunk.
+#[derive(Debug)]
+struct Control {
+    width: u32,
+    height: u32,
+    left: u32,
+    top: u32,
+    /// How long the frame shows, in milliseconds.
+    delay: u64,
+    dispose: u8,
+    blend: u8,
+}
+
+impl Control {
+    fn parse(data: &[u8]) -> Result<Control, Error> {
+        let (num, den) = (u16_at(data, 20)? as u64, u16_at(data, 22)? as u64);
+        Ok(Control {
+            width: u32_at(data, 4)?,
+            height: u32_at(data, 8)?,
+            left: u32_at(data, 12)?,
+            top: u32_at(data, 16)?,
+            // A denominator of 0 means hundredths of a second.
+            delay: num * 1000 / if den == 0 { 100 } else { den },
+            dispose: *data.get(24).ok_or_else(|| format_error("png chunk is too short"))?,
+            blend: *data.get(25).ok_or_else(|| format_error("png chunk is too short"))?,
+        })
+    }
+}
+
+fn push_chunk(out: &mut Vec<u8>, kind: &[u8], data: &[u8]) {
+    let len = data.len() as u32;
+    out.extend_from_slice(&[(len >> 24) as u8, (len >> 16) as u8, (len >> 8) as u8, len as u8]);
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(kind.iter().chain(data));
+    out.extend_from_slice(&[(crc >> 24) as u8, (crc >> 16) as u8, (crc >> 8) as u8, crc as u8]);
+}
+
+/// Decodes one frame's image data, wrapped in the file's header chunks.
+fn decode_frame(ihdr: &[u8], headers: &[(Vec<u8>, Vec<u8>)], control: &Control, data: &[u8]) -> Result<Image, Error> {
+    let mut png = SIGNATURE.to_vec();
+    let mut header = ihdr.to_vec();
+    header[0..4].copy_from_slice(&[(control.width >> 24) as u8, (control.width >> 16) as u8, (control.width >> 8) as u8, control.width as u8]);
+    header[4..8].copy_from_slice(&[(control.height >> 24) as u8, (control.height >> 16) as u8, (control.height >> 8) as u8, control.height as u8]);
+    push_chunk(&mut png, b"IHDR", &header);
+    for (kind, data) in headers {
+        push_chunk(&mut png, kind, data);
+    }
+    push_chunk(&mut png, b"IDAT", data);
+    push_chunk(&mut png, b"IEND", &[]);
+    Ok(Image::from(image::load_from_memory_with_format(&png, ImageFormat::PNG)?))
+}
+
+/// Draws `frame` onto `canvas` at the frame's offset, either replacing what
+/// was there, or alpha compositing over it.
+fn draw(canvas: &mut Image, frame: &Image, control: &Control) {
+    for (n, &src) in frame.pixels.iter().enumerate() {
+        let (x, y) = (control.left + n as u32 % frame.width, control.top + n as u32 / frame.width);
+        if x >= canvas.width || y >= canvas.height {
+            continue;
+        }
+        let dst = &mut canvas.pixels[(y * canvas.width + x) as usize];
+        *dst = if control.blend == 0 { src } else { over(src, *dst) };
+    }
+}
+
+fn over(src: [u8; 4], dst: [u8; 4]) -> [u8; 4] {
+    match (src[3], dst[3]) {
+        (255, _) | (_, 0) => src,
+        (0, _) => dst,
+        (sa, da) => {
+            let (sa, da) = (sa as u32, da as u32 * (255 - sa as u32) / 255);
+            let a = sa + da;
+            let channel = |s: u8, d: u8| ((s as u32 * sa + d as u32 * da + a / 2) / a) as u8;
+            [channel(src[0], dst[0]), channel(src[1], dst[1]), channel(src[2], dst[2]), a as u8]
+        },
+    }
+}
+
+/// Decodes every frame of an animated PNG into a full canvas sized image,
+/// applying each frame's blending and disposal. A PNG that isn't animated
+/// is a single frame. Returns the frames and their delays, in milliseconds.
+pub fn read<R: Read>(mut r: R) -> Result<(Vec<Image>, Vec<u64>), Error> {
+    let mut bytes = Vec::new();
+    r.read_to_end(&mut bytes).map_err(ImageError::IoError)?;
+    if !bytes.starts_with(&SIGNATURE) {
+        return Err(format_error("not a png"));
+    }
+
+    let mut ihdr = None;
+    let mut animated = false;
+    // Chunks like PLTE and tRNS that every frame needs in order to decode.
+    let mut headers = Vec::new();
+    // Each frame's control and data. The default image is only a frame if
+    // an fcTL chunk comes before it.
+    let mut frames: Vec<(Control, Vec<u8>)> = Vec::new();
+    let mut at = SIGNATURE.len();
+    while at + 8 <= bytes.len() {
+        let len = u32_at(&bytes, at)? as usize;
+        let kind = &bytes[at + 4..at + 8];
+        let data = bytes.get(at + 8..at + 8 + len).ok_or_else(|| format_error("png is truncated"))?;
+        at += 12 + len;
+        match kind {
+            b"IHDR" if data.len() == 13 => ihdr = Some(data),
+            b"acTL" => animated = true,
+            b"fcTL" => frames.push((Control::parse(data)?, Vec::new())),
+            b"IDAT" | b"fdAT" => {
+                // fdAT starts with a sequence number IDAT doesn't have.
+                let data = if kind == b"fdAT" { data.get(4..).unwrap_or(&[]) } else { data };
+                if let Some(&mut (_, ref mut frame)) = frames.last_mut() {
+                    frame.extend_from_slice(data);
+                }
+            },
+            b"IEND" => break,
+            b"PLTE" | b"tRNS" | b"gAMA" | b"sRGB" if frames.is_empty() => headers.push((kind.to_vec(), data.to_vec())),
+            _ => {},
+        }
+    }
+    let ihdr = ihdr.ok_or_else(|| format_error("png has no IHDR chunk"))?;
+
+    if !animated {
+        let img = Image::from(image::load_from_memory_with_format(&bytes, ImageFormat::PNG)?);
+        return Ok((vec![img], vec![0]));
+    }
+
+    let (width, height) = (u32_at(ihdr, 0)?, u32_at(ihdr, 4)?);
+    let mut canvas = Image { pixels: vec![[0, 0, 0, 0]; width as usize * height as usize], width, height };
+    let mut imgs = Vec::with_capacity(frames.len());
+    let mut delays = Vec::with_capacity(frames.len());
+    for (control, data) in &frames {
+        let frame = decode_frame(ihdr, &headers, control, data)?;
+        let previous = if control.dispose == 2 { Some(canvas.clone()) } else { None };
+        draw(&mut canvas, &frame, control);
+        imgs.push(canvas.clone());
+        delays.push(control.delay);
+
+        match control.dispose {
+            1 => {
+                let clear = Image { pixels: vec![[0, 0, 0, 0]; frame.pixels.len()], ..frame };
+                draw(&mut canvas, &clear, &Control { blend: 0, ..*control });
+            },
+            2 => canvas = previous.unwrap_or(canvas),
+            _ => {},
+        }
+    }
+    Ok((imgs, delays))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32, over, push_chunk, read, SIGNATURE};
+    use super::super::Image;
+
+    /// A zlib stream with the data stored uncompressed.
+    fn stored_zlib(data: &[u8]) -> Vec<u8> {
+        let mut out = vec![0x78, 0x01, 0x01, data.len() as u8, (data.len() >> 8) as u8, !data.len() as u8, (!data.len() >> 8) as u8];
+        out.extend_from_slice(data);
+        let (mut a, mut b) = (1u32, 0u32);
+        for &byte in data {
+            a = (a + byte as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        let adler = (b << 16) | a;
+        out.extend_from_slice(&[(adler >> 24) as u8, (adler >> 16) as u8, (adler >> 8) as u8, adler as u8]);
+        out
+    }
+
+    fn fctl(seq: u32, w: u32, h: u32, x: u32, y: u32, dispose: u8, blend: u8) -> Vec<u8> {
+        let mut data = Vec::new();
+        for n in &[seq, w, h, x, y] {
+            data.extend_from_slice(&[(n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, *n as u8]);
+        }
+        data.extend_from_slice(&[0, 1, 0, 10, dispose, blend]);
+        data
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"IEND".iter()), 0xAE42_6082);
+    }
+
+    #[test]
+    fn test_read_apng() {
+        let mut png = SIGNATURE.to_vec();
+        push_chunk(&mut png, b"IHDR", &[0, 0, 0, 2, 0, 0, 0, 1, 8, 6, 0, 0, 0]);
+        push_chunk(&mut png, b"acTL", &[0, 0, 0, 2, 0, 0, 0, 0]);
+        push_chunk(&mut png, b"fcTL", &fctl(0, 2, 1, 0, 0, 1, 0));
+        push_chunk(&mut png, b"IDAT", &stored_zlib(&[0, 255, 0, 0, 255, 0, 0, 255, 255]));
+        push_chunk(&mut png, b"fcTL", &fctl(1, 1, 1, 1, 0, 0, 1));
+        let mut fdat = vec![0, 0, 0, 2];
+        fdat.extend(stored_zlib(&[0, 0, 255, 0, 128]));
+        push_chunk(&mut png, b"fdAT", &fdat);
+        push_chunk(&mut png, b"IEND", &[]);
+
+        let (frames, delays) = read(&png[..]).unwrap();
+        assert_eq!(delays, vec![100, 100]);
+        assert_eq!(frames[0], Image { pixels: vec![[255, 0, 0, 255], [0, 0, 255, 255]], width: 2, height: 1 });
+        // The first frame was cleared, so the second blends over nothing.
+        assert_eq!(frames[1], Image { pixels: vec![[0, 0, 0, 0], [0, 255, 0, 128]], width: 2, height: 1 });
+    }
+
+    #[test]
+    fn test_still_png() {
+        let mut png = SIGNATURE.to_vec();
+        push_chunk(&mut png, b"IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0]);
+        push_chunk(&mut png, b"IDAT", &stored_zlib(&[0, 1, 2, 3, 4]));
+        push_chunk(&mut png, b"IEND", &[]);
+        let (frames, delays) = read(&png[..]).unwrap();
+        assert_eq!(frames, vec![Image { pixels: vec![[1, 2, 3, 4]], width: 1, height: 1 }]);
+        assert_eq!(delays, vec![0]);
+        assert!(read(&b"GIF89a"[..]).is_err());
+    }
+
+    #[test]
+    fn test_over() {
+        assert_eq!(over([255, 0, 0, 128], [0, 0, 255, 255]), [128, 0, 127, 255]);
+        assert_eq!(over([1, 2, 3, 0], [4, 5, 6, 7]), [4, 5, 6, 7]);
+    }
+}
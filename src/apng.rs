@@ -0,0 +1,124 @@
+//! Animated PNG output, a full color alternative to Gif.
+
+use std::io;
+use deflate::deflate_bytes_zlib;
+use animated::crc32;
+use super::{Error, Image};
+
+const SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+/// An animated PNG. Unlike a `Gif`, frames keep their full color and alpha,
+/// nothing is quantized.
+///
+/// `delays` holds one entry per frame in `frames`, in hundredths of a
+/// second, the same as a `Gif`'s.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Apng {
+    pub width: u32,
+    pub height: u32,
+    pub frames: Vec<Image>,
+    pub delays: Vec<u16>,
+}
+
+impl Apng {
+    /// Writes the animated PNG to any output that implements Write.
+    ///
+    /// # Errors
+    ///
+    /// Returns the `std::io::Result` of the underlying `write` function calls.
+    pub fn write<W: io::Write>(&self, out: &mut W) -> Result<(), Error> {
+        out.write_all(&SIGNATURE)?;
+        let mut ihdr = Vec::with_capacity(13);
+        push_u32(&mut ihdr, self.width);
+        push_u32(&mut ihdr, self.height);
+        // 8 bit RGBA, default compression and filtering, not interlaced.
+        ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+        chunk(out, b"IHDR", &ihdr)?;
+
+        let mut actl = Vec::with_capacity(8);
+        push_u32(&mut actl, self.frames.len() as u32);
+        push_u32(&mut actl, 0); // Loop forever
+        chunk(out, b"acTL", &actl)?;
+
+        let mut sequence = 0;
+        for (n, (img, &delay)) in self.frames.iter().zip(&self.delays).enumerate() {
+            let mut fctl = Vec::with_capacity(26);
+            push_u32(&mut fctl, sequence);
+            push_u32(&mut fctl, self.width);
+            push_u32(&mut fctl, self.height);
+            push_u32(&mut fctl, 0);
+            push_u32(&mut fctl, 0);
+            fctl.extend_from_slice(&[(delay >> 8) as u8, delay as u8, 0, 100]);
+            // No disposal, and each frame replaces the one before it.
+            fctl.extend_from_slice(&[0, 0]);
+            chunk(out, b"fcTL", &fctl)?;
+            sequence += 1;
+
+            let data = deflate_bytes_zlib(&scanlines(img));
+            if n == 0 {
+                chunk(out, b"IDAT", &data)?;
+            } else {
+                let mut fdat = Vec::with_capacity(data.len() + 4);
+                push_u32(&mut fdat, sequence);
+                fdat.extend_from_slice(&data);
+                chunk(out, b"fdAT", &fdat)?;
+                sequence += 1;
+            }
+        }
+        chunk(out, b"IEND", &[])
+    }
+}
+
+/// A frame's pixels as PNG scanlines, each unfiltered.
+fn scanlines(img: &Image) -> Vec<u8> {
+    let width = img.width as usize;
+    let mut raw = Vec::with_capacity(img.pixels.len() * 4 + img.height as usize);
+    for row in img.pixels.chunks(width.max(1)) {
+        raw.push(0);
+        for px in row {
+            raw.extend_from_slice(px);
+        }
+    }
+    raw
+}
+
+fn push_u32(buf: &mut Vec<u8>, n: u32) {
+    buf.extend_from_slice(&[(n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8]);
+}
+
+fn chunk<W: io::Write>(out: &mut W, kind: &[u8; 4], data: &[u8]) -> Result<(), Error> {
+    let mut len = Vec::with_capacity(4);
+    push_u32(&mut len, data.len() as u32);
+    out.write_all(&len)?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    let mut crc = Vec::with_capacity(4);
+    push_u32(&mut crc, crc32(kind.iter().chain(data)));
+    out.write_all(&crc)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use image;
+    use super::Apng;
+    use super::super::Image;
+    use fixtures::solid;
+
+    #[test]
+    fn test_first_frame_is_a_png() {
+        let frames = vec![
+            Image { pixels: vec![[255, 0, 0, 128], [0, 255, 0, 255], [0, 0, 255, 0], [9, 9, 9, 9]], width: 2, height: 2 },
+            solid(2, 2, [0, 0, 0, 255]),
+        ];
+        let apng = Apng { width: 2, height: 2, frames: frames.clone(), delays: vec![10, 10] };
+        let mut bytes = Vec::new();
+        apng.write(&mut bytes).unwrap();
+
+        // Decoders without animation support show the first frame.
+        let png = image::load_from_memory(&bytes).unwrap();
+        assert_eq!(Image::from(png), frames[0]);
+        let fdats = bytes.windows(4).filter(|w| w == b"fdAT").count();
+        assert_eq!(fdats, 1);
+    }
+}
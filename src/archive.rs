@@ -0,0 +1,216 @@
+//! Reading files out of zip and tar archives, without extracting them first.
+//!
+//! Zip archives can hold stored or deflated files, but not Zip64 extensions
+//! or encryption. Tar archives can be ustar, GNU or pax, uncompressed.
+
+use inflate::inflate_bytes;
+
+/// A file in an archive.
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// Reads every file in a zip or tar archive whose name `wanted` accepts.
+pub fn entries<F: Fn(&str) -> bool>(bytes: &[u8], wanted: F) -> Result<Vec<Entry>, String> {
+    if bytes.starts_with(b"PK\x03\x04") || bytes.starts_with(b"PK\x05\x06") {
+        zip(bytes, wanted)
+    } else if bytes.len() >= 512 {
+        tar(bytes, wanted)
+    } else {
+        Err("not a zip or tar archive".to_string())
+    }
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> Result<usize, String> {
+    bytes.get(at..at + 2)
+        .map(|b| b[0] as usize | (b[1] as usize) << 8)
+        .ok_or_else(truncated)
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> Result<usize, String> {
+    bytes.get(at..at + 4)
+        .map(|b| b[0] as usize | (b[1] as usize) << 8 | (b[2] as usize) << 16 | (b[3] as usize) << 24)
+        .ok_or_else(truncated)
+}
+
+fn truncated() -> String {
+    "archive is truncated".to_string()
+}
+
+fn zip<F: Fn(&str) -> bool>(bytes: &[u8], wanted: F) -> Result<Vec<Entry>, String> {
+    // The end of central directory record is at the end, followed only by a
+    // comment of up to 64 KiB.
+    let search_from = bytes.len().saturating_sub(22 + 0xFFFF);
+    let end = (search_from..bytes.len().saturating_sub(21)).rev()
+        .find(|&at| bytes[at..].starts_with(b"PK\x05\x06"))
+        .ok_or("zip archive has no central directory")?;
+    let count = u16_at(bytes, end + 10)?;
+    let mut at = u32_at(bytes, end + 16)?;
+
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        if !bytes.get(at..).is_some_and(|b| b.starts_with(b"PK\x01\x02")) {
+            return Err("zip central directory is corrupt".to_string());
+        }
+        let method = u16_at(bytes, at + 10)?;
+        let compressed_len = u32_at(bytes, at + 20)?;
+        let name_len = u16_at(bytes, at + 28)?;
+        let extra_len = u16_at(bytes, at + 30)?;
+        let comment_len = u16_at(bytes, at + 32)?;
+        let local = u32_at(bytes, at + 42)?;
+        let name = bytes.get(at + 46..at + 46 + name_len).ok_or_else(truncated)?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        at += 46 + name_len + extra_len + comment_len;
+
+        if name.ends_with('/') || !wanted(&name) {
+            continue;
+        }
+        if compressed_len == 0xFFFF_FFFF || local == 0xFFFF_FFFF {
+            return Err(format!("{} needs Zip64, which isn't supported", name));
+        }
+        let start = local + 30 + u16_at(bytes, local + 26)? + u16_at(bytes, local + 28)?;
+        let data = bytes.get(start..start + compressed_len).ok_or_else(truncated)?;
+        let data = match method {
+            0 => data.to_vec(),
+            8 => inflate_bytes(data).map_err(|e| format!("{}: {}", name, e))?,
+            _ => return Err(format!("{} uses unsupported compression method {}", name, method)),
+        };
+        entries.push(Entry { name, data });
+    }
+    Ok(entries)
+}
+
+/// A nul-terminated string in a tar header.
+fn tar_str(field: &[u8]) -> String {
+    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..len]).into_owned()
+}
+
+fn tar_octal(field: &[u8]) -> Result<usize, String> {
+    let digits = tar_str(field);
+    let digits = digits.trim_matches(|c| c == ' ' || c == '\0');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    usize::from_str_radix(digits, 8).map_err(|_| "tar header is corrupt".to_string())
+}
+
+fn tar<F: Fn(&str) -> bool>(bytes: &[u8], wanted: F) -> Result<Vec<Entry>, String> {
+    let mut entries = Vec::new();
+    let mut at = 0;
+    // Set by a GNU long name or pax header, for the entry after it.
+    let mut long_name = None;
+    while at + 512 <= bytes.len() {
+        let header = &bytes[at..at + 512];
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        if &header[257..262] != b"ustar" {
+            return Err("not a zip or tar archive".to_string());
+        }
+        let size = tar_octal(&header[124..136])?;
+        let data = bytes.get(at + 512..at + 512 + size).ok_or_else(truncated)?;
+        at += 512 + size.div_ceil(512) * 512;
+
+        match header[156] {
+            b'L' => long_name = Some(tar_str(data)),
+            b'x' => {
+                let records = String::from_utf8_lossy(data);
+                if let Some(path) = records.lines().filter_map(|line| line.split_once(" path=")).next_back() {
+                    long_name = Some(path.1.to_string());
+                }
+            },
+            b'0' | b'\0' | b'7' => {
+                let name = long_name.take().unwrap_or_else(|| {
+                    let (prefix, name) = (tar_str(&header[345..500]), tar_str(&header[0..100]));
+                    if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) }
+                });
+                if wanted(&name) {
+                    entries.push(Entry { name, data: data.to_vec() });
+                }
+            },
+            _ => long_name = None,
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{entries, Entry};
+
+    /// A tiny PPM image, deflated.
+    pub const DEFLATED: [u8; 17] = [11, 48, 227, 50, 82, 48, 228, 50, 50, 53, 229, 250, 207, 0, 4, 255, 1];
+    pub const PPM: &[u8] = b"P6\n2 1\n255\n\xff\x00\x00\x00\x00\xff";
+
+    pub fn zip_archive(files: &[(&str, u16, &[u8])]) -> Vec<u8> {
+        let (mut out, mut central) = (Vec::new(), Vec::new());
+        let le16 = |v: usize| vec![v as u8, (v >> 8) as u8];
+        let le32 = |v: usize| vec![v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8];
+        for &(name, method, data) in files {
+            let offset = out.len();
+            out.extend_from_slice(b"PK\x03\x04");
+            out.extend(vec![0; 22]);
+            out.extend(le16(name.len()));
+            out.extend(le16(0));
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(data);
+
+            central.extend_from_slice(b"PK\x01\x02");
+            central.extend(vec![0; 6]);
+            central.extend(le16(method as usize));
+            central.extend(vec![0; 8]);
+            central.extend(le32(data.len()));
+            central.extend(le32(0));
+            central.extend(le16(name.len()));
+            central.extend(vec![0; 12]);
+            central.extend(le32(offset));
+            central.extend_from_slice(name.as_bytes());
+        }
+        let central_offset = out.len();
+        out.extend_from_slice(&central);
+        out.extend_from_slice(b"PK\x05\x06");
+        out.extend(vec![0; 6]);
+        out.extend(le16(files.len()));
+        out.extend(le32(central.len()));
+        out.extend(le32(central_offset));
+        out.extend(le16(0));
+        out
+    }
+
+    fn tar_archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for &(name, data) in files {
+            let mut header = vec![0; 512];
+            header[..name.len()].copy_from_slice(name.as_bytes());
+            header[124..135].copy_from_slice(format!("{:011o}", data.len()).as_bytes());
+            header[156] = b'0';
+            header[257..263].copy_from_slice(b"ustar\0");
+            out.extend(header);
+            out.extend_from_slice(data);
+            out.extend(vec![0; data.len().div_ceil(512) * 512 - data.len()]);
+        }
+        out.extend(vec![0; 1024]);
+        out
+    }
+
+    #[test]
+    fn test_zip() {
+        let archive = zip_archive(&[("a.ppm", 8, &DEFLATED), ("dir/", 0, b""), ("b.ppm", 0, PPM), ("c.txt", 0, b"hi")]);
+        let found = entries(&archive, |name| name.ends_with(".ppm")).unwrap();
+        assert_eq!(found, vec![
+            Entry { name: "a.ppm".to_string(), data: PPM.to_vec() },
+            Entry { name: "b.ppm".to_string(), data: PPM.to_vec() },
+        ]);
+    }
+
+    #[test]
+    fn test_tar() {
+        let archive = tar_archive(&[("frames/1.ppm", PPM), ("frames/notes.txt", b"hi")]);
+        let found = entries(&archive, |name| name.ends_with(".ppm")).unwrap();
+        assert_eq!(found, vec![Entry { name: "frames/1.ppm".to_string(), data: PPM.to_vec() }]);
+        assert!(entries(&[0; 100], |_| true).is_err());
+    }
+}
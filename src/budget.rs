@@ -0,0 +1,131 @@
+//! Keeping encodes under `Options::max_memory`.
+
+use super::{Error, Quantizer};
+
+/// The highest NeuQuant sample rate the budget raises to before it samples
+/// fewer frames instead. Past this, spatial detail would suffer more than
+/// skipping frames does.
+const MAX_SAMPLE_RATE: u32 = 8;
+
+/// Roughly what each distinct color costs `Quantizer::Naive`: its entries in
+/// the count map and in the sorted list the palette is picked from.
+const NAIVE_COLOR_BYTES: usize = 48;
+
+/// What an encode is expected to allocate, in bytes, from `estimate_memory`.
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Default)]
+pub struct MemoryEstimate {
+    /// The decoded frames, four bytes per pixel.
+    pub frames: usize,
+    /// Colors sampled to compute the palette.
+    pub sampling: usize,
+    /// The palettized frames, a byte per pixel.
+    pub indices: usize,
+}
+
+impl MemoryEstimate {
+    /// Everything added up.
+    pub fn total(&self) -> usize {
+        self.frames.saturating_add(self.sampling).saturating_add(self.indices)
+    }
+}
+
+/// How the palette is sampled: the quantizer, with a possibly raised sample
+/// rate, and the distance between sampled frames.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Plan {
+    pub quantizer: Quantizer,
+    pub step: usize,
+}
+
+/// Bytes used to sample every `step`th of `frames` frames of `width` x
+/// `height` pixels for `quantizer`.
+pub fn sampling_bytes(width: u32, height: u32, frames: usize, quantizer: Quantizer, step: usize) -> usize {
+    let sampled = frames.div_ceil(step.max(1));
+    match quantizer {
+        Quantizer::NeuQuant(rate) => {
+            let rate = rate.max(1);
+            width.div_ceil(rate) as usize * height.div_ceil(rate) as usize * 4 * sampled
+        },
+        Quantizer::Naive => (width as usize * height as usize * sampled).min(1 << 24) * NAIVE_COLOR_BYTES,
+        Quantizer::Grayscale => 0,
+    }
+}
+
+/// Picks how to sample so that sampling, plus `reserved` bytes needed for
+/// other buffers, fits in `max_memory`. NeuQuant's sample rate is raised
+/// first, then frames are skipped.
+///
+/// Returns an `Error::MemoryLimit` if even sampling a single frame doesn't
+/// fit, or an `Error::InvalidOption` for a NeuQuant sample rate of 0.
+pub fn plan(width: u32, height: u32, frames: usize, quantizer: Quantizer, max_memory: Option<usize>, reserved: usize) -> Result<Plan, Error> {
+    if quantizer == Quantizer::NeuQuant(0) {
+        return Err(Error::InvalidOption("quantizer", "NeuQuant's sample rate must be at least 1".to_string()));
+    }
+    let mut plan = Plan { quantizer, step: 1 };
+    let max_memory = match max_memory {
+        Some(max_memory) => max_memory,
+        None => return Ok(plan),
+    };
+    loop {
+        let needed = reserved.saturating_add(sampling_bytes(width, height, frames, plan.quantizer, plan.step));
+        if needed <= max_memory {
+            return Ok(plan);
+        }
+        match plan.quantizer {
+            Quantizer::NeuQuant(rate) if rate < MAX_SAMPLE_RATE => plan.quantizer = Quantizer::NeuQuant(rate + 1),
+            _ if plan.step < frames => plan.step = (plan.step * 2).min(frames),
+            _ => return Err(Error::MemoryLimit(needed)),
+        }
+    }
+}
+
+/// Estimates an encode, with sampling reduced to fit `max_memory` if it can
+/// be.
+pub fn estimate(width: u32, height: u32, frames: usize, quantizer: Quantizer, max_memory: Option<usize>) -> MemoryEstimate {
+    let pixels = (width as usize).saturating_mul(height as usize).saturating_mul(frames);
+    let plan = plan(width, height, frames, quantizer, max_memory, pixels).unwrap_or(Plan { quantizer, step: 1 });
+    MemoryEstimate {
+        frames: pixels.saturating_mul(4),
+        sampling: sampling_bytes(width, height, frames, plan.quantizer, plan.step),
+        indices: pixels,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{estimate, plan, sampling_bytes, Plan};
+    use super::super::{Error, Quantizer};
+
+    #[test]
+    fn test_sampling_bytes() {
+        assert_eq!(sampling_bytes(10, 10, 4, Quantizer::NeuQuant(1), 1), 1600);
+        assert_eq!(sampling_bytes(10, 10, 4, Quantizer::NeuQuant(3), 2), 4 * 4 * 4 * 2);
+        assert_eq!(sampling_bytes(10, 10, 4, Quantizer::Grayscale, 1), 0);
+    }
+
+    #[test]
+    fn test_estimate() {
+        let full = estimate(100, 100, 10, Quantizer::NeuQuant(1), None);
+        assert_eq!((full.frames, full.sampling, full.indices), (400_000, 400_000, 100_000));
+        assert_eq!(full.total(), 900_000);
+        let limited = estimate(100, 100, 10, Quantizer::NeuQuant(1), Some(200_000));
+        assert_eq!(limited.sampling, 100_000);
+    }
+
+    #[test]
+    fn test_plan_raises_sample_rate_then_skips_frames() {
+        let unlimited = plan(100, 100, 10, Quantizer::NeuQuant(1), None, 0).unwrap();
+        assert_eq!(unlimited, Plan { quantizer: Quantizer::NeuQuant(1), step: 1 });
+
+        let rate = plan(100, 100, 10, Quantizer::NeuQuant(1), Some(100_000), 0).unwrap();
+        assert_eq!(rate, Plan { quantizer: Quantizer::NeuQuant(2), step: 1 });
+
+        let step = plan(100, 100, 10, Quantizer::NeuQuant(1), Some(2_000), 0).unwrap();
+        assert_eq!(step, Plan { quantizer: Quantizer::NeuQuant(8), step: 8 });
+
+        match plan(100, 100, 10, Quantizer::Naive, Some(1_000), 0) {
+            Err(Error::MemoryLimit(_)) => {},
+            other => panic!("expected a memory limit error, got {:?}", other),
+        }
+    }
+}
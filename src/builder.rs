@@ -0,0 +1,221 @@
+//! Building a `Gif` one frame at a time.
+
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+use spill::Spill;
+use super::{engiffen_with_delays, write_streamed, write_with_delays, EncodeStats, Error, FrameRate, Gif, Image, Mismatch, Options, Quantizer};
+
+/// Collects frames as they're produced, for example by a capture loop that
+/// runs for minutes, and encodes them into a `Gif` once they've all arrived.
+///
+/// Frames are checked against the first one as they're pushed, so a frame of
+/// the wrong size is reported right away instead of when the loop is over.
+/// The palette is computed over every frame when the builder is finished,
+/// since settings like `auto_trim` and `keep_every_nth` look at the whole
+/// sequence.
+///
+/// For sequences too long to keep in memory, `spill_to` moves the frames
+/// into a temporary file as they're pushed, and `finish_write` encodes them
+/// from there.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{load_image, Error, Gif, GifBuilder, Quantizer};
+/// # fn foo() -> Result<Gif, Error> {
+/// let mut builder = GifBuilder::new(10, Quantizer::NeuQuant(2));
+/// for path in &["tests/ball/ball01.bmp", "tests/ball/ball02.bmp"] {
+///     builder.push_image(load_image(path)?)?;
+/// }
+/// let gif = builder.finish()?;
+/// # Ok(gif)
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct GifBuilder {
+    fps: FrameRate,
+    quantizer: Quantizer,
+    options: Options,
+    frames: Vec<Image>,
+    spill: Option<Spill>,
+    dimensions: Option<(u32, u32)>,
+    delays_ms: Vec<f64>,
+}
+
+impl GifBuilder {
+    /// Creates a builder with default `Options`, showing each frame for the
+    /// time given by `fps` unless it's pushed with its own delay.
+    pub fn new<F: Into<FrameRate>>(fps: F, quantizer: Quantizer) -> GifBuilder {
+        GifBuilder {
+            fps: fps.into(),
+            quantizer,
+            options: Options::default(),
+            frames: Vec::new(),
+            spill: None,
+            dimensions: None,
+            delays_ms: Vec::new(),
+        }
+    }
+
+    /// Replaces the builder's options.
+    pub fn options(mut self, options: Options) -> GifBuilder {
+        self.options = options;
+        self
+    }
+
+    /// Keeps pushed frames in a temporary file in `dir` instead of in memory.
+    /// Frames pushed before this are moved into the file too. The file is
+    /// removed when the builder is dropped or finished.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error::ImageWrite` if the file can't be created or written.
+    pub fn spill_to<P: AsRef<Path>>(mut self, dir: P) -> Result<GifBuilder, Error> {
+        let mut spill = Spill::create(dir.as_ref())?;
+        for img in self.frames.drain(..) {
+            spill.push(&img)?;
+        }
+        self.spill = Some(spill);
+        Ok(self)
+    }
+
+    /// Adds a frame, shown for the builder's frame rate.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error::Mismatch` if the frame's dimensions differ from the
+    /// first frame's and `options.mismatch` is `Mismatch::Error`.
+    pub fn push_image(&mut self, img: Image) -> Result<(), Error> {
+        let delay = self.fps;
+        self.push(img, delay)
+    }
+
+    /// Adds a frame that's shown for `delay`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as `push_image`.
+    pub fn push_image_for(&mut self, img: Image, delay: Duration) -> Result<(), Error> {
+        self.push(img, FrameRate::Delay(delay))
+    }
+
+    fn push(&mut self, img: Image, delay: FrameRate) -> Result<(), Error> {
+        if let (Mismatch::Error, Some(first)) = (self.options.mismatch, self.dimensions) {
+            if first != (img.width, img.height) {
+                return Err(Error::Mismatch(first, (img.width, img.height)));
+            }
+        }
+        self.dimensions.get_or_insert((img.width, img.height));
+        match self.spill {
+            Some(ref mut spill) => spill.push(&img)?,
+            None => self.frames.push(img),
+        }
+        self.delays_ms.push(delay.delay_ms());
+        Ok(())
+    }
+
+    /// The number of frames pushed so far.
+    pub fn len(&self) -> usize {
+        self.delays_ms.len()
+    }
+
+    /// Whether no frames have been pushed yet.
+    pub fn is_empty(&self) -> bool {
+        self.delays_ms.is_empty()
+    }
+
+    /// Encodes the pushed frames into a `Gif`.
+    ///
+    /// Spilled frames are read back into memory first; use `finish_write` to
+    /// avoid that.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error::NoImages` if no frames were pushed, an
+    /// `Error::ImageWrite` if spilled frames can't be read back, or the same
+    /// errors as `engiffen_with_options`.
+    pub fn finish(self) -> Result<Gif, Error> {
+        let frames = match self.spill {
+            Some(ref spill) => (0..spill.len()).map(|n| spill.read(n)).collect::<Result<Vec<_>, _>>()?,
+            None => self.frames,
+        };
+        engiffen_with_delays(&frames, self.delays_ms, self.quantizer, &self.options)
+    }
+
+    /// Encodes the pushed frames straight into `out`, like `engiffen_write`.
+    ///
+    /// Spilled frames are read from the file one at a time: once to sample
+    /// them for the palette and again to write them. As with
+    /// `engiffen_files`, the settings in `options` that change pixels or need
+    /// the whole sequence aren't applied to them.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as `finish` and `Gif::write`.
+    pub fn finish_write<W: io::Write>(self, out: &mut W) -> Result<EncodeStats, Error> {
+        match self.spill {
+            Some(ref spill) => write_streamed(spill.len(), |n| Ok(spill.read(n)?), self.delays_ms, &[], self.quantizer, &self.options, out),
+            None => write_with_delays(&self.frames, self.delays_ms, self.quantizer, &self.options, out),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::time::Duration;
+    use super::GifBuilder;
+    use super::super::{Error, Quantizer};
+    use fixtures::solid;
+
+    #[test]
+    fn test_builder() {
+        let mut builder = GifBuilder::new(10, Quantizer::Naive);
+        assert!(builder.is_empty());
+        builder.push_image(solid(2, 2, [255, 0, 0, 255])).unwrap();
+        builder.push_image_for(solid(2, 2, [0, 0, 255, 255]), Duration::from_millis(500)).unwrap();
+        assert_eq!(builder.len(), 2);
+        let gif = builder.finish().unwrap();
+        let delays: Vec<_> = gif.frames.iter().map(|frame| frame.delay).collect();
+        assert_eq!(delays, vec![10, 50]);
+    }
+
+    #[test]
+    fn test_builder_mismatch() {
+        let mut builder = GifBuilder::new(10, Quantizer::Naive);
+        builder.push_image(solid(2, 2, [255, 0, 0, 255])).unwrap();
+        match builder.push_image(solid(3, 2, [255, 0, 0, 255])) {
+            Err(Error::Mismatch((2, 2), (3, 2))) => {},
+            other => panic!("expected a mismatch, got {:?}", other),
+        }
+        assert_eq!(builder.len(), 1);
+    }
+
+    #[test]
+    fn test_builder_empty() {
+        match GifBuilder::new(10, Quantizer::Naive).finish() {
+            Err(Error::NoImages) => {},
+            other => panic!("expected no images, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_builder_spill() {
+        let frames: Vec<_> = (0..3).map(|n| solid(2, 2, [n * 100, 0, 0, 255])).collect();
+        let mut builder = GifBuilder::new(10, Quantizer::Naive);
+        let mut spilled = GifBuilder::new(10, Quantizer::Naive);
+        builder.push_image(frames[0].clone()).unwrap();
+        spilled.push_image(frames[0].clone()).unwrap();
+        let mut spilled = spilled.spill_to(env::temp_dir()).unwrap();
+        for img in &frames[1..] {
+            builder.push_image(img.clone()).unwrap();
+            spilled.push_image(img.clone()).unwrap();
+        }
+        assert_eq!(spilled.len(), 3);
+        let (mut expected, mut written) = (Vec::new(), Vec::new());
+        builder.finish_write(&mut expected).unwrap();
+        spilled.finish_write(&mut written).unwrap();
+        assert_eq!(written, expected);
+    }
+}
@@ -0,0 +1,58 @@
+//! Stopping an encode from another thread.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use super::{Error, Options};
+
+/// A flag for aborting a long encode from another thread. Clones share the
+/// same flag, so keep one and put another in `Options::cancel`.
+///
+/// Encoding checks the flag between stages and between frames, so it stops
+/// soon after `cancel` is called, returning an `Error::Cancelled`.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Asks every encode using this token to stop.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Tokens are equal when they share a flag.
+impl PartialEq for CancelToken {
+    fn eq(&self, other: &CancelToken) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Returns an `Error::Cancelled` if the encode using `options` was cancelled.
+pub fn check(options: &Options) -> Result<(), Error> {
+    match options.cancel {
+        Some(ref token) if token.is_cancelled() => Err(Error::Cancelled),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CancelToken;
+
+    #[test]
+    fn test_clones_share_a_flag() {
+        let token = CancelToken::new();
+        let other = token.clone();
+        assert_eq!(token, other);
+        assert!(token != CancelToken::new());
+        other.cancel();
+        assert!(token.is_cancelled());
+    }
+}
@@ -0,0 +1,231 @@
+//! A C interface, for applications in other languages that want engiffen
+//! as their Gif exporter. `include/engiffen.h` declares these functions.
+//!
+//! Functions that can fail return 0 on success and -1 on failure, after
+//! which `engiffen_last_error` describes what went wrong.
+
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_double, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::{ptr, slice};
+use super::{engiffen_with_options, Error, FrameRate, Gif, Image, Options, Quantizer};
+
+/// Frames pushed so far, and the settings they'll be encoded with.
+pub struct EngiffenContext {
+    frames: Vec<Image>,
+    fps: FrameRate,
+    quantizer: Quantizer,
+    options: Options,
+    last_error: Option<CString>,
+}
+
+impl EngiffenContext {
+    /// Records `err` for `engiffen_last_error` and returns -1.
+    fn fail<E: ToString>(&mut self, err: E) -> c_int {
+        let message = err.to_string().replace('\0', " ");
+        self.last_error = CString::new(message).ok();
+        -1
+    }
+
+    /// Encodes and clears the pushed frames, turning a panic into an error
+    /// instead of unwinding into C.
+    fn encode(&mut self) -> Result<Gif, String> {
+        let frames = ::std::mem::take(&mut self.frames);
+        let (fps, quantizer, options) = (self.fps, self.quantizer, &self.options);
+        match panic::catch_unwind(AssertUnwindSafe(|| engiffen_with_options(&frames, fps, quantizer, options))) {
+            Ok(result) => result.map_err(|e: Error| e.to_string()),
+            Err(_) => Err("encoding panicked".to_string()),
+        }
+    }
+}
+
+/// Creates a context that encodes at 10 frames per second. Free it with
+/// `engiffen_free`.
+#[no_mangle]
+pub extern "C" fn engiffen_new() -> *mut EngiffenContext {
+    Box::into_raw(Box::new(EngiffenContext {
+        frames: Vec::new(),
+        fps: FrameRate::Fps(10.0),
+        quantizer: Quantizer::NeuQuant(2),
+        options: Options::default(),
+        last_error: None,
+    }))
+}
+
+/// Frees a context from `engiffen_new`, along with any frames it still
+/// holds.
+///
+/// # Safety
+///
+/// `ctx` must be null or a context from `engiffen_new` that hasn't been
+/// freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn engiffen_free(ctx: *mut EngiffenContext) {
+    if !ctx.is_null() {
+        drop(Box::from_raw(ctx));
+    }
+}
+
+/// Sets the frame rate every frame is shown at.
+///
+/// # Safety
+///
+/// `ctx` must be a live context from `engiffen_new`.
+#[no_mangle]
+pub unsafe extern "C" fn engiffen_set_fps(ctx: *mut EngiffenContext, fps: c_double) -> c_int {
+    let ctx = match ctx.as_mut() {
+        Some(ctx) => ctx,
+        None => return -1,
+    };
+    if !(fps.is_finite() && fps > 0.0) {
+        return ctx.fail(format!("frame rate {} isn't a positive number", fps));
+    }
+    ctx.fps = FrameRate::from(fps);
+    0
+}
+
+/// Copies a frame of `width` by `height` RGBA pixels, four bytes each, row
+/// by row from the top.
+///
+/// # Safety
+///
+/// `ctx` must be a live context from `engiffen_new`, and `pixels` must
+/// point to `width * height * 4` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn engiffen_push_rgba(ctx: *mut EngiffenContext, pixels: *const u8, width: u32, height: u32) -> c_int {
+    let ctx = match ctx.as_mut() {
+        Some(ctx) => ctx,
+        None => return -1,
+    };
+    let len = (width as usize).checked_mul(height as usize).and_then(|n| n.checked_mul(4));
+    let len = match len {
+        Some(len) if !pixels.is_null() && len > 0 => len,
+        _ => return ctx.fail(format!("no pixels for a {}x{} frame", width, height)),
+    };
+    let bytes = slice::from_raw_parts(pixels, len);
+    let pixels = bytes.chunks(4).map(|px| [px[0], px[1], px[2], px[3]]).collect();
+    ctx.frames.push(Image { pixels, width, height });
+    0
+}
+
+/// Encodes the pushed frames and hands back the Gif's bytes in `out` and
+/// `out_len`, to be freed with `engiffen_free_buffer`. The frames are
+/// cleared either way, so the context can be reused.
+///
+/// # Safety
+///
+/// `ctx` must be a live context from `engiffen_new`, and `out` and
+/// `out_len` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn engiffen_finish_to_buffer(ctx: *mut EngiffenContext, out: *mut *mut u8, out_len: *mut usize) -> c_int {
+    let ctx = match ctx.as_mut() {
+        Some(ctx) => ctx,
+        None => return -1,
+    };
+    if out.is_null() || out_len.is_null() {
+        return ctx.fail("no place to put the Gif");
+    }
+    let bytes = match ctx.encode().and_then(|gif| gif.to_bytes().map_err(|e| e.to_string())) {
+        Ok(bytes) => bytes.into_boxed_slice(),
+        Err(e) => return ctx.fail(e),
+    };
+    *out_len = bytes.len();
+    *out = Box::into_raw(bytes) as *mut u8;
+    0
+}
+
+/// Frees a buffer from `engiffen_finish_to_buffer`.
+///
+/// # Safety
+///
+/// `buf` and `len` must be null or exactly what `engiffen_finish_to_buffer`
+/// handed back, not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn engiffen_free_buffer(buf: *mut u8, len: usize) {
+    if !buf.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(buf, len)));
+    }
+}
+
+/// Encodes the pushed frames and writes the Gif to the file at `path`,
+/// like `engiffen_finish_to_buffer`.
+///
+/// # Safety
+///
+/// `ctx` must be a live context from `engiffen_new`, and `path` a
+/// null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn engiffen_finish_to_file(ctx: *mut EngiffenContext, path: *const c_char) -> c_int {
+    let ctx = match ctx.as_mut() {
+        Some(ctx) => ctx,
+        None => return -1,
+    };
+    if path.is_null() {
+        return ctx.fail("no path to write the Gif to");
+    }
+    let path = match CStr::from_ptr(path).to_str() {
+        Ok(path) => path.to_string(),
+        Err(e) => return ctx.fail(e),
+    };
+    match ctx.encode().and_then(|gif| gif.write_to_file(&path).map_err(|e| e.to_string())) {
+        Ok(()) => 0,
+        Err(e) => ctx.fail(e),
+    }
+}
+
+/// Describes the last failure, or returns null if nothing has failed. The
+/// string belongs to the context and lasts until the next call that fails
+/// or the context is freed.
+///
+/// # Safety
+///
+/// `ctx` must be null or a live context from `engiffen_new`.
+#[no_mangle]
+pub unsafe extern "C" fn engiffen_last_error(ctx: *const EngiffenContext) -> *const c_char {
+    match ctx.as_ref().and_then(|ctx| ctx.last_error.as_ref()) {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_to_buffer() {
+        unsafe {
+            let ctx = engiffen_new();
+            assert_eq!(engiffen_set_fps(ctx, 20.0), 0);
+            let pixels = [255, 0, 0, 255, 0, 0, 255, 255];
+            assert_eq!(engiffen_push_rgba(ctx, pixels.as_ptr(), 2, 1), 0);
+            assert_eq!(engiffen_push_rgba(ctx, pixels.as_ptr(), 1, 2), 0);
+
+            let (mut buf, mut len) = (ptr::null_mut(), 0);
+            assert_eq!(engiffen_finish_to_buffer(ctx, &mut buf, &mut len), -1);
+            let message = CStr::from_ptr(engiffen_last_error(ctx)).to_str().unwrap();
+            assert!(message.contains("dimensions"), "{}", message);
+
+            assert_eq!(engiffen_push_rgba(ctx, pixels.as_ptr(), 2, 1), 0);
+            assert_eq!(engiffen_finish_to_buffer(ctx, &mut buf, &mut len), 0);
+            assert!(slice::from_raw_parts(buf, len).starts_with(b"GIF89a"));
+            engiffen_free_buffer(buf, len);
+            engiffen_free(ctx);
+        }
+    }
+
+    #[test]
+    fn test_bad_arguments() {
+        unsafe {
+            let ctx = engiffen_new();
+            assert!(engiffen_last_error(ctx).is_null());
+            assert_eq!(engiffen_set_fps(ctx, 0.0), -1);
+            assert_eq!(engiffen_push_rgba(ctx, ptr::null(), 2, 2), -1);
+            assert!(!engiffen_last_error(ctx).is_null());
+            assert_eq!(engiffen_set_fps(ptr::null_mut(), 10.0), -1);
+            assert_eq!(engiffen_finish_to_file(ctx, ptr::null()), -1);
+            engiffen_free(ctx);
+            engiffen_free(ptr::null_mut());
+        }
+    }
+}
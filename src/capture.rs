@@ -0,0 +1,94 @@
+//! Recording the screen with ffmpeg's screen grabber for the platform:
+//! x11grab on Linux and the BSDs, gdigrab on Windows and avfoundation on
+//! macOS.
+
+use std::env;
+use std::ffi::OsString;
+use std::time::Duration;
+use super::{Crop, Error};
+use video::VideoFrames;
+
+/// Owned copies of `args`.
+fn strings(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+/// The ffmpeg arguments that grab `region` of the screen, or all of it, for
+/// `duration` at `fps` frames per second, and the filters to run the
+/// frames through.
+pub fn grab_args(region: Option<Crop>, fps: usize, duration: Duration) -> (Vec<OsString>, String) {
+    let fps = fps.max(1).to_string();
+    let mut filters = format!("fps={}", fps);
+    let mut args = if cfg!(target_os = "windows") {
+        let mut args = strings(&["-f", "gdigrab", "-draw_mouse", "1", "-framerate", &fps]);
+        if let Some(crop) = region {
+            let size = format!("{}x{}", crop.width, crop.height);
+            args.extend(strings(&["-offset_x", &crop.x.to_string(), "-offset_y", &crop.y.to_string(), "-video_size", &size]));
+        }
+        args.extend(strings(&["-i", "desktop"]));
+        args
+    } else if cfg!(target_os = "macos") {
+        // avfoundation grabs whole screens, so the region is cropped out.
+        if let Some(crop) = region {
+            filters = format!("crop={}:{}:{}:{},{}", crop.width, crop.height, crop.x, crop.y, filters);
+        }
+        strings(&["-f", "avfoundation", "-capture_cursor", "1", "-framerate", &fps, "-i", "1:none"])
+    } else {
+        let display = env::var("DISPLAY").unwrap_or_else(|_| ":0".to_string());
+        let mut args = strings(&["-f", "x11grab", "-framerate", &fps]);
+        let input = match region {
+            Some(crop) => {
+                args.extend(strings(&["-video_size", &format!("{}x{}", crop.width, crop.height)]));
+                format!("{}+{},{}", display, crop.x, crop.y)
+            },
+            None => display,
+        };
+        args.extend(strings(&["-i", &input]));
+        args
+    };
+    let seconds = duration.as_secs() as f64 + duration.subsec_millis() as f64 / 1000.0;
+    args.extend(strings(&["-t", &seconds.to_string()]));
+    (args.into_iter().map(OsString::from).collect(), filters)
+}
+
+/// Runs `program` to record the screen.
+pub fn spawn(program: &str, region: Option<Crop>, fps: usize, duration: Duration) -> Result<VideoFrames, Error> {
+    if duration == Duration::default() {
+        return Err(Error::Invalid("can't record the screen for no time at all".to_string()));
+    }
+    let (input, filters) = grab_args(region, fps, duration);
+    VideoFrames::spawn_with(program, &input, &filters)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::{grab_args, spawn};
+    use super::super::{Crop, Error};
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_grab_args() {
+        let region = Crop { x: 10, y: 20, width: 640, height: 480 };
+        let (args, filters) = grab_args(Some(region), 15, Duration::from_millis(2500));
+        let args: Vec<_> = args.iter().map(|arg| arg.to_str().unwrap()).collect();
+        assert_eq!(filters, "fps=15");
+        assert_eq!(&args[..6], &["-f", "x11grab", "-framerate", "15", "-video_size", "640x480"]);
+        assert!(args[7].ends_with("+10,20"));
+        assert_eq!(&args[8..], &["-t", "2.5"]);
+        let (args, _) = grab_args(None, 0, Duration::from_secs(3));
+        assert_eq!(args[3].to_str(), Some("1"));
+    }
+
+    #[test]
+    fn test_bad_recording() {
+        match spawn("engiffen-no-such-ffmpeg", None, 10, Duration::from_secs(1)) {
+            Err(Error::ImageLoad(_)) => {},
+            other => panic!("expected a load error, got {:?}", other),
+        }
+        match spawn("engiffen-no-such-ffmpeg", None, 10, Duration::from_secs(0)) {
+            Err(Error::Invalid(_)) => {},
+            other => panic!("expected an invalid duration, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Global color adjustments applied to frames before quantization.
+
+use super::Image;
+
+/// Adjusts the red, green and blue channels of every pixel, leaving alpha
+/// untouched. `brightness` is added to each channel, then `contrast` scales
+/// the distance from mid-grey and `gamma` reshapes the midtones, with values
+/// above 1 brightening them.
+pub fn adjust(img: &mut Image, brightness: i32, contrast: f32, gamma: f32) {
+    let gamma = if gamma > 0.0 { gamma } else { 1.0 };
+    let mut table = [0u8; 256];
+    for (value, entry) in table.iter_mut().enumerate() {
+        let v = (value as i32 + brightness).clamp(0, 255) as f32 / 255.0;
+        let v = ((v - 0.5) * contrast + 0.5).clamp(0.0, 1.0);
+        *entry = (v.powf(1.0 / gamma) * 255.0).round() as u8;
+    }
+    for px in img.pixels.iter_mut() {
+        for c in 0..3 {
+            px[c] = table[px[c] as usize];
+        }
+    }
+}
+
+/// Rotates every pixel's hue by `degrees` and scales its saturation by
+/// `saturation`, in HSL space. Alpha is left untouched.
+pub fn hue_saturation(img: &mut Image, degrees: f32, saturation: f32) {
+    for px in img.pixels.iter_mut() {
+        let (h, s, l) = to_hsl(px[0], px[1], px[2]);
+        let h = (h + degrees / 360.0).rem_euclid(1.0);
+        let s = (s * saturation).clamp(0.0, 1.0);
+        let (r, g, b) = from_hsl(h, s, l);
+        px[0] = r;
+        px[1] = g;
+        px[2] = b;
+    }
+}
+
+/// Converts a color to hue, saturation and lightness, all in `0..=1`.
+fn to_hsl(r: u8, g: u8, b: u8) -> (f32, f32, f32) {
+    let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let l = (max + min) / 2.0;
+    let d = max - min;
+    if d == 0.0 {
+        return (0.0, 0.0, l);
+    }
+    let s = d / (1.0 - (2.0 * l - 1.0).abs());
+    let h = if max == r {
+        ((g - b) / d).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / d + 2.0
+    } else {
+        (r - g) / d + 4.0
+    };
+    (h / 6.0, s, l)
+}
+
+fn from_hsl(h: f32, s: f32, l: f32) -> (u8, u8, u8) {
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let h = h * 6.0;
+    let x = c * (1.0 - (h.rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = l - c / 2.0;
+    let channel = |v: f32| ((v + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+    (channel(r), channel(g), channel(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{adjust, hue_saturation};
+    use super::super::Image;
+
+    fn greys(values: &[u8]) -> Image {
+        let pixels = values.iter().map(|&v| [v, v, v, 100]).collect();
+        Image { pixels, width: values.len() as u32, height: 1 }
+    }
+
+    fn reds(img: &Image) -> Vec<u8> {
+        img.pixels.iter().map(|px| px[0]).collect()
+    }
+
+    #[test]
+    fn test_unchanged() {
+        let mut img = greys(&[0, 64, 128, 255]);
+        adjust(&mut img, 0, 1.0, 1.0);
+        assert_eq!(reds(&img), vec![0, 64, 128, 255]);
+        assert_eq!(img.pixels[0][3], 100);
+    }
+
+    #[test]
+    fn test_brightness_and_contrast() {
+        let mut img = greys(&[0, 100, 250]);
+        adjust(&mut img, 20, 1.0, 1.0);
+        assert_eq!(reds(&img), vec![20, 120, 255]);
+
+        let mut img = greys(&[0, 32, 224, 255]);
+        adjust(&mut img, 0, 2.0, 1.0);
+        assert_eq!(reds(&img), vec![0, 0, 255, 255]);
+
+        let mut img = greys(&[0, 64, 200, 255]);
+        adjust(&mut img, 0, 0.5, 1.0);
+        assert_eq!(reds(&img), vec![64, 96, 164, 191]);
+    }
+
+    #[test]
+    fn test_gamma() {
+        let mut img = greys(&[0, 64, 255]);
+        adjust(&mut img, 0, 1.0, 2.0);
+        assert_eq!(reds(&img), vec![0, 128, 255]);
+    }
+
+    #[test]
+    fn test_hue_rotation() {
+        let mut img = Image { pixels: vec![[255, 0, 0, 255], [40, 80, 120, 7]], width: 2, height: 1 };
+        hue_saturation(&mut img, 120.0, 1.0);
+        assert_eq!(img.pixels[0], [0, 255, 0, 255]);
+        hue_saturation(&mut img, -120.0, 1.0);
+        assert_eq!(img.pixels, vec![[255, 0, 0, 255], [40, 80, 120, 7]]);
+    }
+
+    #[test]
+    fn test_saturation() {
+        let mut img = Image { pixels: vec![[200, 100, 0, 255], [90, 90, 90, 255]], width: 2, height: 1 };
+        hue_saturation(&mut img, 0.0, 0.0);
+        assert_eq!(img.pixels, vec![[100, 100, 100, 255], [90, 90, 90, 255]]);
+    }
+}
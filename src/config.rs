@@ -0,0 +1,490 @@
+//! Reading encode settings from a config file, so a recipe can be
+//! versioned and reused instead of spelled out in arguments or code.
+//!
+//! Recipes are flat `key = value` lines, a small subset of TOML that any
+//! TOML tool still reads: no tables, no escapes in strings, and arrays only
+//! of numbers, on one line.
+
+use std::path::Path;
+use std::time::Duration;
+use super::{load_image, ChromaKey, Crop, Error, Filter, FrameRate, Image, Mismatch, Options, Position, Quantizer, Rotation, Scale, Watermark};
+
+/// Settings read from a config file by `load_recipe`: the frame rate and
+/// quantizer, if it sets them, and everything else as `Options`.
+///
+/// ```toml
+/// fps = 15
+/// quantizer = "neuquant"
+/// sample_rate = 2
+/// scale = [480, 270]
+/// matte = "#ffffff"
+/// watermark_image = "logo.png"
+/// watermark_position = "bottom-right"
+/// watermark_opacity = 0.5
+/// ```
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Recipe {
+    pub fps: Option<FrameRate>,
+    pub quantizer: Option<Quantizer>,
+    pub options: Options,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Value {
+    Str(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    /// Numbers only, as sizes, rectangles and colors need.
+    Array(Vec<Value>),
+}
+
+/// An integer or float. Only plain decimal numbers are read, not Rust's
+/// `inf` and `NaN` or TOML's hex, octal and binary integers.
+fn number(word: &str) -> Result<Value, String> {
+    if let Ok(n) = word.parse::<i64>() {
+        return Ok(Value::Int(n));
+    }
+    if word.bytes().any(|b| b.is_ascii_digit()) && word.bytes().all(|b| b.is_ascii_digit() || b"+-.eE".contains(&b)) {
+        if let Ok(n) = word.parse::<f64>() {
+            return Ok(Value::Float(n));
+        }
+    }
+    Err(format!("can't read value {}", word))
+}
+
+/// Parses everything after a line's `=`: one value, then at most a comment.
+fn parse_value(s: &str) -> Result<Value, String> {
+    let s = s.trim();
+    let (value, rest) = if let Some(quoted) = s.strip_prefix('"') {
+        let end = quoted.find('"').ok_or("string is missing its closing quote")?;
+        if quoted[..end].contains('\\') {
+            return Err("escapes aren't supported; put paths with backslashes in 'single quotes'".to_string());
+        }
+        (Value::Str(quoted[..end].to_string()), &quoted[end + 1..])
+    } else if let Some(quoted) = s.strip_prefix('\'') {
+        let end = quoted.find('\'').ok_or("string is missing its closing quote")?;
+        (Value::Str(quoted[..end].to_string()), &quoted[end + 1..])
+    } else if let Some(list) = s.strip_prefix('[') {
+        let end = list.find(']').ok_or("array is missing its ], and arrays have to fit on one line")?;
+        let items = list[..end].trim();
+        let items = items.strip_suffix(',').unwrap_or(items);
+        let items = match items.is_empty() {
+            true => Vec::new(),
+            false => items.split(',').map(|item| number(item.trim())).collect::<Result<_, _>>()?,
+        };
+        (Value::Array(items), &list[end + 1..])
+    } else {
+        let end = s.find('#').unwrap_or(s.len());
+        let value = match s[..end].trim() {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            "" => return Err("missing value".to_string()),
+            word => number(word)?,
+        };
+        (value, &s[end..])
+    };
+    let rest = rest.trim();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return Err(format!("unexpected {} after the value", rest));
+    }
+    Ok(value)
+}
+
+fn describe(value: &Value) -> &'static str {
+    match *value {
+        Value::Str(_) => "a string",
+        Value::Int(_) => "an integer",
+        Value::Float(_) => "a number",
+        Value::Bool(_) => "a boolean",
+        Value::Array(_) => "an array",
+    }
+}
+
+fn int(value: &Value, min: i64, max: i64) -> Result<i64, String> {
+    match *value {
+        Value::Int(n) if n >= min && n <= max => Ok(n),
+        Value::Int(n) => Err(format!("{} is outside {} to {}", n, min, max)),
+        ref other => Err(format!("expected an integer, got {}", describe(other))),
+    }
+}
+
+fn float(value: &Value) -> Result<f32, String> {
+    match *value {
+        Value::Float(n) => Ok(n as f32),
+        Value::Int(n) => Ok(n as f32),
+        ref other => Err(format!("expected a number, got {}", describe(other))),
+    }
+}
+
+fn boolean(value: &Value) -> Result<bool, String> {
+    match *value {
+        Value::Bool(b) => Ok(b),
+        ref other => Err(format!("expected true or false, got {}", describe(other))),
+    }
+}
+
+fn string(value: &Value) -> Result<&str, String> {
+    match *value {
+        Value::Str(ref s) => Ok(s),
+        ref other => Err(format!("expected a string, got {}", describe(other))),
+    }
+}
+
+fn ints(value: &Value, len: usize, max: i64) -> Result<Vec<i64>, String> {
+    match *value {
+        Value::Array(ref items) if items.len() == len => items.iter().map(|item| int(item, 0, max)).collect(),
+        _ => Err(format!("expected an array of {} integers", len)),
+    }
+}
+
+/// A color, as `"#rrggbb"` or `[r, g, b]`.
+fn color(value: &Value) -> Result<[u8; 3], String> {
+    if let Value::Str(ref s) = *value {
+        let hex = s.strip_prefix('#').filter(|hex| hex.len() == 6 && hex.is_ascii())
+            .ok_or_else(|| format!("expected a color like #ff8000, got {}", s))?;
+        let channel = |at: usize| u8::from_str_radix(&hex[at..at + 2], 16).map_err(|_| format!("expected a color like #ff8000, got {}", s));
+        return Ok([channel(0)?, channel(2)?, channel(4)?]);
+    }
+    let rgb = ints(value, 3, 255)?;
+    Ok([rgb[0] as u8, rgb[1] as u8, rgb[2] as u8])
+}
+
+fn filter(value: &Value) -> Result<Filter, String> {
+    match string(value)? {
+        "nearest" => Ok(Filter::Nearest),
+        "triangle" => Ok(Filter::Triangle),
+        "catmull-rom" => Ok(Filter::CatmullRom),
+        "gaussian" => Ok(Filter::Gaussian),
+        "lanczos3" => Ok(Filter::Lanczos3),
+        other => Err(format!("unknown filter {}", other)),
+    }
+}
+
+fn position(value: &Value) -> Result<Position, String> {
+    if let Value::Array(_) = *value {
+        let at = ints(value, 2, u32::MAX as i64)?;
+        return Ok(Position::At(at[0] as u32, at[1] as u32));
+    }
+    Ok(match string(value)? {
+        "top-left" => Position::TopLeft,
+        "top" => Position::Top,
+        "top-right" => Position::TopRight,
+        "left" => Position::Left,
+        "center" => Position::Center,
+        "right" => Position::Right,
+        "bottom-left" => Position::BottomLeft,
+        "bottom" => Position::Bottom,
+        "bottom-right" => Position::BottomRight,
+        other => return Err(format!("unknown position {}", other)),
+    })
+}
+
+/// Settings that only make sense together, filled in as their keys are
+/// read and combined once the whole file has been.
+#[derive(Default)]
+struct Pending {
+    sample_rate: Option<u32>,
+    chroma_key: Option<[u8; 3]>,
+    chroma_key_tolerance: u8,
+    watermark: Option<Watermark>,
+    watermark_image: bool,
+}
+
+impl Pending {
+    /// The watermark being built, which is anchored to the bottom right
+    /// corner at full opacity unless the recipe says otherwise.
+    fn watermark(&mut self) -> &mut Watermark {
+        self.watermark.get_or_insert_with(|| Watermark {
+            image: Image { pixels: Vec::new(), width: 0, height: 0 },
+            position: Position::BottomRight,
+            margin: 0,
+            opacity: 1.0,
+        })
+    }
+}
+
+/// Loads an image a recipe names, relative to the recipe's directory.
+fn image(value: &Value, dir: &Path) -> Result<Image, String> {
+    load_image(dir.join(string(value)?)).map_err(|e| e.to_string())
+}
+
+fn apply(recipe: &mut Recipe, pending: &mut Pending, key: &str, value: &Value, dir: &Path) -> Result<(), String> {
+    let options = &mut recipe.options;
+    let u32_max = u32::MAX as i64;
+    match key {
+        "fps" => {
+            let fps = float(value)?;
+            if fps <= 0.0 {
+                return Err("fps must be above 0".to_string());
+            }
+            recipe.fps = Some(FrameRate::Fps(fps));
+        },
+        "quantizer" => recipe.quantizer = Some(match string(value)? {
+            "neuquant" => Quantizer::NeuQuant(1),
+            "naive" => Quantizer::Naive,
+            "grayscale" | "greyscale" => Quantizer::Grayscale,
+            other => return Err(format!("unknown quantizer {}", other)),
+        }),
+        "sample_rate" => pending.sample_rate = Some(int(value, 1, u32_max)? as u32),
+        "alpha_threshold" => options.alpha_threshold = int(value, 0, 255)? as u8,
+        "alpha_dither" => options.alpha_dither = boolean(value)?,
+        "matte" => options.matte = Some(color(value)?),
+        "background" => options.background = Some(image(value, dir)?),
+        "chroma_key" => pending.chroma_key = Some(color(value)?),
+        "chroma_key_tolerance" => pending.chroma_key_tolerance = int(value, 0, 255)? as u8,
+        "opaque" => options.opaque = boolean(value)?,
+        "brightness" => options.brightness = int(value, -255, 255)? as i32,
+        "contrast" => options.contrast = float(value)?,
+        "gamma" => options.gamma = float(value)?,
+        "hue_rotate" => options.hue_rotate = float(value)?,
+        "saturation" => options.saturation = float(value)?,
+        "delay_dither" => options.delay_dither = boolean(value)?,
+        "first_frame_delay" => {
+            let seconds = float(value)?;
+            if seconds < 0.0 {
+                return Err("first_frame_delay can't be negative".to_string());
+            }
+            options.first_frame_delay = Some(Duration::from_millis((seconds * 1000.0).round() as u64));
+        },
+        "keep_every_nth" => options.keep_every_nth = int(value, 0, u32_max)? as usize,
+        "motion_blur" => options.motion_blur = boolean(value)?,
+        "interpolate" => options.interpolate = Some(FrameRate::Fps(float(value)?)),
+        "merge_fast_frames" => options.merge_fast_frames = boolean(value)?,
+        "loop_crossfade" => options.loop_crossfade = int(value, 0, u32_max)? as usize,
+        "mismatch" => options.mismatch = match string(value)? {
+            "error" => Mismatch::Error,
+            "resize" => Mismatch::Resize(None, Filter::Lanczos3),
+            "pad" => Mismatch::Pad([0, 0, 0, 0]),
+            other => return Err(format!("unknown mismatch handling {}", other)),
+        },
+        "crop" => {
+            let crop = ints(value, 4, u32_max)?;
+            options.crop = Some(Crop { x: crop[0] as u32, y: crop[1] as u32, width: crop[2] as u32, height: crop[3] as u32 });
+        },
+        "auto_trim" => options.auto_trim = boolean(value)?,
+        "rotation" => options.rotation = match int(value, 0, 270)? {
+            0 => None,
+            90 => Some(Rotation::Rotate90),
+            180 => Some(Rotation::Rotate180),
+            270 => Some(Rotation::Rotate270),
+            other => return Err(format!("rotation must be 0, 90, 180 or 270, not {}", other)),
+        },
+        "flip_horizontal" => options.flip_horizontal = boolean(value)?,
+        "flip_vertical" => options.flip_vertical = boolean(value)?,
+        "scale" => options.scale = Some(match *value {
+            Value::Array(_) => {
+                let size = ints(value, 2, u32_max)?;
+                Scale::Size(size[0] as u32, size[1] as u32)
+            },
+            _ => Scale::Factor(float(value)?),
+        }),
+        "scale_width" => options.scale = Some(Scale::Width(int(value, 1, u32_max)? as u32)),
+        "scale_height" => options.scale = Some(Scale::Height(int(value, 1, u32_max)? as u32)),
+        "scale_filter" => options.scale_filter = filter(value)?,
+        "integer_scale" => options.integer_scale = int(value, 0, u32_max)? as u32,
+        "downscale_to_fit" => options.downscale_to_fit = boolean(value)?,
+        "max_memory" => options.max_memory = Some(int(value, 0, i64::MAX)? as usize),
+        "max_quantization_error" => options.max_quantization_error = Some(float(value)?),
+        "label_frames" => options.label_frames = boolean(value)?,
+        "watermark_image" => {
+            pending.watermark().image = image(value, dir)?;
+            pending.watermark_image = true;
+        },
+        "watermark_position" => pending.watermark().position = position(value)?,
+        "watermark_margin" => pending.watermark().margin = int(value, 0, u32_max)? as u32,
+        "watermark_opacity" => pending.watermark().opacity = float(value)?,
+        _ => return Err(format!("unknown setting {}", key)),
+    }
+    Ok(())
+}
+
+/// Parses a recipe, loading any images it names relative to `dir`.
+pub fn parse(text: &str, dir: &Path) -> Result<Recipe, Error> {
+    let mut recipe = Recipe::default();
+    let mut pending = Pending::default();
+    let mut seen = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let fail = |message: String| Error::Config(n + 1, message);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            return Err(fail("tables aren't supported; settings like watermark_image go at the top level".to_string()));
+        }
+        let eq = line.find('=').ok_or_else(|| fail("expected key = value".to_string()))?;
+        let key = line[..eq].trim().to_string();
+        let value = parse_value(&line[eq + 1..]).map_err(&fail)?;
+        if seen.contains(&key) {
+            return Err(fail(format!("{} is set twice", key)));
+        }
+        apply(&mut recipe, &mut pending, &key, &value, dir).map_err(&fail)?;
+        seen.push(key);
+    }
+
+    if let Some(rate) = pending.sample_rate {
+        match recipe.quantizer {
+            Some(Quantizer::NeuQuant(_)) | None => recipe.quantizer = Some(Quantizer::NeuQuant(rate)),
+            _ => {},
+        }
+    }
+    if let Some(color) = pending.chroma_key {
+        recipe.options.chroma_key = Some(ChromaKey { color, tolerance: pending.chroma_key_tolerance });
+    }
+    if let Some(watermark) = pending.watermark {
+        if !pending.watermark_image {
+            return Err(Error::Config(0, "watermark settings need a watermark_image".to_string()));
+        }
+        recipe.options.watermark = Some(watermark);
+    }
+    Ok(recipe)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use super::{parse, parse_value, Value};
+    use super::super::{Error, FrameRate, Quantizer, Scale};
+
+    #[test]
+    fn test_values() {
+        assert_eq!(parse_value(" \"a # b\" # c").unwrap(), Value::Str("a # b".to_string()));
+        assert_eq!(parse_value("'C:\\frames'").unwrap(), Value::Str("C:\\frames".to_string()));
+        assert_eq!(parse_value("-2.5 # d").unwrap(), Value::Float(-2.5));
+        assert_eq!(parse_value("[1, 2.5, ]").unwrap(), Value::Array(vec![Value::Int(1), Value::Float(2.5)]));
+        assert_eq!(parse_value("[]").unwrap(), Value::Array(vec![]));
+        assert!(parse_value("\"open").is_err());
+        assert!(parse_value("[1 2]").is_err());
+        assert!(parse_value("[\"x\"]").is_err());
+        assert!(parse_value("inf").is_err());
+        assert!(parse_value("0x10").is_err());
+    }
+
+    #[test]
+    fn test_parse() {
+        let recipe = parse("# preview\nfps = 12.5\nsample_rate = 3\nscale = [320, 240] # small\nmatte = \"#ff8000\"\nchroma_key = [0, 255, 0]\nchroma_key_tolerance = 40\n", Path::new(".")).unwrap();
+        assert_eq!(recipe.fps, Some(FrameRate::Fps(12.5)));
+        assert_eq!(recipe.quantizer, Some(Quantizer::NeuQuant(3)));
+        assert_eq!(recipe.options.scale, Some(Scale::Size(320, 240)));
+        assert_eq!(recipe.options.matte, Some([255, 128, 0]));
+        assert_eq!(recipe.options.chroma_key.unwrap().tolerance, 40);
+    }
+
+    #[test]
+    fn test_parse_images() {
+        let recipe = parse("watermark_image = \"tests/ball/ball01.bmp\"\nwatermark_opacity = 0.5\n", Path::new(".")).unwrap();
+        assert_eq!(recipe.options.watermark.unwrap().opacity, 0.5);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let line = |text: &str| match parse(text, Path::new(".")) {
+            Err(Error::Config(line, _)) => line,
+            other => panic!("expected a config error, got {:?}", other),
+        };
+        assert_eq!(line("fps = 10\nfsp = 10\n"), 2);
+        assert_eq!(line("\n\nalpha_threshold = 300\n"), 3);
+        assert_eq!(line("fps = 10\nfps = 12\n"), 2);
+        assert_eq!(line("opaque = true false\n"), 1);
+        assert_eq!(line("watermark_opacity = 1\n"), 0);
+    }
+
+    #[test]
+    fn test_unsupported_toml() {
+        let error = |text: &str| match parse(text, Path::new(".")) {
+            Err(Error::Config(line, reason)) => (line, reason),
+            other => panic!("expected a config error, got {:?}", other),
+        };
+        let (line, reason) = error("fps = 10\nscale = [320,\n    240]\n");
+        assert_eq!(line, 2);
+        assert!(reason.contains("one line"), "{}", reason);
+        assert_eq!(error("fps = 10\ncrop = [0, 0, # x, y\n  64, 64]\n").0, 2);
+        let (line, reason) = error("fps = 10\n\nwatermark_image = \"logo\\u0041.png\"\n");
+        assert_eq!(line, 3);
+        assert!(reason.contains("escapes"), "{}", reason);
+        assert_eq!(error("quantizer = \"\"\"naive\"\"\"\n").0, 1);
+        assert_eq!(error("fps = 10\n[watermark]\nimage = \"logo.png\"\n").0, 2);
+        assert_eq!(error("fps = 10\n[[watermark]]\nimage = \"logo.png\"\n").0, 2);
+    }
+
+    /// A xorshift generator, so the property tests below are repeatable.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+            &items[self.next() as usize % items.len()]
+        }
+    }
+
+    #[test]
+    fn test_values_round_trip() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        let text_chars: Vec<char> = "ab #=[],.-_/:\\'é".chars().collect();
+        for _ in 0..2000 {
+            let (text, expected) = match rng.next() % 5 {
+                0 => {
+                    let n = rng.next() as i64 >> (rng.next() % 64);
+                    (n.to_string(), Value::Int(n))
+                },
+                1 => {
+                    let n = (rng.next() as i64 >> 20) as f64 / 1024.0 + 0.5;
+                    (format!("{:?}", n), Value::Float(n))
+                },
+                2 => {
+                    let b = rng.next() % 2 == 0;
+                    (b.to_string(), Value::Bool(b))
+                },
+                3 => {
+                    let s: String = (0..rng.next() % 12).map(|_| *rng.pick(&text_chars)).collect();
+                    let quoted = match s.contains('\\') || s.contains('"') {
+                        true if s.contains('\'') => continue,
+                        true => format!("'{}'", s),
+                        false => format!("\"{}\"", s),
+                    };
+                    (quoted, Value::Str(s))
+                },
+                _ => {
+                    let items: Vec<i64> = (0..rng.next() % 5).map(|_| (rng.next() % 1000) as i64).collect();
+                    let list: Vec<String> = items.iter().map(|n| n.to_string()).collect();
+                    (format!("[{}]", list.join(", ")), Value::Array(items.into_iter().map(Value::Int).collect()))
+                },
+            };
+            let comment = if rng.next() % 2 == 0 { " # a comment, with [brackets] and \"quotes\"" } else { "" };
+            assert_eq!(parse_value(&format!(" {}{}", text, comment)), Ok(expected), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_parse_garbage() {
+        // Arbitrary lines of recipe-like text always parse or fail with the
+        // number of a line in the text, rather than panicking.
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        let keys = ["fps", "scale", "crop", "matte", "rotation", "watermark_position", "watermark_margin", "first_frame_delay", "max_memory", "nope", ""];
+        let pieces = ["=", " ", "\"", "'", "[", "]", ",", "#", "\\", "-", ".", "e", "1", "99999999999999999999", "1e400", "true", "#ff8000", "é", "\n"];
+        for _ in 0..2000 {
+            let mut text = String::new();
+            for _ in 0..rng.next() % 4 {
+                let key = *rng.pick(&keys);
+                text.push_str(key);
+                for _ in 0..rng.next() % 10 {
+                    let piece = *rng.pick(&pieces);
+                    text.push_str(piece);
+                }
+                text.push('\n');
+            }
+            match parse(&text, Path::new(".")) {
+                Ok(_) => {},
+                Err(Error::Config(line, _)) => assert!(line <= text.lines().count(), "{:?}", text),
+                Err(other) => panic!("expected a config error for {:?}, got {:?}", text, other),
+            }
+        }
+    }
+}
@@ -0,0 +1,244 @@
+//! Decoding existing Gifs back into frames.
+
+use std::io::Read;
+use gif::{self, ColorOutput, DisposalMethod, SetParameter};
+use image::ImageError;
+use std::sync::Arc;
+use super::{Error, Frame, Gif, Image};
+
+impl From<gif::DecodingError> for Error {
+    fn from(err: gif::DecodingError) -> Error {
+        match err {
+            gif::DecodingError::Io(err) => Error::ImageLoad(ImageError::IoError(err)),
+            err => Error::ImageLoad(ImageError::FormatError(err.to_string())),
+        }
+    }
+}
+
+/// Decodes every frame of a Gif into a full canvas sized image, applying
+/// each frame's disposal method. Returns the frames and their delays, in
+/// hundredths of a second.
+pub fn read<R: Read>(r: R) -> Result<(Vec<Image>, Vec<u16>), Error> {
+    let mut decoder = gif::Decoder::new(r);
+    decoder.set(ColorOutput::RGBA);
+    let mut reader = decoder.read_info()?;
+    let (width, height) = (reader.width() as u32, reader.height() as u32);
+    let mut canvas = Image { pixels: vec![[0, 0, 0, 0]; (width * height) as usize], width, height };
+    let mut imgs = Vec::new();
+    let mut delays = Vec::new();
+    while let Some(frame) = reader.read_next_frame()? {
+        let previous = match frame.dispose {
+            DisposalMethod::Previous => Some(canvas.clone()),
+            _ => None,
+        };
+        let (left, top) = (frame.left as u32, frame.top as u32);
+        let (w, h) = (frame.width as u32, frame.height as u32);
+        for (n, px) in frame.buffer.chunks(4).enumerate() {
+            let (x, y) = (left + n as u32 % w, top + n as u32 / w);
+            // Transparent pixels let the canvas underneath show through.
+            if x < width && y < height && px[3] > 0 {
+                canvas.pixels[(y * width + x) as usize] = [px[0], px[1], px[2], px[3]];
+            }
+        }
+        imgs.push(canvas.clone());
+        delays.push(frame.delay);
+
+        match frame.dispose {
+            DisposalMethod::Background => {
+                for y in top..(top + h).min(height) {
+                    for x in left..(left + w).min(width) {
+                        canvas.pixels[(y * width + x) as usize] = [0, 0, 0, 0];
+                    }
+                }
+            },
+            DisposalMethod::Previous => canvas = previous.unwrap_or(canvas),
+            DisposalMethod::Any | DisposalMethod::Keep => (),
+        }
+    }
+    Ok((imgs, delays))
+}
+
+/// Decodes a Gif into its palette and each frame's indices, as they are,
+/// without drawing them, labelling frames with the comments written before
+/// them. Frames with palettes of their own are errors, since a `Gif` only
+/// has the one.
+pub fn read_indexed<R: Read>(mut r: R) -> Result<Gif, Error> {
+    let mut bytes = Vec::new();
+    r.read_to_end(&mut bytes).map_err(ImageError::IoError)?;
+    let mut labels = comments(&bytes).into_iter();
+    let mut decoder = gif::Decoder::new(&bytes[..]);
+    decoder.set(ColorOutput::Indexed);
+    let mut reader = decoder.read_info()?;
+    let (width, height) = (reader.width(), reader.height());
+    let palette = reader.global_palette().map(|palette| palette.to_vec()).unwrap_or_default();
+    let mut frames = Vec::new();
+    while let Some(decoded) = reader.read_next_frame()? {
+        if decoded.palette.is_some() {
+            return Err(Error::Invalid(format!("frame {} has a palette of its own", frames.len())));
+        }
+        if decoded.buffer.len() != decoded.width as usize * decoded.height as usize {
+            return Err(Error::Invalid(format!("frame {} has {} indices for {}x{} pixels", frames.len(), decoded.buffer.len(), decoded.width, decoded.height)));
+        }
+        frames.push(Frame {
+            left: decoded.left,
+            top: decoded.top,
+            delay: decoded.delay,
+            transparent: decoded.transparent,
+            dispose: decoded.dispose.into(),
+            label: labels.next().unwrap_or_default(),
+            ..Frame::new(Arc::new(decoded.buffer.to_vec()), decoded.width, decoded.height)
+        });
+    }
+    Ok(Gif { palette, width, height, frames })
+}
+
+/// The comment written before each frame of a Gif, if any, found by
+/// walking the Gif's blocks. The walk stops at anything it doesn't
+/// understand, leaving the decoder to report it.
+fn comments(bytes: &[u8]) -> Vec<Option<String>> {
+    let mut comments = Vec::new();
+    walk_blocks(bytes, &mut comments);
+    comments
+}
+
+fn walk_blocks(bytes: &[u8], comments: &mut Vec<Option<String>>) -> Option<()> {
+    let palette_len = |flags: u8| if flags & 0x80 != 0 { 3 << ((flags & 7) + 1) } else { 0 };
+    let mut at = 13 + palette_len(*bytes.get(10)?);
+    let mut comment = None;
+    loop {
+        match *bytes.get(at)? {
+            0x21 => {
+                let (data, end) = sub_blocks(bytes, at + 2)?;
+                if *bytes.get(at + 1)? == gif::Extension::Comment as u8 {
+                    comment = Some(String::from_utf8_lossy(&data).into_owned());
+                }
+                at = end;
+            },
+            0x2C => {
+                // The image descriptor, any local palette, and the LZW
+                // minimum code size come before the image data.
+                at += 10 + palette_len(*bytes.get(at + 9)?) + 1;
+                at = sub_blocks(bytes, at)?.1;
+                comments.push(comment.take());
+            },
+            _ => return Some(()),
+        }
+    }
+}
+
+/// The data of the sub-blocks starting at `at`, joined, and the position
+/// after their terminator.
+fn sub_blocks(bytes: &[u8], mut at: usize) -> Option<(Vec<u8>, usize)> {
+    let mut data = Vec::new();
+    loop {
+        let len = *bytes.get(at)? as usize;
+        if len == 0 {
+            return Some((data, at + 1));
+        }
+        data.extend_from_slice(bytes.get(at + 1..at + 1 + len)?);
+        at += 1 + len;
+    }
+}
+
+/// Checks that `gif` is consistent, then encodes it and decodes the result
+/// to check that it reads back as the same frames and palette.
+pub fn verify(gif: &Gif) -> Result<(), Error> {
+    let colors = gif.palette_len();
+    if gif.palette.len() % 3 != 0 || colors == 0 || colors > 256 {
+        return Err(Error::Invalid(format!("palette has {} bytes, which isn't 1 to 256 colors", gif.palette.len())));
+    }
+    for (n, frame) in gif.frames.iter().enumerate() {
+        if frame.indices.len() != frame.width as usize * frame.height as usize {
+            return Err(Error::Invalid(format!("frame {} has {} indices for {}x{} pixels", n, frame.indices.len(), frame.width, frame.height)));
+        }
+        if frame.left as u32 + frame.width as u32 > gif.width as u32 || frame.top as u32 + frame.height as u32 > gif.height as u32 {
+            return Err(Error::Invalid(format!("frame {} extends past the canvas", n)));
+        }
+        if let Some(&idx) = frame.indices.iter().chain(&frame.transparent).find(|&&idx| idx as usize >= colors) {
+            return Err(Error::Invalid(format!("frame {} uses index {}, past the end of the palette", n, idx)));
+        }
+    }
+
+    let bytes = gif.to_bytes()?;
+    let mut decoder = gif::Decoder::new(&bytes[..]);
+    decoder.set(ColorOutput::Indexed);
+    let mut reader = decoder.read_info()?;
+    if (reader.width(), reader.height()) != gif.dimensions() {
+        return Err(Error::Invalid(format!("decoded as {}x{}", reader.width(), reader.height())));
+    }
+    if !reader.global_palette().is_some_and(|palette| palette.starts_with(&gif.palette)) {
+        return Err(Error::Invalid("decoded palette doesn't match".to_string()));
+    }
+    let mut count = 0;
+    while let Some(decoded) = reader.read_next_frame()? {
+        let matches = gif.frames.get(count).is_some_and(|frame| {
+            decoded.buffer[..] == frame.indices[..] && decoded.delay == frame.delay && decoded.transparent == frame.transparent
+                && (decoded.left, decoded.top, decoded.width, decoded.height) == (frame.left, frame.top, frame.width, frame.height)
+        });
+        if !matches {
+            return Err(Error::Invalid(format!("decoded frame {} doesn't match", count)));
+        }
+        count += 1;
+    }
+    if count != gif.frame_count() {
+        return Err(Error::Invalid(format!("decoded {} of {} frames", count, gif.frame_count())));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::{read, read_indexed, verify};
+    use super::super::{engiffen, Disposal, Image, Quantizer};
+    use fixtures::solid;
+
+    #[test]
+    fn test_round_trip() {
+        let colors = [[255, 0, 0, 255], [0, 0, 255, 255], [0, 0, 0, 0]];
+        let imgs: Vec<_> = colors.iter().map(|&px| solid(3, 2, px)).collect();
+        let gif = engiffen(&imgs, 20, Quantizer::Naive).unwrap();
+        let mut bytes = Vec::new();
+        gif.write(&mut bytes).unwrap();
+
+        let (frames, delays) = read(&bytes[..]).unwrap();
+        assert_eq!(delays, vec![5, 5, 5]);
+        assert_eq!(frames[0], imgs[0]);
+        assert_eq!(frames[1], imgs[1]);
+        // Without a disposal method, a transparent frame leaves the one
+        // before it showing.
+        assert_eq!(frames[2], imgs[1]);
+    }
+
+    #[test]
+    fn test_read_indexed() {
+        let imgs = vec![
+            Image { pixels: vec![[255, 0, 0, 255], [0, 0, 0, 0]], width: 2, height: 1 },
+            Image { pixels: vec![[0, 0, 255, 255], [0, 255, 0, 255]], width: 2, height: 1 },
+        ];
+        let mut gif = engiffen(&imgs, 10, Quantizer::Naive).unwrap();
+        gif.frames[1].dispose = Disposal::Background;
+        gif.frames[1].label = Some(format!("frames/{}.png", "x".repeat(300)));
+        let read = read_indexed(&gif.to_bytes().unwrap()[..]).unwrap();
+        assert!(read.palette.starts_with(&gif.palette));
+        assert_eq!(read.frames, gif.frames);
+        assert_eq!(read.to_bytes().unwrap(), gif.to_bytes().unwrap());
+        assert!(read_indexed(&b"GIF89a"[..]).is_err());
+    }
+
+    #[test]
+    fn test_verify() {
+        let imgs = vec![Image { pixels: vec![[255, 0, 0, 255], [0, 0, 0, 0]], width: 2, height: 1 }; 2];
+        let mut gif = engiffen(&imgs, 10, Quantizer::Naive).unwrap();
+        verify(&gif).unwrap();
+        Arc::make_mut(&mut gif.frames[1].indices)[0] = 7;
+        assert!(verify(&gif).is_err());
+        Arc::make_mut(&mut gif.frames[1].indices).pop();
+        assert!(verify(&gif).is_err());
+    }
+
+    #[test]
+    fn test_garbage() {
+        assert!(read(&b"not a gif"[..]).is_err());
+    }
+}
@@ -0,0 +1,150 @@
+//! Decoding PNGs and TIFFs with 16 bits per channel, and bringing them down
+//! to 8 bits by rounding or dithering rather than dropping the low byte.
+
+use std::io::Cursor;
+use image::{self, ColorType, DecodingResult, ImageDecoder, ImageError};
+use png::{self, HasParameters};
+use alpha::BAYER;
+use super::{Error, Image};
+
+impl From<png::DecodingError> for Error {
+    fn from(err: png::DecodingError) -> Error {
+        match err {
+            png::DecodingError::IoError(err) => Error::ImageLoad(ImageError::IoError(err)),
+            err => Error::ImageLoad(ImageError::FormatError(err.to_string())),
+        }
+    }
+}
+
+const PNG_SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+/// Decodes `bytes` if they're a PNG or TIFF with 16 bits per channel, or
+/// returns `None` so that the image crate can decode anything else.
+pub fn decode(bytes: &[u8], dither: bool) -> Option<Result<Image, Error>> {
+    // The IHDR chunk always comes first, so a PNG's bit depth is always at
+    // the same offset.
+    let samples = if bytes.starts_with(&PNG_SIGNATURE) && bytes.get(24) == Some(&16) {
+        decode_png(bytes)
+    } else if bytes.starts_with(b"II*\0") || bytes.starts_with(b"MM\0*") {
+        decode_tiff(bytes)?
+    } else {
+        return None;
+    };
+    Some(samples.map(|(width, height, pixels)| {
+        Image { pixels: to_8_bit(&pixels, width, dither), width, height }
+    }))
+}
+
+/// A decoded image's size and 16 bit RGBA pixels.
+type Samples = (u32, u32, Vec<[u16; 4]>);
+
+/// Expands 16 bit gray, gray and alpha, or RGB samples to RGBA.
+fn to_rgba(samples: &[u16], channels: usize) -> Vec<[u16; 4]> {
+    samples.chunks(channels).map(|px| match *px {
+        [g] => [g, g, g, 0xFFFF],
+        [g, a] => [g, g, g, a],
+        [r, g, b] => [r, g, b, 0xFFFF],
+        [r, g, b, a] => [r, g, b, a],
+        _ => [0, 0, 0, 0],
+    }).collect()
+}
+
+fn decode_png(bytes: &[u8]) -> Result<Samples, Error> {
+    let mut decoder = png::Decoder::new(bytes);
+    decoder.set(png::TRANSFORM_EXPAND);
+    let (info, mut reader) = decoder.read_info()?;
+    let mut buf = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buf)?;
+    let channels = match info.color_type {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::RGB => 3,
+        png::ColorType::RGBA | png::ColorType::Indexed => 4,
+    };
+    let samples: Vec<_> = buf.chunks(2).map(|b| (b[0] as u16) << 8 | b[1] as u16).collect();
+    Ok((info.width, info.height, to_rgba(&samples, channels)))
+}
+
+/// Decodes a TIFF if it has 16 bits per channel.
+fn decode_tiff(bytes: &[u8]) -> Option<Result<Samples, Error>> {
+    let mut decoder = match image::tiff::TIFFDecoder::new(Cursor::new(bytes)) {
+        Ok(decoder) => decoder,
+        Err(e) => return Some(Err(e.into())),
+    };
+    let channels = match decoder.colortype() {
+        Ok(ColorType::Gray(16)) => 1,
+        Ok(ColorType::RGB(16)) => 3,
+        Ok(ColorType::RGBA(16)) => 4,
+        _ => return None,
+    };
+    Some((|| {
+        let (width, height) = decoder.dimensions()?;
+        match decoder.read_image()? {
+            DecodingResult::U16(samples) => Ok((width, height, to_rgba(&samples, channels))),
+            DecodingResult::U8(_) => Err(ImageError::FormatError("expected 16 bit samples".to_string()).into()),
+        }
+    })())
+}
+
+/// Scales 16 bit samples down to 8 bits. Without dithering each sample is
+/// rounded to the nearest value. With it, color channels are rounded up or
+/// down in an ordered pattern so that areas average out to their original
+/// value, which keeps smooth gradients from banding. Alpha is always
+/// rounded.
+pub fn to_8_bit(pixels: &[[u16; 4]], width: u32, dither: bool) -> Vec<[u8; 4]> {
+    let width = width.max(1) as usize;
+    pixels.iter().enumerate().map(|(n, px)| {
+        let round = |v: u16| ((v as u32 * 255 + 0x7FFF) / 0xFFFF) as u8;
+        if !dither {
+            return [round(px[0]), round(px[1]), round(px[2]), round(px[3])];
+        }
+        let (x, y) = (n % width, n / width);
+        // Thresholds spread evenly over the gap between two 8 bit values.
+        let threshold = (BAYER[y % 4][x % 4] as u32 * 2 + 1) * 0xFFFF / 32;
+        let dithered = |v: u16| {
+            let scaled = v as u32 * 255;
+            (scaled / 0xFFFF + (scaled % 0xFFFF > threshold) as u32) as u8
+        };
+        [dithered(px[0]), dithered(px[1]), dithered(px[2]), round(px[3])]
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use png::{self, HasParameters};
+    use super::{decode, to_8_bit};
+
+    /// A 16 bit RGB PNG.
+    fn png_16(width: u32, height: u32, samples: &[u16]) -> Vec<u8> {
+        let mut out = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut out, width, height);
+            encoder.set(png::ColorType::RGB).set(png::BitDepth::Sixteen);
+            let bytes: Vec<u8> = samples.iter().flat_map(|&s| vec![(s >> 8) as u8, s as u8]).collect();
+            encoder.write_header().unwrap().write_image_data(&bytes).unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn test_decode_png() {
+        let png = png_16(2, 1, &[0xFFFF, 0x8080, 0x0000, 0x00FF, 0x0180, 0x01FF]);
+        // Dropping the low byte would give 0, 1 and 1 for the second pixel.
+        let img = decode(&png, false).unwrap().unwrap();
+        assert_eq!(img.pixels, vec![[255, 128, 0, 255], [1, 1, 2, 255]]);
+        assert!(decode(b"BM", false).is_none());
+    }
+
+    #[test]
+    fn test_dither_averages_out() {
+        // Exactly halfway between 100 and 101.
+        let half = (100 * 0xFFFF + 0xFFFF / 2) / 255;
+        let pixels = vec![[half as u16, 0, 0xFFFF, 0xFFFF]; 16];
+        let rounded = to_8_bit(&pixels, 4, false);
+        assert!(rounded.iter().all(|px| px[0] == rounded[0][0]));
+        let dithered = to_8_bit(&pixels, 4, true);
+        let sum: u32 = dithered.iter().map(|px| px[0] as u32).sum();
+        assert_eq!(sum, 100 * 16 + 8);
+        assert!(dithered.iter().all(|px| px[1] == 0 && px[2] == 255 && px[3] == 255));
+    }
+}
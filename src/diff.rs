@@ -0,0 +1,79 @@
+//! Comparing the rendered frames of two Gifs.
+
+use super::{Error, Gif, Image};
+
+/// How one frame of a Gif differs from the same frame of another.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FrameDiff {
+    /// How many pixels differ at all.
+    pub changed: usize,
+    /// The largest difference in any channel of any pixel, alpha included.
+    pub max_delta: u8,
+    /// Each pixel's absolute difference per color channel, fully opaque, if
+    /// difference images were asked for.
+    pub difference: Option<Image>,
+}
+
+impl FrameDiff {
+    /// Whether the frames are identical.
+    pub fn is_same(&self) -> bool {
+        self.changed == 0
+    }
+}
+
+/// Compares the frames of `a` and `b` as they'd be shown, so Gifs that
+/// encode the same pictures with different palettes still match.
+pub fn diff(a: &Gif, b: &Gif, images: bool) -> Result<Vec<FrameDiff>, Error> {
+    if a.dimensions() != b.dimensions() {
+        return Err(Error::Mismatch((a.width as u32, a.height as u32), (b.width as u32, b.height as u32)));
+    }
+    if a.frame_count() != b.frame_count() {
+        return Err(Error::Length(a.frame_count(), b.frame_count()));
+    }
+    Ok(a.rgba_frames().zip(b.rgba_frames()).map(|(a, b)| {
+        let mut changed = 0;
+        let mut max_delta = 0;
+        let mut pixels = Vec::with_capacity(if images { a.len() / 4 } else { 0 });
+        for (pa, pb) in a.chunks(4).zip(b.chunks(4)) {
+            let delta: Vec<u8> = pa.iter().zip(pb).map(|(&ca, &cb)| (ca as i16 - cb as i16).unsigned_abs() as u8).collect();
+            let largest = *delta.iter().max().unwrap_or(&0);
+            if largest > 0 {
+                changed += 1;
+                max_delta = max_delta.max(largest);
+            }
+            if images {
+                pixels.push([delta[0], delta[1], delta[2], 255]);
+            }
+        }
+        let difference = if images {
+            Some(Image { pixels, width: a.width(), height: a.height() })
+        } else {
+            None
+        };
+        FrameDiff { changed, max_delta, difference }
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::diff;
+    use super::super::{engiffen, Error, Image, Quantizer};
+
+    #[test]
+    fn test_diff() {
+        let a = vec![Image { pixels: vec![[255, 0, 0, 255], [0, 0, 0, 255]], width: 2, height: 1 }; 2];
+        let mut b = a.clone();
+        b[1].pixels[1] = [0, 40, 0, 255];
+        let (a, b) = (engiffen(&a, 10, Quantizer::Naive).unwrap(), engiffen(&b, 10, Quantizer::Naive).unwrap());
+
+        let diffs = diff(&a, &b, true).unwrap();
+        assert!(diffs[0].is_same());
+        assert_eq!((diffs[1].changed, diffs[1].max_delta), (1, 40));
+        assert_eq!(diffs[1].difference.as_ref().unwrap().pixels, vec![[0, 0, 0, 255], [0, 40, 0, 255]]);
+        assert_eq!(diff(&a, &b, false).unwrap()[1].difference, None);
+        match diff(&a, &a.slice(..1), false) {
+            Err(Error::Length(2, 1)) => (),
+            other => panic!("expected a length error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+//! Presets that trade encoding speed for quality.
+
+use super::{Filter, Options, Quantizer};
+
+/// How much work to put into an encode, for a quick preview or a final
+/// render without tuning the quantizer and dithering settings one by one.
+///
+/// `Draft` trains NeuQuant on one pixel in every 10 by 10 block, resizes
+/// with a triangle filter and doesn't dither. `Balanced` trains on one
+/// pixel in every 3 by 3 block and dithers the delays. `Best` trains on
+/// every pixel and dithers both the delays and the alpha channel.
+///
+/// ```rust
+/// # use engiffen::{Effort, Options, Quantizer};
+/// let effort = Effort::Draft;
+/// let options = effort.apply(Options::default());
+/// assert_eq!(effort.quantizer(), Quantizer::NeuQuant(10));
+/// assert!(!options.delay_dither);
+/// ```
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Effort {
+    Draft,
+    Balanced,
+    Best,
+}
+
+impl Effort {
+    /// The quantizer to encode with at this effort.
+    pub fn quantizer(self) -> Quantizer {
+        match self {
+            Effort::Draft => Quantizer::NeuQuant(10),
+            Effort::Balanced => Quantizer::NeuQuant(3),
+            Effort::Best => Quantizer::NeuQuant(1),
+        }
+    }
+
+    /// `options` with the dithering and resampling settings for this effort.
+    /// Everything else is left as it is.
+    pub fn apply(self, options: Options) -> Options {
+        let (delay_dither, alpha_dither, scale_filter) = match self {
+            Effort::Draft => (false, false, Filter::Triangle),
+            Effort::Balanced => (true, false, Filter::Lanczos3),
+            Effort::Best => (true, true, Filter::Lanczos3),
+        };
+        Options { delay_dither, alpha_dither, scale_filter, ..options }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Effort;
+    use super::super::{engiffen_with_options, Filter, Image, Options, Quantizer};
+
+    #[test]
+    fn test_effort() {
+        let options = Options { opaque: true, ..Options::default() };
+        let best = Effort::Best.apply(options.clone());
+        assert!(best.delay_dither && best.alpha_dither && best.opaque);
+        assert_eq!(Effort::Draft.apply(best).scale_filter, Filter::Triangle);
+        assert_eq!(Effort::Balanced.quantizer(), Quantizer::NeuQuant(3));
+
+        let pixels = (0..400).map(|n| [n as u8, (n * 3 % 256) as u8, 90, 255]).collect();
+        let imgs = vec![Image { pixels, width: 20, height: 20 }];
+        for &effort in &[Effort::Draft, Effort::Balanced, Effort::Best] {
+            let gif = engiffen_with_options(&imgs, 10, effort.quantizer(), &effort.apply(Options::default())).unwrap();
+            assert_eq!(gif.frame_count(), 1);
+        }
+    }
+}
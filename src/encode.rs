@@ -0,0 +1,1193 @@
+//! The `engiffen_*` entry points, which turn frames into a `Gif`.
+
+use std::io;
+use std::borrow::Cow;
+use std::cell::RefCell;
+#[cfg(feature = "fs")] use std::fs::File;
+#[cfg(feature = "fs")] use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use image::{self, GenericImage, Pixel, RgbaImage};
+use rayon::prelude::*;
+#[cfg(feature = "fs")] use animated;
+use budget;
+use cancel;
+#[cfg(feature = "fs")] use decode;
+use layout::{self, SideBySide};
+use pool;
+use resume::{self, Checkpoint};
+use sequence::{self, Transition};
+use stats::{self, EncodeStats};
+use timing::{self, FrameRate, Timestamp};
+use transform::{self, Scale};
+use encoder::AnimationEncoder;
+#[cfg(feature = "apng")] use super::{Apng, ApngEncoder};
+#[cfg(feature = "fs")] use super::load_image;
+use super::{all_gray, assemble, check_scale, engiffen_with_delays, frame, gif_delays, gif_dimensions, has_transparency, learn_palette, palettize_frames, prepare, quantize_samples, remap, sample_colors, write_frames, Error, Frame, FrameRef, Gif, Image, IndexedImage, Mapping, Options, Quantizer, Samples};
+
+/// Converts a sequence of images into a `Gif` at a given frame rate. The `quantizer`
+/// parameter selects the algorithm that quantizes the palette into 256-colors.
+///
+/// The frame rate can be anything that converts into a `FrameRate`: a number
+/// of frames per second (`10`, `12.5`) or a `std::time::Duration` per frame.
+///
+/// Gifs store frame delays in hundredths of a second, so each delay is rounded
+/// to the nearest hundredth, to a minimum of 1 (100 fps) and a maximum of
+/// 65535 (about 11 minutes). Most browsers slow down delays under 2
+/// hundredths to 10, so frame rates above 50 fps won't play at full speed.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{load_images, engiffen, Gif, Error, Quantizer};
+/// # fn foo() -> Result<Gif, Error> {
+/// let paths = vec!["tests/ball/ball01.bmp", "tests/ball/ball02.bmp", "tests/ball/ball03.bmp"];
+/// let images = load_images(&paths);
+/// let gif = engiffen(&images, 10, Quantizer::NeuQuant(2))?;
+/// assert_eq!(gif.frames.len(), 3);
+/// # Ok(gif)
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// If any image dimensions differ, this function will return an Error::Mismatch
+/// containing tuples of the conflicting image dimensions.
+pub fn engiffen<F: Into<FrameRate>>(imgs: &[Image], fps: F, quantizer: Quantizer) -> Result<Gif, Error> {
+    engiffen_with_options(imgs, fps, quantizer, &Options::default())
+}
+
+/// Converts a sequence of images into a `Gif` like `engiffen`, with additional
+/// settings given by `options`.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{load_images, engiffen_with_options, Gif, Error, Options, Quantizer};
+/// # fn foo() -> Result<Gif, Error> {
+/// let paths = vec!["tests/ball/ball01.bmp", "tests/ball/ball02.bmp", "tests/ball/ball03.bmp"];
+/// let images = load_images(&paths);
+/// let options = Options { alpha_threshold: 128, ..Options::default() };
+/// let gif = engiffen_with_options(&images, 10, Quantizer::NeuQuant(2), &options)?;
+/// # Ok(gif)
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns the same errors as `engiffen`, unless `options.mismatch` allows
+/// frames of different sizes. A `background` whose dimensions differ from the
+/// (possibly resized) frames' also results in an `Error::Mismatch`.
+pub fn engiffen_with_options<F: Into<FrameRate>>(imgs: &[Image], fps: F, quantizer: Quantizer, options: &Options) -> Result<Gif, Error> {
+    let delays = vec![fps.into().delay_ms(); imgs.len()];
+    engiffen_with_delays(imgs, delays, quantizer, options)
+}
+
+/// Converts frames from an iterator into a `Gif`, for frames that come from
+/// a producer or a fallible loader, stopping at the first error.
+///
+/// This is a convenience wrapper around `engiffen_with_options`: every frame
+/// is collected before encoding starts, because the palette has to be
+/// learned from all of them before any can be mapped onto it, and an
+/// iterator can only be read once. For sequences too long to hold in memory,
+/// use `engiffen_files`, which decodes each image twice instead.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{load_image, engiffen_iter, Gif, Error, Options, Quantizer};
+/// # fn foo() -> Result<Gif, Error> {
+/// let paths = vec!["tests/ball/ball01.bmp", "tests/ball/ball02.bmp"];
+/// let gif = engiffen_iter(paths.iter().map(load_image), 10, Quantizer::NeuQuant(2), &Options::default())?;
+/// # Ok(gif)
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns the first error from `frames`, or the same errors as
+/// `engiffen_with_options`.
+pub fn engiffen_iter<I, F>(frames: I, fps: F, quantizer: Quantizer, options: &Options) -> Result<Gif, Error>
+    where I: IntoIterator<Item = Result<Image, Error>>, F: Into<FrameRate>
+{
+    let imgs = frames.into_iter().collect::<Result<Vec<_>, _>>()?;
+    engiffen_with_options(&imgs, fps, quantizer, options)
+}
+
+/// Converts a sequence of timestamped images into a `Gif`, deriving each
+/// frame's delay from the time until the next frame. This suits captures
+/// where frames weren't recorded at a constant rate. Timestamps can be
+/// `Duration`s measured from any fixed starting point, or `Instant`s.
+///
+/// The last frame has no following timestamp, so it's shown for the median
+/// of the other frames' delays, or for 100 ms if it's the only frame.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{load_image, engiffen_timestamped, Gif, Error, Options, Quantizer};
+/// # use std::time::Duration;
+/// # fn foo() -> Result<Gif, Error> {
+/// let frames = vec![
+///     (load_image("tests/ball/ball01.bmp")?, Duration::from_millis(0)),
+///     (load_image("tests/ball/ball02.bmp")?, Duration::from_millis(40)),
+///     (load_image("tests/ball/ball03.bmp")?, Duration::from_millis(250)),
+/// ];
+/// let gif = engiffen_timestamped(frames, Quantizer::NeuQuant(2), &Options::default())?;
+/// let delays: Vec<_> = gif.frames.iter().map(|frame| frame.delay).collect();
+/// assert_eq!(delays, vec![4, 21, 21]);
+/// # Ok(gif)
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns an `Error::InvalidOption` if the timestamps don't strictly
+/// increase, and otherwise the same errors as `engiffen_with_options`.
+pub fn engiffen_timestamped<I, T>(frames: I, quantizer: Quantizer, options: &Options) -> Result<Gif, Error>
+    where I: IntoIterator<Item = (Image, T)>, T: Timestamp {
+    let (imgs, timestamps): (Vec<_>, Vec<_>) = frames.into_iter().unzip();
+    let delays = timing::delays_from_timestamps(&timestamps)?;
+    engiffen_with_delays(&imgs, delays, quantizer, options)
+}
+
+/// Concatenates several sequences of images into a single `Gif`, joining each
+/// pair of consecutive segments with the given `transition`. All segments
+/// share one palette, computed over every frame.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{load_images, engiffen_segments, Gif, Error, Options, Quantizer, Transition};
+/// # fn foo() -> Result<Gif, Error> {
+/// let take_one = load_images(&["tests/ball/ball01.bmp", "tests/ball/ball02.bmp", "tests/ball/ball03.bmp"]);
+/// let take_two = load_images(&["tests/ball/ball05.bmp", "tests/ball/ball06.bmp", "tests/ball/ball07.bmp"]);
+/// let gif = engiffen_segments(&[&take_one, &take_two], 10, Quantizer::NeuQuant(2),
+///     &Options::default(), Transition::Crossfade(2))?;
+/// assert_eq!(gif.frames.len(), 4);
+/// # Ok(gif)
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns the same errors as `engiffen_with_options`.
+pub fn engiffen_segments<F: Into<FrameRate>>(segments: &[&[Image]], fps: F, quantizer: Quantizer, options: &Options, transition: Transition) -> Result<Gif, Error> {
+    let imgs = sequence::join(segments, transition)?;
+    engiffen_with_options(&imgs, fps, quantizer, options)
+}
+
+/// Combines several sequences of the same length into a single `Gif` with
+/// their frames laid out in a grid, `columns` cells wide, e.g. to compare
+/// simulation runs side by side. Cells are filled left to right, top to
+/// bottom, and empty cells in the last row are transparent.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{load_images, engiffen_grid, Gif, Error, Options, Quantizer};
+/// # fn foo() -> Result<Gif, Error> {
+/// let run_one = load_images(&["tests/ball/ball01.bmp", "tests/ball/ball02.bmp"]);
+/// let run_two = load_images(&["tests/ball/ball05.bmp", "tests/ball/ball06.bmp"]);
+/// let gif = engiffen_grid(&[&run_one, &run_two], 2, 10, Quantizer::NeuQuant(2), &Options::default())?;
+/// # Ok(gif)
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns `Error::Length` if the sequences have different numbers of
+/// frames, and `Error::Mismatch` if any frame's dimensions differ from the
+/// rest. Otherwise returns the same errors as `engiffen_with_options`.
+pub fn engiffen_grid<F: Into<FrameRate>>(sequences: &[&[Image]], columns: usize, fps: F, quantizer: Quantizer, options: &Options) -> Result<Gif, Error> {
+    let imgs = layout::grid(sequences, columns, 0, [0, 0, 0, 0])?;
+    engiffen_with_options(&imgs, fps, quantizer, options)
+}
+
+/// Puts two sequences of the same length next to each other in a single
+/// `Gif`, e.g. to show footage before and after a change. `style` sets the
+/// separator between them and, with the `text` feature, their labels.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{load_images, engiffen_side_by_side, Gif, Error, Options, Quantizer, SideBySide};
+/// # fn foo() -> Result<Gif, Error> {
+/// let before = load_images(&["tests/ball/ball01.bmp", "tests/ball/ball02.bmp"]);
+/// let after = load_images(&["tests/ball/ball05.bmp", "tests/ball/ball06.bmp"]);
+/// let gif = engiffen_side_by_side(&before, &after, 10, Quantizer::NeuQuant(2),
+///     &Options::default(), &SideBySide::default())?;
+/// # Ok(gif)
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns the same errors as `engiffen_grid`.
+pub fn engiffen_side_by_side<F: Into<FrameRate>>(left: &[Image], right: &[Image], fps: F, quantizer: Quantizer, options: &Options, style: &SideBySide) -> Result<Gif, Error> {
+    let imgs = layout::side_by_side(left, right, style)?;
+    engiffen_with_options(&imgs, fps, quantizer, options)
+}
+
+/// Builds several `Gif`s of the same frames at different sizes, like a full
+/// size version, a 480px wide one and a thumbnail, sharing a single palette
+/// computation. Returns one `Gif` per entry in `scales`.
+///
+/// The palette is computed from the frames at the size `options` produces,
+/// and each `Scale` is applied on top of that, with `options.scale_filter`.
+/// Mapping resized frames onto the palette is much cheaper than quantizing
+/// each size separately.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{load_images, engiffen_sizes, Gif, Error, Options, Quantizer, Scale};
+/// # fn foo() -> Result<Vec<Gif>, Error> {
+/// let images = load_images(&["tests/ball/ball01.bmp", "tests/ball/ball02.bmp", "tests/ball/ball03.bmp"]);
+/// let gifs = engiffen_sizes(&images, 10, Quantizer::NeuQuant(2), &Options::default(),
+///     &[Scale::Factor(1.0), Scale::Width(480), Scale::Width(64)])?;
+/// # Ok(gifs)
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns the same errors as `engiffen_with_options`.
+pub fn engiffen_sizes<F: Into<FrameRate>>(imgs: &[Image], fps: F, quantizer: Quantizer, options: &Options, scales: &[Scale]) -> Result<Vec<Gif>, Error> {
+    for &scale in scales {
+        check_scale(scale)?;
+    }
+    let delays_ms = vec![fps.into().delay_ms(); imgs.len()];
+    let (imgs, delays) = prepare(imgs, delays_ms, options)?;
+    let (palette, palettized_imgs, transparency) = palettize_frames(&imgs, quantizer, options, &mut EncodeStats::default())?;
+    let (width, height) = (imgs[0].width, imgs[0].height);
+    let gifs = scales.iter().map(|scale| {
+        let (w, h) = scale.dimensions(width, height);
+        let images = if (w, h) == (width, height) {
+            palettized_imgs.clone()
+        } else {
+            pool::install(options, || {
+                let resized: Vec<_> = imgs.par_iter().map(|img| transform::resize(img, w, h, options.scale_filter)).collect();
+                remap(&resized, &palette, transparency, options.alpha_threshold)
+            })
+        };
+        assemble(palette.clone(), images, transparency, delays.clone(), w, h)
+    }).collect();
+    gifs
+}
+
+/// Converts any of the image crate's images, like `ImageBuffer`s, into a
+/// `Gif`, without wrapping each one in an `Image` first. Takes the same
+/// arguments as `engiffen_with_options`.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate engiffen;
+/// # extern crate image;
+/// # use engiffen::{engiffen_generic, Options, Quantizer};
+/// # fn main() {
+/// let frames = vec![image::RgbImage::from_pixel(4, 4, image::Rgb([255, 0, 0])); 3];
+/// let gif = engiffen_generic(&frames, 10, Quantizer::Naive, &Options::default()).unwrap();
+/// assert_eq!(gif.frames.len(), 3);
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns the same errors as `engiffen_with_options`.
+pub fn engiffen_generic<I, F>(imgs: &[I], fps: F, quantizer: Quantizer, options: &Options) -> Result<Gif, Error>
+    where I: GenericImage + Sync, I::Pixel: Pixel<Subpixel = u8>, F: Into<FrameRate>
+{
+    let imgs: Vec<Image> = pool::install(options, || imgs.par_iter().map(Image::from_generic).collect());
+    engiffen_with_options(&imgs, fps, quantizer, options)
+}
+
+/// Converts the frames of an animation decoded by the image crate, e.g. from
+/// `ImageDecoder::into_frames`, into a `Gif`, keeping each frame's delay.
+///
+/// Frames drawn at an offset are composited over the frames before them,
+/// so each frame of the result shows the full picture.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # extern crate engiffen;
+/// # extern crate image;
+/// # use engiffen::{engiffen_animation, Options, Quantizer};
+/// # use image::ImageDecoder;
+/// # use std::fs::File;
+/// # fn main() {
+/// let decoder = image::gif::Decoder::new(File::open("in.gif").unwrap());
+/// let frames = decoder.into_frames().unwrap();
+/// let gif = engiffen_animation(frames, Quantizer::NeuQuant(2), &Options::default()).unwrap();
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns the same errors as `engiffen_with_options`.
+pub fn engiffen_animation<I>(frames: I, quantizer: Quantizer, options: &Options) -> Result<Gif, Error>
+    where I: IntoIterator<Item = image::Frame>
+{
+    let mut delays = Vec::new();
+    let layers: Vec<_> = frames.into_iter().map(|frame| {
+        let delay = frame.delay();
+        delays.push(*delay.numer() as f64 * 1000.0 / (*delay.denom()).max(1) as f64);
+        let (left, top) = (frame.left(), frame.top());
+        (Image::from(frame.into_buffer()), left, top)
+    }).collect();
+    let imgs = sequence::flatten_layers(&layers);
+    engiffen_with_delays(&imgs, delays, quantizer, options)
+}
+
+/// Decodes an existing Gif and encodes it again with `quantizer` and
+/// `options`, keeping its frame timing. Useful for shrinking a Gif made
+/// elsewhere, or cropping, scaling or retiming it.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{reprocess_gif, Options, Quantizer, Scale};
+/// # use std::fs::File;
+/// let options = Options { scale: Some(Scale::Width(320)), ..Options::default() };
+/// let gif = reprocess_gif("in.gif", Quantizer::NeuQuant(2), &options).unwrap();
+/// gif.write(&mut File::create("out.gif").unwrap()).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Returns the same errors as `load_gif` and `engiffen_with_options`.
+#[cfg(feature = "fs")]
+pub fn reprocess_gif<P: AsRef<Path>>(path: P, quantizer: Quantizer, options: &Options) -> Result<Gif, Error> {
+    let file = File::open(path).map_err(image::ImageError::IoError)?;
+    let (imgs, delays) = decode::read(io::BufReader::new(file))?;
+    let delays_ms = delays.into_iter().map(|cs| cs as f64 * 10.0).collect();
+    engiffen_with_delays(&imgs, delays_ms, quantizer, options)
+}
+
+/// Decodes an animated PNG and encodes it as a Gif with `quantizer` and
+/// `options`, keeping its frame timing.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{reprocess_apng, Options, Quantizer};
+/// # use std::fs::File;
+/// let gif = reprocess_apng("in.png", Quantizer::NeuQuant(2), &Options::default()).unwrap();
+/// gif.write(&mut File::create("out.gif").unwrap()).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Returns the same errors as `load_apng` and `engiffen_with_options`.
+#[cfg(feature = "fs")]
+pub fn reprocess_apng<P: AsRef<Path>>(path: P, quantizer: Quantizer, options: &Options) -> Result<Gif, Error> {
+    let file = File::open(path).map_err(image::ImageError::IoError)?;
+    let (imgs, delays) = animated::read(io::BufReader::new(file))?;
+    let delays_ms = delays.into_iter().map(|ms| ms as f64).collect();
+    engiffen_with_delays(&imgs, delays_ms, quantizer, options)
+}
+
+/// Converts borrowed frames into a `Gif`, like `engiffen_with_options`.
+///
+/// When `options` only change palette and timing settings (the alpha
+/// threshold, delay dithering and the first frame's delay), the frames are
+/// quantized straight from the borrowed pixels without being copied. Any
+/// other option needs frames of its own to work on, so the frames are then
+/// copied first.
+///
+/// # Examples
+///
+/// ```rust
+/// # use engiffen::{engiffen_borrowed, FrameRef, Options, Quantizer};
+/// let buffers = vec![vec![[255, 0, 0, 255]; 4], vec![[0, 0, 255, 255]; 4]];
+/// let frames: Vec<_> = buffers.iter().map(|buf| FrameRef { pixels: buf, width: 2, height: 2 }).collect();
+/// let gif = engiffen_borrowed(&frames, 10, Quantizer::Naive, &Options::default()).unwrap();
+/// assert_eq!(gif.frames.len(), 2);
+/// ```
+///
+/// # Errors
+///
+/// Returns the same errors as `engiffen_with_options`.
+pub fn engiffen_borrowed<F: Into<FrameRate>>(frames: &[FrameRef], fps: F, quantizer: Quantizer, options: &Options) -> Result<Gif, Error> {
+    let delays_ms = vec![fps.into().delay_ms(); frames.len()];
+    if !leaves_pixels(options) {
+        let imgs: Vec<Image> = frames.iter().map(FrameRef::to_image).collect();
+        return engiffen_with_delays(&imgs, delays_ms, quantizer, options);
+    }
+
+    let first = match frames.first() {
+        Some(first) => (first.width, first.height),
+        None => return Err(Error::NoImages),
+    };
+    if let Some(frame) = frames.iter().find(|frame| (frame.width, frame.height) != first) {
+        return Err(Error::Mismatch(first, (frame.width, frame.height)));
+    }
+    gif_dimensions(first.0, first.1)?;
+    let delays = gif_delays(&delays_ms, options)?;
+    let (palette, palettized_imgs, transparency) = palettize_frames(frames, quantizer, options, &mut EncodeStats::default())?;
+    assemble(palette, palettized_imgs, transparency, delays, first.0, first.1)
+}
+
+/// Whether `options` only change palette and timing settings, so that
+/// frames can be quantized or written without being changed first.
+fn leaves_pixels(options: &Options) -> bool {
+    *options == Options {
+        alpha_threshold: options.alpha_threshold,
+        delay_dither: options.delay_dither,
+        first_frame_delay: options.first_frame_delay,
+        cancel: options.cancel.clone(),
+        thread_pool: options.thread_pool.clone(),
+        max_memory: options.max_memory,
+        ..Options::default()
+    }
+}
+
+/// Converts frames that are already paletted into a `Gif`. When every frame
+/// has the same palette and transparent index, and `options` only change
+/// timing (the delay dithering and the first frame's delay), their indices
+/// and palette are used as they are, without being expanded to RGBA and
+/// quantized again. Otherwise the frames are expanded and encoded with
+/// `quantizer`, like `engiffen_with_options`.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{load_indexed_image, engiffen_indexed, Options, Quantizer};
+/// let frames: Vec<_> = ["frame01.png", "frame02.png"].iter()
+///     .filter_map(|path| load_indexed_image(path).unwrap())
+///     .collect();
+/// let gif = engiffen_indexed(&frames, 10, Quantizer::Naive, &Options::default()).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Returns an `Error::NoImages` if `frames` is empty or an `Error::Mismatch`
+/// if the frames' dimensions differ, plus the errors of
+/// `engiffen_with_options` when the frames have to be quantized. A frame
+/// without one index per pixel, or one whose indices run past the end of the
+/// palette it shares, is an `Error::InvalidOption`.
+pub fn engiffen_indexed<F: Into<FrameRate>>(frames: &[IndexedImage], fps: F, quantizer: Quantizer, options: &Options) -> Result<Gif, Error> {
+    let delays_ms = vec![fps.into().delay_ms(); frames.len()];
+    let first = match frames.first() {
+        Some(first) => first,
+        None => return Err(Error::NoImages),
+    };
+    for (n, frame) in frames.iter().enumerate() {
+        if frame.indices.len() as u64 != frame.width as u64 * frame.height as u64 {
+            return Err(Error::InvalidOption("indices", format!("frame {} has {} indices for {}x{} pixels", n, frame.indices.len(), frame.width, frame.height)));
+        }
+    }
+    let shared = frames.iter().all(|frame| frame.same_palette(first));
+    // A palette too big for a Gif can only be fixed by quantizing.
+    if !shared || !leaves_pixels(options) || first.palette.len() > 256 * 3 || first.palette.is_empty() {
+        let imgs: Vec<Image> = frames.iter().map(IndexedImage::to_image).collect();
+        return engiffen_with_delays(&imgs, delays_ms, quantizer, options);
+    }
+    if let Some(frame) = frames.iter().find(|frame| (frame.width, frame.height) != (first.width, first.height)) {
+        return Err(Error::Mismatch((first.width, first.height), (frame.width, frame.height)));
+    }
+    let colors = first.palette.len() / 3;
+    for (n, frame) in frames.iter().enumerate() {
+        if let Some(&idx) = frame.indices.iter().find(|&&idx| idx as usize >= colors) {
+            return Err(Error::InvalidOption("indices", format!("frame {} uses index {}, past the end of its {} color palette", n, idx, colors)));
+        }
+    }
+    gif_dimensions(first.width, first.height)?;
+    let delays = gif_delays(&delays_ms, options)?;
+    cancel::check(options)?;
+    let indices = frames.iter().map(|frame| Arc::new(frame.indices.clone())).collect();
+    assemble(first.palette.clone(), indices, first.transparent, delays, first.width, first.height)
+}
+
+/// Converts a sequence of images into a Gif and writes it straight to `out`,
+/// like `engiffen_with_options` followed by `Gif::write`. Each frame is
+/// mapped onto the palette just before it's written, so the palettized
+/// frames are never all held in memory at once.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{load_images, engiffen_write, Error, Options, Quantizer};
+/// # use std::fs::File;
+/// # fn foo() -> Result<(), Error> {
+/// let images = load_images(&["tests/ball/ball01.bmp", "tests/ball/ball02.bmp"]);
+/// let mut output = File::create("output.gif")?;
+/// engiffen_write(&images, 10, Quantizer::NeuQuant(2), &Options::default(), &mut output)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns the same errors as `engiffen_with_options` and `Gif::write`.
+pub fn engiffen_write<F, W>(imgs: &[Image], fps: F, quantizer: Quantizer, options: &Options, out: &mut W) -> Result<EncodeStats, Error>
+    where F: Into<FrameRate>, W: io::Write
+{
+    let delays_ms = vec![fps.into().delay_ms(); imgs.len()];
+    write_with_delays(imgs, delays_ms, quantizer, options, out)
+}
+
+/// Shared implementation of `engiffen_write`, given each frame's exact delay
+/// in milliseconds.
+pub fn write_with_delays<W: io::Write>(imgs: &[Image], delays_ms: Vec<f64>, quantizer: Quantizer, options: &Options, out: &mut W) -> Result<EncodeStats, Error> {
+    let mut stats = EncodeStats::default();
+    let time_prepare = Instant::now();
+    let (imgs, delays) = prepare(imgs, delays_ms, options)?;
+    stats::add_since(&mut stats.preparing, time_prepare);
+
+    let mapping = learn_palette(&imgs, quantizer, options, 0, &mut stats)?;
+    let (width, height) = gif_dimensions(imgs[0].width, imgs[0].height)?;
+    let mut mapping_time = Duration::default();
+    let mut out = stats::Counter { inner: out, count: 0 };
+    let mut previous = None;
+    {
+        let frames = imgs.iter().zip(delays).enumerate().map(|(n, (img, delay))| {
+            cancel::check(options)?;
+            let time_map = Instant::now();
+            let indices = mapping.index_after(&img.pixels, &mut previous);
+            stats::add_since(&mut mapping_time, time_map);
+            mapping.check_error(n, &img.pixels, &indices, options)?;
+            Ok(Cow::Owned(frame(indices, mapping.transparency, delay, width, height)))
+        });
+        write_frames(&mut out, width, height, &mapping.palette, frames)?;
+    }
+    stats.mapping = mapping_time;
+    stats.bytes_written = Some(out.count);
+    Ok(stats)
+}
+
+/// Encodes the images at `paths` straight into `out` while holding only one
+/// decoded frame in memory at a time, for sequences too long to load at once.
+/// Every image is decoded twice: once to sample its colors for the palette,
+/// then again to map it onto the palette and write it, after which its pixels
+/// are dropped.
+///
+/// Frames are encoded as they are decoded, so the settings in `options` that
+/// change pixels or need the whole sequence, like transforms and
+/// `keep_every_nth`, aren't applied. The alpha threshold, cancel token and
+/// timing settings are.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{engiffen_files, Error, Options, Quantizer};
+/// # use std::fs::File;
+/// # fn foo() -> Result<(), Error> {
+/// let paths = (1..1000).map(|n| format!("frames/{:04}.png", n)).collect::<Vec<_>>();
+/// let mut output = File::create("output.gif")?;
+/// engiffen_files(&paths, 30, Quantizer::NeuQuant(4), &Options::default(), &mut output)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns an `Error::ImageLoad` if any image fails to load, unlike
+/// `load_images`, which skips it. Otherwise, returns the same errors as
+/// `engiffen_write`, where frames of different sizes are always an
+/// `Error::Mismatch`.
+#[cfg(feature = "fs")]
+pub fn engiffen_files<P, F, W>(paths: &[P], fps: F, quantizer: Quantizer, options: &Options, out: &mut W) -> Result<EncodeStats, Error>
+    where P: AsRef<Path>, F: Into<FrameRate>, W: io::Write
+{
+    let delays_ms = vec![fps.into().delay_ms(); paths.len()];
+    let labels = match options.label_frames {
+        true => paths.iter().map(|path| path.as_ref().display().to_string()).collect(),
+        false => Vec::new(),
+    };
+    write_streamed(paths.len(), |n| load_image(&paths[n]), delays_ms, &labels, quantizer, options, out)
+}
+
+/// Encodes `frame_count` frames of the given `(width, height)`, drawn by
+/// `generate`, straight into `out`, for animations that are computed rather
+/// than loaded, like charts, fractals or test patterns. Like
+/// `engiffen_files`, only one frame is held in memory at a time, and the
+/// same settings in `options` apply.
+///
+/// `generate` is given the frame number and a transparent image to draw
+/// into. It's called twice for every frame, once to sample its colors and
+/// once to write it, and must draw the same frame both times.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate engiffen;
+/// # extern crate image;
+/// # use engiffen::{engiffen_generated, Error, Options, Quantizer};
+/// # fn foo() -> Result<(), Error> {
+/// let mut output = Vec::new();
+/// engiffen_generated(30, (64, 64), |n, canvas: &mut image::RgbaImage| {
+///     for (x, _, pixel) in canvas.enumerate_pixels_mut() {
+///         *pixel = image::Rgba([((x as usize + n * 8) % 256) as u8, 0, 128, 255]);
+///     }
+/// }, 15, Quantizer::Naive, &Options::default(), &mut output)?;
+/// # Ok(())
+/// # }
+/// # fn main() { foo().unwrap(); }
+/// ```
+///
+/// # Errors
+///
+/// Returns the same errors as `engiffen_write`.
+pub fn engiffen_generated<G, F, W>(frame_count: usize, (width, height): (u32, u32), generate: G, fps: F, quantizer: Quantizer, options: &Options, out: &mut W) -> Result<EncodeStats, Error>
+    where G: Fn(usize, &mut RgbaImage), F: Into<FrameRate>, W: io::Write
+{
+    let delays_ms = vec![fps.into().delay_ms(); frame_count];
+    let draw = |n| {
+        let mut canvas = RgbaImage::new(width, height);
+        generate(n, &mut canvas);
+        Ok(Image::from(canvas))
+    };
+    write_streamed(frame_count, draw, delays_ms, &[], quantizer, options, out)
+}
+
+/// Encodes `count` frames into `out`, calling `load` for each frame once to
+/// sample it and once more to write it, so that only one frame is held in
+/// memory at a time. Frames are labelled with `labels`, if there are any.
+pub fn write_streamed<L, W>(count: usize, load: L, delays_ms: Vec<f64>, labels: &[String], quantizer: Quantizer, options: &Options, out: &mut W) -> Result<EncodeStats, Error>
+    where L: Fn(usize) -> Result<Image, Error>, W: io::Write
+{
+    if count == 0 {
+        return Err(Error::NoImages);
+    }
+    timing::check(&delays_ms)?;
+    let mut stats = EncodeStats::default();
+    let mut samples: Option<Samples> = None;
+    let mut dimensions = None;
+    let mut plan = budget::Plan { quantizer, step: 1 };
+    for n in 0..count {
+        cancel::check(options)?;
+        let img = load(n)?;
+        let (width, height) = *dimensions.get_or_insert((img.width, img.height));
+        if (img.width, img.height) != (width, height) {
+            return Err(Error::Mismatch((width, height), (img.width, img.height)));
+        }
+        if n == 0 {
+            gif_dimensions(width, height)?;
+            plan = budget::plan(width, height, count, quantizer, options.max_memory, 0)?;
+        }
+        if n % plan.step != 0 {
+            // Frames that aren't sampled still decide whether the palette
+            // needs a transparent index.
+            if let Some(ref mut samples) = samples {
+                let frames = [img];
+                let (transparency, gray) = pool::install(options, || {
+                    (has_transparency(&frames, options.alpha_threshold), samples.gray && all_gray(&frames, options.alpha_threshold))
+                });
+                samples.transparency |= transparency;
+                samples.gray = gray;
+            }
+            continue;
+        }
+        let sampled = sample_colors(&[img], budget::Plan { step: 1, ..plan }, options, &mut stats);
+        samples = Some(match samples {
+            Some(mut samples) => {
+                samples.merge(sampled);
+                samples
+            },
+            None => sampled,
+        });
+    }
+    let samples = samples.expect("at least one frame was sampled");
+    stats.colors_sampled = samples.len();
+    let mapping = quantize_samples(&samples, &mut stats);
+    drop(samples);
+    stats.frames = count;
+    stats.palette_colors = mapping.palette.len() / 3;
+
+    let (width, height) = dimensions.expect("at least one frame was loaded");
+    let delays = gif_delays(&delays_ms, options)?;
+    let mut mapping_time = Duration::default();
+    let mut out = stats::Counter { inner: out, count: 0 };
+    let mut previous = None;
+    {
+        let frames = delays.into_iter().enumerate().map(|(n, delay)| {
+            cancel::check(options)?;
+            let img = load(n)?;
+            let time_map = Instant::now();
+            let indices = mapping.index_after(&img.pixels, &mut previous);
+            stats::add_since(&mut mapping_time, time_map);
+            mapping.check_error(n, &img.pixels, &indices, options)?;
+            let frame = frame(indices, mapping.transparency, delay, width as u16, height as u16);
+            Ok(Cow::Owned(Frame { label: labels.get(n).cloned(), ..frame }))
+        });
+        write_frames(&mut out, width as u16, height as u16, &mapping.palette, frames)?;
+    }
+    stats.mapping = mapping_time;
+    stats.bytes_written = Some(out.count);
+    Ok(stats)
+}
+
+/// Like `engiffen_with_options`, also returning how long each stage of the
+/// encode took, for applications that want to show performance data.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{load_images, engiffen_with_stats, Error, Options, Quantizer};
+/// # fn foo() -> Result<(), Error> {
+/// let images = load_images(&["tests/ball/ball01.bmp", "tests/ball/ball02.bmp"]);
+/// let (gif, stats) = engiffen_with_stats(&images, 10, Quantizer::NeuQuant(2), &Options::default())?;
+/// println!("Computed the palette in {:?}", stats.quantizing);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns the same errors as `engiffen_with_options`.
+pub fn engiffen_with_stats<F: Into<FrameRate>>(imgs: &[Image], fps: F, quantizer: Quantizer, options: &Options) -> Result<(Gif, EncodeStats), Error> {
+    let mut stats = EncodeStats::default();
+    let time_prepare = Instant::now();
+    let delays_ms = vec![fps.into().delay_ms(); imgs.len()];
+    let (imgs, delays) = prepare(imgs, delays_ms, options)?;
+    stats::add_since(&mut stats.preparing, time_prepare);
+    gif_dimensions(imgs[0].width, imgs[0].height)?;
+    let (palette, palettized_imgs, transparency) = palettize_frames(&imgs, quantizer, options, &mut stats)?;
+    Ok((assemble(palette, palettized_imgs, transparency, delays, imgs[0].width, imgs[0].height)?, stats))
+}
+
+/// Like `engiffen_write`, for sequences long enough that an interrupted run
+/// shouldn't have to start over.
+///
+/// After each frame is written and `out` flushed, `on_checkpoint` is called
+/// with a `Checkpoint` to save. To resume, truncate the output to the saved
+/// `bytes_written`, open it for appending and call this again with the same
+/// frames, options and `Some(checkpoint)`: the palette isn't computed again,
+/// and frames already written are skipped.
+///
+/// So that a resumed Gif comes out the same as one written in a single go,
+/// pixels are always mapped to the closest palette color, which can differ
+/// slightly from what `engiffen_write` picks with NeuQuant.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{load_images, engiffen_resumable, Checkpoint, Error, Options, Quantizer};
+/// # use std::fs::OpenOptions;
+/// # fn foo(saved: Option<Checkpoint>) -> Result<(), Error> {
+/// let images = load_images(&["tests/ball/ball01.bmp", "tests/ball/ball02.bmp"]);
+/// let file = OpenOptions::new().create(true).write(true).open("output.gif")?;
+/// file.set_len(saved.as_ref().map_or(0, |checkpoint| checkpoint.bytes_written))?;
+/// let mut file = OpenOptions::new().append(true).open("output.gif")?;
+/// engiffen_resumable(&images, 10, Quantizer::NeuQuant(2), &Options::default(), &mut file, saved.as_ref(), |checkpoint| {
+///     // Save the checkpoint somewhere
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns the same errors as `engiffen_write`, or an `Error::Invalid` if
+/// `resume` doesn't fit these frames.
+pub fn engiffen_resumable<F, W, C>(imgs: &[Image], fps: F, quantizer: Quantizer, options: &Options, out: &mut W, resume: Option<&Checkpoint>, mut on_checkpoint: C) -> Result<(), Error>
+    where F: Into<FrameRate>, W: io::Write, C: FnMut(&Checkpoint)
+{
+    let delays_ms = vec![fps.into().delay_ms(); imgs.len()];
+    let (imgs, delays) = prepare(imgs, delays_ms, options)?;
+    let (width, height) = gif_dimensions(imgs[0].width, imgs[0].height)?;
+    let (mapping, start, written) = match resume {
+        Some(checkpoint) => {
+            if checkpoint.frames_written > imgs.len() {
+                return Err(Error::Invalid(format!("checkpoint is past the last of {} frames", imgs.len())));
+            }
+            let mapping = Mapping::from_palette(checkpoint.palette.clone(), checkpoint.transparency, options.alpha_threshold);
+            (mapping, checkpoint.frames_written, checkpoint.bytes_written)
+        },
+        None => {
+            let learned = learn_palette(&imgs, quantizer, options, 0, &mut EncodeStats::default())?;
+            (Mapping::from_palette(learned.palette, learned.transparency, options.alpha_threshold), 0, 0)
+        },
+    };
+    let skip = if resume.is_some() { resume::header_len(width, height, &mapping.palette)? } else { 0 };
+
+    let counter = RefCell::new(stats::Counter { inner: out, count: written });
+    let mut shared = resume::Skip { inner: resume::Shared(&counter), skip };
+    let flushed = || -> Result<u64, Error> {
+        let mut counter = counter.borrow_mut();
+        io::Write::flush(&mut *counter)?;
+        Ok(counter.count)
+    };
+    // Each step reports the frames before it as written, then maps the next
+    // one; the last step only reports.
+    let mut previous = None;
+    let frames = (start..imgs.len() + 1).filter_map(|n| {
+        if n > start {
+            match flushed() {
+                Ok(bytes_written) => on_checkpoint(&Checkpoint {
+                    palette: mapping.palette.clone(),
+                    transparency: mapping.transparency,
+                    frames_written: n,
+                    bytes_written,
+                }),
+                Err(err) => return Some(Err(err)),
+            }
+        }
+        if n == imgs.len() {
+            return None;
+        }
+        if let Err(err) = cancel::check(options) {
+            return Some(Err(err));
+        }
+        let indices = mapping.index_after(&imgs[n].pixels, &mut previous);
+        if let Err(e) = mapping.check_error(n, &imgs[n].pixels, &indices, options) {
+            return Some(Err(e));
+        }
+        Some(Ok(Cow::Owned(frame(indices, mapping.transparency, delays[n], width, height))))
+    });
+    write_frames(&mut shared, width, height, &mapping.palette, frames)
+}
+
+/// Converts a sequence of images into an animated PNG instead of a `Gif`.
+/// Frames go through the same `options` but skip quantization, so colors
+/// and alpha are kept exactly. Requires the `apng` feature.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{load_images, engiffen_apng, Error, Options};
+/// # use std::fs::File;
+/// # fn foo() -> Result<(), Error> {
+/// let images = load_images(&["tests/ball/ball01.bmp", "tests/ball/ball02.bmp", "tests/ball/ball03.bmp"]);
+/// let apng = engiffen_apng(&images, 10, &Options::default())?;
+/// apng.write(&mut File::create("out.png")?)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns the same errors as `engiffen_with_options`.
+#[cfg(feature = "apng")]
+pub fn engiffen_apng<F: Into<FrameRate>>(imgs: &[Image], fps: F, options: &Options) -> Result<Apng, Error> {
+    engiffen_with_encoder(imgs, fps, &ApngEncoder, options)
+}
+
+/// Converts a sequence of images into an animation with any
+/// `AnimationEncoder`, after applying `options` the same way `engiffen_with_options`
+/// does. `engiffen_with_options` is this with a `GifEncoder`.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{load_images, engiffen_with_encoder, Error, GifEncoder, Options, Quantizer};
+/// # fn foo() -> Result<(), Error> {
+/// let images = load_images(&["tests/ball/ball01.bmp", "tests/ball/ball02.bmp"]);
+/// let encoder = GifEncoder { quantizer: Quantizer::NeuQuant(2) };
+/// let gif = engiffen_with_encoder(&images, 10, &encoder, &Options::default())?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns the same errors as `engiffen_with_options`, or any the encoder
+/// returns.
+pub fn engiffen_with_encoder<E, F>(imgs: &[Image], fps: F, encoder: &E, options: &Options) -> Result<E::Output, Error>
+    where E: AnimationEncoder, F: Into<FrameRate>
+{
+    let delays_ms = vec![fps.into().delay_ms(); imgs.len()];
+    let (frames, delays) = prepare(imgs, delays_ms, options)?;
+    encoder.encode(frames, delays, options)
+}
+
+/// The largest width or height of a preview `Gif`.
+const PREVIEW_SIZE: u32 = 160;
+/// The highest frame rate of a preview `Gif`.
+const PREVIEW_FPS: f64 = 10.0;
+
+/// Quickly builds a small, low frame rate `Gif` for previews, such as in a
+/// UI, without having to tune scales and sample rates.
+///
+/// Frames are shrunk to fit in a 160 pixel square (unless `options` already
+/// sets a `scale`) and thinned out to at most 10 fps, and the palette is
+/// trained on a sparse sample of pixels. Everything else in `options` is
+/// applied as usual.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{load_images, engiffen_preview, Gif, Error, Options};
+/// # fn foo() -> Result<Gif, Error> {
+/// let images = load_images(&["tests/ball/ball01.bmp", "tests/ball/ball02.bmp", "tests/ball/ball03.bmp"]);
+/// let preview = engiffen_preview(&images, 30, &Options::default())?;
+/// # Ok(preview)
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns the same errors as `engiffen_with_options`.
+pub fn engiffen_preview<F: Into<FrameRate>>(imgs: &[Image], fps: F, options: &Options) -> Result<Gif, Error> {
+    let delay_ms = fps.into().delay_ms();
+    timing::check(&[delay_ms])?;
+    let mut options = options.clone();
+    if let (None, Some(first)) = (options.scale, imgs.first()) {
+        let largest = first.width.max(first.height);
+        if largest > PREVIEW_SIZE {
+            options.scale = Some(Scale::Factor(PREVIEW_SIZE as f32 / largest as f32));
+        }
+    }
+    let nth = (1000.0 / PREVIEW_FPS / delay_ms).ceil() as usize;
+    options.keep_every_nth = options.keep_every_nth.max(nth);
+    engiffen_with_delays(imgs, vec![delay_ms; imgs.len()], Quantizer::NeuQuant(4), &options)
+}
+
+// The tests load their frames from `tests/`.
+#[cfg(all(test, feature = "fs"))]
+mod tests {
+    use super::{engiffen, engiffen_animation, engiffen_borrowed, engiffen_files, engiffen_generated, engiffen_grid, engiffen_indexed, engiffen_iter, engiffen_preview, engiffen_resumable, engiffen_segments, engiffen_sizes, engiffen_timestamped, engiffen_with_encoder, engiffen_with_options, engiffen_with_stats, engiffen_write, reprocess_gif};
+    use super::super::{load_image, load_images, read_gif, AnimationEncoder, Crop, Error, FrameRef, Image, IndexedImage, Options, Quantizer, Rotation, Scale, Transition};
+    use std::time::{Duration, Instant};
+    use std::borrow::Cow;
+    use image::{self, RgbaImage};
+    use fixtures::{delays, indices, solid, transparencies, transparent_frames};
+
+    #[test]
+    fn test_indexed_passthrough() {
+        let frame = |indices: Vec<u8>| IndexedImage { indices, palette: vec![10, 20, 30, 40, 50, 60], transparent: Some(1), width: 2, height: 1 };
+        let frames = vec![frame(vec![0, 1]), frame(vec![1, 0])];
+        let gif = engiffen_indexed(&frames, 10, Quantizer::NeuQuant(1), &Options::default()).unwrap();
+        assert_eq!(gif.palette, frames[0].palette);
+        assert_eq!(*gif.frames[1].indices, vec![1, 0]);
+        assert_eq!(gif.frames[1].transparent, Some(1));
+
+        // Different palettes have to be quantized.
+        let mut mixed = frames.clone();
+        mixed[1].palette = vec![0, 0, 0, 255, 255, 255];
+        let gif = engiffen_indexed(&mixed, 10, Quantizer::Naive, &Options::default()).unwrap();
+        assert_ne!(gif.palette, frames[0].palette);
+        assert_eq!(gif.frames.len(), 2);
+
+        let invalid = |frames: &[IndexedImage]| match engiffen_indexed(frames, 10, Quantizer::Naive, &Options::default()) {
+            Err(Error::InvalidOption("indices", _)) => {},
+            other => panic!("expected invalid indices, got {:?}", other.map(|_| ())),
+        };
+        invalid(&[frame(vec![0, 1]), frame(vec![0, 1, 1])]);
+        invalid(&[frame(vec![0, 1]), frame(vec![0, 2])]);
+    }
+
+    #[test]
+    fn test_grid() {
+        let red = vec![solid(2, 2, [255, 0, 0, 255]); 2];
+        let blue = vec![solid(2, 2, [0, 0, 255, 255]); 2];
+        let gif = engiffen_grid(&[&red, &blue, &red], 2, 10, Quantizer::Naive, &Options::default()).unwrap();
+        assert_eq!((gif.width, gif.height), (4, 4));
+        assert_eq!(gif.frames.len(), 2);
+        assert_eq!(transparencies(&gif), vec![Some(2), Some(2)]);
+    }
+
+    #[test]
+    fn test_sizes_share_a_palette() {
+        let gifs = engiffen_sizes(&transparent_frames(), 10, Quantizer::Naive, &Options::default(),
+            &[Scale::Factor(1.0), Scale::Size(2, 2), Scale::Factor(2.0)]).unwrap();
+        let sizes: Vec<_> = gifs.iter().map(|gif| (gif.width, gif.height)).collect();
+        assert_eq!(sizes, vec![(4, 4), (2, 2), (8, 8)]);
+        assert!(gifs.iter().all(|gif| gif.palette == gifs[0].palette));
+        assert_eq!(gifs[0], engiffen(&transparent_frames(), 10, Quantizer::Naive).unwrap());
+        assert_eq!(gifs[2].frames[0].indices.len(), 64);
+        assert!(engiffen_sizes(&transparent_frames(), 10, Quantizer::Naive, &Options::default(), &[Scale::Width(0)]).is_err());
+    }
+
+    #[test]
+    fn test_preview() {
+        let imgs = vec![solid(320, 20, [200, 10, 10, 255]); 8];
+        let gif = engiffen_preview(&imgs, 30, &Options::default()).unwrap();
+        assert_eq!((gif.width, gif.height), (160, 10));
+        assert_eq!(gif.frames.len(), 3);
+        assert_eq!(delays(&gif)[..2], [10, 10]);
+    }
+
+    #[test]
+    fn test_borrowed_frames_match_owned() {
+        let imgs = transparent_frames();
+        let frames: Vec<_> = imgs.iter().map(FrameRef::from).collect();
+        let options = Options { first_frame_delay: Some(Duration::from_secs(1)), ..Options::default() };
+        let borrowed = engiffen_borrowed(&frames, 10, Quantizer::Naive, &options).unwrap();
+        assert_eq!(borrowed, engiffen_with_options(&imgs, 10, Quantizer::Naive, &options).unwrap());
+
+        // Options that change the frames fall back to copying them.
+        let options = Options { opaque: true, ..Options::default() };
+        let borrowed = engiffen_borrowed(&frames, 10, Quantizer::Naive, &options).unwrap();
+        assert_eq!(borrowed, engiffen_with_options(&imgs, 10, Quantizer::Naive, &options).unwrap());
+    }
+
+    #[test]
+    fn test_animation_frames() {
+        let red = image::RgbaImage::from_pixel(2, 2, image::Rgba([255, 0, 0, 255]));
+        let blue = image::RgbaImage::from_pixel(1, 1, image::Rgba([0, 0, 255, 255]));
+        // Delays are num_rational Ratios, which this crate doesn't depend on
+        // directly, so build them from a new frame's zero delay.
+        let one_second = image::Frame::new(blue.clone()).delay() + 1;
+        let frames = vec![
+            image::Frame::from_parts(red, 0, 0, one_second),
+            image::Frame::from_parts(blue, 1, 1, one_second / 4),
+        ];
+        let gif = engiffen_animation(frames, Quantizer::Naive, &Options::default()).unwrap();
+        assert_eq!(delays(&gif), vec![100, 25]);
+        assert_eq!((gif.width, gif.height), (2, 2));
+        assert_eq!(gif.frames[0].indices[..3], gif.frames[1].indices[..3]);
+        assert!(gif.frames[1].indices[3] != gif.frames[0].indices[3]);
+    }
+
+    #[test]
+    fn test_reprocess_gif() {
+        let imgs: Vec<_> = (0..3).map(|n| solid(4, 4, [n * 100, 0, 0, 255])).collect();
+        let gif = engiffen(&imgs, 25, Quantizer::Naive).unwrap();
+        let path = std::env::temp_dir().join("engiffen-test-reprocess.gif");
+        gif.write(&mut std::fs::File::create(&path).unwrap()).unwrap();
+
+        let options = Options { crop: Some(Crop { x: 1, y: 1, width: 2, height: 3 }), ..Options::default() };
+        let out = reprocess_gif(&path, Quantizer::Naive, &options).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((out.width, out.height), (2, 3));
+        assert_eq!(delays(&out), delays(&gif));
+        assert_eq!(out.frames.len(), 3);
+    }
+
+    #[test]
+    fn test_engiffen_write() {
+        let imgs = transparent_frames();
+        let options = Options { scale: Some(Scale::Factor(0.5)), ..Options::default() };
+        let gif = engiffen_with_options(&imgs, 10, Quantizer::Naive, &options).unwrap();
+        let mut written = Vec::new();
+        let stats = engiffen_write(&imgs, 10, Quantizer::Naive, &options, &mut written).unwrap();
+        assert_eq!(written, gif.to_bytes().unwrap());
+        assert_eq!(stats.bytes_written, Some(written.len() as u64));
+    }
+
+    #[test]
+    fn test_engiffen_with_stats() {
+        let imgs = transparent_frames();
+        let (gif, stats) = engiffen_with_stats(&imgs, 10, Quantizer::Naive, &Options::default()).unwrap();
+        assert_eq!(gif, engiffen(&imgs, 10, Quantizer::Naive).unwrap());
+        assert_eq!(stats.frames, imgs.len());
+        assert_eq!(stats.palette_colors, gif.palette_len());
+        assert!(stats.colors_sampled > 0);
+        assert_eq!(stats.bytes_written, None);
+    }
+
+    #[test]
+    fn test_custom_encoder() {
+        struct Sizes;
+        impl AnimationEncoder for Sizes {
+            type Output = Vec<(u32, u32, u16)>;
+            fn encode(&self, frames: Cow<[Image]>, delays: Vec<u16>, _options: &Options) -> Result<Self::Output, Error> {
+                Ok(frames.iter().zip(delays).map(|(img, delay)| (img.width, img.height, delay)).collect())
+            }
+        }
+
+        let imgs = vec![solid(4, 3, [0, 0, 0, 255]); 2];
+        let options = Options { rotation: Some(Rotation::Rotate90), ..Options::default() };
+        let sizes = engiffen_with_encoder(&imgs, 20, &Sizes, &options).unwrap();
+        assert_eq!(sizes, vec![(3, 4, 5), (3, 4, 5)]);
+    }
+
+    #[test]
+    fn test_generated() {
+        let calls = ::std::cell::Cell::new(0);
+        let mut out = Vec::new();
+        let stats = engiffen_generated(4, (3, 2), |n, canvas: &mut RgbaImage| {
+            calls.set(calls.get() + 1);
+            canvas.put_pixel(n as u32 % 3, 0, image::Rgba([255, 0, 0, 255]));
+        }, 10, Quantizer::Naive, &Options::default(), &mut out).unwrap();
+        assert_eq!(calls.get(), 8);
+        assert_eq!(stats.frames, 4);
+
+        let (frames, _) = read_gif(&out[..]).unwrap();
+        assert_eq!(frames.len(), 4);
+        assert_eq!((frames[1].width, frames[1].height), (3, 2));
+        assert_eq!(frames[1].pixels[1], [255, 0, 0, 255]);
+        assert_eq!(frames[0].pixels[1][3], 0);
+        assert!(engiffen_generated(0, (3, 2), |_, _: &mut RgbaImage| (), 10, Quantizer::Naive, &Options::default(), &mut out).is_err());
+    }
+
+    #[test]
+    fn test_resumable() {
+        let imgs: Vec<_> = (0..4).map(|n| Image { pixels: vec![[n * 60, 0, 0, 255], [0, 0, 0, 0]], width: 2, height: 1 }).collect();
+        let mut whole = Vec::new();
+        let mut checkpoints = Vec::new();
+        engiffen_resumable(&imgs, 10, Quantizer::Naive, &Options::default(), &mut whole, None, |c| checkpoints.push(c.clone())).unwrap();
+        assert_eq!(checkpoints.len(), 4);
+        assert_eq!(checkpoints[3].bytes_written as usize, whole.len() - 1);
+        assert_eq!(read_gif(&whole[..]).unwrap().0.len(), 4);
+
+        // Interrupted after two frames, with part of the third written.
+        let checkpoint = checkpoints[1].clone();
+        let mut resumed = whole[..checkpoint.bytes_written as usize + 5].to_vec();
+        resumed.truncate(checkpoint.bytes_written as usize);
+        let mut later = Vec::new();
+        engiffen_resumable(&imgs, 10, Quantizer::Naive, &Options::default(), &mut resumed, Some(&checkpoint), |c| later.push(c.frames_written)).unwrap();
+        assert_eq!(later, vec![3, 4]);
+        assert_eq!(resumed, whole);
+    }
+
+    #[test]
+    fn test_engiffen_iter() {
+        let imgs = transparent_frames();
+        let gif = engiffen_iter(imgs.iter().cloned().map(Ok), 10, Quantizer::Naive, &Options::default()).unwrap();
+        assert_eq!(gif, engiffen(&imgs, 10, Quantizer::Naive).unwrap());
+
+        let failing = vec![Ok(imgs[0].clone()), load_image("tests/no-such.png"), Ok(imgs[0].clone())];
+        assert!(matches!(engiffen_iter(failing, 10, Quantizer::Naive, &Options::default()), Err(Error::ImageLoad(_))));
+    }
+
+    #[test]
+    fn test_engiffen_files() {
+        let paths = ["tests/ball/ball01.bmp", "tests/ball/ball02.bmp", "tests/ball/ball03.bmp"];
+        let imgs = load_images(&paths);
+        for &quantizer in &[Quantizer::Naive, Quantizer::NeuQuant(4)] {
+            let (mut loaded, mut streamed) = (Vec::new(), Vec::new());
+            engiffen_write(&imgs, 10, quantizer, &Options::default(), &mut loaded).unwrap();
+            let stats = engiffen_files(&paths, 10, quantizer, &Options::default(), &mut streamed).unwrap();
+            assert_eq!(streamed, loaded);
+            assert_eq!(stats.frames, 3);
+        }
+
+        let missing = ["tests/ball/ball01.bmp", "tests/no-such.png"];
+        assert!(matches!(engiffen_files(&missing, 10, Quantizer::Naive, &Options::default(), &mut Vec::new()), Err(Error::ImageLoad(_))));
+    }
+
+    #[cfg(feature = "apng")]
+    #[test]
+    fn test_apng_round_trip() {
+        use super::super::{engiffen_apng, read_apng};
+        let imgs = vec![
+            Image { pixels: vec![[255, 0, 0, 255], [0, 0, 0, 0]], width: 2, height: 1 },
+            Image { pixels: vec![[0, 255, 0, 128], [0, 0, 255, 255]], width: 2, height: 1 },
+        ];
+        let mut bytes = Vec::new();
+        engiffen_apng(&imgs, 20, &Options::default()).unwrap().write(&mut bytes).unwrap();
+        let (frames, delays) = read_apng(&bytes[..]).unwrap();
+        assert_eq!(frames, imgs);
+        assert_eq!(delays, vec![Duration::from_millis(50); 2]);
+    }
+
+    #[test]
+    fn test_timestamped_frames() {
+        let frame = transparent_frames().remove(0);
+        let start = Instant::now();
+        let frames = vec![
+            (frame.clone(), start),
+            (frame.clone(), start + Duration::from_millis(100)),
+            (frame, start + Duration::from_millis(130)),
+        ];
+        let gif = engiffen_timestamped(frames, Quantizer::Naive, &Options::default()).unwrap();
+        assert_eq!(delays(&gif), vec![10, 3, 10]);
+    }
+
+    #[test]
+    fn test_segments_dip_to_black() {
+        let red = vec![solid(4, 4, [200, 0, 0, 255]); 3];
+        let blue = vec![solid(4, 4, [0, 0, 200, 255]); 3];
+        let gif = engiffen_segments(&[&red, &blue], 10, Quantizer::Naive, &Options::default(), Transition::DipToBlack(1)).unwrap();
+        let colors: Vec<_> = indices(&gif).iter().map(|img| {
+            let idx = img[0] as usize * 3;
+            (gif.palette[idx], gif.palette[idx + 2])
+        }).collect();
+        assert_eq!(colors, vec![(200, 0), (200, 0), (100, 0), (0, 100), (0, 200), (0, 200)]);
+    }
+}
@@ -0,0 +1,63 @@
+//! The output stage, separate from frame preparation so that more container
+//! formats can be added without touching the pipeline.
+
+use std::borrow::Cow;
+use super::{assemble, gif_dimensions, palettize_frames, EncodeStats, Error, Gif, Image, Options, Quantizer};
+#[cfg(feature = "apng")] use super::Apng;
+
+/// Turns fully prepared frames into an animation.
+///
+/// By the time `encode` is called every transform and adjustment in the
+/// `Options` has been applied, and all frames have the same dimensions.
+/// `delays` holds one entry per frame, in hundredths of a second.
+///
+/// This is the in-memory path, used by `engiffen_with_encoder`. The
+/// streaming writers, `engiffen_write`, `engiffen_files` and `Gif::write`
+/// with the `parallel-write` feature, are written against the Gif format
+/// directly and don't go through an encoder, so other formats get neither.
+///
+/// `GifEncoder` and, with the `apng` feature, `ApngEncoder` are provided.
+/// WebP output isn't: nothing this crate depends on can encode or decode
+/// WebP, so an encoder for it, which the trait leaves room for, would have
+/// to be written and tested from scratch.
+pub trait AnimationEncoder {
+    type Output;
+
+    fn encode(&self, frames: Cow<[Image]>, delays: Vec<u16>, options: &Options) -> Result<Self::Output, Error>;
+}
+
+/// Encodes a `Gif`, quantizing frames to a shared palette.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct GifEncoder {
+    pub quantizer: Quantizer,
+}
+
+impl AnimationEncoder for GifEncoder {
+    type Output = Gif;
+
+    fn encode(&self, frames: Cow<[Image]>, delays: Vec<u16>, options: &Options) -> Result<Gif, Error> {
+        gif_dimensions(frames[0].width, frames[0].height)?;
+        let (palette, palettized_imgs, transparency) = palettize_frames(&frames, self.quantizer, options, &mut EncodeStats::default())?;
+        assemble(palette, palettized_imgs, transparency, delays, frames[0].width, frames[0].height)
+    }
+}
+
+/// Encodes an `Apng`, keeping frames at full color. Requires the `apng`
+/// feature.
+#[cfg(feature = "apng")]
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
+pub struct ApngEncoder;
+
+#[cfg(feature = "apng")]
+impl AnimationEncoder for ApngEncoder {
+    type Output = Apng;
+
+    fn encode(&self, frames: Cow<[Image]>, delays: Vec<u16>, _options: &Options) -> Result<Apng, Error> {
+        Ok(Apng {
+            width: frames[0].width,
+            height: frames[0].height,
+            frames: frames.into_owned(),
+            delays,
+        })
+    }
+}
@@ -0,0 +1,218 @@
+//! Reading the EXIF orientation tag cameras use to record which way up a
+//! photo was taken, instead of rotating its pixels, and the time it was
+//! taken.
+
+use transform::{flip_horizontal, flip_vertical, rotate};
+use super::{Image, Rotation};
+
+const ORIENTATION_TAG: u16 = 0x0112;
+#[cfg(feature = "fs")] const DATE_TIME_TAG: u16 = 0x0132;
+#[cfg(feature = "fs")] const EXIF_IFD_TAG: u16 = 0x8769;
+#[cfg(feature = "fs")] const DATE_TIME_ORIGINAL_TAG: u16 = 0x9003;
+
+/// The orientation recorded in a JPEG's EXIF data, from 1 to 8, or `None` if
+/// it isn't a JPEG or has no orientation tag.
+pub fn orientation(bytes: &[u8]) -> Option<u16> {
+    let tiff = Tiff::new(exif_tiff(bytes)?)?;
+    tiff.entry(tiff.first_ifd()?, ORIENTATION_TAG)
+        .and_then(|entry| tiff.u16_at(entry + 8))
+        .filter(|orientation| (1..=8).contains(orientation))
+}
+
+/// When a JPEG's EXIF data says the photo was taken, in seconds since the
+/// Unix epoch, from its `DateTimeOriginal` tag or failing that its
+/// `DateTime`. Cameras record their local time without a time zone, so
+/// it's read as if it were UTC, which keeps photos from one camera in
+/// order.
+#[cfg(feature = "fs")]
+pub fn taken(bytes: &[u8]) -> Option<i64> {
+    let tiff = Tiff::new(exif_tiff(bytes)?)?;
+    let ifd = tiff.first_ifd()?;
+    let original = tiff.entry(ifd, EXIF_IFD_TAG)
+        .and_then(|entry| tiff.u32_at(entry + 8))
+        .and_then(|exif_ifd| tiff.entry(exif_ifd as usize, DATE_TIME_ORIGINAL_TAG));
+    let entry = original.or_else(|| tiff.entry(ifd, DATE_TIME_TAG))?;
+    // "YYYY:MM:DD HH:MM:SS", in the 20 bytes the entry points to.
+    let at = tiff.u32_at(entry + 8)? as usize;
+    parse_date_time(tiff.data.get(at..at + 19)?)
+}
+
+#[cfg(feature = "fs")]
+fn parse_date_time(text: &[u8]) -> Option<i64> {
+    let text = ::std::str::from_utf8(text).ok()?;
+    let field = |range: ::std::ops::Range<usize>| text.get(range)?.parse::<i64>().ok();
+    let (year, month, day) = (field(0..4)?, field(5..7)?, field(8..10)?);
+    let (hour, minute, second) = (field(11..13)?, field(14..16)?, field(17..19)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    // Days since the epoch of the proleptic Gregorian date, counting years
+    // from March so that leap days come last.
+    let (y, m) = if month <= 2 { (year - 1, month + 9) } else { (year, month - 3) };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * m + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+    Some(days * 86_400 + hour * 3600 + minute * 60 + second)
+}
+
+/// The TIFF structure in a JPEG's `APP1` EXIF segment.
+fn exif_tiff(bytes: &[u8]) -> Option<&[u8]> {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut at = 2;
+    while bytes.get(at) == Some(&0xFF) {
+        let marker = *bytes.get(at + 1)?;
+        // Image data starts at the start of scan marker, so the metadata is
+        // over by then.
+        if marker == 0xDA || marker == 0xD9 {
+            return None;
+        }
+        let len = (*bytes.get(at + 2)? as usize) << 8 | *bytes.get(at + 3)? as usize;
+        let segment = bytes.get(at + 4..at + 2 + len)?;
+        if marker == 0xE1 && segment.starts_with(b"Exif\0\0") {
+            return Some(&segment[6..]);
+        }
+        at += 2 + len;
+    }
+    None
+}
+
+/// An EXIF TIFF header and the directories of tags it points to.
+struct Tiff<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a [u8]) -> Option<Tiff<'a>> {
+        let big_endian = match data.get(0..2)? {
+            b"MM" => true,
+            b"II" => false,
+            _ => return None,
+        };
+        let tiff = Tiff { data, big_endian };
+        if tiff.u16_at(2)? != 42 {
+            return None;
+        }
+        Some(tiff)
+    }
+
+    fn u16_at(&self, at: usize) -> Option<u16> {
+        self.data.get(at..at + 2).map(|b| {
+            if self.big_endian { (b[0] as u16) << 8 | b[1] as u16 } else { (b[1] as u16) << 8 | b[0] as u16 }
+        })
+    }
+
+    fn u32_at(&self, at: usize) -> Option<u32> {
+        let (a, b) = (self.u16_at(at)? as u32, self.u16_at(at + 2)? as u32);
+        Some(if self.big_endian { a << 16 | b } else { b << 16 | a })
+    }
+
+    fn first_ifd(&self) -> Option<usize> {
+        self.u32_at(4).map(|at| at as usize)
+    }
+
+    /// Where the entry for `tag` in the directory at `ifd` is.
+    fn entry(&self, ifd: usize, tag: u16) -> Option<usize> {
+        let count = self.u16_at(ifd)? as usize;
+        (0..count).map(|n| ifd + 2 + n * 12).find(|&entry| self.u16_at(entry) == Some(tag))
+    }
+}
+
+/// Turns and flips an image so that it's upright, given its EXIF
+/// orientation.
+pub fn orient(img: Image, orientation: u16) -> Image {
+    match orientation {
+        2 => flip_horizontal(&img),
+        3 => rotate(&img, Rotation::Rotate180),
+        4 => flip_vertical(&img),
+        5 => flip_horizontal(&rotate(&img, Rotation::Rotate90)),
+        6 => rotate(&img, Rotation::Rotate90),
+        7 => flip_horizontal(&rotate(&img, Rotation::Rotate270)),
+        8 => rotate(&img, Rotation::Rotate270),
+        _ => img,
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{orient, orientation};
+    #[cfg(feature = "fs")] use super::{parse_date_time, taken};
+    use super::super::Image;
+
+    /// An APP1 segment holding an EXIF orientation tag.
+    pub fn exif_segment(orientation: u16, big_endian: bool) -> Vec<u8> {
+        let le = |n: u16| if big_endian { vec![(n >> 8) as u8, n as u8] } else { vec![n as u8, (n >> 8) as u8] };
+        let mut tiff = if big_endian { b"MM".to_vec() } else { b"II".to_vec() };
+        tiff.extend(le(42));
+        tiff.extend(if big_endian { vec![0, 0, 0, 8] } else { vec![8, 0, 0, 0] });
+        tiff.extend(le(1));
+        tiff.extend(le(0x0112));
+        tiff.extend(le(3));
+        tiff.extend(if big_endian { vec![0, 0, 0, 1] } else { vec![1, 0, 0, 0] });
+        tiff.extend(le(orientation));
+        tiff.extend(vec![0, 0, 0, 0, 0, 0]);
+
+        let len = tiff.len() + 8;
+        let mut segment = vec![0xFF, 0xE1, (len >> 8) as u8, len as u8];
+        segment.extend_from_slice(b"Exif\0\0");
+        segment.extend(tiff);
+        segment
+    }
+
+    #[test]
+    fn test_orientation() {
+        for &big_endian in &[true, false] {
+            let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0, 0, 4, 0, 0];
+            jpeg.extend(exif_segment(6, big_endian));
+            jpeg.extend_from_slice(&[0xFF, 0xDA]);
+            assert_eq!(orientation(&jpeg), Some(6));
+        }
+        assert_eq!(orientation(&[0xFF, 0xD8, 0xFF, 0xDA, 0, 2]), None);
+        assert_eq!(orientation(b"BM"), None);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn test_taken() {
+        // A big endian TIFF whose first IFD points to an EXIF IFD holding
+        // DateTimeOriginal.
+        let mut tiff = b"MM\0\x2a\0\0\0\x08".to_vec();
+        tiff.extend_from_slice(&[0, 1, 0x87, 0x69, 0, 4, 0, 0, 0, 1, 0, 0, 0, 26, 0, 0, 0, 0]);
+        tiff.extend_from_slice(&[0, 1, 0x90, 0x03, 0, 2, 0, 0, 0, 20, 0, 0, 0, 44, 0, 0, 0, 0]);
+        tiff.extend_from_slice(b"2021:03:14 15:09:26\0");
+        let len = tiff.len() + 8;
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1, (len >> 8) as u8, len as u8];
+        jpeg.extend_from_slice(b"Exif\0\0");
+        jpeg.extend(tiff);
+        jpeg.extend_from_slice(&[0xFF, 0xDA]);
+        assert_eq!(taken(&jpeg), Some(1_615_734_566));
+        assert_eq!(orientation(&jpeg), None);
+
+        let mut oriented = vec![0xFF, 0xD8];
+        oriented.extend(exif_segment(6, false));
+        assert_eq!(taken(&oriented), None);
+        assert_eq!(parse_date_time(b"1970:01:01 00:00:00"), Some(0));
+        assert_eq!(parse_date_time(b"2000:02:29 00:00:01"), Some(951_782_401));
+        assert_eq!(parse_date_time(b"    :  :     :  :  "), None);
+    }
+
+    #[test]
+    fn test_orient() {
+        // 1 2
+        // 3 4
+        let img = Image { pixels: vec![[1; 4], [2; 4], [3; 4], [4; 4]], width: 2, height: 2 };
+        let corners = |orientation| orient(img.clone(), orientation).pixels.iter().map(|px| px[0]).collect::<Vec<_>>();
+        assert_eq!(corners(1), vec![1, 2, 3, 4]);
+        assert_eq!(corners(2), vec![2, 1, 4, 3]);
+        assert_eq!(corners(3), vec![4, 3, 2, 1]);
+        assert_eq!(corners(4), vec![3, 4, 1, 2]);
+        assert_eq!(corners(5), vec![1, 3, 2, 4]);
+        assert_eq!(corners(6), vec![3, 1, 4, 2]);
+        assert_eq!(corners(7), vec![4, 2, 3, 1]);
+        assert_eq!(corners(8), vec![2, 4, 1, 3]);
+    }
+}
@@ -0,0 +1,415 @@
+//! Decoding OpenEXR images, the linear format most renderers write, and tone
+//! mapping them like Radiance HDR images.
+//!
+//! Only single part scanline images are read, uncompressed or with RLE,
+//! ZIPS or ZIP compression, which is what renderers write unless told
+//! otherwise. Tiled, deep and multi-part images, and the lossy and wavelet
+//! compressions, are an `Error::Unsupported`.
+
+use std::borrow::Cow;
+use std::iter;
+use image::ImageError;
+use inflate::inflate_bytes_zlib;
+use hdr::{srgb, ToneMap};
+use super::{Error, Image};
+
+const MAGIC: [u8; 4] = [0x76, 0x2F, 0x31, 0x01];
+
+/// Version flags for images that aren't single part scanline images.
+const TILED: u32 = 0x200;
+const DEEP_OR_MULTIPART: u32 = 0x1800;
+
+/// How many times larger than the file decoded samples can be. Deflate
+/// tops out at a little over 1000 to 1, so anything bigger is a header
+/// lying about its size.
+const MAX_EXPANSION: u64 = 1032;
+
+fn format_error<S: Into<String>>(msg: S) -> Error {
+    Error::ImageLoad(ImageError::FormatError(msg.into()))
+}
+
+/// Splits `n` bytes off the front of `bytes`.
+fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Result<&'a [u8], Error> {
+    if bytes.len() < n {
+        return Err(format_error("exr file ends early"));
+    }
+    let (head, tail) = bytes.split_at(n);
+    *bytes = tail;
+    Ok(head)
+}
+
+fn read_u32(bytes: &mut &[u8]) -> Result<u32, Error> {
+    let b = take(bytes, 4)?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn read_i32(bytes: &mut &[u8]) -> Result<i32, Error> {
+    read_u32(bytes).map(|n| n as i32)
+}
+
+/// Splits a null terminated name off the front of `bytes`.
+fn read_name<'a>(bytes: &mut &'a [u8]) -> Result<&'a [u8], Error> {
+    let end = bytes.iter().position(|&b| b == 0).ok_or_else(|| format_error("exr header name isn't terminated"))?;
+    let name = take(bytes, end)?;
+    take(bytes, 1)?;
+    Ok(name)
+}
+
+/// How a channel's samples are stored.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+enum Sample {
+    Uint,
+    Half,
+    Float,
+}
+
+impl Sample {
+    fn size(self) -> usize {
+        match self {
+            Sample::Half => 2,
+            Sample::Uint | Sample::Float => 4,
+        }
+    }
+
+    fn read(self, b: &[u8]) -> f32 {
+        match self {
+            Sample::Uint => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32,
+            Sample::Half => half(u16::from_le_bytes([b[0], b[1]])),
+            Sample::Float => f32::from_bits(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        }
+    }
+}
+
+/// Widens an IEEE 754 half precision float.
+fn half(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 == 0 { 1.0 } else { -1.0 };
+    let (exponent, fraction) = ((bits >> 10) & 0x1f, (bits & 0x3ff) as f32);
+    sign * match exponent {
+        0 => fraction * 2f32.powi(-24),
+        31 if fraction == 0.0 => f32::INFINITY,
+        31 => f32::NAN,
+        e => (1.0 + fraction / 1024.0) * 2f32.powi(e as i32 - 15),
+    }
+}
+
+/// Where a channel's samples go: red, green, blue, alpha or, for
+/// luminance-only images, all three colors.
+const LUMINANCE: usize = 4;
+
+struct Channel {
+    slot: Option<usize>,
+    sample: Sample,
+}
+
+fn parse_channels(mut list: &[u8]) -> Result<Vec<Channel>, Error> {
+    let mut channels = Vec::new();
+    loop {
+        let name = read_name(&mut list)?;
+        if name.is_empty() {
+            return Ok(channels);
+        }
+        let sample = match read_u32(&mut list)? {
+            0 => Sample::Uint,
+            1 => Sample::Half,
+            2 => Sample::Float,
+            n => return Err(format_error(format!("exr channel has unknown pixel type {}", n))),
+        };
+        // The linear flag and three reserved bytes.
+        take(&mut list, 4)?;
+        if (read_i32(&mut list)?, read_i32(&mut list)?) != (1, 1) {
+            return Err(Error::Unsupported("Subsampled OpenEXR"));
+        }
+        let slot = match name {
+            b"R" => Some(0),
+            b"G" => Some(1),
+            b"B" => Some(2),
+            b"A" => Some(3),
+            b"Y" => Some(LUMINANCE),
+            _ => None,
+        };
+        channels.push(Channel { slot, sample });
+    }
+}
+
+/// Undoes OpenEXR's run length encoding, where a negative count is followed
+/// by that many bytes as they are, and any other count by one byte to
+/// repeat count + 1 times.
+fn unrle(mut data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::new();
+    while let Some((&count, rest)) = data.split_first() {
+        data = rest;
+        let count = count as i8;
+        if count < 0 {
+            out.extend_from_slice(take(&mut data, -(count as isize) as usize)?);
+        } else {
+            let byte = take(&mut data, 1)?[0];
+            out.extend(iter::repeat(byte).take(count as usize + 1));
+        }
+    }
+    Ok(out)
+}
+
+/// Undoes the delta coding and byte splitting RLE and ZIP compressed chunks
+/// go through before they're compressed, which put the first byte of every
+/// pair of bytes before all the second ones.
+fn unsplit(mut data: Vec<u8>) -> Vec<u8> {
+    for n in 1..data.len() {
+        data[n] = data[n - 1].wrapping_add(data[n]).wrapping_sub(128);
+    }
+    let (first, second) = data.split_at(data.len().div_ceil(2));
+    let mut out = Vec::with_capacity(data.len());
+    for (n, &byte) in first.iter().enumerate() {
+        out.push(byte);
+        out.extend(second.get(n));
+    }
+    out
+}
+
+/// Decodes `bytes` if they're an OpenEXR image, or returns `None` so that
+/// they can be decoded as anything else.
+pub fn decode(bytes: &[u8], tone_map: ToneMap) -> Option<Result<Image, Error>> {
+    if !bytes.starts_with(&MAGIC) {
+        return None;
+    }
+    Some(decode_exr(bytes, tone_map))
+}
+
+fn decode_exr(bytes: &[u8], tone_map: ToneMap) -> Result<Image, Error> {
+    let mut rest = &bytes[MAGIC.len()..];
+    let version = read_u32(&mut rest)?;
+    if version & TILED != 0 {
+        return Err(Error::Unsupported("Tiled OpenEXR"));
+    }
+    if version & DEEP_OR_MULTIPART != 0 {
+        return Err(Error::Unsupported("Deep and multi-part OpenEXR"));
+    }
+    let (mut channels, mut compression, mut window) = (None, None, None);
+    loop {
+        let name = read_name(&mut rest)?;
+        if name.is_empty() {
+            break;
+        }
+        read_name(&mut rest)?;
+        let len = read_u32(&mut rest)? as usize;
+        let mut value = take(&mut rest, len)?;
+        match name {
+            b"channels" => channels = Some(parse_channels(value)?),
+            b"compression" => compression = value.first().cloned(),
+            b"dataWindow" => window = Some([read_i32(&mut value)?, read_i32(&mut value)?, read_i32(&mut value)?, read_i32(&mut value)?]),
+            _ => {},
+        }
+    }
+    let channels = channels.ok_or_else(|| format_error("exr file has no channel list"))?;
+    let [x_min, y_min, x_max, y_max] = window.ok_or_else(|| format_error("exr file has no data window"))?;
+    let compression = compression.ok_or_else(|| format_error("exr file has no compression attribute"))?;
+    let lines = match compression {
+        0..=2 => 1,
+        3 => 16,
+        4 => return Err(Error::Unsupported("PIZ compressed OpenEXR")),
+        5 => return Err(Error::Unsupported("PXR24 compressed OpenEXR")),
+        6 | 7 => return Err(Error::Unsupported("B44 compressed OpenEXR")),
+        8 | 9 => return Err(Error::Unsupported("DWA compressed OpenEXR")),
+        n => return Err(format_error(format!("exr file has unknown compression {}", n))),
+    };
+
+    let (width, height) = (x_max as i64 - x_min as i64 + 1, y_max as i64 - y_min as i64 + 1);
+    if width < 1 || height < 1 {
+        return Err(format_error(format!("exr data window is {}x{}", width, height)));
+    }
+    let line_len = width as u64 * channels.iter().map(|channel| channel.sample.size() as u64).sum::<u64>();
+    if line_len.saturating_mul(height as u64) > bytes.len() as u64 * MAX_EXPANSION {
+        return Err(format_error(format!("exr data window of {}x{} is too large for the file", width, height)));
+    }
+    let (width, height, line_len) = (width as usize, height as usize, line_len as usize);
+    let offsets = take(&mut rest, height.div_ceil(lines) * 8)?;
+
+    let mut pixels = vec![[0.0, 0.0, 0.0, 1.0]; width * height];
+    for offset in offsets.chunks(8) {
+        let offset = u64::from_le_bytes([offset[0], offset[1], offset[2], offset[3], offset[4], offset[5], offset[6], offset[7]]);
+        let mut chunk = bytes.get(offset as usize..).ok_or_else(|| format_error("exr chunk is past the end of the file"))?;
+        let y = read_i32(&mut chunk)? as i64 - y_min as i64;
+        let len = read_u32(&mut chunk)? as usize;
+        let data = take(&mut chunk, len)?;
+        if y < 0 || y as usize >= height || y as usize % lines != 0 {
+            return Err(format_error(format!("exr chunk starts at line {}, outside the data window", y + y_min as i64)));
+        }
+        let y = y as usize;
+        let expected = line_len * lines.min(height - y);
+        // Chunks that compression wouldn't make any smaller are stored as
+        // they are.
+        let raw = match compression {
+            _ if data.len() == expected => Cow::Borrowed(data),
+            1 => Cow::Owned(unsplit(unrle(data)?)),
+            2 | 3 => Cow::Owned(unsplit(inflate_bytes_zlib(data).map_err(|e| format_error(format!("exr chunk: {}", e)))?)),
+            _ => Cow::Borrowed(data),
+        };
+        if raw.len() != expected {
+            return Err(format_error(format!("exr chunk at line {} holds {} bytes instead of {}", y, raw.len(), expected)));
+        }
+        for (row, line) in raw.chunks(line_len).enumerate() {
+            let out = &mut pixels[(y + row) * width..(y + row + 1) * width];
+            let mut start = 0;
+            for channel in &channels {
+                let size = channel.sample.size();
+                if let Some(slot) = channel.slot {
+                    for (px, sample) in out.iter_mut().zip(line[start..start + size * width].chunks(size)) {
+                        let value = channel.sample.read(sample);
+                        match slot {
+                            LUMINANCE => { px[0] = value; px[1] = value; px[2] = value; },
+                            slot => px[slot] = value,
+                        }
+                    }
+                }
+                start += size * width;
+            }
+        }
+    }
+
+    let pixels = pixels.into_iter().map(|[r, g, b, a]| {
+        // Colors are stored premultiplied by alpha.
+        let a = if a.is_nan() { 0.0 } else { a.clamp(0.0, 1.0) };
+        let color = |c: f32| srgb(tone_map.apply(if a > 0.0 { c / a } else { c }));
+        [color(r), color(g), color(b), (a * 255.0).round() as u8]
+    }).collect();
+    Ok(Image { pixels, width: width as u32, height: height as u32 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, half, MAGIC};
+    use super::super::{Error, ToneMap};
+
+    /// An OpenEXR file with one half float channel of each name, and
+    /// `chunks` as its chunk data, one chunk per `lines` lines.
+    fn exr(names: &[&str], compression: u8, (width, height): (i32, i32), lines: i32, chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        let mut attr = |name: &str, kind: &str, value: &[u8]| {
+            for s in &[name, kind] {
+                bytes.extend_from_slice(s.as_bytes());
+                bytes.push(0);
+            }
+            bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(value);
+        };
+        let mut list = Vec::new();
+        for name in names {
+            list.extend_from_slice(name.as_bytes());
+            list.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0]);
+        }
+        list.push(0);
+        attr("channels", "chlist", &list);
+        attr("compression", "compression", &[compression]);
+        let window: Vec<u8> = [0, 0, width - 1, height - 1].iter().flat_map(|n: &i32| n.to_le_bytes().to_vec()).collect();
+        attr("dataWindow", "box2i", &window);
+        attr("displayWindow", "box2i", &window);
+        bytes.push(0);
+        let mut offset = bytes.len() + chunks.len() * 8;
+        for chunk in chunks {
+            bytes.extend_from_slice(&(offset as u64).to_le_bytes());
+            offset += 8 + chunk.len();
+        }
+        for (n, chunk) in chunks.iter().enumerate() {
+            bytes.extend_from_slice(&(n as i32 * lines).to_le_bytes());
+            bytes.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(chunk);
+        }
+        bytes
+    }
+
+    fn halves(values: &[u16]) -> Vec<u8> {
+        values.iter().flat_map(|n| n.to_le_bytes().to_vec()).collect()
+    }
+
+    /// The byte splitting and delta coding OpenEXR's RLE and ZIP
+    /// compressions apply first.
+    fn split(data: &[u8]) -> Vec<u8> {
+        let mut out: Vec<u8> = data.iter().step_by(2).chain(data.iter().skip(1).step_by(2)).cloned().collect();
+        for n in (1..out.len()).rev() {
+            out[n] = out[n].wrapping_sub(out[n - 1]).wrapping_add(128);
+        }
+        out
+    }
+
+    /// A zlib stream of `data` in one stored deflate block.
+    fn zlib_stored(data: &[u8]) -> Vec<u8> {
+        let mut out = vec![0x78, 0x01, 0x01];
+        out.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(!(data.len() as u16)).to_le_bytes());
+        out.extend_from_slice(data);
+        let (mut a, mut b) = (1u32, 0u32);
+        for &byte in data {
+            a = (a + byte as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        out.extend_from_slice(&(b << 16 | a).to_be_bytes());
+        out
+    }
+
+    // Half floats for 0, 0.5, 1 and 4.
+    const ZERO: u16 = 0x0000;
+    const HALF: u16 = 0x3800;
+    const ONE: u16 = 0x3c00;
+    const FOUR: u16 = 0x4400;
+
+    #[test]
+    fn test_half() {
+        assert_eq!((half(ZERO), half(HALF), half(ONE), half(FOUR)), (0.0, 0.5, 1.0, 4.0));
+        assert_eq!(half(0xc000), -2.0);
+        assert_eq!(half(0x0001), 2f32.powi(-24));
+        assert!(half(0x7c00).is_infinite() && half(0x7e00).is_nan());
+    }
+
+    #[test]
+    fn test_decode_uncompressed() {
+        // Channels are stored in name order, each a whole line at a time.
+        let lines = [halves(&[HALF, ONE, ZERO, ONE, ZERO, ONE, ONE, FOUR]), halves(&[ZERO, ZERO, ZERO, ZERO, ZERO, ZERO, ZERO, ZERO])];
+        let bytes = exr(&["A", "B", "G", "R"], 0, (2, 2), 1, &lines);
+        let img = decode(&bytes, ToneMap::Reinhard).unwrap().unwrap();
+        assert_eq!((img.width, img.height), (2, 2));
+        // Half transparent red at 1.0 is 2.0 once unpremultiplied, which
+        // Reinhard maps to 2/3.
+        assert_eq!(img.pixels[0], [213, 0, 0, 128]);
+        // 4.0 maps to 0.8, and 1.0 to 0.5.
+        assert_eq!(img.pixels[1], [231, 188, 188, 255]);
+        assert_eq!(img.pixels[2], [0, 0, 0, 0]);
+        assert!(decode(b"#?RADIANCE", ToneMap::Reinhard).is_none());
+    }
+
+    #[test]
+    fn test_decode_compressed() {
+        let line = halves(&[ONE, HALF, ZERO]);
+        let expected = [188, 188, 188, 255];
+
+        // RLE, of runs of one byte as it is and one repeated four times.
+        let ones = halves(&[ONE; 5]);
+        assert_eq!(split(&ones), vec![0x00, 0x80, 0x80, 0x80, 0x80, 0xbc, 0x80, 0x80, 0x80, 0x80]);
+        let rle = vec![0xff, 0x00, 3, 0x80, 0xff, 0xbc, 3, 0x80];
+        let img = decode(&exr(&["Y"], 1, (5, 1), 1, &[rle]), ToneMap::Reinhard).unwrap().unwrap();
+        assert_eq!(img.pixels, vec![[188, 188, 188, 255]; 5]);
+
+        // ZIP holds 16 lines per chunk.
+        let lines: Vec<u8> = line.iter().cycle().take(line.len() * 17).cloned().collect();
+        let chunks = [zlib_stored(&split(&lines[..line.len() * 16])), zlib_stored(&split(&lines[line.len() * 16..]))];
+        let img = decode(&exr(&["Y"], 3, (3, 17), 16, &chunks), ToneMap::Reinhard).unwrap().unwrap();
+        assert_eq!(img.pixels.len(), 51);
+        assert!(img.pixels.chunks(3).all(|row| row[0] == expected && row[1] == [156, 156, 156, 255]));
+    }
+
+    #[test]
+    fn test_unsupported_exr() {
+        let chunks = [halves(&[ONE])];
+        match decode(&exr(&["Y"], 4, (1, 1), 32, &chunks), ToneMap::Reinhard).unwrap() {
+            Err(Error::Unsupported("PIZ compressed OpenEXR")) => {},
+            other => panic!("expected PIZ to be unsupported, got {:?}", other),
+        }
+        let mut tiled = exr(&["Y"], 0, (1, 1), 1, &chunks);
+        tiled[5] = 2;
+        assert!(matches!(decode(&tiled, ToneMap::Reinhard).unwrap(), Err(Error::Unsupported(_))));
+
+        // Truncated files, and windows bigger than the data, are errors.
+        let bytes = exr(&["Y"], 0, (1, 1), 1, &chunks);
+        assert!(matches!(decode(&bytes[..bytes.len() - 1], ToneMap::Reinhard).unwrap(), Err(Error::ImageLoad(_))));
+        assert!(matches!(decode(&exr(&["Y"], 0, (100_000, 100_000), 1, &[]), ToneMap::Reinhard).unwrap(), Err(Error::ImageLoad(_))));
+    }
+}
@@ -0,0 +1,30 @@
+//! Frames and Gif inspection helpers the tests share.
+
+use super::{Gif, Image};
+
+/// A `width` x `height` image of one color.
+pub fn solid(width: u32, height: u32, px: [u8; 4]) -> Image {
+    Image { pixels: vec![px; (width * height) as usize], width, height }
+}
+
+/// One 4x4 frame with a transparent pixel, a red one, and black for the rest.
+pub fn transparent_frames() -> Vec<Image> {
+    // Mostly black opaque pixels, so an index shared with transparency
+    // would most likely land on black.
+    let mut img = solid(4, 4, [0, 0, 0, 255]);
+    img.pixels[0] = [0, 0, 0, 0];
+    img.pixels[1] = [255, 0, 0, 255];
+    vec![img]
+}
+
+pub fn delays(gif: &Gif) -> Vec<u16> {
+    gif.frames.iter().map(|frame| frame.delay).collect()
+}
+
+pub fn transparencies(gif: &Gif) -> Vec<Option<u8>> {
+    gif.frames.iter().map(|frame| frame.transparent).collect()
+}
+
+pub fn indices(gif: &Gif) -> Vec<Vec<u8>> {
+    gif.frames.iter().map(|frame| frame.indices.to_vec()).collect()
+}
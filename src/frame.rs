@@ -0,0 +1,162 @@
+//! Frames of an encoded `Gif`.
+
+use std::fmt;
+use std::sync::Arc;
+use gif::DisposalMethod;
+
+/// What happens to a frame's area of the canvas once the frame's delay is
+/// over, before the next frame is drawn.
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash, Default)]
+pub enum Disposal {
+    /// Leave it up to the decoder. Most treat this like `Keep`.
+    Any,
+    /// Leave the frame in place, so the next one is drawn over it.
+    #[default]
+    Keep,
+    /// Clear the frame's area to transparent.
+    Background,
+    /// Restore the area to what it was before the frame was drawn.
+    Previous,
+}
+
+impl From<Disposal> for DisposalMethod {
+    fn from(disposal: Disposal) -> DisposalMethod {
+        match disposal {
+            Disposal::Any => DisposalMethod::Any,
+            Disposal::Keep => DisposalMethod::Keep,
+            Disposal::Background => DisposalMethod::Background,
+            Disposal::Previous => DisposalMethod::Previous,
+        }
+    }
+}
+
+impl From<DisposalMethod> for Disposal {
+    fn from(method: DisposalMethod) -> Disposal {
+        match method {
+            DisposalMethod::Any => Disposal::Any,
+            DisposalMethod::Keep => Disposal::Keep,
+            DisposalMethod::Background => Disposal::Background,
+            DisposalMethod::Previous => Disposal::Previous,
+        }
+    }
+}
+
+/// One frame of a `Gif`: palette indices for a `width` x `height` rectangle,
+/// drawn with its top left corner at `left`, `top` on the Gif's canvas.
+/// Frames made by `engiffen` cover the whole canvas.
+#[derive(Eq, PartialEq, Clone, Hash)]
+pub struct Frame {
+    /// Palette indices, row by row. Identical frames share one buffer, and
+    /// cloning a frame doesn't copy it.
+    pub indices: Arc<Vec<u8>>,
+    pub width: u16,
+    pub height: u16,
+    pub left: u16,
+    pub top: u16,
+    /// How long the frame is displayed for, in hundredths of a second (the
+    /// unit the Gif format itself uses).
+    pub delay: u16,
+    /// The palette index this frame treats as transparent, or `None` if the
+    /// frame is fully opaque.
+    pub transparent: Option<u8>,
+    pub dispose: Disposal,
+    /// A name for the frame, like the path of the image it was made from,
+    /// so tooling can map frames back to their sources. Written as a
+    /// comment before the frame, and read back by `Gif::read`.
+    pub label: Option<String>,
+}
+
+impl Frame {
+    /// A frame of `indices` at the top left of the canvas, shown for no time
+    /// at all, without transparency.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `indices` doesn't hold `width * height` entries.
+    pub fn new<I: Into<Arc<Vec<u8>>>>(indices: I, width: u16, height: u16) -> Frame {
+        let indices = indices.into();
+        assert_eq!(indices.len(), width as usize * height as usize, "Frame indices don't match its dimensions");
+        Frame {
+            indices,
+            width,
+            height,
+            left: 0,
+            top: 0,
+            delay: 0,
+            transparent: None,
+            dispose: Disposal::default(),
+            label: None,
+        }
+    }
+
+    /// The palette index at `(x, y)`, relative to the frame's own top left
+    /// corner, or `None` if that's outside the frame.
+    pub fn index(&self, x: u16, y: u16) -> Option<u8> {
+        if x < self.width && y < self.height {
+            Some(self.indices[y as usize * self.width as usize + x as usize])
+        } else {
+            None
+        }
+    }
+
+    /// Sets the palette index at `(x, y)`, relative to the frame's own top
+    /// left corner, copying the indices first if other frames share them.
+    /// Returns whether `(x, y)` was inside the frame.
+    pub fn set_index(&mut self, x: u16, y: u16, idx: u8) -> bool {
+        if x < self.width && y < self.height {
+            Arc::make_mut(&mut self.indices)[y as usize * self.width as usize + x as usize] = idx;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl fmt::Debug for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Frame {{ indices: Vec<u8 x {:?}>, width: {:?}, height: {:?}, left: {:?}, top: {:?}, delay: {:?}, transparent: {:?}, dispose: {:?}, label: {:?} }}",
+            self.indices.len(),
+            self.width,
+            self.height,
+            self.left,
+            self.top,
+            self.delay,
+            self.transparent,
+            self.dispose,
+            self.label
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::Frame;
+
+    #[test]
+    fn test_index() {
+        let mut frame = Frame::new(vec![0, 1, 2, 3, 4, 5], 3, 2);
+        assert_eq!(frame.index(2, 0), Some(2));
+        assert_eq!(frame.index(0, 1), Some(3));
+        assert_eq!(frame.index(3, 0), None);
+        assert!(frame.set_index(1, 1, 9));
+        assert!(!frame.set_index(1, 2, 9));
+        assert_eq!(*frame.indices, vec![0, 1, 2, 3, 9, 5]);
+    }
+
+    #[test]
+    fn test_shared_indices() {
+        let mut frame = Frame::new(vec![0, 1, 2, 3], 2, 2);
+        let copy = frame.clone();
+        assert!(Arc::ptr_eq(&frame.indices, &copy.indices));
+        frame.set_index(0, 0, 9);
+        assert_eq!(*copy.indices, vec![0, 1, 2, 3]);
+        assert_eq!(*frame.indices, vec![9, 1, 2, 3]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_new_checks_dimensions() {
+        Frame::new(vec![0; 5], 3, 2);
+    }
+}
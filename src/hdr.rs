@@ -0,0 +1,86 @@
+//! Tone mapping Radiance HDR images down to displayable colors. OpenEXR
+//! images are decoded in `exr` and tone mapped the same way.
+
+use std::io::Cursor;
+use image::hdr::HDRDecoder;
+use super::{Error, Image};
+
+/// How linear high dynamic range colors are compressed into the displayable
+/// range.
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash, Default)]
+pub enum ToneMap {
+    /// `c / (1 + c)`, which keeps midtones close to the original and rolls
+    /// highlights off gently.
+    #[default]
+    Reinhard,
+    /// A fit of the ACES filmic curve, with more contrast and saturated
+    /// highlights.
+    Aces,
+}
+
+impl ToneMap {
+    /// Maps a linear channel value to the 0..1 range.
+    pub fn apply(self, c: f32) -> f32 {
+        let c = c.max(0.0);
+        let mapped = match self {
+            ToneMap::Reinhard => c / (1.0 + c),
+            ToneMap::Aces => (c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14),
+        };
+        mapped.clamp(0.0, 1.0)
+    }
+}
+
+/// Encodes a linear 0..1 value with the sRGB transfer curve.
+pub fn srgb(c: f32) -> u8 {
+    let encoded = if c <= 0.003_130_8 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 };
+    (encoded * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+/// Decodes `bytes` if they're a Radiance HDR image, or returns `None` so
+/// that the image crate can decode anything else.
+pub fn decode(bytes: &[u8], tone_map: ToneMap) -> Option<Result<Image, Error>> {
+    if !bytes.starts_with(b"#?RADIANCE") && !bytes.starts_with(b"#?RGBE") {
+        return None;
+    }
+    Some((|| {
+        let decoder = HDRDecoder::new(Cursor::new(bytes))?;
+        let meta = decoder.metadata();
+        let pixels = decoder.read_image_hdr()?.into_iter().map(|px| {
+            let [r, g, b] = px.data;
+            [srgb(tone_map.apply(r)), srgb(tone_map.apply(g)), srgb(tone_map.apply(b)), 255]
+        }).collect();
+        Ok(Image { pixels, width: meta.width, height: meta.height })
+    })())
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgb;
+    use image::hdr::HDREncoder;
+    use super::{decode, srgb, ToneMap};
+
+    #[test]
+    fn test_tone_maps() {
+        for &op in &[ToneMap::Reinhard, ToneMap::Aces] {
+            assert_eq!(op.apply(0.0), 0.0);
+            assert!(op.apply(1000.0) > 0.95);
+            assert!(op.apply(0.5) < op.apply(1.0));
+        }
+        assert_eq!(ToneMap::Reinhard.apply(1.0), 0.5);
+        assert_eq!((srgb(0.0), srgb(1.0)), (0, 255));
+    }
+
+    #[test]
+    fn test_decode_hdr() {
+        let mut bytes = Vec::new();
+        let pixels = vec![Rgb { data: [0.0, 1.0, 50.0] }, Rgb { data: [1.0, 1.0, 1.0] }];
+        HDREncoder::new(&mut bytes).encode(&pixels, 2, 1).unwrap();
+        let img = decode(&bytes, ToneMap::Reinhard).unwrap().unwrap();
+        assert_eq!((img.width, img.height), (2, 1));
+        assert_eq!(img.pixels[0][0], 0);
+        // Reinhard maps 1.0 to 0.5, which is 188 in sRGB.
+        assert_eq!(img.pixels[1], [188, 188, 188, 255]);
+        assert!(img.pixels[0][2] > 250);
+        assert!(decode(b"BM", ToneMap::Aces).is_none());
+    }
+}
@@ -0,0 +1,356 @@
+//! Converting images with embedded ICC color profiles, like Display P3
+//! screenshots, to sRGB, so that frames from different sources match.
+//!
+//! Only matrix and curve RGB profiles are understood, which covers the
+//! profiles screens and cameras embed. Images with lookup table profiles,
+//! or gray and CMYK ones, are left as they are.
+
+use inflate::inflate_bytes_zlib;
+use super::Image;
+
+const PNG_SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+/// From the D50 XYZ profiles are defined in to linear sRGB, with the
+/// Bradford adaptation to sRGB's D65 white point folded in.
+const XYZ_TO_SRGB: [[f64; 3]; 3] = [
+    [3.1338561, -1.6168667, -0.4906146],
+    [-0.9787684, 1.9161415, 0.0334540],
+    [0.0719453, -0.2289914, 1.4052427],
+];
+
+/// How finely linear light is divided when it's encoded back to sRGB.
+const LINEAR_STEPS: usize = 4096;
+
+/// The ICC profile embedded in a PNG's `iCCP` chunk or a JPEG's `APP2`
+/// segments, if there is one.
+pub fn embedded(bytes: &[u8]) -> Option<Vec<u8>> {
+    if bytes.starts_with(&PNG_SIGNATURE) {
+        png_profile(bytes)
+    } else if bytes.starts_with(&[0xFF, 0xD8]) {
+        jpeg_profile(bytes)
+    } else {
+        None
+    }
+}
+
+fn png_profile(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut at = PNG_SIGNATURE.len();
+    loop {
+        let len = u32_at(bytes, at)? as usize;
+        let kind = bytes.get(at + 4..at + 8)?;
+        // The profile has to come before the image data.
+        if kind == b"IDAT" || kind == b"IEND" {
+            return None;
+        }
+        if kind == b"iCCP" {
+            let data = bytes.get(at + 8..at + 8 + len)?;
+            // A profile name, then a compression method that is always 0.
+            let name_end = data.iter().position(|&b| b == 0)?;
+            return inflate_bytes_zlib(data.get(name_end + 2..)?).ok();
+        }
+        at += 12 + len;
+    }
+}
+
+/// JPEGs split profiles over 64K across segments, each numbered.
+fn jpeg_profile(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut chunks = Vec::new();
+    let mut at = 2;
+    while bytes.get(at) == Some(&0xFF) {
+        let marker = *bytes.get(at + 1)?;
+        if marker == 0xDA || marker == 0xD9 {
+            break;
+        }
+        let len = (*bytes.get(at + 2)? as usize) << 8 | *bytes.get(at + 3)? as usize;
+        let segment = bytes.get(at + 4..at + 2 + len)?;
+        if marker == 0xE2 && segment.starts_with(b"ICC_PROFILE\0") && segment.len() > 14 {
+            chunks.push((segment[12], &segment[14..]));
+        }
+        at += 2 + len;
+    }
+    if chunks.is_empty() {
+        return None;
+    }
+    chunks.sort_by_key(|&(n, _)| n);
+    Some(chunks.into_iter().flat_map(|(_, chunk)| chunk.iter().cloned()).collect())
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> Option<u32> {
+    bytes.get(at..at + 4).map(|b| (b[0] as u32) << 24 | (b[1] as u32) << 16 | (b[2] as u32) << 8 | b[3] as u32)
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> Option<u16> {
+    bytes.get(at..at + 2).map(|b| (b[0] as u16) << 8 | b[1] as u16)
+}
+
+/// An `s15Fixed16Number`.
+fn fixed_at(bytes: &[u8], at: usize) -> Option<f64> {
+    u32_at(bytes, at).map(|n| n as i32 as f64 / 65536.0)
+}
+
+/// What an RGB profile says each channel's values mean: how they map to
+/// linear light, and the XYZ color of each channel at full strength.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    /// Linear light for each 8 bit value of red, green and blue.
+    curves: [Vec<f64>; 3],
+    /// The columns are red's, green's and blue's XYZ.
+    colorants: [[f64; 3]; 3],
+}
+
+impl Profile {
+    /// Parses a matrix and curve RGB profile, or returns `None` for any
+    /// other kind, or one that can't be read.
+    pub fn parse(icc: &[u8]) -> Option<Profile> {
+        if icc.get(16..20)? != b"RGB " || icc.get(36..40)? != b"acsp" {
+            return None;
+        }
+        let tag = |sig: &[u8]| -> Option<&[u8]> {
+            let count = u32_at(icc, 128)? as usize;
+            (0..count.min(1024)).map(|n| 132 + n * 12).find(|&at| icc.get(at..at + 4) == Some(sig)).and_then(|at| {
+                let (offset, size) = (u32_at(icc, at + 4)? as usize, u32_at(icc, at + 8)? as usize);
+                icc.get(offset..offset.checked_add(size)?)
+            })
+        };
+        let xyz = |sig: &[u8]| -> Option<[f64; 3]> {
+            let data = tag(sig).filter(|data| data.starts_with(b"XYZ "))?;
+            Some([fixed_at(data, 8)?, fixed_at(data, 12)?, fixed_at(data, 16)?])
+        };
+        let (red, green, blue) = (xyz(b"rXYZ")?, xyz(b"gXYZ")?, xyz(b"bXYZ")?);
+        let mut colorants = [[0.0; 3]; 3];
+        for row in 0..3 {
+            colorants[row] = [red[row], green[row], blue[row]];
+        }
+        Some(Profile {
+            curves: [curve(tag(b"rTRC")?)?, curve(tag(b"gTRC")?)?, curve(tag(b"bTRC")?)?],
+            colorants,
+        })
+    }
+
+    /// Converts `img`'s colors from this profile to sRGB. Colors sRGB can't
+    /// show, like Display P3's most saturated ones, are clipped.
+    pub fn to_srgb(&self, img: &mut Image) {
+        let mut matrix = [[0.0; 3]; 3];
+        for (row, out) in matrix.iter_mut().enumerate() {
+            for (col, m) in out.iter_mut().enumerate() {
+                *m = (0..3).map(|k| XYZ_TO_SRGB[row][k] * self.colorants[k][col]).sum();
+            }
+        }
+        if self.is_srgb(&matrix) {
+            return;
+        }
+        let encoded: Vec<u8> = (0..=LINEAR_STEPS).map(|n| {
+            (srgb_encode(n as f64 / LINEAR_STEPS as f64) * 255.0).round() as u8
+        }).collect();
+        for px in &mut img.pixels {
+            let linear = [self.curves[0][px[0] as usize], self.curves[1][px[1] as usize], self.curves[2][px[2] as usize]];
+            for c in 0..3 {
+                let v = (0..3).map(|k| matrix[c][k] * linear[k]).sum::<f64>().clamp(0.0, 1.0);
+                px[c] = encoded[(v * LINEAR_STEPS as f64).round() as usize];
+            }
+        }
+    }
+
+    /// Whether converting with `matrix` would leave colors as they are, as
+    /// for images tagged with an sRGB profile.
+    fn is_srgb(&self, matrix: &[[f64; 3]; 3]) -> bool {
+        let identity = (0..3).all(|row| (0..3).all(|col| {
+            (matrix[row][col] - if row == col { 1.0 } else { 0.0 }).abs() < 0.01
+        }));
+        identity && self.curves.iter().all(|curve| curve.iter().enumerate().all(|(n, &v)| {
+            (srgb_encode(v) - n as f64 / 255.0).abs() < 0.5 / 255.0
+        }))
+    }
+}
+
+/// Reads a `curv` or `para` tone curve, sampled at each 8 bit value.
+fn curve(data: &[u8]) -> Option<Vec<f64>> {
+    let sample = |f: &dyn Fn(f64) -> f64| (0..256).map(|n| f(n as f64 / 255.0).clamp(0.0, 1.0)).collect();
+    match data.get(0..4)? {
+        b"curv" => match u32_at(data, 8)? as usize {
+            0 => Some(sample(&|x| x)),
+            1 => {
+                let gamma = u16_at(data, 12)? as f64 / 256.0;
+                Some(sample(&|x| x.powf(gamma)))
+            },
+            count => {
+                let table = (0..count).map(|n| u16_at(data, 12 + n * 2).map(|v| v as f64 / 65535.0)).collect::<Option<Vec<f64>>>()?;
+                Some(sample(&|x| {
+                    let at = x * (count - 1) as f64;
+                    let n = (at as usize).min(count - 2);
+                    table[n] + (table[n + 1] - table[n]) * (at - n as f64)
+                }))
+            },
+        },
+        b"para" => {
+            let kind = u16_at(data, 8)?;
+            let params = [1, 3, 4, 5, 7].get(kind as usize)?;
+            let mut p = [1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+            for (n, param) in p.iter_mut().enumerate().take(*params) {
+                *param = fixed_at(data, 12 + n * 4)?;
+            }
+            let [g, a, b, c, d, e, f] = p;
+            Some(sample(&|x| match kind {
+                0 => x.powf(g),
+                1 => if x >= -b / a { (a * x + b).powf(g) } else { 0.0 },
+                2 => if x >= -b / a { (a * x + b).powf(g) + c } else { c },
+                3 => if x >= d { (a * x + b).powf(g) } else { c * x },
+                _ => if x >= d { (a * x + b).powf(g) + e } else { c * x + f },
+            }))
+        },
+        _ => None,
+    }
+}
+
+fn srgb_encode(v: f64) -> f64 {
+    if v <= 0.0031308 { v * 12.92 } else { 1.055 * v.powf(1.0 / 2.4) - 0.055 }
+}
+
+#[cfg(test)]
+mod tests {
+    use png::{self, HasParameters};
+    use animated::crc32;
+    use super::{embedded, Profile};
+    use super::super::{read_image, Image, LoadOptions};
+
+    /// sRGB's primaries, adapted to D50 as profiles store them.
+    const SRGB_COLORANTS: [[f64; 3]; 3] = [[0.4361, 0.2225, 0.0139], [0.3851, 0.7169, 0.0971], [0.1431, 0.0606, 0.7141]];
+
+    fn push_u32(buf: &mut Vec<u8>, n: u32) {
+        buf.extend_from_slice(&[(n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8]);
+    }
+
+    /// A matrix and curve RGB profile with the same `curve` tag for every
+    /// channel.
+    fn profile(colorants: [[f64; 3]; 3], curve: &[u8]) -> Vec<u8> {
+        let mut icc = vec![0; 128];
+        icc[16..20].copy_from_slice(b"RGB ");
+        icc[36..40].copy_from_slice(b"acsp");
+        let mut data = Vec::new();
+        let mut tags = Vec::new();
+        for (sig, xyz) in [b"rXYZ", b"gXYZ", b"bXYZ"].iter().zip(&colorants) {
+            let mut tag = b"XYZ \0\0\0\0".to_vec();
+            for &v in xyz {
+                push_u32(&mut tag, (v * 65536.0).round() as i32 as u32);
+            }
+            tags.push((*sig, tag));
+        }
+        for sig in &[b"rTRC", b"gTRC", b"bTRC"] {
+            tags.push((*sig, curve.to_vec()));
+        }
+        push_u32(&mut icc, tags.len() as u32);
+        let start = 132 + tags.len() * 12;
+        for (sig, tag) in &tags {
+            icc.extend_from_slice(&sig[..]);
+            push_u32(&mut icc, (start + data.len()) as u32);
+            push_u32(&mut icc, tag.len() as u32);
+            data.extend_from_slice(tag);
+        }
+        icc.extend(data);
+        icc
+    }
+
+    /// A `curv` tag with a plain gamma.
+    fn gamma(gamma: f64) -> Vec<u8> {
+        let g = (gamma * 256.0) as u16;
+        vec![b'c', b'u', b'r', b'v', 0, 0, 0, 0, 0, 0, 0, 1, (g >> 8) as u8, g as u8]
+    }
+
+    /// The parametric curve sRGB itself uses.
+    fn srgb_curve() -> Vec<u8> {
+        let mut tag = b"para\0\0\0\0\0\x03\0\0".to_vec();
+        for &v in &[2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045] {
+            push_u32(&mut tag, (v * 65536.0f64).round() as u32);
+        }
+        tag
+    }
+
+    #[test]
+    fn test_linear_profile() {
+        let profile = Profile::parse(&profile(SRGB_COLORANTS, &gamma(1.0))).unwrap();
+        let mut img = Image { pixels: vec![[128, 128, 128, 7], [0, 0, 0, 255], [255, 255, 255, 255]], width: 3, height: 1 };
+        profile.to_srgb(&mut img);
+        // Linear light halfway up is lighter than sRGB's 128.
+        assert!(img.pixels[0][0] >= 187 && img.pixels[0][0] <= 189);
+        assert_eq!(img.pixels[0][0], img.pixels[0][2]);
+        assert_eq!(img.pixels[0][3], 7);
+        assert_eq!(&img.pixels[1..], &[[0, 0, 0, 255], [255, 255, 255, 255]]);
+    }
+
+    #[test]
+    fn test_srgb_profile() {
+        let profile = Profile::parse(&profile(SRGB_COLORANTS, &srgb_curve())).unwrap();
+        let pixels: Vec<_> = (0..256).map(|n| [n as u8, (255 - n) as u8, 40, 255]).collect();
+        let mut img = Image { pixels: pixels.clone(), width: 256, height: 1 };
+        profile.to_srgb(&mut img);
+        assert_eq!(img.pixels, pixels);
+    }
+
+    #[test]
+    fn test_wide_gamut() {
+        // Display P3's primaries, adapted to D50.
+        let p3 = [[0.5151, 0.2412, -0.0011], [0.2920, 0.6922, 0.0419], [0.1571, 0.0666, 0.7841]];
+        let profile = Profile::parse(&profile(p3, &srgb_curve())).unwrap();
+        let mut img = Image { pixels: vec![[200, 100, 100, 255], [90, 90, 90, 255]], width: 2, height: 1 };
+        profile.to_srgb(&mut img);
+        // The same numbers are a more saturated red in P3.
+        assert!(img.pixels[0][0] > 200 && img.pixels[0][1] < 100);
+        assert!(img.pixels[1].iter().take(3).all(|&c| (c as i32 - 90).abs() <= 1));
+        assert!(Profile::parse(b"not a profile").is_none());
+    }
+
+    /// A 1x1 RGB PNG with `icc` in an `iCCP` chunk, stored uncompressed.
+    fn tagged_png(rgb: [u8; 3], icc: &[u8]) -> Vec<u8> {
+        let mut png = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut png, 1, 1);
+            encoder.set(png::ColorType::RGB).set(png::BitDepth::Eight);
+            encoder.write_header().unwrap().write_image_data(&rgb).unwrap();
+        }
+        let (mut a, mut b) = (1u32, 0u32);
+        for &byte in icc {
+            a = (a + byte as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        let mut chunk = b"iCCPtest\0\0\x78\x01\x01".to_vec();
+        let len = icc.len() as u16;
+        chunk.extend_from_slice(&[len as u8, (len >> 8) as u8, !len as u8, (!len >> 8) as u8]);
+        chunk.extend_from_slice(icc);
+        push_u32(&mut chunk, b << 16 | a);
+        let mut spliced = png[..33].to_vec();
+        push_u32(&mut spliced, chunk.len() as u32 - 4);
+        let crc = crc32(chunk.iter());
+        push_u32(&mut chunk, crc);
+        spliced.extend(chunk);
+        spliced.extend_from_slice(&png[33..]);
+        spliced
+    }
+
+    #[test]
+    fn test_tagged_png() {
+        let icc = profile(SRGB_COLORANTS, &gamma(1.0));
+        let png = tagged_png([128, 0, 255], &icc);
+        assert_eq!(embedded(&png), Some(icc));
+        let img = read_image(&png[..], &LoadOptions::default()).unwrap();
+        assert!(img.pixels[0][0] > 180 && img.pixels[0][1] == 0 && img.pixels[0][2] == 255);
+        let options = LoadOptions { color_profiles: false, ..LoadOptions::default() };
+        assert_eq!(read_image(&png[..], &options).unwrap().pixels, vec![[128, 0, 255, 255]]);
+    }
+
+    #[test]
+    fn test_embedded() {
+        let icc = profile(SRGB_COLORANTS, &gamma(1.8));
+        let mut jpeg = vec![0xFF, 0xD8];
+        for (n, chunk) in icc.chunks(100).enumerate() {
+            let len = 2 + 14 + chunk.len();
+            jpeg.extend_from_slice(&[0xFF, 0xE2, (len >> 8) as u8, len as u8]);
+            jpeg.extend_from_slice(b"ICC_PROFILE\0");
+            jpeg.extend_from_slice(&[n as u8 + 1, icc.chunks(100).count() as u8]);
+            jpeg.extend_from_slice(chunk);
+        }
+        jpeg.extend_from_slice(&[0xFF, 0xDA]);
+        assert_eq!(embedded(&jpeg), Some(icc));
+        assert_eq!(embedded(&[0xFF, 0xD8, 0xFF, 0xDA]), None);
+        assert_eq!(embedded(b"BM"), None);
+    }
+}
@@ -0,0 +1,190 @@
+//! Decoding paletted PNGs and Gifs without expanding them to RGBA, so that
+//! frames which already share a palette can be written out as they are.
+
+use gif::{self, ColorOutput};
+use png::{self, HasParameters};
+use super::{Error, Image};
+
+/// An image made of indices into its own palette, as loaded by
+/// `load_indexed_image`.
+#[derive(Debug, Eq, PartialEq, Clone, Hash)]
+pub struct IndexedImage {
+    /// One index per pixel, row by row.
+    pub indices: Vec<u8>,
+    /// Three bytes (red, green, blue) per color.
+    pub palette: Vec<u8>,
+    /// The index of the palette's fully transparent color, if it has one.
+    pub transparent: Option<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl IndexedImage {
+    /// Whether `other` uses exactly the same palette and transparent index,
+    /// so that its indices mean the same colors.
+    pub fn same_palette(&self, other: &IndexedImage) -> bool {
+        self.palette == other.palette && self.transparent == other.transparent
+    }
+
+    /// Expands the indices to RGBA pixels. Indices past the end of the
+    /// palette are black.
+    pub fn to_image(&self) -> Image {
+        let pixels = self.indices.iter().map(|&idx| {
+            if Some(idx) == self.transparent {
+                return [0, 0, 0, 0];
+            }
+            let at = idx as usize * 3;
+            match self.palette.get(at..at + 3) {
+                Some(rgb) => [rgb[0], rgb[1], rgb[2], 255],
+                None => [0, 0, 0, 255],
+            }
+        }).collect();
+        Image { pixels, width: self.width, height: self.height }
+    }
+}
+
+/// Decodes `bytes` if they're a paletted PNG or a Gif that a Gif frame can
+/// hold as it is, or returns `None` if the image needs expanding to RGBA
+/// first.
+pub fn decode(bytes: &[u8]) -> Option<Result<IndexedImage, Error>> {
+    if bytes.starts_with(&[137, 80, 78, 71, 13, 10, 26, 10]) {
+        decode_png(bytes)
+    } else if bytes.starts_with(b"GIF8") {
+        decode_gif(bytes)
+    } else {
+        None
+    }
+}
+
+fn decode_png(bytes: &[u8]) -> Option<Result<IndexedImage, Error>> {
+    // The IHDR chunk always comes first, so the color type is always at the
+    // same offset.
+    if bytes.get(25) != Some(&3) {
+        return None;
+    }
+    let mut decoder = png::Decoder::new(bytes);
+    decoder.set(png::TRANSFORM_IDENTITY);
+    let mut reader = match decoder.read_info() {
+        Ok((_, reader)) => reader,
+        Err(e) => return Some(Err(e.into())),
+    };
+    let (width, height, bits, interlaced, palette, trns) = {
+        let info = reader.info();
+        (info.width, info.height, info.bit_depth as usize, info.interlaced, info.palette.clone(), info.trns.clone())
+    };
+    // Gifs only have one transparent index, and nothing partially
+    // transparent.
+    let transparent = match trns {
+        Some(ref alphas) => {
+            let mut translucent = alphas.iter().enumerate().filter(|&(_, &a)| a < 255);
+            match (translucent.next(), translucent.next()) {
+                (None, _) => None,
+                (Some((idx, &0)), None) => Some(idx as u8),
+                _ => return None,
+            }
+        },
+        None => None,
+    };
+    // The decoder doesn't place packed pixels of interlaced passes.
+    if interlaced && bits < 8 {
+        return None;
+    }
+    let palette = palette.unwrap_or_default();
+    let mut buf = vec![0; reader.output_buffer_size()];
+    if let Err(e) = reader.next_frame(&mut buf) {
+        return Some(Err(e.into()));
+    }
+
+    let stride = (width as usize * bits).div_ceil(8);
+    let mut indices = Vec::with_capacity(width as usize * height as usize);
+    for row in buf.chunks(stride.max(1)).take(height as usize) {
+        for x in 0..width as usize {
+            let bit = x * bits;
+            let shift = 8 - bits - bit % 8;
+            indices.push((row[bit / 8] >> shift) & ((1u16 << bits) - 1) as u8);
+        }
+    }
+    Some(Ok(IndexedImage { indices, palette, transparent, width, height }))
+}
+
+/// Decodes a Gif's first frame, if it covers the whole canvas.
+fn decode_gif(bytes: &[u8]) -> Option<Result<IndexedImage, Error>> {
+    let mut decoder = gif::Decoder::new(bytes);
+    // Both crates' decoders have a `set` method.
+    gif::SetParameter::set(&mut decoder, ColorOutput::Indexed);
+    let mut reader = match decoder.read_info() {
+        Ok(reader) => reader,
+        Err(e) => return Some(Err(e.into())),
+    };
+    let (width, height) = (reader.width(), reader.height());
+    let global = reader.global_palette().map(|palette| palette.to_vec());
+    let frame = match reader.read_next_frame() {
+        Ok(Some(frame)) => frame,
+        Ok(None) => return None,
+        Err(e) => return Some(Err(e.into())),
+    };
+    if (frame.left, frame.top, frame.width, frame.height) != (0, 0, width, height) {
+        return None;
+    }
+    Some(Ok(IndexedImage {
+        indices: frame.buffer.to_vec(),
+        palette: frame.palette.clone().or(global).unwrap_or_default(),
+        transparent: frame.transparent,
+        width: width as u32,
+        height: height as u32,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use png::{self, HasParameters};
+    use super::{decode, IndexedImage};
+    use super::super::{Frame, Gif};
+
+    /// A paletted PNG with `bits` bits per index.
+    fn png(width: u32, height: u32, bits: png::BitDepth, data: &[u8], trns: Option<&[u8]>) -> Vec<u8> {
+        let mut out = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut out, width, height);
+            encoder.set(png::ColorType::Indexed).set(bits);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_chunk(*b"PLTE", &[255, 0, 0, 0, 255, 0, 0, 0, 255, 9, 9, 9]).unwrap();
+            if let Some(trns) = trns {
+                writer.write_chunk(*b"tRNS", trns).unwrap();
+            }
+            writer.write_image_data(data).unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn test_decode_png() {
+        // Two rows of three 2 bit indices: 0 1 2, then 3 2 1.
+        let bytes = png(3, 2, png::BitDepth::Two, &[0b0001_1000, 0b1110_0100], Some(&[255, 0, 255]));
+        let img = decode(&bytes).unwrap().unwrap();
+        assert_eq!(img.indices, vec![0, 1, 2, 3, 2, 1]);
+        assert_eq!(img.transparent, Some(1));
+        assert_eq!(img.to_image().pixels[..2], [[255, 0, 0, 255], [0, 0, 0, 0]]);
+
+        // Partial transparency needs expanding.
+        let bytes = png(1, 1, png::BitDepth::Eight, &[0], Some(&[128]));
+        assert!(decode(&bytes).is_none());
+        assert!(decode(b"BM").is_none());
+    }
+
+    #[test]
+    fn test_decode_gif() {
+        let gif = Gif { palette: vec![1, 2, 3, 4, 5, 6], width: 2, height: 1, frames: vec![Frame::new(vec![1, 0], 2, 1)] };
+        let img = decode(&gif.to_bytes().unwrap()).unwrap().unwrap();
+        assert_eq!(img.indices, vec![1, 0]);
+        assert_eq!(&img.palette[..6], &gif.palette[..]);
+        assert_eq!(img.to_image().pixels, vec![[4, 5, 6, 255], [1, 2, 3, 255]]);
+    }
+
+    #[test]
+    fn test_same_palette() {
+        let img = IndexedImage { indices: vec![0], palette: vec![1, 2, 3], transparent: None, width: 1, height: 1 };
+        assert!(img.same_palette(&IndexedImage { indices: vec![1], ..img.clone() }));
+        assert!(!img.same_palette(&IndexedImage { transparent: Some(0), ..img.clone() }));
+    }
+}
@@ -0,0 +1,134 @@
+//! Combining several sequences into one, frame by frame.
+
+use std::borrow::Cow;
+use super::{Error, Image};
+#[cfg(feature = "text")] use text::{self, Caption};
+
+/// Lays out equal-length sequences in a grid, `columns` cells wide, filling
+/// rows left to right. Cells are `gap` pixels apart, and the gaps and any
+/// empty cells in the last row are filled with `fill`.
+pub fn grid(sequences: &[&[Image]], columns: usize, gap: u32, fill: [u8; 4]) -> Result<Vec<Image>, Error> {
+    let first = match sequences.iter().find_map(|s| s.first()) {
+        Some(img) => img,
+        None => return Err(Error::NoImages),
+    };
+    let (cell_width, cell_height) = (first.width, first.height);
+    let frames = sequences[0].len();
+    for sequence in sequences {
+        if sequence.len() != frames {
+            return Err(Error::Length(frames, sequence.len()));
+        }
+        if let Some(img) = sequence.iter().find(|img| (img.width, img.height) != (cell_width, cell_height)) {
+            return Err(Error::Mismatch((cell_width, cell_height), (img.width, img.height)));
+        }
+    }
+
+    let columns = columns.max(1).min(sequences.len());
+    let rows = sequences.len().div_ceil(columns);
+    let width = cell_width * columns as u32 + gap * (columns as u32 - 1);
+    let height = cell_height * rows as u32 + gap * (rows as u32 - 1);
+    let out = (0..frames).map(|n| {
+        let mut pixels = vec![fill; (width * height) as usize];
+        for (cell, sequence) in sequences.iter().enumerate() {
+            let left = (cell % columns) as u32 * (cell_width + gap);
+            let top = (cell / columns) as u32 * (cell_height + gap);
+            for (y, row) in sequence[n].pixels.chunks(cell_width as usize).enumerate() {
+                let start = ((top + y as u32) * width + left) as usize;
+                pixels[start..start + row.len()].copy_from_slice(row);
+            }
+        }
+        Image { pixels, width, height }
+    }).collect();
+    Ok(out)
+}
+
+/// How `engiffen_side_by_side` lays out its two sequences.
+#[derive(Debug, Eq, PartialEq, Clone, Hash)]
+pub struct SideBySide {
+    /// Width in pixels of the bar between the two sequences.
+    pub separator: u32,
+    pub separator_color: [u8; 4],
+    /// Text drawn at the top of the left and right sequences, like "before"
+    /// and "after". Needs the `text` feature.
+    #[cfg(feature = "text")]
+    pub labels: Option<(String, String)>,
+}
+
+impl Default for SideBySide {
+    fn default() -> SideBySide {
+        SideBySide {
+            separator: 2,
+            separator_color: [255, 255, 255, 255],
+            #[cfg(feature = "text")]
+            labels: None,
+        }
+    }
+}
+
+/// Puts two equal-length sequences next to each other.
+pub fn side_by_side(left: &[Image], right: &[Image], style: &SideBySide) -> Result<Vec<Image>, Error> {
+    let (left, right) = (Cow::Borrowed(left), Cow::Borrowed(right));
+    #[cfg(feature = "text")]
+    let (left, right) = match style.labels {
+        Some((ref left_label, ref right_label)) => {
+            let label = |text: &str| vec![Caption { text: text.to_string(), position: ::Position::Top, ..Caption::default() }];
+            (text::apply(left, &label(left_label)), text::apply(right, &label(right_label)))
+        },
+        None => (left, right),
+    };
+    grid(&[&left, &right], 2, style.separator, style.separator_color)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{grid, side_by_side, SideBySide};
+    use super::super::{Error, Image};
+
+    fn frames(values: &[u8]) -> Vec<Image> {
+        values.iter().map(|&v| Image { pixels: vec![[v; 4]], width: 1, height: 1 }).collect()
+    }
+
+    #[test]
+    fn test_grid() {
+        let (a, b, c) = (frames(&[1, 2]), frames(&[3, 4]), frames(&[5, 6]));
+        let out = grid(&[&a, &b, &c], 2, 1, [9; 4]).unwrap();
+        assert_eq!(out.len(), 2);
+        assert_eq!((out[0].width, out[0].height), (3, 3));
+        let values: Vec<_> = out[1].pixels.iter().map(|px| px[0]).collect();
+        assert_eq!(values, vec![2, 9, 4, 9, 9, 9, 6, 9, 9]);
+    }
+
+    #[test]
+    fn test_grid_length_mismatch() {
+        let (a, b) = (frames(&[1, 2]), frames(&[3]));
+        match grid(&[&a, &b], 2, 0, [0; 4]) {
+            Err(Error::Length(expected, actual)) => assert_eq!((expected, actual), (2, 1)),
+            _ => panic!("Sequences of different lengths should be an error"),
+        }
+    }
+
+    #[test]
+    fn test_side_by_side() {
+        let (a, b) = (frames(&[1, 2]), frames(&[3, 4]));
+        let style = SideBySide { separator_color: [7; 4], ..SideBySide::default() };
+        let out = side_by_side(&a, &b, &style).unwrap();
+        let values: Vec<_> = out[0].pixels.iter().map(|px| px[0]).collect();
+        assert_eq!(values, vec![1, 7, 7, 3]);
+    }
+
+    #[test]
+    #[cfg(feature = "text")]
+    fn test_side_by_side_labels() {
+        use fixtures::solid;
+        let blank = vec![solid(20, 20, [0, 0, 0, 255])];
+        let mut style = SideBySide::default();
+        let plain = side_by_side(&blank, &blank, &style).unwrap();
+        style.labels = Some(("a".to_string(), "b".to_string()));
+        let labeled = side_by_side(&blank, &blank, &style).unwrap();
+        assert!(plain[0] != labeled[0]);
+        let cell = |img: &Image, left: usize| -> Vec<[u8; 4]> {
+            img.pixels.chunks(42).flat_map(|row| row[left..left + 20].to_vec()).collect()
+        };
+        assert!(cell(&labeled[0], 0) != cell(&labeled[0], 22));
+    }
+}
@@ -10,27 +10,117 @@ extern crate gif;
 extern crate color_quant;
 extern crate lab;
 extern crate rayon;
+extern crate png;
 extern crate fnv;
+#[cfg(feature = "apng")] extern crate deflate;
+#[cfg(all(feature = "mmap", unix))] extern crate libc;
+#[cfg(feature = "globbing")] extern crate glob;
+#[cfg(any(feature = "archives", feature = "hdr", feature = "icc"))] extern crate inflate;
 
 use std::io;
-use std::{error, fmt, f32};
+#[cfg(feature = "fs")] use std::fs::File;
+use std::{cmp, error, fmt, f32, mem};
 use std::borrow::Cow;
-use std::path::Path;
-use image::GenericImage;
-use gif::{Frame, Encoder, Repeat, SetParameter};
+use std::hash::{Hash, Hasher};
+use std::ops::{Index, IndexMut, RangeBounds};
+#[cfg(feature = "fs")] use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use image::{DynamicImage, GenericImage, ImageBuffer, Pixel, RgbaImage};
+use gif::{Encoder, Repeat, SetParameter};
 use color_quant::NeuQuant;
 use lab::Lab;
 use rayon::prelude::*;
-use fnv::FnvHashMap;
+use fnv::{FnvHashMap, FnvHashSet, FnvHasher};
 
-#[cfg(feature = "debug-stderr")] use std::time::{Instant};
+mod alpha;
+mod animated;
+#[cfg(feature = "archives")] mod archive;
+mod budget;
+mod builder;
+mod cancel;
+#[cfg(feature = "capture")] mod capture;
+#[cfg(feature = "capi")] mod capi;
+#[cfg(feature = "apng")] mod apng;
+mod color;
+#[cfg(feature = "fs")] mod config;
+mod decode;
+mod depth;
+mod diff;
+mod effort;
+mod encode;
+mod encoder;
+mod exif;
+#[cfg(test)] #[allow(dead_code)] mod fixtures;
+#[cfg(feature = "hdr")] mod exr;
+mod frame;
+#[cfg(feature = "icc")] mod icc;
+#[cfg(feature = "hdr")] mod hdr;
+mod indexed;
+mod layout;
+mod load;
+mod natural;
+#[cfg(feature = "fs")] mod order;
+#[cfg(all(feature = "mmap", unix))] mod mmap;
+mod overlay;
+mod pool;
+mod quality;
+mod raw;
+mod resume;
+mod sequence;
+mod spill;
+mod sprite;
+mod stats;
+#[cfg(feature = "text")] mod text;
+mod timing;
+mod transform;
+#[cfg(feature = "ffmpeg")] mod video;
+#[cfg(feature = "y4m")] mod y4m;
+
+#[cfg(feature = "apng")] pub use apng::Apng;
+pub use builder::GifBuilder;
+pub use budget::MemoryEstimate;
+pub use cancel::CancelToken;
+#[cfg(feature = "fs")] pub use config::Recipe;
+pub use diff::FrameDiff;
+pub use effort::Effort;
+pub use encode::{engiffen, engiffen_animation, engiffen_borrowed, engiffen_generated, engiffen_generic, engiffen_grid, engiffen_indexed, engiffen_iter, engiffen_preview, engiffen_resumable, engiffen_segments, engiffen_side_by_side, engiffen_sizes, engiffen_timestamped, engiffen_with_encoder, engiffen_with_options, engiffen_with_stats, engiffen_write};
+#[cfg(feature = "apng")] pub use encode::engiffen_apng;
+#[cfg(feature = "fs")] pub use encode::{engiffen_files, reprocess_apng, reprocess_gif};
+use encode::{write_streamed, write_with_delays};
+pub use load::{natural_cmp, read_apng, read_gif, read_image, read_indexed_image, read_raw_frames, slice_sprite_sheet, sprite_sheet};
+#[cfg(feature = "fs")] pub use load::{export_sprite_sheet, load_apng, load_gif, load_image, load_image_unoriented, load_image_with, load_images, load_images_from_dir, load_images_from_dir_sorted, load_images_strict, load_indexed_image, load_recipe, load_sprite_sheet, sort_paths};
+#[cfg(feature = "fs")] use load::write_sprite_sheet;
+#[cfg(all(feature = "mmap", unix))] pub use load::{load_image_mapped, load_images_mapped};
+#[cfg(feature = "y4m")] pub use load::read_y4m;
+#[cfg(feature = "ffmpeg")] pub use load::load_video;
+#[cfg(feature = "capture")] pub use load::{capture_screen, record_screen};
+#[cfg(feature = "globbing")] pub use load::load_images_glob;
+#[cfg(feature = "archives")] pub use load::load_images_from_archive;
+pub use encoder::AnimationEncoder;
+#[cfg(feature = "apng")] pub use encoder::ApngEncoder;
+pub use encoder::GifEncoder;
+pub use frame::{Disposal, Frame};
+pub use indexed::IndexedImage;
+pub use layout::SideBySide;
+#[cfg(feature = "fs")] pub use order::SortOrder;
+pub use overlay::{Position, Watermark};
+pub use pool::ThreadPool;
+pub use quality::{Quality, QualityReport};
+pub use raw::{RawFormat, RawFrames};
+#[cfg(feature = "hdr")] pub use hdr::ToneMap;
+#[cfg(feature = "ffmpeg")] pub use video::VideoFrames;
+#[cfg(feature = "y4m")] pub use y4m::Y4mFrames;
+pub use resume::Checkpoint;
+pub use sequence::Transition;
+pub use sprite::SpriteSheet;
+pub use stats::EncodeStats;
+#[cfg(feature = "text")] pub use text::{BurnIn, Caption, Stamp};
+pub use timing::{FrameRate, Timestamp};
+pub use transform::{Crop, Filter, FrameHook, Mismatch, Rotation, Scale};
 
-#[cfg(feature = "debug-stderr")]
-fn ms(duration: Instant) -> u64 {
-    let duration = duration.elapsed();
-    duration.as_secs() * 1000 + duration.subsec_nanos() as u64 / 1000000
-}
 
+#[allow(clippy::upper_case_acronyms)]
 type RGBA = [u8; 4];
 
 /// A color quantizing strategy.
@@ -57,33 +147,495 @@ type RGBA = [u8; 4];
 ///
 /// The `Naive` strategy is fastest when you know that your input images
 /// have a limited color range, but will produce terrible banding otherwise.
+///
+/// The `Grayscale` strategy skips quantization entirely: frames are turned
+/// to grey and mapped onto a fixed ramp of 256 levels (255 if a transparent
+/// index is needed), which is both fast and lossless for monochrome content.
+/// When every frame is already grey, `NeuQuant` uses the same ramp instead
+/// of training on them.
+///
+/// Every strategy is deterministic: the same frames and `Options` always
+/// encode to the same bytes, however many threads do the work. NeuQuant's
+/// "pseudorandom" subset is a fixed stride through the samples rather than
+/// a seeded generator, and colors that `Naive` sees equally often are
+/// ranked by value.
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub enum Quantizer {
     Naive,
     NeuQuant(u32),
+    Grayscale,
+}
+
+/// A key color that is turned into transparency, for sources that can only
+/// render against a solid (e.g. green screen) background.
+///
+/// A pixel is keyed out when each of its red, green and blue channels is
+/// within `tolerance` of the key `color`'s.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct ChromaKey {
+    pub color: [u8; 3],
+    pub tolerance: u8,
+}
+
+/// Optional settings that control how `engiffen_with_options` builds a Gif.
+///
+/// Construct it with struct update syntax to only override the settings you
+/// care about.
+///
+/// ```rust
+/// # use engiffen::Options;
+/// let options = Options {
+///     alpha_threshold: 128,
+///     ..Options::default()
+/// };
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct Options {
+    /// Pixels with an alpha value below this threshold are encoded as
+    /// transparent; all others are encoded as opaque. The default of 1 only
+    /// treats fully transparent pixels as transparent, while 128 turns
+    /// anti-aliased edges into clean cutouts instead of opaque halos.
+    pub alpha_threshold: u8,
+    /// Dither the alpha channel with an ordered (Bayer) pattern instead of
+    /// cutting it off at `alpha_threshold`. Smooth alpha gradients such as
+    /// drop shadows then fade out as a stipple rather than a jagged edge.
+    /// The pattern is the same on every frame, so it doesn't flicker.
+    pub alpha_dither: bool,
+    /// Blend the color of partially transparent pixels over this RGB matte
+    /// color, weighted by their alpha. Pick the background color of the page
+    /// the Gif will be shown on, and soft edges that end up opaque will blend
+    /// into it. Alpha itself is still handled by `alpha_threshold` or
+    /// `alpha_dither`.
+    pub matte: Option<[u8; 3]>,
+    /// A static background that every frame is alpha-composited onto. It must
+    /// have the same dimensions as the frames, and is scaled along with them.
+    pub background: Option<Image>,
+    /// Make pixels matching this key color fully transparent. Keying happens
+    /// first, so keyed out areas show the `background` if there is one.
+    pub chroma_key: Option<ChromaKey>,
+    /// Ignore alpha entirely: every pixel is made fully opaque (after the
+    /// `background` and `matte` are applied), no transparent index is
+    /// emitted, and all 256 palette slots are used for colors.
+    pub opaque: bool,
+    /// Added to the red, green and blue channels of every pixel, from -255
+    /// (black) to 255 (white). Useful for dark game footage.
+    pub brightness: i32,
+    /// Stretches (above 1) or squashes (below 1) colors away from or toward
+    /// mid-grey. 1 leaves them as is.
+    pub contrast: f32,
+    /// Gamma correction: values above 1 brighten the midtones and values
+    /// below 1 darken them, without clipping highlights like `brightness`.
+    pub gamma: f32,
+    /// Rotates the hue of every pixel by this many degrees, to make color
+    /// shifted variants of an animation.
+    pub hue_rotate: f32,
+    /// Scales the saturation of every pixel: 0 turns frames grey, values
+    /// above 1 make colors more vivid, and 1 leaves them as is.
+    pub saturation: f32,
+    /// Gifs store delays in hundredths of a second, so most frame rates (like
+    /// 30 fps, 3.33cs per frame) can't be represented exactly. This option
+    /// alternates between the two closest delays (3, 3, 4, 3, 3, 4...) so the
+    /// average playback rate matches the requested one instead of drifting.
+    pub delay_dither: bool,
+    /// Show the first frame for this long instead of the regular frame delay,
+    /// so viewers can take in the initial state of a terminal or UI recording
+    /// before the animation starts.
+    pub first_frame_delay: Option<Duration>,
+    /// Only keep every nth frame, starting with the first, to bring down the
+    /// frame rate (a value of 4 turns a 60 fps capture into 15 fps). Each
+    /// kept frame is shown for the combined delay of the frames it replaces,
+    /// so playback speed doesn't change. Values of 0 and 1 keep every frame.
+    pub keep_every_nth: usize,
+    /// When dropping frames with `keep_every_nth`, average each group of
+    /// frames into the frame that is kept instead of discarding them. Fast
+    /// motion then turns into motion blur rather than stutter.
+    pub motion_blur: bool,
+    /// Synthesize in-between frames by cross-blending neighboring frames, so
+    /// that the output plays at this (higher) frame rate. Smooths out choppy,
+    /// low frame rate sources; the total duration stays the same. Rates
+    /// above 50 frames per second are capped at 50, the fastest browsers
+    /// show.
+    pub interpolate: Option<FrameRate>,
+    /// Merge runs of frames shown for less than 20 ms each, which browsers
+    /// would slow down to 100 ms, into frames they do show on time, so that
+    /// sources above 50 frames per second keep their speed. The first frame
+    /// of each run is kept, or with `motion_blur`, their average.
+    pub merge_fast_frames: bool,
+    /// Crossfade the last this many frames into the first ones, so that the
+    /// point where the animation loops is invisible (cinemagraph style). The
+    /// Gif ends up with that many fewer frames. Limited to half the frames.
+    pub loop_crossfade: usize,
+    /// What to do with frames whose dimensions differ from the first frame's.
+    /// By default this is an `Error::Mismatch`, but frames can also be resized
+    /// or padded to a common size.
+    pub mismatch: Mismatch,
+    /// Cut every frame down to this region before any scaling, e.g. to trim
+    /// off window chrome.
+    pub crop: Option<Crop>,
+    /// Cut away rows and columns along the edges whose pixels all share the
+    /// color of the top left corner in every frame, like the empty margins of
+    /// a terminal recording. Applied after `crop`.
+    pub auto_trim: bool,
+    /// Turn every frame clockwise, e.g. for phone captures recorded in the
+    /// wrong orientation. Applied after cropping, so `crop` is always in the
+    /// coordinates of the source frames.
+    pub rotation: Option<Rotation>,
+    /// Mirror every frame left to right, after any `rotation`.
+    pub flip_horizontal: bool,
+    /// Mirror every frame top to bottom, after any `rotation`.
+    pub flip_vertical: bool,
+    /// Resize every frame before encoding, e.g. to ship a 4K capture as an
+    /// 800px wide Gif.
+    pub scale: Option<Scale>,
+    /// The resampling filter used by `scale`.
+    pub scale_filter: Filter,
+    /// Blow every pixel up into an n x n block, after any `scale`, so tiny
+    /// sprite animations stay crisp. Values of 0 and 1 leave frames as is.
+    pub integer_scale: u32,
+    /// Shrink frames that end up wider or taller than a Gif can be (65535
+    /// pixels) to fit, keeping their aspect ratio, instead of failing with
+    /// an `Error::DimensionsTooLarge`. Applied after everything else, with
+    /// `scale_filter`.
+    pub downscale_to_fit: bool,
+    /// Arbitrary processing, like annotations or masks, run on each frame
+    /// after every other transform and timing change but before alpha
+    /// handling and quantization.
+    pub map_frames: Option<FrameHook>,
+    /// An image, like a logo, composited onto every frame after scaling.
+    pub watermark: Option<Watermark>,
+    /// Text drawn onto ranges of frames, after scaling so that it stays
+    /// crisp. Needs the `text` feature.
+    #[cfg(feature = "text")]
+    pub captions: Vec<Caption>,
+    /// Stamp the frame number or elapsed time onto frames. Needs the `text`
+    /// feature.
+    #[cfg(feature = "text")]
+    pub burn_in: Option<BurnIn>,
+    /// Checked between stages and frames; once cancelled, encoding stops
+    /// with an `Error::Cancelled`.
+    pub cancel: Option<CancelToken>,
+    /// Where the encode's parallel work runs, instead of rayon's global
+    /// pool.
+    pub thread_pool: Option<ThreadPool>,
+    /// A ceiling, in bytes, on the memory used to compute the palette and
+    /// hold the palettized frames. To stay under it, NeuQuant's sample rate
+    /// is raised, up to 8, and then only some of the frames are sampled. The
+    /// frames themselves, and copies made by transforms, aren't counted.
+    ///
+    /// Palettized frames take a byte per pixel. If they don't fit, encoding
+    /// fails with an `Error::MemoryLimit`; `engiffen_write` and
+    /// `engiffen_files` write each frame as soon as it's palettized, so
+    /// they don't need to hold them.
+    pub max_memory: Option<usize>,
+    /// Fail with an `Error::QuantizationError` if any frame's pixels end up
+    /// further than this from their source once mapped onto the palette,
+    /// as a root mean square over the red, green and blue channels (0 to
+    /// 255), so that automated pipelines don't ship visibly broken Gifs.
+    /// Around 10 is hard to notice. Only the frames' last step, mapping onto
+    /// the palette, is measured, after every transform has been applied.
+    pub max_quantization_error: Option<f32>,
+    /// Label each frame with the path of the image it was loaded from, in
+    /// `engiffen_files`, so the labels end up as comments in the Gif. Other
+    /// functions can't tell where frames came from; label their results
+    /// with `Gif::set_labels`.
+    pub label_frames: bool,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            alpha_threshold: 1,
+            alpha_dither: false,
+            matte: None,
+            background: None,
+            chroma_key: None,
+            opaque: false,
+            brightness: 0,
+            contrast: 1.0,
+            gamma: 1.0,
+            hue_rotate: 0.0,
+            saturation: 1.0,
+            delay_dither: false,
+            first_frame_delay: None,
+            keep_every_nth: 1,
+            motion_blur: false,
+            interpolate: None,
+            merge_fast_frames: false,
+            loop_crossfade: 0,
+            mismatch: Mismatch::Error,
+            crop: None,
+            auto_trim: false,
+            rotation: None,
+            flip_horizontal: false,
+            flip_vertical: false,
+            scale: None,
+            scale_filter: Filter::Lanczos3,
+            integer_scale: 1,
+            downscale_to_fit: false,
+            map_frames: None,
+            watermark: None,
+            #[cfg(feature = "text")]
+            captions: Vec::new(),
+            #[cfg(feature = "text")]
+            burn_in: None,
+            cancel: None,
+            thread_pool: None,
+            max_memory: None,
+            max_quantization_error: None,
+            label_frames: false,
+        }
+    }
 }
 
-/// An image, currently a wrapper around `image::DynamicImage`. If loaded from
-/// disk through the `load_image` or `load_images` functions, its path property
-/// contains the path used to read it from disk.
+/// An image, stored as RGBA pixels row by row from the top left. Images can
+/// be loaded from disk with `load_image` and `load_images`, or converted
+/// from the image crate's `DynamicImage` and `RgbaImage` with `From`.
+///
+/// Images are `Send + Sync`, so frames can be decoded or generated on other
+/// threads.
+#[derive(Clone, Eq, PartialEq)]
 pub struct Image {
     pub pixels: Vec<RGBA>,
     pub width: u32,
     pub height: u32,
 }
 
+impl Image {
+    /// Builds an image from a buffer of RGBA bytes, row by row from the top
+    /// left, like frames rendered in memory by a game engine or plotting
+    /// library. Returns `None` if the buffer isn't exactly `width * height * 4`
+    /// bytes long.
+    ///
+    /// ```rust
+    /// # use engiffen::Image;
+    /// let rgba = vec![255, 0, 0, 255, 0, 0, 255, 255];
+    /// let img = Image::from_raw(2, 1, &rgba).unwrap();
+    /// assert_eq!(img.pixels, vec![[255, 0, 0, 255], [0, 0, 255, 255]]);
+    /// assert!(Image::from_raw(3, 1, &rgba).is_none());
+    /// assert!(Image::from_raw(u32::MAX, u32::MAX, &[]).is_none());
+    /// ```
+    pub fn from_raw(width: u32, height: u32, rgba: &[u8]) -> Option<Image> {
+        Image::from_bytes(width, height, rgba, [0, 1, 2, 3])
+    }
+
+    /// Like `from_raw`, for buffers in BGRA order, as handed back by most
+    /// window capture and GPU readback APIs.
+    ///
+    /// ```rust
+    /// # use engiffen::Image;
+    /// let img = Image::from_bgra(1, 1, &[3, 2, 1, 4]).unwrap();
+    /// assert_eq!(img.pixels, vec![[1, 2, 3, 4]]);
+    /// ```
+    pub fn from_bgra(width: u32, height: u32, bgra: &[u8]) -> Option<Image> {
+        Image::from_bytes(width, height, bgra, [2, 1, 0, 3])
+    }
+
+    /// Like `from_raw`, for buffers in ARGB order.
+    ///
+    /// ```rust
+    /// # use engiffen::Image;
+    /// let img = Image::from_argb(1, 1, &[4, 1, 2, 3]).unwrap();
+    /// assert_eq!(img.pixels, vec![[1, 2, 3, 4]]);
+    /// ```
+    pub fn from_argb(width: u32, height: u32, argb: &[u8]) -> Option<Image> {
+        Image::from_bytes(width, height, argb, [1, 2, 3, 0])
+    }
+
+    /// Builds an image from 4 byte pixels, taking red, green, blue and alpha
+    /// from the positions in `order`.
+    fn from_bytes(width: u32, height: u32, bytes: &[u8], order: [usize; 4]) -> Option<Image> {
+        let len = (width as u64).checked_mul(height as u64).and_then(|n| n.checked_mul(4));
+        if len != Some(bytes.len() as u64) {
+            return None;
+        }
+        let pixels = bytes.chunks_exact(4).map(|c| [c[order[0]], c[order[1]], c[order[2]], c[order[3]]]).collect();
+        Some(Image { pixels, width, height })
+    }
+
+    /// Copies any of the image crate's images, such as an `ImageBuffer` of any
+    /// 8-bit pixel type or a `SubImage`, into an `Image`.
+    ///
+    /// ```rust
+    /// # extern crate engiffen;
+    /// # extern crate image;
+    /// # use engiffen::Image;
+    /// # fn main() {
+    /// let gray = image::GrayImage::from_pixel(2, 2, image::Luma([80]));
+    /// let img = Image::from_generic(&gray);
+    /// assert_eq!(img.pixels, vec![[80, 80, 80, 255]; 4]);
+    /// # }
+    /// ```
+    pub fn from_generic<I>(img: &I) -> Image
+        where I: GenericImage, I::Pixel: Pixel<Subpixel = u8>
+    {
+        let (width, height) = img.dimensions();
+        let pixels = img.pixels().map(|(_, _, px)| px.to_rgba().data).collect();
+        Image { pixels, width, height }
+    }
+
+    /// Copies the image into an `image::RgbaImage`, for further processing
+    /// with the image crate.
+    pub fn to_rgba_image(&self) -> RgbaImage {
+        let raw = self.pixels.iter().flat_map(|px| px.iter().cloned()).collect();
+        ImageBuffer::from_raw(self.width, self.height, raw)
+            .expect("An image's pixel count doesn't match its dimensions")
+    }
+}
+
+impl From<RgbaImage> for Image {
+    fn from(buf: RgbaImage) -> Image {
+        let (width, height) = buf.dimensions();
+        let pixels = buf.into_raw().chunks(4).map(|c| [c[0], c[1], c[2], c[3]]).collect();
+        Image { pixels, width, height }
+    }
+}
+
+impl From<DynamicImage> for Image {
+    fn from(img: DynamicImage) -> Image {
+        Image::from(img.to_rgba())
+    }
+}
+
+impl From<Image> for RgbaImage {
+    fn from(img: Image) -> RgbaImage {
+        img.to_rgba_image()
+    }
+}
+
+/// How images are decoded by `load_image_with`.
+///
+/// # Examples
+///
+/// ```rust
+/// # use engiffen::LoadOptions;
+/// let options = LoadOptions {
+///     orient: false,
+///     ..LoadOptions::default()
+/// };
+/// ```
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct LoadOptions {
+    /// Turn JPEGs upright as their EXIF orientation tag says. On by default.
+    pub orient: bool,
+    /// PNGs and TIFFs with 16 bits per channel are brought down to 8 bits by
+    /// rounding each sample. With this set, color channels are dithered
+    /// instead, which keeps smooth gradients in scientific renders from
+    /// banding.
+    pub dither_16_bit: bool,
+    /// How Radiance HDR and OpenEXR images are tone mapped to displayable
+    /// colors.
+    #[cfg(feature = "hdr")]
+    pub tone_map: ToneMap,
+    /// Convert PNGs and JPEGs with an embedded ICC color profile, like
+    /// Display P3 screenshots, to sRGB, so that their colors match frames
+    /// without one. On by default when the `icc` feature is enabled.
+    #[cfg(feature = "icc")]
+    pub color_profiles: bool,
+}
+
+impl Default for LoadOptions {
+    fn default() -> LoadOptions {
+        LoadOptions {
+            orient: true,
+            dither_16_bit: false,
+            #[cfg(feature = "hdr")]
+            tone_map: ToneMap::default(),
+            #[cfg(feature = "icc")]
+            color_profiles: true,
+        }
+    }
+}
+
+/// A frame whose pixels are borrowed rather than owned, so that frames
+/// already in memory can be engiffened with `engiffen_borrowed` without
+/// copying them. Pixels are RGBA, row by row from the top left.
+///
+/// Byte buffers can be viewed as pixels with a crate like bytemuck, e.g.
+/// `bytemuck::cast_slice::<u8, [u8; 4]>(&buffer)`.
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash)]
+pub struct FrameRef<'a> {
+    pub pixels: &'a [RGBA],
+    pub width: u32,
+    pub height: u32,
+}
+
+impl<'a> FrameRef<'a> {
+    /// Copies the frame into an owned `Image`.
+    pub fn to_image(&self) -> Image {
+        Image { pixels: self.pixels.to_vec(), width: self.width, height: self.height }
+    }
+}
+
+impl<'a> From<&'a Image> for FrameRef<'a> {
+    fn from(img: &'a Image) -> FrameRef<'a> {
+        FrameRef { pixels: &img.pixels, width: img.width, height: img.height }
+    }
+}
+
+/// What the quantizers need from a frame, so they can read owned and
+/// borrowed frames alike.
+trait Pixels: Sync {
+    fn pixels(&self) -> &[RGBA];
+    fn width(&self) -> u32;
+    fn height(&self) -> u32;
+}
+
+impl Pixels for Image {
+    fn pixels(&self) -> &[RGBA] { &self.pixels }
+    fn width(&self) -> u32 { self.width }
+    fn height(&self) -> u32 { self.height }
+}
+
+impl<'a> Pixels for FrameRef<'a> {
+    fn pixels(&self) -> &[RGBA] { self.pixels }
+    fn width(&self) -> u32 { self.width }
+    fn height(&self) -> u32 { self.height }
+}
+
 impl fmt::Debug for Image {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Image {{ dimensions: {} x {} }}", self.width, self.height)
     }
 }
 
+/// Everything that can go wrong while loading, encoding or writing. More
+/// variants may be added, so matches need a catch-all arm.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     NoImages,
     Mismatch((u32, u32), (u32, u32)),
+    Length(usize, usize),
     ImageLoad(image::ImageError),
     ImageWrite(io::Error),
+    Invalid(String),
+    Cancelled,
+    /// Encoding needs at least this many bytes, more than
+    /// `Options::max_memory` allows.
+    MemoryLimit(usize),
+    /// A config file couldn't be read, on this line (or 0 for a problem
+    /// with the file as a whole), for this reason.
+    Config(usize, String),
+    /// A `ThreadPool`'s threads couldn't be started, for this reason.
+    ThreadPool(String),
+    /// The gif crate refused to encode the frames, for this reason. Failing
+    /// to write the encoded bytes is an `ImageWrite` instead.
+    Encode(String),
+    /// Frames of this width and height are too large for a Gif, which can
+    /// be at most 65535 pixels on a side. See `Options::downscale_to_fit`.
+    DimensionsTooLarge(u32, u32),
+    /// The named field of `Options`, `SpriteSheet` or `IndexedImage`, the
+    /// quantizer, or the frame rate (`"fps"`) is out of range, for this
+    /// reason.
+    InvalidOption(&'static str, String),
+    /// The frame at this index was this far from its source once mapped
+    /// onto the palette, over `Options::max_quantization_error`.
+    QuantizationError(usize, f32),
+    /// Images in this format, like WebP, or OpenEXR without the `hdr`
+    /// feature, can't be decoded by this build.
+    Unsupported(&'static str),
+    #[cfg(feature = "globbing")]
+    Pattern(glob::PatternError),
 }
 
 impl From<image::ImageError> for Error {
@@ -103,8 +655,22 @@ impl fmt::Display for Error {
         match *self {
             Error::NoImages => write!(f, "No frames sent for engiffening"),
             Error::Mismatch(_, _) => write!(f, "Frames don't have the same dimensions"),
+            Error::Length(expected, actual) => write!(f, "Sequences don't have the same number of frames ({} and {})", expected, actual),
             Error::ImageLoad(ref e) => write!(f, "Image load error: {}", e),
             Error::ImageWrite(ref e) => write!(f, "Image write error: {}", e),
+            Error::Invalid(ref reason) => write!(f, "Invalid Gif: {}", reason),
+            Error::Cancelled => write!(f, "Engiffening was cancelled"),
+            Error::MemoryLimit(needed) => write!(f, "Engiffening needs at least {} bytes, over the memory limit", needed),
+            Error::Config(0, ref reason) => write!(f, "Bad config: {}", reason),
+            Error::Config(line, ref reason) => write!(f, "Bad config on line {}: {}", line, reason),
+            Error::ThreadPool(ref reason) => write!(f, "Couldn't start a thread pool: {}", reason),
+            Error::Encode(ref reason) => write!(f, "Couldn't encode the Gif: {}", reason),
+            Error::DimensionsTooLarge(width, height) => write!(f, "Frames of {}x{} are too large for a Gif, which can be at most 65535 pixels on a side", width, height),
+            Error::InvalidOption(name, ref reason) => write!(f, "Invalid option {}: {}", name, reason),
+            Error::QuantizationError(n, error) => write!(f, "Frame {} is {:.1} off its source once quantized, over the limit", n, error),
+            Error::Unsupported(format) => write!(f, "{} images aren't supported", format),
+            #[cfg(feature = "globbing")]
+            Error::Pattern(ref e) => write!(f, "Bad glob pattern: {}", e),
         }
     }
 }
@@ -114,36 +680,114 @@ impl error::Error for Error {
         match *self {
             Error::NoImages => "No frames sent for engiffening",
             Error::Mismatch(_, _) => "Frames don't have the same dimensions",
+            Error::Length(_, _) => "Sequences don't have the same number of frames",
             Error::ImageLoad(_) => "Unable to load image",
             Error::ImageWrite(_) => "Unable to write image",
+            Error::Invalid(_) => "Invalid Gif",
+            Error::Cancelled => "Engiffening was cancelled",
+            Error::MemoryLimit(_) => "Engiffening needs more memory than allowed",
+            Error::Config(_, _) => "Bad config file",
+            Error::ThreadPool(_) => "Couldn't start a thread pool",
+            Error::Encode(_) => "Couldn't encode the Gif",
+            Error::DimensionsTooLarge(_, _) => "Frames are too large for a Gif",
+            Error::InvalidOption(_, _) => "Invalid option",
+            Error::QuantizationError(_, _) => "Quantization error over the limit",
+            Error::Unsupported(_) => "Unsupported image format",
+            #[cfg(feature = "globbing")]
+            Error::Pattern(_) => "Bad glob pattern",
+        }
+    }
+
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::ImageLoad(ref e) => Some(e),
+            Error::ImageWrite(ref e) => Some(e),
+            #[cfg(feature = "globbing")]
+            Error::Pattern(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Error {
+    /// Sorts an error from the gif crate's encoder: it reports frames it
+    /// can't encode as invalid input, and anything else comes from the
+    /// writer underneath.
+    fn encoding(err: io::Error) -> Error {
+        match err.kind() {
+            io::ErrorKind::InvalidInput => Error::Encode(err.to_string()),
+            _ => Error::ImageWrite(err),
         }
     }
 }
 
 /// Struct representing an animated Gif
+///
+/// Every frame's indices refer to the one shared `palette`, which holds
+/// three bytes (red, green, blue) per color.
+///
+/// Gifs are `Send + Sync`. Frames share their indices through an `Arc`, so
+/// a clone of a Gif, or frames repeated by `ping_pong`, don't copy them.
 #[derive(Eq, PartialEq, Clone, Hash)]
 pub struct Gif {
     pub palette: Vec<u8>,
-    pub transparency: Option<u8>,
     pub width: u16,
     pub height: u16,
-    pub images: Vec<Vec<u8>>,
-    pub delay: u16,
+    pub frames: Vec<Frame>,
 }
 
 impl fmt::Debug for Gif {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Gif {{ palette: Vec<u8 x {:?}>, transparency: {:?}, width: {:?}, height: {:?}, images: Vec<Vec<u8> x {:?}>, delay: {:?} }}",
+        write!(f, "Gif {{ palette: Vec<u8 x {:?}>, width: {:?}, height: {:?}, frames: Vec<Frame x {:?}> }}",
             self.palette.len(),
-            self.transparency,
             self.width,
             self.height,
-            self.images.len(),
-            self.delay
+            self.frames.len()
         )
     }
 }
 
+impl Index<usize> for Gif {
+    type Output = Frame;
+
+    fn index(&self, n: usize) -> &Frame {
+        &self.frames[n]
+    }
+}
+
+impl IndexMut<usize> for Gif {
+    fn index_mut(&mut self, n: usize) -> &mut Frame {
+        &mut self.frames[n]
+    }
+}
+
+impl IntoIterator for Gif {
+    type Item = Frame;
+    type IntoIter = std::vec::IntoIter<Frame>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.frames.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Gif {
+    type Item = &'a Frame;
+    type IntoIter = std::slice::Iter<'a, Frame>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.frames.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut Gif {
+    type Item = &'a mut Frame;
+    type IntoIter = std::slice::IterMut<'a, Frame>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.frames.iter_mut()
+    }
+}
+
 impl Gif {
     /// Writes the animated Gif to any output that implements Write.
     ///
@@ -161,268 +805,2162 @@ impl Gif {
     /// # }
     /// ```
     ///
+    /// With the `parallel-write` feature, frames are compressed on the rayon
+    /// pool `write` is called from, which is the global one unless it's run
+    /// inside `ThreadPool::install`.
+    ///
     /// # Errors
     ///
     /// Returns the `std::io::Result` of the underlying `write` function calls.
-    pub fn write<W: io::Write>(&self, mut out: &mut W) -> Result<(), Error> {
-        let mut encoder = Encoder::new(&mut out, self.width, self.height, &self.palette)?;
-        encoder.set(Repeat::Infinite)?;
-        for img in &self.images {
-            let mut frame = Frame::default();
-            frame.delay = self.delay / 10;
-            frame.width = self.width;
-            frame.height = self.height;
-            frame.buffer = Cow::Borrowed(&*img);
-            frame.transparent = self.transparency;
-            encoder.write_frame(&frame)?;
-        }
-        Ok(())
+    pub fn write<W: io::Write>(&self, out: &mut W) -> Result<(), Error> {
+        #[cfg(feature = "parallel-write")]
+        return write_frames_parallel(out, self.width, self.height, &self.palette, &self.frames);
+        #[cfg(not(feature = "parallel-write"))]
+        write_frames(out, self.width, self.height, &self.palette, self.frames.iter().map(|frame| Ok(Cow::Borrowed(frame))))
     }
-}
-
-/// Loads an image from the given file path.
-///
-/// # Examples
-///
-/// ```rust,no_run
-/// # use engiffen::{load_image, Image, Error};
-/// # use std::path::PathBuf;
-/// # fn foo() -> Result<Image, Error> {
-/// let image = load_image("test/ball/ball01.bmp")?;
-/// # Ok(image)
-/// # }
-/// ```
-///
-/// # Errors
-///
-/// Returns an error if the path can't be read or if the image can't be decoded
-pub fn load_image<P>(path: P) -> Result<Image, Error>
-    where P: AsRef<Path> {
-    let img = image::open(&path)?;
-    let mut pixels: Vec<RGBA> = Vec::with_capacity(0);
-    for (_, _, px) in img.pixels() {
-        pixels.push(px.data);
-    }
-    Ok(Image {
-        pixels: pixels,
-        width: img.width(),
-        height: img.height(),
-    })
-}
-
-/// Loads images from a list of given paths. Errors encountered while loading files
-/// are skipped.
-///
-/// # Examples
-///
-/// ```rust,no_run
-/// # use engiffen::load_images;
-/// let paths = vec!["tests/ball/ball06.bmp", "tests/ball/ball07.bmp", "tests/ball/ball08.bmp"];
-/// let images = load_images(&paths);
-/// assert_eq!(images.len(), 2); // The last path doesn't exist. It was silently skipped.
-/// ```
-///
-/// Skips images that fail to load. If all images fail, returns an empty vector.
-pub fn load_images<P>(paths: &[P]) -> Vec<Image>
-    where P: AsRef<Path> {
-    paths.iter()
-        .map(|path| load_image(path))
-        .filter_map(|img| img.ok())
-        .collect()
-}
 
-/// Converts a sequence of images into a `Gif` at a given frame rate. The `quantizer`
-/// parameter selects the algorithm that quantizes the palette into 256-colors.
-///
-/// # Examples
-///
-/// ```rust,no_run
-/// # use engiffen::{load_images, engiffen, Gif, Error, Quantizer};
-/// # fn foo() -> Result<Gif, Error> {
-/// let paths = vec!["tests/ball/ball01.bmp", "tests/ball/ball02.bmp", "tests/ball/ball03.bmp"];
-/// let images = load_images(&paths);
-/// let gif = engiffen(&images, 10, Quantizer::NeuQuant(2))?;
-/// assert_eq!(gif.images.len(), 3);
-/// # Ok(gif)
-/// # }
-/// ```
-///
-/// # Errors
-///
-/// If any image dimensions differ, this function will return an Error::Mismatch
-/// containing tuples of the conflicting image dimensions.
-pub fn engiffen(imgs: &[Image], fps: usize, quantizer: Quantizer) -> Result<Gif, Error> {
-    if imgs.is_empty() {
-        return Err(Error::NoImages);
+    /// Reads a Gif back as its palette and frame indices, as they were
+    /// written, so that a Gif cached with `write` or `to_bytes` can be
+    /// changed and written again later without quantizing it again. The
+    /// palette comes back padded to a power of two colors.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use engiffen::{engiffen, Error, Gif, Image, Quantizer};
+    /// # fn foo() -> Result<(), Error> {
+    /// # let images = vec![Image { pixels: vec![[255, 0, 0, 255]; 4], width: 2, height: 2 }];
+    /// let cached = engiffen(&images, 10, Quantizer::NeuQuant(2))?.to_bytes()?;
+    /// let mut gif = Gif::read(&cached[..])?;
+    /// gif.reverse();
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error::ImageLoad` if the data isn't a Gif, or an
+    /// `Error::Invalid` if a frame has a palette of its own.
+    pub fn read<R: io::Read>(r: R) -> Result<Gif, Error> {
+        decode::read_indexed(r)
     }
-    #[cfg(feature = "debug-stderr")] eprintln!("Engiffening {} images", imgs.len());
-
-    let (width, height) = {
-        let ref first = imgs[0];
-        let first_dimensions = (first.width, first.height);
-        for img in imgs.iter() {
-            let other_dimensions = (img.width, img.height);
-            if first_dimensions != other_dimensions {
-                return Err(Error::Mismatch(first_dimensions, other_dimensions));
-            }
-        }
-        first_dimensions
-    };
-
-    let (palette, palettized_imgs, transparency) = match quantizer {
-        Quantizer::NeuQuant(sample_rate) => neuquant_palettize(&imgs, sample_rate, width, height),
-        Quantizer::Naive => naive_palettize(&imgs),
-    };
 
-    let delay = (1000 / fps) as u16;
-
-    Ok(Gif {
-        palette: palette,
-        transparency: transparency,
-        width: width as u16,
-        height: height as u16,
-        images: palettized_imgs,
-        delay: delay,
-    })
-}
+    /// The number of frames.
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
 
-fn neuquant_palettize(imgs: &[Image], sample_rate: u32, width: u32, height: u32) -> (Vec<u8>, Vec<Vec<u8>>, Option<u8>) {
-    let image_len = (width * height * 4 / sample_rate / sample_rate) as usize;
-    let width = width as usize;
-    let sample_rate = sample_rate as usize;
-    let transparent_black = [0u8; 4];
-    #[cfg(feature = "debug-stderr")] let time_push = Instant::now();
-    let colors: Vec<u8> = imgs.par_iter().map(|img| {
-        let mut temp: Vec<_> = Vec::with_capacity(image_len);
-        for (n, px) in img.pixels.iter().enumerate() {
-            if sample_rate > 1 {
-                if n % sample_rate != 0 || (n / width) % sample_rate != 0 {
-                    continue;
-                }
-            }
-            if px[3] == 0 {
-                temp.extend_from_slice(&transparent_black);
-            } else {
-                temp.extend_from_slice(&px[..3]);
-                temp.push(255);
-            }
-        }
-        temp
-    }).reduce(|| Vec::with_capacity(image_len * imgs.len()), |mut acc, img| {
-        acc.extend_from_slice(&img);
-        acc
-    });
-    #[cfg(feature = "debug-stderr")]
-    eprintln!("Neuquant: Concatenated {} bytes in {} ms.", colors.len(), ms(time_push));
-
-    #[cfg(feature = "debug-stderr")] let time_quant = Instant::now();
-    let quant = NeuQuant::new(10, 256, &colors);
-    #[cfg(feature = "debug-stderr")]
-    eprintln!("Neuquant: Computed palette in {} ms.", ms(time_quant));
-
-    #[cfg(feature = "debug-stderr")] let time_map = Instant::now();
-    let mut transparency = None;
-    let mut cache: FnvHashMap<RGBA, u8> = FnvHashMap::default();
-    let palettized_imgs: Vec<Vec<u8>> = imgs.iter().map(|img| {
-        img.pixels.iter().map(|px| {
-            *cache.entry(*px).or_insert_with(|| {
-                let idx = quant.index_of(px) as u8;
-                if transparency.is_none() && px[3] == 0 {
-                    transparency = Some(idx);
-                }
-                idx
-            })
-        }).collect()
-    }).collect();
-    #[cfg(feature = "debug-stderr")]
-    eprintln!("Neuquant: Mapped pixels to palette in {} ms.", ms(time_map));
+    /// How long one loop of the animation takes to play, adding up every
+    /// frame's delay.
+    pub fn total_duration(&self) -> Duration {
+        let cs: u64 = self.frames.iter().map(|frame| frame.delay as u64).sum();
+        Duration::from_millis(cs * 10)
+    }
 
-    (quant.color_map_rgb(), palettized_imgs, transparency)
-}
+    /// The width and height of the canvas.
+    pub fn dimensions(&self) -> (u16, u16) {
+        (self.width, self.height)
+    }
 
-fn naive_palettize(imgs: &[Image]) -> (Vec<u8>, Vec<Vec<u8>>, Option<u8>) {
-    #[cfg(feature = "debug-stderr")] let time_count = Instant::now();
-    let frequencies: FnvHashMap<RGBA, usize> = imgs.par_iter().map(|img| {
-        let mut fr: FnvHashMap<RGBA, usize> = FnvHashMap::default();
-        for pixel in img.pixels.iter() {
-            let num = fr.entry(*pixel).or_insert(0);
-            *num += 1;
-        }
-        fr
-    }).reduce(|| FnvHashMap::default(), |mut acc, fr| {
-        for (color, count) in fr {
-            let num = acc.entry(color).or_insert(0);
-            *num += count;
-        }
-        acc
-    });
-    #[cfg(feature = "debug-stderr")]
-    eprintln!("Naive: Counted color frequencies in {} ms", ms(time_count));
-    #[cfg(feature = "debug-stderr")] let time_palette = Instant::now();
-    let mut sorted_frequencies = frequencies.into_iter()
-        .collect::<Vec<_>>();
-    sorted_frequencies.sort_by(|a, b| b.1.cmp(&a.1));
-    let sorted = sorted_frequencies.into_iter().map(|c| {
-        (c.0, Lab::from_rgba(&c.0))
-    }).collect::<Vec<_>>();
+    /// The number of colors in the palette, including the slot reserved for
+    /// transparency if there is one.
+    pub fn palette_len(&self) -> usize {
+        self.palette.len() / 3
+    }
 
-    let (palette, rest) = if sorted.len() > 256 {
-        (&sorted[..256], &sorted[256..])
-    } else {
-        (&sorted[..], &[] as &[_])
-    };
+    /// Roughly how many bytes the Gif takes up in memory: the palette and
+    /// every frame's indices, as allocated. Indices shared between frames
+    /// are counted once.
+    pub fn memory_usage(&self) -> usize {
+        let mut seen = FnvHashSet::default();
+        let frames: usize = self.frames.iter().map(|frame| {
+            let first = seen.insert(Arc::as_ptr(&frame.indices));
+            mem::size_of::<Frame>() + if first { frame.indices.capacity() } else { 0 }
+        }).sum();
+        mem::size_of::<Gif>() + self.palette.capacity() + frames
+    }
 
-    let mut map: FnvHashMap<RGBA, u8> = FnvHashMap::default();
-    for (i, color) in palette.iter().enumerate() {
-        map.insert(color.0, i as u8);
+    /// Compares each frame, as it would be shown, with the same frame of
+    /// `other`, for checking an animation against a known good one. With
+    /// `images`, each result includes an image of the differences.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error::Mismatch` if the Gifs' dimensions differ, or an
+    /// `Error::Length` if they don't have the same number of frames.
+    pub fn diff(&self, other: &Gif, images: bool) -> Result<Vec<FrameDiff>, Error> {
+        diff::diff(self, other, images)
     }
-    for color in rest {
-        let closest_index = palette.iter().enumerate().fold((0, f32::INFINITY), |closest, (idx, p)| {
-            let dist = p.1.squared_distance(&color.1);
-            if closest.1 < dist {
-                closest
-            } else {
-                (idx, dist)
-            }
-        }).0;
-        let closest_rgb = palette[closest_index].0;
-        let index = *map.get(&closest_rgb).expect("A color we assigned to the palette is somehow missing from the palette index map.");
-        map.insert(color.0, index);
+
+    /// Checks the Gif before it ships: every frame fits the canvas and only
+    /// uses indices in the palette, and encoding it then decoding the result
+    /// gives back the same palette and frames.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error::Invalid` describing the first problem found.
+    pub fn verify(&self) -> Result<(), Error> {
+        decode::verify(self)
     }
-    #[cfg(feature = "debug-stderr")]
-    eprintln!("Naive: Computed palette in {} ms.", ms(time_palette));
 
-    #[cfg(feature = "debug-stderr")]let time_index = Instant::now();
-    let palettized_imgs: Vec<Vec<u8>> = imgs.par_iter().map(|img| {
-        img.pixels.iter().map(|px| {
-            *map.get(px).expect("A color in an image was not added to the palette map.")
-        }).collect()
-    }).collect();
-    #[cfg(feature = "debug-stderr")]
-    eprintln!("Naive: Mapped pixels to palette in {} ms", ms(time_index));
+    /// Measures how closely each frame matches the image it was made from,
+    /// with PSNR and SSIM, so automated pipelines can reject encodes below a
+    /// quality bar. `sources` should be the frames as they went into the
+    /// quantizer, after any resizing or retiming in the `Options`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use engiffen::{Image, engiffen, Quantizer};
+    /// # fn foo() -> Result<(), engiffen::Error> {
+    /// # let images: Vec<Image> = vec![];
+    /// let gif = engiffen(&images, 10, Quantizer::NeuQuant(2))?;
+    /// if gif.quality(&images)?.min_psnr() < 30.0 {
+    ///     println!("Too lossy");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error::Length` if there isn't one source per frame, or an
+    /// `Error::Mismatch` if a source's dimensions differ from the Gif's.
+    pub fn quality(&self, sources: &[Image]) -> Result<QualityReport, Error> {
+        quality::report(self, sources)
+    }
 
-    let mut palette_as_bytes = Vec::with_capacity(palette.len() * 3);
-    for color in palette {
-        palette_as_bytes.extend_from_slice(&color.0[0..3]);
+    /// Draws a heatmap of how far each pixel of each frame, as it would be
+    /// shown, is from the frame in `sources` it was made from: black where
+    /// they match, through blue and red to yellow, and white for colors 64
+    /// or more apart. Banding across smooth areas calls for dithering or a
+    /// better quantizer, and hot spots in small details for more colors.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as `quality`.
+    pub fn error_maps(&self, sources: &[Image]) -> Result<Vec<RgbaImage>, Error> {
+        quality::error_maps(self, sources)
     }
 
-    (palette_as_bytes, palettized_imgs, None)
-}
+    /// The frame at `n`, if there is one.
+    pub fn frame(&self, n: usize) -> Option<&Frame> {
+        self.frames.get(n)
+    }
 
-#[cfg(test)]
-#[allow(unused_must_use)]
-mod tests {
-    use super::{load_image, engiffen, Error, Quantizer};
-    use std::fs::{read_dir, File};
+    /// The frame at `n`, if there is one, for changing its indices, timing
+    /// or transparency before writing.
+    pub fn frame_mut(&mut self, n: usize) -> Option<&mut Frame> {
+        self.frames.get_mut(n)
+    }
 
-    #[test]
-    fn test_error_on_size_mismatch() {
-        let imgs: Vec<_> = read_dir("tests/mismatched_size").unwrap()
-        .map(|e| e.unwrap().path())
-        .map(|path| load_image(&path).unwrap())
-        .collect();
+    /// Encodes the animated Gif into a new buffer, for handing to something
+    /// that wants the bytes themselves, like an HTTP response.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error::ImageWrite` if encoding fails.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut bytes = Vec::new();
+        self.write(&mut bytes)?;
+        Ok(bytes)
+    }
 
-        let res = engiffen(&imgs, 30, Quantizer::NeuQuant(1));
+    /// Creates or truncates the file at `path` and writes the animated Gif to
+    /// it.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use engiffen::{Image, engiffen, Quantizer};
+    /// # fn foo() -> Result<(), engiffen::Error> {
+    /// # let images: Vec<Image> = vec![];
+    /// let gif = engiffen(&images, 10, Quantizer::NeuQuant(2))?;
+    /// gif.write_to_file("output.gif")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error::ImageWrite` whose message names the path if the
+    /// file can't be created or written.
+    #[cfg(feature = "fs")]
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        let with_path = |err: io::Error| io::Error::new(err.kind(), format!("{}: {}", path.display(), err));
+        let mut out = io::BufWriter::new(File::create(path).map_err(with_path)?);
+        match self.write(&mut out) {
+            Err(Error::ImageWrite(err)) => return Err(Error::ImageWrite(with_path(err))),
+            result => result?,
+        }
+        io::Write::flush(&mut out).map_err(with_path)?;
+        Ok(())
+    }
+
+    /// Renders each frame back into a full color image of the whole canvas,
+    /// the way a viewer shows it: frames are drawn over what's left of the
+    /// ones before them, following each one's `dispose`. Parts of the canvas
+    /// nothing has been drawn on are fully transparent.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use engiffen::{Image, engiffen, Quantizer};
+    /// # fn foo() -> Result<(), engiffen::Error> {
+    /// # let images: Vec<Image> = vec![];
+    /// let gif = engiffen(&images, 10, Quantizer::NeuQuant(2))?;
+    /// for frame in gif.rgba_frames() {
+    ///     println!("{:?}", frame.get_pixel(0, 0));
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn rgba_frames<'a>(&'a self) -> impl Iterator<Item = RgbaImage> + 'a {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let mut canvas = vec![0u8; width * height * 4];
+        self.frames.iter().map(move |frame| {
+            let previous = if frame.dispose == Disposal::Previous { Some(canvas.clone()) } else { None };
+            let area = |n: usize| {
+                let (x, y) = (frame.left as usize + n % frame.width as usize, frame.top as usize + n / frame.width as usize);
+                if x < width && y < height { Some((y * width + x) * 4) } else { None }
+            };
+            for (n, &idx) in frame.indices.iter().enumerate() {
+                if Some(idx) == frame.transparent {
+                    continue;
+                }
+                if let Some(i) = area(n) {
+                    let color = idx as usize * 3;
+                    canvas[i..i + 3].copy_from_slice(&self.palette[color..color + 3]);
+                    canvas[i + 3] = 255;
+                }
+            }
+            let img = ImageBuffer::from_raw(width as u32, height as u32, canvas.clone()).expect("canvas matches the Gif's dimensions");
+            match frame.dispose {
+                Disposal::Background => for i in (0..frame.indices.len()).filter_map(&area) {
+                    canvas[i..i + 4].copy_from_slice(&[0, 0, 0, 0]);
+                },
+                Disposal::Previous => canvas = previous.unwrap_or_default(),
+                Disposal::Any | Disposal::Keep => (),
+            }
+            img
+        })
+    }
+
+    /// Draws the palette as a grid of `cell` x `cell` pixel squares, 16 to a
+    /// row in palette order, for seeing at a glance which colors the
+    /// quantizer picked. Indices that frames use for transparency are left
+    /// transparent.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use engiffen::{Image, engiffen, Quantizer};
+    /// # fn foo() -> Result<(), engiffen::Error> {
+    /// # let images: Vec<Image> = vec![];
+    /// let gif = engiffen(&images, 10, Quantizer::NeuQuant(2))?;
+    /// gif.palette_swatch(16).save("palette.png")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn palette_swatch(&self, cell: u32) -> RgbaImage {
+        let transparent: Vec<u8> = self.frames.iter().filter_map(|frame| frame.transparent).collect();
+        swatch(&self.palette, &transparent, cell)
+    }
+
+    /// Writes every frame into `dir` as a numbered PNG, `frame0000.png`,
+    /// `frame0001.png` and so on, for inspecting or post-processing the
+    /// result frame by frame.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error::ImageWrite` if a file can't be written.
+    #[cfg(feature = "fs")]
+    pub fn export_frames<P: AsRef<Path>>(&self, dir: P) -> Result<(), Error> {
+        for (n, frame) in self.rgba_frames().enumerate() {
+            frame.save(dir.as_ref().join(format!("frame{:04}.png", n)))?;
+        }
+        Ok(())
+    }
+
+    /// Lays the frames, as they'd be shown, out on a sprite sheet like
+    /// `export_sprite_sheet` does, writing it to `path` as a PNG and each
+    /// frame's tile and delay alongside it as JSON.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error::NoImages` if the Gif has no frames, or an
+    /// `Error::ImageWrite` if a file can't be written.
+    #[cfg(feature = "fs")]
+    pub fn export_sprite_sheet<P: AsRef<Path>>(&self, path: P, columns: usize) -> Result<SpriteSheet, Error> {
+        let frames: Vec<Image> = self.rgba_frames().map(Image::from).collect();
+        let delays_ms: Vec<u32> = self.frames.iter().map(|frame| frame.delay as u32 * 10).collect();
+        write_sprite_sheet(&frames, &delays_ms, columns, path.as_ref())
+    }
+
+    /// Adds the frames of `other` after this Gif's own, for stitching several
+    /// takes together without quantizing everything again.
+    ///
+    /// If `other` uses a different palette, each of its colors is mapped to
+    /// the closest one in this Gif's palette. Its transparent pixels reuse
+    /// this Gif's transparent index, or a new palette slot if there's room;
+    /// when there isn't, they're drawn in the closest color to black.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error::Mismatch` if the two Gifs' dimensions differ.
+    pub fn append(&mut self, other: &Gif) -> Result<(), Error> {
+        if (self.width, self.height) != (other.width, other.height) {
+            return Err(Error::Mismatch((self.width as u32, self.height as u32), (other.width as u32, other.height as u32)));
+        }
+        if self.palette == other.palette {
+            self.frames.extend_from_slice(&other.frames);
+            return Ok(());
+        }
+
+        let reserved: Vec<u8> = self.frames.iter().filter_map(|frame| frame.transparent).collect();
+        let colors = self.palette.len() / 3;
+        let closest = |color: &[u8]| (0..colors).filter(|&idx| !reserved.contains(&(idx as u8))).min_by_key(|&idx| {
+            (0..3).map(|c| (self.palette[idx * 3 + c] as i32 - color[c] as i32).pow(2)).sum::<i32>()
+        }).unwrap_or(0) as u8;
+        let mapping: Vec<u8> = other.palette.chunks(3).map(&closest).collect();
+        let black = closest(&[0, 0, 0]);
+
+        let needs_transparency = other.frames.iter().any(|frame| frame.transparent.is_some());
+        let transparent = match reserved.first() {
+            Some(&idx) => Some(idx),
+            None if needs_transparency && colors < 256 => {
+                self.palette.extend_from_slice(&[0, 0, 0]);
+                Some(colors as u8)
+            },
+            None => None,
+        };
+
+        for frame in &other.frames {
+            let indices = frame.indices.iter().map(|&idx| match frame.transparent {
+                Some(t) if t == idx => transparent.unwrap_or(black),
+                _ => mapping.get(idx as usize).cloned().unwrap_or(black),
+            }).collect();
+            self.frames.push(Frame {
+                indices: Arc::new(indices),
+                transparent: frame.transparent.and(transparent),
+                ..frame.clone()
+            });
+        }
+        Ok(())
+    }
+
+    /// Adds `img` as a new last frame, shown for a frame at `fps`, mapping
+    /// its colors to the closest ones in the existing palette, so that an
+    /// animation can be extended a frame at a time, for example one read
+    /// back with `Gif::read`, without quantizing it again. Fully transparent
+    /// pixels reuse the Gif's transparent index, or a new palette slot if
+    /// there's room; when there isn't, they're drawn in the closest color
+    /// to black. Use `push_frame_with_options` for frames encoded with a
+    /// different `alpha_threshold`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use engiffen::{engiffen, Error, Image, Quantizer};
+    /// # fn foo() -> Result<(), Error> {
+    /// let red = Image { pixels: vec![[255, 0, 0, 255]; 4], width: 2, height: 2 };
+    /// let mut gif = engiffen(&[red.clone()], 10, Quantizer::Naive)?;
+    /// gif.push_frame(&red, 10)?;
+    /// assert_eq!(gif.frame_count(), 2);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error::Mismatch` if `img`'s dimensions differ from the
+    /// Gif's, or an `Error::InvalidOption` if `fps` can't be shown.
+    pub fn push_frame<F: Into<FrameRate>>(&mut self, img: &Image, fps: F) -> Result<(), Error> {
+        self.push_frame_with_options(img, fps, &Options::default())
+    }
+
+    /// Like `push_frame`, with pixels below `options.alpha_threshold` made
+    /// transparent, the way `engiffen_with_options` keys frames. Only
+    /// `alpha_threshold` is read from `options`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error::Mismatch` if `img`'s dimensions differ from the
+    /// Gif's, or an `Error::InvalidOption` if `fps` can't be shown.
+    pub fn push_frame_with_options<F: Into<FrameRate>>(&mut self, img: &Image, fps: F, options: &Options) -> Result<(), Error> {
+        let threshold = options.alpha_threshold;
+        if (img.width, img.height) != (self.width as u32, self.height as u32) {
+            return Err(Error::Mismatch((self.width as u32, self.height as u32), (img.width, img.height)));
+        }
+        let delay = gif_delays(&[fps.into().delay_ms()], &Options::default())?[0];
+        let reserved: Vec<u8> = self.frames.iter().filter_map(|frame| frame.transparent).collect();
+        let colors = self.palette.len() / 3;
+        let transparent = match reserved.first() {
+            Some(&idx) => Some(idx),
+            None if img.pixels.iter().any(|px| px[3] < threshold) && colors < 256 => {
+                self.palette.extend_from_slice(&[0, 0, 0]);
+                Some(colors as u8)
+            },
+            None => None,
+        };
+        let palette = &self.palette;
+        let closest = |px: &RGBA| (0..colors).filter(|&idx| !reserved.contains(&(idx as u8))).min_by_key(|&idx| {
+            (0..3).map(|c| (palette[idx * 3 + c] as i32 - px[c] as i32).pow(2)).sum::<i32>()
+        }).unwrap_or(0) as u8;
+        let mut cache: FnvHashMap<RGBA, u8> = FnvHashMap::default();
+        let indices: Vec<u8> = img.pixels.iter().map(|px| match transparent {
+            Some(t) if px[3] < threshold => t,
+            _ => {
+                let opaque = if px[3] < threshold { [0, 0, 0, 255] } else { *px };
+                *cache.entry(opaque).or_insert_with(|| closest(&opaque))
+            },
+        }).collect();
+        let frame = frame(Arc::new(indices), transparent, delay, self.width, self.height);
+        self.frames.push(frame);
+        Ok(())
+    }
+
+    /// A new Gif with just the frames in `range`, sharing this one's palette,
+    /// for trimming a capture without encoding it again.
+    ///
+    /// Frames that only cover part of the canvas were drawn over the ones
+    /// before them, which a slice may leave out.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use engiffen::{Image, engiffen, Quantizer};
+    /// # fn foo() -> Result<(), engiffen::Error> {
+    /// # let images: Vec<Image> = vec![];
+    /// let gif = engiffen(&images, 10, Quantizer::NeuQuant(2))?;
+    /// let middle = gif.slice(10..gif.frames.len() - 10);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is out of bounds, like slicing a `Vec` does.
+    pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> Gif {
+        let range = (range.start_bound().cloned(), range.end_bound().cloned());
+        Gif {
+            palette: self.palette.clone(),
+            width: self.width,
+            height: self.height,
+            frames: self.frames[range].to_vec(),
+        }
+    }
+
+    /// Sets every frame's delay for a new frame rate, without re-quantizing.
+    /// Delays are rounded like they are in `engiffen`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use engiffen::{Image, engiffen, Quantizer};
+    /// # fn foo() -> Result<(), engiffen::Error> {
+    /// # let images: Vec<Image> = vec![];
+    /// let mut gif = engiffen(&images, 10, Quantizer::NeuQuant(2))?;
+    /// gif.retime(25)?;
+    /// assert!(gif.frames.iter().all(|frame| frame.delay == 4));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error::InvalidOption` if `fps` can't be shown, like a
+    /// rate of zero. The Gif is left as it was.
+    pub fn retime<F: Into<FrameRate>>(&mut self, fps: F) -> Result<(), Error> {
+        let delay = gif_delays(&[fps.into().delay_ms()], &Options::default())?[0];
+        for frame in self.frames.iter_mut() {
+            frame.delay = delay;
+        }
+        Ok(())
+    }
+
+    /// Speeds up playback by `multiplier` by dividing every frame's delay by
+    /// it, so 2.0 plays twice as fast and 0.5 at half speed. The relative
+    /// timing between frames is kept.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error::InvalidOption` if `multiplier` isn't a finite
+    /// number above zero. The Gif is left as it was.
+    pub fn change_speed(&mut self, multiplier: f32) -> Result<(), Error> {
+        if !(multiplier.is_finite() && multiplier > 0.0) {
+            return Err(Error::InvalidOption("multiplier", format!("{} isn't a finite speed above zero", multiplier)));
+        }
+        let delays_ms: Vec<f64> = self.frames.iter()
+            .map(|frame| frame.delay as f64 * 10.0 / multiplier as f64)
+            .collect();
+        for (frame, delay) in self.frames.iter_mut().zip(timing::frame_delays(&delays_ms, false)) {
+            frame.delay = delay;
+        }
+        Ok(())
+    }
+
+    /// Labels the frames with `labels`, in order, for example with the paths
+    /// of the images they were made from. The labels are written as
+    /// comments before each frame. Frames past the last label keep theirs.
+    pub fn set_labels<I, S>(&mut self, labels: I)
+        where I: IntoIterator<Item = S>, S: Into<String>
+    {
+        for (frame, label) in self.frames.iter_mut().zip(labels) {
+            frame.label = Some(label.into());
+        }
+    }
+
+    /// Reverses the order of the frames, so the animation plays backwards.
+    /// Each frame keeps its own delay and transparency, and the palette is
+    /// reused as is.
+    pub fn reverse(&mut self) {
+        self.frames.reverse();
+    }
+
+    /// Appends the frames in reverse order after the forward pass, leaving out
+    /// the first and last frame so neither is shown twice in a row. The
+    /// result loops seamlessly back and forth, boomerang style.
+    pub fn ping_pong(&mut self) {
+        let len = self.frames.len();
+        if len < 3 {
+            return;
+        }
+        for n in (1..len - 1).rev() {
+            let frame = self.frames[n].clone();
+            self.frames.push(frame);
+        }
+    }
+}
+
+/// Estimates the memory, in bytes, that encoding `frames` frames of `width` x
+/// `height` pixels with `quantizer` needs, before any of it is allocated, so
+/// that applications can warn users or pick other settings first. If
+/// `options.max_memory` is set, sampling is estimated as reduced to fit.
+///
+/// Transforms in `options` aren't accounted for: give the dimensions and
+/// frame count they produce.
+///
+/// # Examples
+///
+/// ```rust
+/// # use engiffen::{estimate_memory, Options, Quantizer};
+/// let estimate = estimate_memory(1920, 1080, 600, Quantizer::NeuQuant(2), &Options::default());
+/// if estimate.total() > 4 << 30 {
+///     println!("this needs {} MB", estimate.total() >> 20);
+/// }
+/// ```
+pub fn estimate_memory(width: u32, height: u32, frames: usize, quantizer: Quantizer, options: &Options) -> MemoryEstimate {
+    budget::estimate(width, height, frames, quantizer, options.max_memory)
+}
+
+/// Samples the colors of `imgs` that `quantizer` computes a palette from. This
+/// is the first of the stages `engiffen` runs, followed by `quantize` and
+/// `palettize`; running them separately allows caching a palette, sampling
+/// only some of the frames, or mapping frames onto a palette as they arrive.
+///
+/// The stages use frames as they are, so transforms and other settings that
+/// change pixels need to have been applied already. Only `alpha_threshold`
+/// `cancel` and `max_memory` are read from `options`.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{load_images, sample, quantize, palettize, Gif, Error, Options, Quantizer};
+/// # fn foo() -> Result<Gif, Error> {
+/// let images = load_images(&["tests/ball/ball01.bmp", "tests/ball/ball02.bmp", "tests/ball/ball03.bmp"]);
+/// let options = Options::default();
+/// let samples = sample(&images[..1], Quantizer::NeuQuant(2), &options)?;
+/// let palette = quantize(&samples);
+/// let gif = palettize(&images, &palette, 10, &options)?;
+/// # Ok(gif)
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns an `Error::NoImages` if `imgs` is empty, an `Error::MemoryLimit`
+/// if sampling a frame doesn't fit in `options.max_memory`, or
+/// `Error::Cancelled`.
+pub fn sample(imgs: &[Image], quantizer: Quantizer, options: &Options) -> Result<Samples, Error> {
+    if imgs.is_empty() {
+        return Err(Error::NoImages);
+    }
+    cancel::check(options)?;
+    let plan = budget::plan(imgs[0].width, imgs[0].height, imgs.len(), quantizer, options.max_memory, 0)?;
+    Ok(sample_colors(imgs, plan, options, &mut EncodeStats::default()))
+}
+
+/// Computes a palette from sampled colors.
+pub fn quantize(samples: &Samples) -> Palette {
+    Palette { mapping: quantize_samples(samples, &mut EncodeStats::default()) }
+}
+
+/// Maps frames onto a palette and assembles them into a `Gif`, showing each
+/// for the time given by `fps`. The frames don't need to be the ones the
+/// palette was sampled from; colors it hasn't seen get the closest entry.
+/// If the palette has no transparent index, transparent pixels get the
+/// closest opaque color.
+///
+/// # Errors
+///
+/// Returns an `Error::NoImages` if `imgs` is empty, an `Error::Mismatch` if
+/// the frames' dimensions differ, or `Error::Cancelled`.
+pub fn palettize<F: Into<FrameRate>>(imgs: &[Image], palette: &Palette, fps: F, options: &Options) -> Result<Gif, Error> {
+    if imgs.is_empty() {
+        return Err(Error::NoImages);
+    }
+    transform::conform(Cow::Borrowed(imgs), Mismatch::Error)?;
+    let mapping = &palette.mapping;
+    let palettized_imgs = mapping.index_frames(imgs, options)?;
+    mapping.check_frames(imgs, &palettized_imgs, options)?;
+    let delays = gif_delays(&vec![fps.into().delay_ms(); imgs.len()], options)?;
+    assemble(mapping.palette.clone(), palettized_imgs, mapping.transparency, delays, imgs[0].width, imgs[0].height)
+}
+
+/// Shared implementation of the `engiffen` functions, given each frame's exact
+/// delay in milliseconds.
+fn engiffen_with_delays(imgs: &[Image], delays_ms: Vec<f64>, quantizer: Quantizer, options: &Options) -> Result<Gif, Error> {
+    let (imgs, delays) = prepare(imgs, delays_ms, options)?;
+    GifEncoder { quantizer }.encode(imgs, delays, options)
+}
+
+/// Runs every step before quantization: the transforms, overlays, timing
+/// changes and per-pixel adjustments in `options`. Returns the final frames
+/// and their Gif delays.
+fn prepare<'a>(imgs: &'a [Image], delays_ms: Vec<f64>, options: &Options) -> Result<(Cow<'a, [Image]>, Vec<u16>), Error> {
+    if imgs.is_empty() {
+        return Err(Error::NoImages);
+    }
+
+    check_options(options)?;
+    timing::check(&delays_ms)?;
+    cancel::check(options)?;
+    let imgs = transform::conform(Cow::Borrowed(imgs), options.mismatch)?;
+    if let Some(crop) = options.crop {
+        check_crop(crop, imgs[0].width, imgs[0].height)?;
+    }
+    if let Some(ref background) = options.background {
+        let first_dimensions = (imgs[0].width, imgs[0].height);
+        let background_dimensions = (background.width, background.height);
+        if first_dimensions != background_dimensions {
+            return Err(Error::Mismatch(first_dimensions, background_dimensions));
+        }
+    }
+    // The background goes through the same transforms as the frames, so that
+    // it still lines up with them.
+    let region = transform::region(&imgs, options);
+    let background = options.background.as_ref().map(|bg| transform::apply_to(bg, region, options));
+    let imgs = transform::apply(imgs, region, options);
+    let imgs = match options.watermark {
+        Some(ref mark) => overlay::apply(imgs, mark),
+        None => imgs,
+    };
+    #[cfg(feature = "text")]
+    let imgs = text::apply(imgs, &options.captions);
+    #[cfg(feature = "text")]
+    let imgs = match options.burn_in {
+        Some(ref burn_in) => text::burn_in(imgs, &delays_ms, burn_in),
+        None => imgs,
+    };
+
+    let (imgs, delays_ms) = sequence::decimate(imgs, delays_ms, options.keep_every_nth, options.motion_blur);
+    let (imgs, delays_ms) = match options.interpolate {
+        // Frames shorter than this would be slowed down to 100 ms anyway.
+        Some(fps) => sequence::interpolate(&imgs, &delays_ms, fps.delay_ms().max(timing::MIN_DELAY_MS)),
+        None => (imgs, delays_ms),
+    };
+    let (imgs, delays_ms) = sequence::loop_crossfade(imgs, delays_ms, options.loop_crossfade);
+    let (imgs, delays_ms) = match options.merge_fast_frames {
+        true => sequence::merge_fast(imgs, delays_ms, timing::MIN_DELAY_MS, options.motion_blur),
+        false => (imgs, delays_ms),
+    };
+    let imgs = match options.map_frames {
+        Some(ref hook) => hook.run(imgs)?,
+        None => imgs,
+    };
+    cancel::check(options)?;
+    let imgs = preprocess(imgs, options, background.as_deref());
+    let imgs = fit_dimensions(imgs, options);
+    cancel::check(options)?;
+
+    let delays = gif_delays(&delays_ms, options)?;
+    Ok((imgs, delays))
+}
+
+/// Checks the settings in `options` that would otherwise make nonsense of
+/// the frames.
+fn check_options(options: &Options) -> Result<(), Error> {
+    let invalid = |name, reason: &str| Err(Error::InvalidOption(name, reason.to_string()));
+    if !(options.gamma.is_finite() && options.gamma > 0.0) {
+        return invalid("gamma", "must be a positive number");
+    }
+    if !(options.contrast.is_finite() && options.contrast >= 0.0) {
+        return invalid("contrast", "must be a number of at least 0");
+    }
+    if !(options.saturation.is_finite() && options.saturation >= 0.0) {
+        return invalid("saturation", "must be a number of at least 0");
+    }
+    if !options.hue_rotate.is_finite() {
+        return invalid("hue_rotate", "must be a number of degrees");
+    }
+    if let Some(scale) = options.scale {
+        check_scale(scale)?;
+    }
+    if let Some(ref mark) = options.watermark {
+        if !(0.0..=1.0).contains(&mark.opacity) {
+            return invalid("watermark", "opacity must be from 0 to 1");
+        }
+    }
+    if let Some(rate) = options.interpolate {
+        let delay = rate.delay_ms();
+        if !(delay.is_finite() && delay > 0.0) {
+            return invalid("interpolate", "must be a positive frame rate");
+        }
+    }
+    if options.max_quantization_error.is_some_and(|limit| limit.is_nan() || limit < 0.0) {
+        return invalid("max_quantization_error", "must be a number of at least 0");
+    }
+    Ok(())
+}
+
+/// Checks that `scale` is a real size, not a factor of zero, below zero or
+/// NaN, or a size of zero pixels.
+fn check_scale(scale: Scale) -> Result<(), Error> {
+    let invalid = |reason: &str| Err(Error::InvalidOption("scale", reason.to_string()));
+    match scale {
+        Scale::Factor(factor) if !(factor.is_finite() && factor > 0.0) => invalid("factor must be a positive number"),
+        Scale::Width(0) | Scale::Height(0) | Scale::Size(0, _) | Scale::Size(_, 0) => invalid("size must be at least 1 pixel"),
+        _ => Ok(()),
+    }
+}
+
+/// Checks that `crop` is at least a pixel in size and lies within frames of
+/// `width` x `height`.
+fn check_crop(crop: Crop, width: u32, height: u32) -> Result<(), Error> {
+    if crop.width == 0 || crop.height == 0 {
+        return Err(Error::InvalidOption("crop", "must be at least 1 pixel wide and tall".to_string()));
+    }
+    if crop.x as u64 + crop.width as u64 > width as u64 || crop.y as u64 + crop.height as u64 > height as u64 {
+        return Err(Error::InvalidOption("crop", format!("{}x{} at ({}, {}) doesn't fit in {}x{} frames",
+            crop.width, crop.height, crop.x, crop.y, width, height)));
+    }
+    Ok(())
+}
+
+/// Converts exact delays into Gif delays, applying the timing settings in
+/// `options`, or returns an `Error::InvalidOption` for a frame rate that
+/// can't be shown.
+fn gif_delays(delays_ms: &[f64], options: &Options) -> Result<Vec<u16>, Error> {
+    timing::check(delays_ms)?;
+    let mut delays = timing::frame_delays(delays_ms, options.delay_dither);
+    if let (Some(first), Some(duration)) = (delays.first_mut(), options.first_frame_delay) {
+        *first = timing::frame_delays(&[FrameRate::Delay(duration).delay_ms()], false)[0];
+    }
+    Ok(delays)
+}
+
+/// A palette, every frame's indices into it, and the transparent index.
+type Palettized = (Vec<u8>, Vec<Arc<Vec<u8>>>, Option<u8>);
+
+fn palettize_frames<T: Pixels>(imgs: &[T], quantizer: Quantizer, options: &Options, stats: &mut EncodeStats) -> Result<Palettized, Error> {
+    let indices_len = imgs.iter().map(|img| img.pixels().len()).sum();
+    let mapping = learn_palette(imgs, quantizer, options, indices_len, stats)?;
+    let time_map = Instant::now();
+    let palettized_imgs = mapping.index_frames(imgs, options)?;
+    stats::add_since(&mut stats.mapping, time_map);
+    mapping.check_frames(imgs, &palettized_imgs, options)?;
+    Ok((mapping.palette, palettized_imgs, mapping.transparency))
+}
+
+/// Computes a palette for `imgs` without mapping any of them onto it yet,
+/// keeping within `options.max_memory` when `reserved` bytes are needed for
+/// other buffers.
+///
+/// Training NeuQuant isn't interrupted by `options.cancel`, but the token
+/// is checked as soon as it's done.
+fn learn_palette<T: Pixels>(imgs: &[T], quantizer: Quantizer, options: &Options, reserved: usize, stats: &mut EncodeStats) -> Result<Mapping, Error> {
+    let plan = budget::plan(imgs[0].width(), imgs[0].height(), imgs.len(), quantizer, options.max_memory, reserved)?;
+    let samples = sample_colors(imgs, plan, options, stats);
+    let mapping = quantize_samples(&samples, stats);
+    stats.frames = imgs.len();
+    stats.palette_colors = mapping.palette.len() / 3;
+    Ok(mapping)
+}
+
+/// Colors sampled from frames, which `quantize` computes a palette from.
+/// Created by `sample`.
+#[derive(Debug, Clone)]
+pub struct Samples {
+    colors: Sampled,
+    transparency: bool,
+    /// Whether every opaque pixel is a shade of grey, in which case an exact
+    /// grey ramp is used instead of training NeuQuant.
+    gray: bool,
+    alpha_threshold: u8,
+}
+
+#[derive(Debug, Clone)]
+enum Sampled {
+    /// Every sampled pixel, as RGBA bytes.
+    NeuQuant(Vec<u8>),
+    /// How often each color appears.
+    Naive(FnvHashMap<RGBA, usize>),
+    Grayscale,
+}
+
+impl Samples {
+    /// The number of colors sampled. For `Quantizer::Naive` only distinct
+    /// colors are counted, and `Quantizer::Grayscale` samples none.
+    pub fn len(&self) -> usize {
+        match self.colors {
+            Sampled::NeuQuant(ref colors) => colors.len() / 4,
+            Sampled::Naive(ref frequencies) => frequencies.len(),
+            Sampled::Grayscale => 0,
+        }
+    }
+
+    /// Whether no colors were sampled.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether any sampled frame had a transparent pixel, in which case the
+    /// palette gets a transparent index.
+    pub fn has_transparency(&self) -> bool {
+        self.transparency
+    }
+
+    /// Adds colors sampled from more frames, with the same quantizer.
+    fn merge(&mut self, other: Samples) {
+        self.transparency |= other.transparency;
+        self.gray &= other.gray;
+        match (&mut self.colors, other.colors) {
+            (&mut Sampled::NeuQuant(ref mut colors), Sampled::NeuQuant(more)) => colors.extend_from_slice(&more),
+            (&mut Sampled::Naive(ref mut frequencies), Sampled::Naive(more)) => {
+                for (color, count) in more {
+                    *frequencies.entry(color).or_insert(0) += count;
+                }
+            },
+            (&mut Sampled::Grayscale, Sampled::Grayscale) => {},
+            _ => panic!("samples from different quantizers can't be merged"),
+        }
+    }
+}
+
+/// A palette computed by `quantize`, which `palettize` maps frames onto.
+pub struct Palette {
+    mapping: Mapping,
+}
+
+impl Palette {
+    /// The palette's RGB colors, including the transparent entry.
+    pub fn colors(&self) -> &[u8] {
+        &self.mapping.palette
+    }
+
+    /// The transparent index, if the palette has one.
+    pub fn transparency(&self) -> Option<u8> {
+        self.mapping.transparency
+    }
+
+    /// Draws the palette like `Gif::palette_swatch`.
+    pub fn swatch(&self, cell: u32) -> RgbaImage {
+        let transparent: Vec<u8> = self.mapping.transparency.into_iter().collect();
+        swatch(&self.mapping.palette, &transparent, cell)
+    }
+}
+
+/// How many colors a palette swatch has in each row.
+const SWATCH_COLUMNS: u32 = 16;
+
+/// Draws the RGB `palette` as a grid of `cell` pixel squares, leaving the
+/// `transparent` indices clear.
+fn swatch(palette: &[u8], transparent: &[u8], cell: u32) -> RgbaImage {
+    let cell = cell.max(1);
+    let colors = (palette.len() / 3) as u32;
+    let columns = colors.clamp(1, SWATCH_COLUMNS);
+    let rows = colors.div_ceil(SWATCH_COLUMNS).max(1);
+    ImageBuffer::from_fn(columns * cell, rows * cell, |x, y| {
+        let idx = (y / cell) * columns + x / cell;
+        if idx >= colors || transparent.contains(&(idx as u8)) {
+            return image::Rgba([0, 0, 0, 0]);
+        }
+        let color = idx as usize * 3;
+        image::Rgba([palette[color], palette[color + 1], palette[color + 2], 255])
+    })
+}
+
+impl fmt::Debug for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Palette {{ colors: Vec<u8 x {:?}>, transparency: {:?} }}",
+            self.mapping.palette.len(),
+            self.mapping.transparency
+        )
+    }
+}
+
+fn sample_colors<T: Pixels>(imgs: &[T], plan: budget::Plan, options: &Options, stats: &mut EncodeStats) -> Samples {
+    let alpha_threshold = options.alpha_threshold;
+    let (transparency, gray) = pool::install(options, || {
+        (has_transparency(imgs, alpha_threshold), matches!(plan.quantizer, Quantizer::NeuQuant(_)) && all_gray(imgs, alpha_threshold))
+    });
+    let time_sample = Instant::now();
+    let colors = pool::install(options, || match plan.quantizer {
+        Quantizer::NeuQuant(sample_rate) => Sampled::NeuQuant(neuquant_sample(imgs, sample_rate, plan.step, transparency, alpha_threshold)),
+        Quantizer::Naive => Sampled::Naive(naive_sample(imgs, plan.step, transparency, alpha_threshold)),
+        Quantizer::Grayscale => Sampled::Grayscale,
+    });
+    stats::add_since(&mut stats.sampling, time_sample);
+    let samples = Samples { colors, transparency, gray, alpha_threshold };
+    stats.colors_sampled = samples.len();
+    samples
+}
+
+fn quantize_samples(samples: &Samples, stats: &mut EncodeStats) -> Mapping {
+    let time_quant = Instant::now();
+    let mapping = match samples.colors {
+        Sampled::NeuQuant(_) if samples.gray => grayscale_quantize(samples.transparency, samples.alpha_threshold),
+        Sampled::NeuQuant(ref colors) => neuquant_quantize(colors, samples.transparency, samples.alpha_threshold),
+        Sampled::Naive(ref frequencies) => naive_quantize(frequencies, samples.transparency, samples.alpha_threshold),
+        Sampled::Grayscale => grayscale_quantize(samples.transparency, samples.alpha_threshold),
+    };
+    stats::add_since(&mut stats.quantizing, time_quant);
+    mapping
+}
+
+/// A palette, and how to find each pixel's index in it.
+struct Mapping {
+    palette: Vec<u8>,
+    transparency: Option<u8>,
+    alpha_threshold: u8,
+    indexer: Indexer,
+}
+
+enum Indexer {
+    NeuQuant(NeuQuant),
+    /// The closest color, for a palette computed earlier.
+    Nearest,
+    Exact(FnvHashMap<RGBA, u8>),
+    /// The index of the brightest grey.
+    Grayscale(u32),
+}
+
+impl Mapping {
+    /// Maps frames onto an existing palette by picking the closest color.
+    fn from_palette(palette: Vec<u8>, transparency: Option<u8>, alpha_threshold: u8) -> Mapping {
+        Mapping { palette, transparency, alpha_threshold, indexer: Indexer::Nearest }
+    }
+
+    fn nearest(&self, px: &RGBA) -> u8 {
+        let colors = self.palette.len() / 3 - self.transparency.map_or(0, |_| 1);
+        (0..colors).min_by_key(|&idx| {
+            let color = &self.palette[idx * 3..idx * 3 + 3];
+            (0..3).map(|c| (color[c] as i32 - px[c] as i32).pow(2)).sum::<i32>()
+        }).unwrap_or(0) as u8
+    }
+
+    /// Maps one frame's pixels onto the palette.
+    fn index(&self, pixels: &[RGBA]) -> Vec<u8> {
+        let mut cache: FnvHashMap<RGBA, u8> = FnvHashMap::default();
+        pixels.iter().map(|px| match self.transparency {
+            Some(idx) if px[3] < self.alpha_threshold => idx,
+            _ => match self.indexer {
+                Indexer::NeuQuant(ref quant) => *cache.entry(*px).or_insert_with(|| quant.index_of(px) as u8),
+                Indexer::Nearest => *cache.entry(*px).or_insert_with(|| self.nearest(px)),
+                Indexer::Exact(ref map) => map.get(px).cloned().unwrap_or_else(|| self.nearest(px)),
+                // Without a transparent index, grey levels are their own
+                // indices.
+                Indexer::Grayscale(255) if px[0] == px[1] && px[1] == px[2] => px[0],
+                Indexer::Grayscale(top) => {
+                    let luma = (px[0] as u32 * 299 + px[1] as u32 * 587 + px[2] as u32 * 114 + 500) / 1000;
+                    ((luma * top + 127) / 255) as u8
+                },
+            },
+        }).collect()
+    }
+
+    /// Maps every frame onto the palette. Frames with the same pixels as an
+    /// earlier one reuse its indices instead of being mapped again, since
+    /// captures often hold long runs of identical frames.
+    fn index_frames<T: Pixels>(&self, imgs: &[T], options: &Options) -> Result<Vec<Arc<Vec<u8>>>, Error> {
+        let hashes: Vec<u64> = pool::install(options, || imgs.par_iter().map(|img| {
+            let mut hasher = FnvHasher::default();
+            img.pixels().hash(&mut hasher);
+            hasher.finish()
+        }).collect());
+        // The first frame with each frame's pixels, checked in full in case
+        // two different frames hash the same.
+        let mut seen: FnvHashMap<u64, Vec<usize>> = FnvHashMap::default();
+        let originals: Vec<usize> = hashes.iter().enumerate().map(|(n, hash)| {
+            let candidates = seen.entry(*hash).or_default();
+            match candidates.iter().find(|&&m| imgs[m].pixels() == imgs[n].pixels()) {
+                Some(&m) => m,
+                None => {
+                    candidates.push(n);
+                    n
+                },
+            }
+        }).collect();
+
+        let mapped: Vec<Result<(usize, Vec<u8>), Error>> = pool::install(options, || originals.par_iter().enumerate()
+            .filter(|&(n, &m)| n == m)
+            .map(|(n, _)| {
+                cancel::check(options)?;
+                Ok((n, self.index(imgs[n].pixels())))
+            })
+            .collect());
+        let mut mapped = mapped.into_iter().collect::<Result<FnvHashMap<_, _>, _>>()?;
+        let mut palettized: Vec<Arc<Vec<u8>>> = Vec::with_capacity(imgs.len());
+        for (n, &m) in originals.iter().enumerate() {
+            let indices = if n == m { Arc::new(mapped.remove(&n).expect("every distinct frame is mapped")) } else { palettized[m].clone() };
+            palettized.push(indices);
+        }
+        Ok(palettized)
+    }
+
+    /// Checks every frame against `options.max_quantization_error`, given
+    /// the indices `index_frames` mapped them to.
+    fn check_frames<T: Pixels>(&self, imgs: &[T], indices: &[Arc<Vec<u8>>], options: &Options) -> Result<(), Error> {
+        if options.max_quantization_error.is_none() {
+            return Ok(());
+        }
+        let checked: Vec<Result<(), Error>> = pool::install(options, || imgs.par_iter().zip(indices).enumerate()
+            .map(|(n, (img, indices))| self.check_error(n, img.pixels(), indices, options))
+            .collect());
+        checked.into_iter().collect()
+    }
+
+    /// Checks that frame `n`'s `pixels`, mapped to `indices`, are within
+    /// `options.max_quantization_error` of their source.
+    fn check_error(&self, n: usize, pixels: &[RGBA], indices: &[u8], options: &Options) -> Result<(), Error> {
+        match options.max_quantization_error {
+            Some(limit) => {
+                let error = quality::mapping_error(pixels, indices, &self.palette, self.transparency) as f32;
+                if error > limit { Err(Error::QuantizationError(n, error)) } else { Ok(()) }
+            },
+            None => Ok(()),
+        }
+    }
+
+    /// Maps one frame's pixels, reusing the indices of the `previous` frame
+    /// if the two are identical, for encoders that map frames one at a time.
+    fn index_after(&self, pixels: &[RGBA], previous: &mut Option<(Vec<RGBA>, Arc<Vec<u8>>)>) -> Arc<Vec<u8>> {
+        if let Some((ref last, ref indices)) = *previous {
+            if last[..] == *pixels {
+                return indices.clone();
+            }
+        }
+        let indices = Arc::new(self.index(pixels));
+        *previous = Some((pixels.to_vec(), indices.clone()));
+        indices
+    }
+}
+
+/// Encodes frames as an endlessly looping Gif.
+fn write_frames<'a, W, I>(mut out: &mut W, width: u16, height: u16, palette: &[u8], frames: I) -> Result<(), Error>
+    where W: io::Write, I: IntoIterator<Item = Result<Cow<'a, Frame>, Error>>
+{
+    let mut encoder = Encoder::new(&mut out, width, height, palette).map_err(Error::encoding)?;
+    encoder.set(Repeat::Infinite).map_err(Error::encoding)?;
+    for frame in frames {
+        let frame = frame?;
+        if let Some(ref label) = frame.label {
+            encoder.write_raw_extension(gif::Extension::Comment as u8, &[label.as_bytes()]).map_err(Error::encoding)?;
+        }
+        let frame = gif::Frame {
+            delay: frame.delay,
+            dispose: frame.dispose.into(),
+            transparent: frame.transparent,
+            left: frame.left,
+            top: frame.top,
+            width: frame.width,
+            height: frame.height,
+            buffer: match frame {
+                Cow::Borrowed(frame) => Cow::Borrowed(&frame.indices[..]),
+                Cow::Owned(frame) => Cow::Owned(Arc::try_unwrap(frame.indices).unwrap_or_else(|shared| shared.to_vec())),
+            },
+            ..gif::Frame::default()
+        };
+        encoder.write_frame(&frame).map_err(Error::encoding)?;
+    }
+    Ok(())
+}
+
+/// How many frames each of rayon's threads compresses at a time in
+/// `write_frames_parallel`, which holds each batch's compressed frames.
+#[cfg(feature = "parallel-write")]
+const FRAMES_PER_THREAD: usize = 4;
+
+/// Encodes frames like `write_frames`, LZW compressing batches of them in
+/// parallel, each as a Gif of its own, and splicing the frames out of
+/// those in order. The bytes are the same as `write_frames` writes.
+#[cfg(feature = "parallel-write")]
+fn write_frames_parallel<W: io::Write>(out: &mut W, width: u16, height: u16, palette: &[u8], frames: &[Frame]) -> Result<(), Error> {
+    // A Gif without frames is its header, then the trailer byte.
+    let mut header = Vec::new();
+    write_frames(&mut header, width, height, palette, std::iter::empty())?;
+    let trailer = header.split_off(header.len() - 1);
+    out.write_all(&header)?;
+    for batch in frames.chunks(rayon::current_num_threads() * FRAMES_PER_THREAD) {
+        let encoded: Vec<Result<Vec<u8>, Error>> = batch.par_iter().map(|frame| {
+            let mut bytes = Vec::new();
+            write_frames(&mut bytes, width, height, palette, std::iter::once(Ok(Cow::Borrowed(frame))))?;
+            Ok(bytes)
+        }).collect();
+        for bytes in encoded {
+            let bytes = bytes?;
+            out.write_all(&bytes[header.len()..bytes.len() - 1])?;
+        }
+    }
+    out.write_all(&trailer)?;
+    Ok(())
+}
+
+fn assemble(palette: Vec<u8>, palettized_imgs: Vec<Arc<Vec<u8>>>, transparency: Option<u8>, delays: Vec<u16>, width: u32, height: u32) -> Result<Gif, Error> {
+    let (width, height) = gif_dimensions(width, height)?;
+    let frames = palettized_imgs.into_iter().zip(delays)
+        .map(|(indices, delay)| frame(indices, transparency, delay, width, height))
+        .collect();
+    Ok(Gif { palette, width, height, frames })
+}
+
+/// The largest width or height a Gif can have.
+const MAX_DIMENSION: u32 = u16::MAX as u32;
+
+/// Checks that frames of this size fit in a Gif.
+fn gif_dimensions(width: u32, height: u32) -> Result<(u16, u16), Error> {
+    if width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return Err(Error::DimensionsTooLarge(width, height));
+    }
+    Ok((width as u16, height as u16))
+}
+
+/// Shrinks frames too large for a Gif to fit, keeping their aspect ratio,
+/// if `options.downscale_to_fit` asks for it. Encoders that take larger
+/// frames are left to report them.
+fn fit_dimensions<'a>(imgs: Cow<'a, [Image]>, options: &Options) -> Cow<'a, [Image]> {
+    let (width, height) = (imgs[0].width, imgs[0].height);
+    if !options.downscale_to_fit || (width <= MAX_DIMENSION && height <= MAX_DIMENSION) {
+        return imgs;
+    }
+    let factor = MAX_DIMENSION as f64 / width.max(height) as f64;
+    let fit = |side: u32| ((side as f64 * factor).round() as u32).clamp(1, MAX_DIMENSION);
+    let (w, h) = (fit(width), fit(height));
+    Cow::Owned(pool::install(options, || imgs.par_iter().map(|img| transform::resize(img, w, h, options.scale_filter)).collect()))
+}
+
+/// A frame covering the whole canvas.
+fn frame(indices: Arc<Vec<u8>>, transparency: Option<u8>, delay: u16, width: u16, height: u16) -> Frame {
+    // Only frames that actually contain transparent pixels get a transparent
+    // index, so opaque frames can use every palette entry when drawn.
+    let transparent = transparency.filter(|idx| indices.contains(idx));
+    Frame { delay, transparent, ..Frame::new(indices, width, height) }
+}
+
+/// Applies the per-pixel adjustments requested in `options` to every frame.
+/// Borrows the frames unchanged when there is nothing to do.
+fn preprocess<'a>(imgs: Cow<'a, [Image]>, options: &Options, background: Option<&Image>) -> Cow<'a, [Image]> {
+    let adjusted = options.brightness != 0 || options.contrast != 1.0 || options.gamma != 1.0;
+    let recolored = options.hue_rotate != 0.0 || options.saturation != 1.0;
+    if !options.alpha_dither && !options.opaque && options.matte.is_none()
+        && background.is_none() && options.chroma_key.is_none() && !adjusted && !recolored {
+        return imgs;
+    }
+    let mut imgs = imgs.into_owned();
+    pool::install(options, || imgs.par_iter_mut().for_each(|img| {
+        if let Some(key) = options.chroma_key {
+            alpha::chroma_key(img, key);
+        }
+        if adjusted {
+            color::adjust(img, options.brightness, options.contrast, options.gamma);
+        }
+        if recolored {
+            color::hue_saturation(img, options.hue_rotate, options.saturation);
+        }
+        if let Some(background) = background {
+            alpha::composite_over(img, background);
+        }
+        if let Some(color) = options.matte {
+            alpha::matte(img, color);
+        }
+        if options.opaque {
+            alpha::flatten(img);
+        } else if options.alpha_dither {
+            alpha::dither(img);
+        }
+    }));
+    Cow::Owned(imgs)
+}
+
+/// Samples every `step`th frame.
+///
+/// Without `transparency`, no frame has a pixel below `alpha_threshold`, so
+/// alpha isn't checked at all.
+fn neuquant_sample<T: Pixels>(imgs: &[T], sample_rate: u32, step: usize, transparency: bool, alpha_threshold: u8) -> Vec<u8> {
+    let (width, height) = (imgs[0].width(), imgs[0].height());
+    // Frames can be up to 65535 pixels a side, which overflows in u32.
+    let image_len = (width as u64 * height as u64 * 4 / (sample_rate as u64 * sample_rate as u64)) as usize;
+    let width = (width as usize).max(1);
+    let sample_rate = sample_rate as usize;
+    imgs.par_chunks(step).map(|chunk| {
+        let img = &chunk[0];
+        let mut temp: Vec<_> = Vec::with_capacity(image_len);
+        for (row, line) in img.pixels().chunks(width).enumerate() {
+            if row % sample_rate != 0 {
+                continue;
+            }
+            let sampled = line.iter().step_by(sample_rate);
+            if transparency {
+                // Transparent pixels get their own palette slot, so they
+                // shouldn't pull any of the quantized colors towards them.
+                for px in sampled.filter(|px| px[3] >= alpha_threshold) {
+                    temp.extend_from_slice(&[px[0], px[1], px[2], 255]);
+                }
+            } else {
+                for px in sampled {
+                    temp.extend_from_slice(&[px[0], px[1], px[2], 255]);
+                }
+            }
+        }
+        temp
+    }).reduce(|| Vec::with_capacity(image_len * imgs.len().div_ceil(step)), |mut acc, img| {
+        acc.extend_from_slice(&img);
+        acc
+    })
+}
+
+fn neuquant_quantize(colors: &[u8], transparency: bool, alpha_threshold: u8) -> Mapping {
+    let transparency = if transparency { Some(255) } else { None };
+    let quant = NeuQuant::new(10, if transparency.is_some() { 255 } else { 256 }, colors);
+    let mut palette = quant.color_map_rgb();
+    if transparency.is_some() {
+        palette.extend_from_slice(&[0, 0, 0]);
+    }
+
+    Mapping { palette, transparency, alpha_threshold, indexer: Indexer::NeuQuant(quant) }
+}
+
+/// Maps frames onto an existing palette, picking the closest color for each
+/// pixel. Pixels with alpha below `alpha_threshold` get the `transparency`
+/// index, if there is one.
+fn remap(imgs: &[Image], palette: &[u8], transparency: Option<u8>, alpha_threshold: u8) -> Vec<Arc<Vec<u8>>> {
+    let mapping = Mapping::from_palette(palette.to_vec(), transparency, alpha_threshold);
+    mapping.index_frames(imgs, &Options::default()).expect("remapping can't be cancelled")
+}
+
+/// Whether any frame contains a pixel with alpha below `alpha_threshold`. If
+/// so, one palette slot is reserved for transparency, directly after the
+/// quantized colors, so that it can never collide with an opaque color.
+fn has_transparency<T: Pixels>(imgs: &[T], alpha_threshold: u8) -> bool {
+    imgs.par_iter().any(|img| img.pixels().iter().any(|px| px[3] < alpha_threshold))
+}
+
+/// Whether every pixel that isn't transparent is a shade of grey.
+fn all_gray<T: Pixels>(imgs: &[T], alpha_threshold: u8) -> bool {
+    imgs.par_iter().all(|img| img.pixels().iter().all(|px| px[3] < alpha_threshold || (px[0] == px[1] && px[1] == px[2])))
+}
+
+/// Counts the colors of every `step`th frame.
+fn naive_sample<T: Pixels>(imgs: &[T], step: usize, transparency: bool, alpha_threshold: u8) -> FnvHashMap<RGBA, usize> {
+    imgs.par_chunks(step).map(|chunk| {
+        let img = &chunk[0];
+        let mut fr: FnvHashMap<RGBA, usize> = FnvHashMap::default();
+        if transparency {
+            for pixel in img.pixels().iter().filter(|px| px[3] >= alpha_threshold) {
+                *fr.entry(*pixel).or_insert(0) += 1;
+            }
+        } else {
+            for pixel in img.pixels() {
+                *fr.entry(*pixel).or_insert(0) += 1;
+            }
+        }
+        fr
+    }).reduce(FnvHashMap::default, |mut acc, fr| {
+        for (color, count) in fr {
+            let num = acc.entry(color).or_insert(0);
+            *num += count;
+        }
+        acc
+    })
+}
+
+fn naive_quantize(frequencies: &FnvHashMap<RGBA, usize>, has_transparency: bool, alpha_threshold: u8) -> Mapping {
+    let mut sorted_frequencies = frequencies.iter()
+        .map(|(color, count)| (*color, *count))
+        .collect::<Vec<_>>();
+    // Colors seen equally often are ordered by value, since the order they
+    // come out of the map depends on how the frames were split between
+    // threads.
+    sorted_frequencies.sort_by_key(|&(color, count)| (cmp::Reverse(count), color));
+    let sorted = sorted_frequencies.into_iter().map(|c| {
+        (c.0, Lab::from_rgba(&c.0))
+    }).collect::<Vec<_>>();
+
+    let max_colors = if has_transparency { 255 } else { 256 };
+    let (palette, rest) = if sorted.len() > max_colors {
+        (&sorted[..max_colors], &sorted[max_colors..])
+    } else {
+        (&sorted[..], &[] as &[_])
+    };
+
+    let mut map: FnvHashMap<RGBA, u8> = FnvHashMap::default();
+    for (i, color) in palette.iter().enumerate() {
+        map.insert(color.0, i as u8);
+    }
+    for color in rest {
+        let closest_index = palette.iter().enumerate().fold((0, f32::INFINITY), |closest, (idx, p)| {
+            let dist = p.1.squared_distance(&color.1);
+            if closest.1 < dist {
+                closest
+            } else {
+                (idx, dist)
+            }
+        }).0;
+        let closest_rgb = palette[closest_index].0;
+        let index = *map.get(&closest_rgb).expect("A color we assigned to the palette is somehow missing from the palette index map.");
+        map.insert(color.0, index);
+    }
+
+    let transparency = if has_transparency { Some(palette.len() as u8) } else { None };
+    let mut palette_as_bytes = Vec::with_capacity(palette.len() * 3 + 3);
+    for color in palette {
+        palette_as_bytes.extend_from_slice(&color.0[0..3]);
+    }
+    if transparency.is_some() {
+        palette_as_bytes.extend_from_slice(&[0, 0, 0]);
+    }
+
+    Mapping { palette: palette_as_bytes, transparency, alpha_threshold, indexer: Indexer::Exact(map) }
+}
+
+fn grayscale_quantize(transparency: bool, alpha_threshold: u8) -> Mapping {
+    let transparency = if transparency { Some(255) } else { None };
+    // Without a transparent index every level gets its own entry, otherwise
+    // the ramp is stretched over the first 255.
+    let top = if transparency.is_some() { 254 } else { 255 };
+
+    let mut palette = Vec::with_capacity(256 * 3);
+    for level in 0..=top {
+        let grey = ((level * 255 + top / 2) / top) as u8;
+        palette.extend_from_slice(&[grey, grey, grey]);
+    }
+    if transparency.is_some() {
+        palette.extend_from_slice(&[0, 0, 0]);
+    }
+    Mapping { palette, transparency, alpha_threshold, indexer: Indexer::Grayscale(top) }
+}
+
+// The tests load their frames from `tests/`.
+#[cfg(all(test, feature = "fs"))]
+#[allow(unused_must_use)]
+mod tests {
+    use super::{engiffen, engiffen_files, engiffen_preview, engiffen_with_options, engiffen_with_stats, engiffen_write, estimate_memory, load_image, load_images, load_images_from_dir, naive_sample, neuquant_sample, palettize, quantize, read_gif, sample, CancelToken, Crop, Disposal, Error, Filter, Frame, FrameHook, FrameRate, Gif, Image, Mapping, Mismatch, Options, Quantizer, Rotation, Scale, ThreadPool};
+    use std::fs::File;
+    use std::time::Duration;
+    use std::mem;
+    use std::sync::Arc;
+    use std::io::Read;
+    use image;
+    use fixtures::{delays, indices, solid, transparencies, transparent_frames};
+
+    #[test]
+    fn test_reserved_transparent_index_naive() {
+        let gif = engiffen(&transparent_frames(), 10, Quantizer::Naive).unwrap();
+        let transparent = transparencies(&gif)[0].expect("Frames with alpha should have a transparent index");
+        assert_eq!(transparent, 2);
+        assert_eq!(gif.palette.len(), 9);
+        assert_eq!(gif.frames[0].indices[0], transparent);
+        assert!(gif.frames[0].indices[1..].iter().all(|&idx| idx != transparent));
+    }
+
+    #[test]
+    fn test_reserved_transparent_index_neuquant() {
+        let gif = engiffen(&transparent_frames(), 10, Quantizer::NeuQuant(1)).unwrap();
+        assert_eq!(transparencies(&gif), vec![Some(255)]);
+        assert_eq!(gif.palette.len(), 256 * 3);
+        assert_eq!(gif.frames[0].indices[0], 255);
+        assert!(gif.frames[0].indices[1..].iter().all(|&idx| idx != 255));
+    }
+
+    #[test]
+    fn test_per_frame_transparency() {
+        let mut imgs = transparent_frames();
+        imgs.push(solid(4, 4, [255, 0, 0, 255]));
+        let gif = engiffen(&imgs, 10, Quantizer::Naive).unwrap();
+        assert_eq!(transparencies(&gif), vec![Some(2), None]);
+    }
+
+    #[test]
+    fn test_alpha_threshold() {
+        let mut pixels = vec![[0, 0, 0, 255]; 16];
+        pixels[0] = [255, 255, 255, 100];
+        pixels[1] = [255, 255, 255, 200];
+        let imgs = vec![Image { pixels, width: 4, height: 4 }];
+
+        let gif = engiffen(&imgs, 10, Quantizer::Naive).unwrap();
+        assert_eq!(transparencies(&gif), vec![None]);
+
+        let options = Options { alpha_threshold: 128, ..Options::default() };
+        for quantizer in &[Quantizer::Naive, Quantizer::NeuQuant(1)] {
+            let gif = engiffen_with_options(&imgs, 10, *quantizer, &options).unwrap();
+            let transparent = transparencies(&gif)[0].expect("Alpha below the threshold should be transparent");
+            assert_eq!(gif.frames[0].indices[0], transparent);
+            assert!(gif.frames[0].indices[1] != transparent);
+        }
+    }
+
+    #[test]
+    fn test_mismatch_resize() {
+        let imgs = vec![
+            solid(4, 4, [0, 0, 0, 255]),
+            solid(2, 2, [0, 0, 0, 255]),
+        ];
+        let options = Options { mismatch: Mismatch::Resize(None, Filter::Triangle), ..Options::default() };
+        let gif = engiffen_with_options(&imgs, 10, Quantizer::Naive, &options).unwrap();
+        assert_eq!((gif.width, gif.height), (4, 4));
+        assert!(indices(&gif).iter().all(|img| img.len() == 16));
+    }
+
+    #[test]
+    fn test_scale_with_background() {
+        let options = Options {
+            scale: Some(Scale::Width(2)),
+            background: Some(solid(4, 4, [0, 0, 255, 255])),
+            ..Options::default()
+        };
+        let gif = engiffen_with_options(&transparent_frames(), 10, Quantizer::Naive, &options).unwrap();
+        assert_eq!((gif.width, gif.height), (2, 2));
+        assert_eq!(gif.frames[0].indices.len(), 4);
+        assert_eq!(transparencies(&gif), vec![None]);
+    }
+
+    #[test]
+    fn test_crop() {
+        let options = Options {
+            crop: Some(Crop { x: 1, y: 0, width: 3, height: 4 }),
+            ..Options::default()
+        };
+        let gif = engiffen_with_options(&transparent_frames(), 10, Quantizer::Naive, &options).unwrap();
+        assert_eq!((gif.width, gif.height), (3, 4));
+        assert_eq!(transparencies(&gif), vec![None]);
+    }
+
+    #[test]
+    fn test_auto_trim() {
+        let mut frames = vec![solid(5, 5, [255; 4]); 2];
+        frames[0].pixels[6] = [0, 0, 0, 255];
+        frames[1].pixels[13] = [0, 0, 0, 255];
+        let options = Options { auto_trim: true, ..Options::default() };
+        let gif = engiffen_with_options(&frames, 10, Quantizer::Naive, &options).unwrap();
+        assert_eq!((gif.width, gif.height), (3, 2));
+    }
+
+    #[test]
+    fn test_rotate_and_flip() {
+        let mut frame = solid(3, 2, [255; 4]);
+        frame.pixels[0] = [0, 0, 0, 255];
+        let options = Options { rotation: Some(Rotation::Rotate90), flip_vertical: true, ..Options::default() };
+        let gif = engiffen_with_options(&[frame], 10, Quantizer::Naive, &options).unwrap();
+        assert_eq!((gif.width, gif.height), (2, 3));
+        let black = gif.frames[0].indices[5] as usize * 3;
+        assert_eq!(&gif.palette[black..black + 3], &[0, 0, 0]);
+    }
+
+    #[test]
+    fn test_map_frames() {
+        let frames = vec![solid(2, 2, [255; 4]); 3];
+        let options = Options {
+            map_frames: Some(FrameHook::new(|index, frame| {
+                if index == 1 {
+                    frame.put_pixel(0, 0, image::Rgba([0, 0, 0, 255]));
+                }
+            })),
+            ..Options::default()
+        };
+        let gif = engiffen_with_options(&frames, 10, Quantizer::Naive, &options).unwrap();
+        assert_eq!(gif.frames[0].indices, gif.frames[2].indices);
+        assert!(gif.frames[1].indices[0] != gif.frames[1].indices[1]);
+    }
+
+    #[test]
+    fn test_map_frames_resize_is_an_error() {
+        let frames = vec![solid(2, 2, [255; 4])];
+        let options = Options {
+            map_frames: Some(FrameHook::new(|_, frame| *frame = image::ImageBuffer::new(1, 1))),
+            ..Options::default()
+        };
+        match engiffen_with_options(&frames, 10, Quantizer::Naive, &options) {
+            Err(Error::Mismatch(expected, actual)) => assert_eq!((expected, actual), ((2, 2), (1, 1))),
+            _ => panic!("Resizing a frame in the hook should be an error"),
+        }
+    }
+
+    #[test]
+    fn test_grayscale() {
+        let gif = engiffen(&transparent_frames(), 10, Quantizer::Grayscale).unwrap();
+        assert_eq!(gif.palette.len(), 256 * 3);
+        assert_eq!(transparencies(&gif), vec![Some(255)]);
+        assert_eq!(gif.frames[0].indices[1], 76);
+        assert_eq!(gif.frames[0].indices[2], 0);
+
+        let imgs = vec![Image { pixels: vec![[255, 255, 255, 255], [10, 10, 10, 255]], width: 2, height: 1 }];
+        let gif = engiffen(&imgs, 10, Quantizer::Grayscale).unwrap();
+        assert_eq!(*gif.frames[0].indices, vec![255, 10]);
+        assert_eq!(&gif.palette[30..33], &[10, 10, 10]);
+        assert_eq!(transparencies(&gif), vec![None]);
+    }
+
+    #[test]
+    fn test_gray_input_skips_neuquant() {
+        let imgs: Vec<_> = (0..2).map(|n| Image {
+            pixels: (0..256).map(|v| [v as u8, v as u8, v as u8, 255 - n]).collect(),
+            width: 16,
+            height: 16,
+        }).collect();
+        let gif = engiffen(&imgs, 10, Quantizer::NeuQuant(1)).unwrap();
+        assert_eq!(gif.palette.len(), 256 * 3);
+        assert_eq!(*gif.frames[0].indices, (0..256).map(|v| v as u8).collect::<Vec<_>>());
+
+        let mut colored = imgs.clone();
+        colored[1].pixels[0] = [255, 0, 0, 255];
+        let gif = engiffen(&colored, 10, Quantizer::NeuQuant(1)).unwrap();
+        assert_ne!(*gif.frames[0].indices, (0..256).map(|v| v as u8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_opaque_sampling_skips_alpha() {
+        // Opaque pixels with partial alpha, 3 wide so that sampled columns
+        // don't line up with every other pixel of the whole image.
+        let img = Image {
+            pixels: (0..9).map(|v| [v as u8, 0, 0, 200]).collect(),
+            width: 3,
+            height: 3,
+        };
+        let imgs = [img];
+        let opaque = neuquant_sample(&imgs, 2, 1, false, 128);
+        assert_eq!(opaque, neuquant_sample(&imgs, 2, 1, true, 128));
+        let reds: Vec<_> = opaque.chunks(4).map(|px| px[0]).collect();
+        assert_eq!(reds, vec![0, 2, 6, 8]);
+        assert!(opaque.chunks(4).all(|px| px[3] == 255));
+        assert_eq!(naive_sample(&imgs, 1, false, 128), naive_sample(&imgs, 1, true, 128));
+    }
+
+    #[test]
+    fn test_identical_frames_share_indices() {
+        let mapping = Mapping::from_palette(vec![0, 0, 0, 255, 255, 255], None, 128);
+        let dark = Image { pixels: vec![[10, 10, 10, 255], [250, 250, 250, 255]], width: 2, height: 1 };
+        let light = solid(2, 1, [250, 250, 250, 255]);
+        let imgs = vec![dark.clone(), light.clone(), dark.clone(), light, dark];
+        let indices = mapping.index_frames(&imgs, &Options::default()).unwrap();
+        assert_eq!(indices, imgs.iter().map(|img| Arc::new(mapping.index(&img.pixels))).collect::<Vec<_>>());
+        assert!(Arc::ptr_eq(&indices[0], &indices[4]));
+        assert!(!Arc::ptr_eq(&indices[0], &indices[1]));
+
+        let mut previous = None;
+        let first = mapping.index_after(&imgs[0].pixels, &mut previous);
+        assert_eq!(*first, vec![0, 1]);
+        assert!(Arc::ptr_eq(&mapping.index_after(&imgs[0].pixels, &mut previous), &first));
+        assert_eq!(*mapping.index_after(&imgs[1].pixels, &mut previous), vec![1, 1]);
+    }
+
+    #[test]
+    fn test_image_crate_conversions() {
+        let mut buf = image::RgbaImage::new(2, 1);
+        buf.put_pixel(1, 0, image::Rgba([1, 2, 3, 4]));
+        let img = Image::from(buf.clone());
+        assert_eq!(img.pixels, vec![[0, 0, 0, 0], [1, 2, 3, 4]]);
+        assert_eq!(image::RgbaImage::from(img.clone()).into_raw(), buf.clone().into_raw());
+        assert_eq!(Image::from(image::DynamicImage::ImageRgba8(buf)), img);
+    }
+
+    #[test]
+    fn test_to_bytes() {
+        let imgs = vec![solid(2, 2, [255, 0, 0, 255])];
+        let gif = engiffen(&imgs, 10, Quantizer::Naive).unwrap();
+        let mut written = Vec::new();
+        gif.write(&mut written).unwrap();
+        let bytes = gif.to_bytes().unwrap();
+        assert_eq!(bytes, written);
+        assert_eq!(&bytes[..6], b"GIF89a");
+    }
+
+    #[test]
+    fn test_write_to_file() {
+        let imgs = vec![solid(2, 2, [255, 0, 0, 255])];
+        let gif = engiffen(&imgs, 10, Quantizer::Naive).unwrap();
+        let path = std::env::temp_dir().join("engiffen-test-write-to-file.gif");
+        gif.write_to_file(&path).unwrap();
+        let mut written = Vec::new();
+        File::open(&path).unwrap().read_to_end(&mut written).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written, gif.to_bytes().unwrap());
+
+        match gif.write_to_file("tests/no-such-dir/out.gif") {
+            Err(Error::ImageWrite(err)) => assert!(err.to_string().contains("tests/no-such-dir/out.gif")),
+            other => panic!("expected a write error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_gif_frames() {
+        let imgs = vec![
+            Image { pixels: vec![[255, 0, 0, 255], [0, 0, 0, 0]], width: 2, height: 1 },
+            Image { pixels: vec![[0, 0, 255, 255], [0, 255, 0, 255]], width: 2, height: 1 },
+        ];
+        let gif = engiffen(&imgs, 10, Quantizer::Naive).unwrap();
+        let frames: Vec<_> = gif.rgba_frames().map(|frame| frame.into_raw()).collect();
+        assert_eq!(frames, vec![
+            vec![255, 0, 0, 255, 0, 0, 0, 0],
+            vec![0, 0, 255, 255, 0, 255, 0, 255],
+        ]);
+    }
+
+    #[test]
+    fn test_partial_frames() {
+        let mut gif = Gif {
+            palette: vec![255, 0, 0, 0, 0, 255],
+            width: 2,
+            height: 2,
+            frames: vec![Frame::new(vec![0; 4], 2, 2), Frame::new(vec![1], 1, 1), Frame::new(vec![1], 1, 1)],
+        };
+        gif.frames[1].left = 1;
+        gif.frames[1].dispose = Disposal::Background;
+        gif.frames[2].top = 1;
+
+        let (red, blue, clear) = (vec![255, 0, 0, 255], vec![0, 0, 255, 255], vec![0, 0, 0, 0]);
+        let rendered: Vec<_> = gif.rgba_frames().map(|frame| frame.into_raw()).collect();
+        assert_eq!(rendered, vec![
+            [&red[..], &red, &red, &red].concat(),
+            [&red[..], &blue, &red, &red].concat(),
+            [&red[..], &clear, &blue, &red].concat(),
+        ]);
+
+        let (decoded, _) = read_gif(&gif.to_bytes().unwrap()[..]).unwrap();
+        let decoded: Vec<_> = decoded.into_iter().map(|img| img.to_rgba_image().into_raw()).collect();
+        assert_eq!(decoded, rendered);
+    }
+
+    #[test]
+    fn test_append() {
+        let red = vec![solid(2, 2, [255, 0, 0, 255])];
+        let blue = vec![Image { pixels: vec![[0, 0, 255, 255], [0, 0, 0, 0], [0, 0, 250, 255], [0, 0, 250, 255]], width: 2, height: 2 }];
+        let mut gif = engiffen(&[red[0].clone(), blue[0].clone()], 10, Quantizer::Naive).unwrap();
+        let first = gif.clone();
+        gif.append(&first).unwrap();
+        assert_eq!(gif.frames.len(), 4);
+        assert_eq!(gif.frames[2], first.frames[0]);
+
+        let mut gif = engiffen(&red, 10, Quantizer::Naive).unwrap();
+        gif.append(&engiffen(&blue, 20, Quantizer::Naive).unwrap()).unwrap();
+        assert_eq!(delays(&gif), vec![10, 5]);
+        // Only red is available, but a slot is added for transparency.
+        assert_eq!(gif.palette, vec![255, 0, 0, 0, 0, 0]);
+        assert_eq!(*gif.frames[1].indices, vec![0, 1, 0, 0]);
+        assert_eq!(gif.frames[1].transparent, Some(1));
+
+        let small = engiffen(&[Image { pixels: vec![[0, 0, 0, 255]], width: 1, height: 1 }], 10, Quantizer::Naive).unwrap();
+        assert!(gif.append(&small).is_err());
+    }
+
+    #[test]
+    fn test_slice() {
+        let imgs: Vec<_> = (0..5).map(|n| solid(2, 2, [n * 50, 0, 0, 255])).collect();
+        let gif = engiffen(&imgs, 10, Quantizer::Naive).unwrap();
+        let middle = gif.slice(1..4);
+        assert_eq!(middle.palette, gif.palette);
+        assert_eq!(middle.frames, gif.frames[1..4].to_vec());
+        assert_eq!(gif.slice(3..).frames.len(), 2);
+        assert_eq!(gif.slice(..=0).frames, vec![gif.frames[0].clone()]);
+    }
+
+    #[test]
+    fn test_gif_iteration() {
+        let imgs: Vec<_> = (0..3).map(|n| solid(2, 2, [n * 100, 0, 0, 255])).collect();
+        let mut gif = engiffen(&imgs, 10, Quantizer::Naive).unwrap();
+        for frame in &mut gif {
+            frame.delay *= 2;
+        }
+        gif[1].delay = 5;
+        assert_eq!((&gif).into_iter().map(|frame| frame.delay).collect::<Vec<_>>(), vec![20, 5, 20]);
+        assert_eq!(gif[2], gif.frames[2]);
+        let owned: Vec<Frame> = gif.clone().into_iter().collect();
+        assert_eq!(owned, gif.frames);
+    }
+
+    #[test]
+    fn test_gif_accessors() {
+        let imgs = transparent_frames();
+        let gif = engiffen(&imgs, 8, Quantizer::Naive).unwrap();
+        assert_eq!(gif.frame_count(), imgs.len());
+        assert_eq!(gif.total_duration(), Duration::from_millis(130 * imgs.len() as u64));
+        assert_eq!(gif.dimensions(), (imgs[0].width as u16, imgs[0].height as u16));
+        assert_eq!(gif.palette_len() * 3, gif.palette.len());
+    }
+
+    #[test]
+    fn test_export_frames() {
+        let imgs: Vec<_> = (0..2).map(|n| solid(2, 2, [n * 200, 0, 0, 255])).collect();
+        let gif = engiffen(&imgs, 10, Quantizer::Naive).unwrap();
+        let dir = std::env::temp_dir().join("engiffen-test-export-frames");
+        std::fs::create_dir_all(&dir).unwrap();
+        gif.export_frames(&dir).unwrap();
+        let second = load_image(dir.join("frame0001.png")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(second, imgs[1]);
+    }
+
+    #[test]
+    #[cfg(feature = "parallel-write")]
+    fn test_parallel_write() {
+        use std::borrow::Cow;
+        let imgs: Vec<_> = (0..40).map(|n| Image { pixels: vec![[n * 6, 0, 0, 255], [0, 0, 0, 0], [0, n, 255, 255], [9, 9, 9, 255]], width: 2, height: 2 }).collect();
+        let mut gif = engiffen(&imgs, 10, Quantizer::Naive).unwrap();
+        gif.frames[3].label = Some("three".to_string());
+        let mut serial = Vec::new();
+        super::write_frames(&mut serial, gif.width, gif.height, &gif.palette, gif.frames.iter().map(|frame| Ok(Cow::Borrowed(frame)))).unwrap();
+        assert_eq!(gif.to_bytes().unwrap(), serial);
+        gif.frames.clear();
+        assert_eq!(Gif::read(&gif.to_bytes().unwrap()[..]).unwrap().frame_count(), 0);
+    }
+
+    #[test]
+    fn test_palette_swatch() {
+        let gif = engiffen(&transparent_frames(), 10, Quantizer::Naive).unwrap();
+        let swatch = gif.palette_swatch(4);
+        let colors = gif.palette_len() as u32;
+        assert_eq!(swatch.dimensions(), (colors.min(16) * 4, colors.div_ceil(16) * 4));
+        let transparent = gif.frames[0].transparent.unwrap() as u32;
+        assert_eq!(swatch.get_pixel(transparent % 16 * 4 + 3, transparent / 16 * 4)[3], 0);
+        let red = (0..colors).find(|&n| gif.palette[n as usize * 3..n as usize * 3 + 3] == [255, 0, 0]).unwrap();
+        assert_eq!(swatch.get_pixel(red % 16 * 4, red / 16 * 4 + 2).data, [255, 0, 0, 255]);
+
+        let palette = quantize(&sample(&transparent_frames(), Quantizer::Naive, &Options::default()).unwrap());
+        assert_eq!(palette.swatch(1).dimensions().1, 1);
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn thread_safe<T: Send + Sync>() {}
+        thread_safe::<Image>();
+        thread_safe::<Gif>();
+        thread_safe::<Frame>();
+
+        let mut gif = engiffen(&transparent_frames(), 10, Quantizer::Naive).unwrap();
+        let shared = ::std::thread::spawn({
+            let gif = gif.clone();
+            move || gif.frames[0].indices.clone()
+        }).join().unwrap();
+        assert!(Arc::ptr_eq(&shared, &gif.frames[0].indices));
+        let single = gif.memory_usage();
+        gif.frames.push(gif.frames[0].clone());
+        assert_eq!(gif.memory_usage(), single + mem::size_of::<Frame>());
+    }
+
+    #[test]
+    fn test_thread_pool() {
+        let imgs = transparent_frames();
+        let options = Options { thread_pool: Some(ThreadPool::new(1).unwrap()), ..Options::default() };
+        for &quantizer in &[Quantizer::Naive, Quantizer::NeuQuant(2)] {
+            let pooled = engiffen_with_options(&imgs, 10, quantizer, &options).unwrap();
+            assert!(pooled == engiffen_with_options(&imgs, 10, quantizer, &Options::default()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_deterministic() {
+        // 400 colors seen once each, so that which ones make the palette
+        // comes down to how ties are broken.
+        let imgs: Vec<Image> = (0..16u32).map(|n| {
+            let pixels = (0..25u32).map(|i| [(n * 25 + i) as u8, ((n * 25 + i) >> 8) as u8 * 80, i as u8 * 9, 255]).collect();
+            Image { pixels, width: 5, height: 5 }
+        }).collect();
+        for &quantizer in &[Quantizer::Naive, Quantizer::NeuQuant(1)] {
+            let gifs: Vec<Vec<u8>> = [1, 3, 8].iter().map(|&threads| {
+                let options = Options { thread_pool: Some(ThreadPool::new(threads).unwrap()), ..Options::default() };
+                engiffen_with_options(&imgs, 10, quantizer, &options).unwrap().to_bytes().unwrap()
+            }).collect();
+            assert!(gifs[0] == gifs[1] && gifs[1] == gifs[2], "{:?}", quantizer);
+        }
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        let imgs = transparent_frames();
+        let invalid = |quantizer, options: &Options| match engiffen_with_options(&imgs, 10, quantizer, options) {
+            Err(Error::InvalidOption(name, _)) => name,
+            other => panic!("expected an invalid option, got {:?}", other.map(|_| ())),
+        };
+        assert_eq!(invalid(Quantizer::NeuQuant(0), &Options::default()), "quantizer");
+        assert_eq!(invalid(Quantizer::Naive, &Options { gamma: 0.0, ..Options::default() }), "gamma");
+        assert_eq!(invalid(Quantizer::Naive, &Options { saturation: f32::NAN, ..Options::default() }), "saturation");
+        for &scale in &[Scale::Factor(0.0), Scale::Factor(-2.0), Scale::Factor(f32::NAN), Scale::Factor(f32::INFINITY), Scale::Width(0), Scale::Height(0), Scale::Size(4, 0)] {
+            assert_eq!(invalid(Quantizer::Naive, &Options { scale: Some(scale), ..Options::default() }), "scale");
+        }
+        for &crop in &[Crop { x: 10, y: 10, width: 2, height: 2 }, Crop { x: 0, y: 0, width: 0, height: 0 }, Crop { x: 2, y: 0, width: 3, height: 4 }] {
+            assert_eq!(invalid(Quantizer::Naive, &Options { crop: Some(crop), ..Options::default() }), "crop");
+        }
+    }
+
+    #[test]
+    fn test_push_frame() {
+        let imgs = transparent_frames();
+        let mut gif = Gif::read(&engiffen(&imgs, 10, Quantizer::Naive).unwrap().to_bytes().unwrap()[..]).unwrap();
+        let mut pink = imgs[0].clone();
+        pink.pixels[2] = [250, 10, 10, 255];
+        gif.push_frame(&pink, 20).unwrap();
+        assert_eq!(gif.frame_count(), 2);
+        assert_eq!(gif.frames[1].delay, 5);
+        let (frames, _) = read_gif(&gif.to_bytes().unwrap()[..]).unwrap();
+        assert_eq!(frames[1].pixels[2], [255, 0, 0, 255]);
+        assert_eq!(frames[1].pixels[3], [0, 0, 0, 255]);
+        assert_eq!(gif.frames[1].transparent, gif.frames[0].transparent);
+
+        // An opaque Gif gets a transparent slot when a frame needs one.
+        let opaque = vec![solid(4, 4, [0, 0, 255, 255])];
+        let mut gif = engiffen(&opaque, 10, Quantizer::Naive).unwrap();
+        let colors = gif.palette_len();
+        gif.push_frame(&imgs[0], 10).unwrap();
+        assert_eq!(gif.frames[1].transparent, Some(colors as u8));
+        assert!(gif.push_frame(&Image { pixels: vec![], width: 0, height: 0 }, 10).is_err());
+
+        // Frames pushed with a higher alpha threshold are keyed like frames
+        // encoded with it.
+        let mut faded = imgs[0].clone();
+        faded.pixels[1][3] = 100;
+        let options = Options { alpha_threshold: 128, ..Options::default() };
+        let mut gif = engiffen_with_options(&imgs, 10, Quantizer::Naive, &options).unwrap();
+        let transparent = gif.frames[0].transparent.unwrap();
+        gif.push_frame_with_options(&faded, 10, &options).unwrap();
+        assert_eq!(gif.frames[1].indices[1], transparent);
+        gif.push_frame(&faded, 10).unwrap();
+        assert!(gif.frames[2].indices[1] != transparent);
+    }
+
+    #[test]
+    fn test_labels() {
+        let paths = ["tests/ball/ball01.bmp", "tests/ball/ball02.bmp"];
+        let mut out = Vec::new();
+        let options = Options { label_frames: true, ..Options::default() };
+        engiffen_files(&paths, 10, Quantizer::Naive, &options, &mut out).unwrap();
+        let gif = Gif::read(&out[..]).unwrap();
+        assert_eq!(gif.frames[1].label.as_deref(), Some(paths[1]));
+
+        let mut reversed = gif.clone();
+        reversed.reverse();
+        reversed.set_labels(vec!["last"]);
+        let labels: Vec<_> = Gif::read(&reversed.to_bytes().unwrap()[..]).unwrap().frames.into_iter().map(|frame| frame.label).collect();
+        assert_eq!(labels, vec![Some("last".to_string()), Some(paths[0].to_string())]);
+        assert!(read_gif(&reversed.to_bytes().unwrap()[..]).is_ok());
+    }
+
+    #[test]
+    fn test_max_quantization_error() {
+        let pixels = (0..256).map(|n| [n as u8, (n * 7 % 256) as u8, 0, 255]).collect();
+        let imgs = vec![Image { pixels, width: 16, height: 16 }];
+        let strict = Options { max_quantization_error: Some(1.0), ..Options::default() };
+        match engiffen_with_options(&imgs, 10, Quantizer::Grayscale, &strict) {
+            Err(Error::QuantizationError(0, error)) => assert!(error > 1.0),
+            other => panic!("expected a quantization error, got {:?}", other.map(|_| ())),
+        }
+        assert!(engiffen_write(&imgs, 10, Quantizer::Grayscale, &strict, &mut Vec::new()).is_err());
+        let lenient = Options { max_quantization_error: Some(255.0), ..Options::default() };
+        assert!(engiffen_with_options(&imgs, 10, Quantizer::Grayscale, &lenient).is_ok());
+        let exact = transparent_frames();
+        assert!(engiffen_with_options(&exact, 10, Quantizer::Naive, &Options { max_quantization_error: Some(0.0), ..Options::default() }).is_ok());
+    }
+
+    #[test]
+    fn test_frame_rate() {
+        let imgs = transparent_frames();
+        for fps in &[0.0, -10.0] {
+            match engiffen(&imgs, *fps, Quantizer::Naive) {
+                Err(Error::InvalidOption("fps", _)) => (),
+                other => panic!("expected an invalid frame rate, got {:?}", other.map(|_| ())),
+            }
+        }
+        assert!(engiffen_preview(&imgs, 0, &Options::default()).is_err());
+
+        // Five ms frames merge in fours into frames browsers show on time.
+        let imgs: Vec<Image> = (0..8).map(|_| imgs[0].clone()).collect();
+        let options = Options { merge_fast_frames: true, ..Options::default() };
+        let gif = engiffen_with_options(&imgs, 200, Quantizer::Naive, &options).unwrap();
+        assert_eq!(gif.frames.iter().map(|frame| frame.delay).collect::<Vec<_>>(), vec![2, 2]);
+        let gif = engiffen_with_options(&imgs, 200, Quantizer::Naive, &Options::default()).unwrap();
+        assert_eq!(gif.frames.len(), imgs.len());
+    }
+
+    #[test]
+    fn test_error_source() {
+        use std::error::Error as StdError;
+        let err = load_image("tests/no-such.png").unwrap_err();
+        assert!(err.source().is_some());
+        assert!(Error::NoImages.source().is_none());
+        assert_eq!(Error::InvalidOption("gamma", "must be positive".to_string()).to_string(), "Invalid option gamma: must be positive");
+    }
+
+    #[test]
+    fn test_dimensions_too_large() {
+        let imgs = vec![solid(70_000, 1, [255, 0, 0, 255])];
+        match engiffen(&imgs, 10, Quantizer::Naive) {
+            Err(Error::DimensionsTooLarge(70_000, 1)) => (),
+            other => panic!("expected dimensions too large, got {:?}", other.map(|_| ())),
+        }
+        assert!(matches!(engiffen_write(&imgs, 10, Quantizer::Naive, &Options::default(), &mut Vec::new()), Err(Error::DimensionsTooLarge(70_000, 1))));
+
+        let options = Options { downscale_to_fit: true, scale_filter: Filter::Nearest, ..Options::default() };
+        let gif = engiffen_with_options(&imgs, 10, Quantizer::Naive, &options).unwrap();
+        assert_eq!((gif.width, gif.height), (65535, 1));
+    }
+
+    #[test]
+    fn test_cancel() {
+        let imgs = transparent_frames();
+        let token = CancelToken::new();
+        let options = Options { cancel: Some(token.clone()), ..Options::default() };
+        assert!(engiffen_with_options(&imgs, 10, Quantizer::Naive, &options).is_ok());
+
+        token.cancel();
+        match engiffen_with_options(&imgs, 10, Quantizer::Naive, &options) {
+            Err(Error::Cancelled) => (),
+            other => panic!("expected a cancellation, got {:?}", other),
+        }
+        let mut out = Vec::new();
+        assert!(matches!(engiffen_write(&imgs, 10, Quantizer::Naive, &options, &mut out), Err(Error::Cancelled)));
+
+        // A hook can cancel partway through.
+        let token = CancelToken::new();
+        let from_hook = token.clone();
+        let options = Options {
+            map_frames: Some(FrameHook::new(move |_, _| from_hook.cancel())),
+            cancel: Some(token),
+            ..Options::default()
+        };
+        assert!(engiffen_with_options(&imgs, 10, Quantizer::Naive, &options).is_err());
+    }
+
+    #[test]
+    fn test_pipeline_stages() {
+        let imgs = load_images(&["tests/ball/ball01.bmp", "tests/ball/ball02.bmp"]);
+        let options = Options::default();
+        for &quantizer in &[Quantizer::Naive, Quantizer::NeuQuant(4), Quantizer::Grayscale] {
+            let samples = sample(&imgs, quantizer, &options).unwrap();
+            let gif = palettize(&imgs, &quantize(&samples), 10, &options).unwrap();
+            assert_eq!(gif, engiffen(&imgs, 10, quantizer).unwrap());
+        }
+
+        // A palette sampled from one frame still maps colors only seen in another.
+        let red = solid(2, 2, [255, 0, 0, 255]);
+        let pink = solid(2, 2, [255, 100, 100, 255]);
+        let palette = quantize(&sample(&[red], Quantizer::Naive, &options).unwrap());
+        assert_eq!(palette.colors(), &[255, 0, 0]);
+        assert_eq!(indices(&palettize(&[pink], &palette, 10, &options).unwrap()), vec![vec![0; 4]]);
+    }
+
+    #[test]
+    fn test_max_memory() {
+        let imgs: Vec<_> = (0..4).map(|n| solid(16, 16, [n * 60, 0, 0, 255])).collect();
+        let options = Options { max_memory: Some(2000), ..Options::default() };
+        let (_, stats) = engiffen_with_stats(&imgs, 10, Quantizer::NeuQuant(1), &options).unwrap();
+        assert_eq!(stats.colors_sampled, 6 * 6 * 4);
+
+        // The palettized frames alone take 1024 bytes.
+        let options = Options { max_memory: Some(1000), ..Options::default() };
+        assert!(matches!(engiffen_with_options(&imgs, 10, Quantizer::NeuQuant(1), &options), Err(Error::MemoryLimit(_))));
+        assert!(engiffen_write(&imgs, 10, Quantizer::NeuQuant(1), &options, &mut Vec::new()).is_ok());
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let gif = engiffen(&transparent_frames(), 10, Quantizer::Naive).unwrap();
+        assert!(gif.memory_usage() >= gif.palette.len() + 16);
+        let estimate = estimate_memory(4, 4, 1, Quantizer::Naive, &Options::default());
+        assert_eq!((estimate.frames, estimate.indices), (64, 16));
+    }
+
+    #[test]
+    fn test_background_mismatch() {
+        let options = Options {
+            background: Some(solid(2, 2, [0, 0, 0, 255])),
+            ..Options::default()
+        };
+        match engiffen_with_options(&transparent_frames(), 10, Quantizer::Naive, &options) {
+            Err(Error::Mismatch(frames, background)) => assert_eq!((frames, background), ((4, 4), (2, 2))),
+            _ => panic!("A background of the wrong size should be an error"),
+        }
+    }
+
+    #[test]
+    fn test_background_fills_transparency() {
+        let options = Options {
+            background: Some(solid(4, 4, [0, 0, 255, 255])),
+            ..Options::default()
+        };
+        let gif = engiffen_with_options(&transparent_frames(), 10, Quantizer::Naive, &options).unwrap();
+        assert_eq!(transparencies(&gif), vec![None]);
+        let idx = gif.frames[0].indices[0] as usize;
+        assert_eq!(&gif.palette[idx * 3..idx * 3 + 3], &[0, 0, 255]);
+    }
+
+    #[test]
+    fn test_opaque_option() {
+        let options = Options { opaque: true, ..Options::default() };
+        for quantizer in &[Quantizer::Naive, Quantizer::NeuQuant(1)] {
+            let gif = engiffen_with_options(&transparent_frames(), 10, *quantizer, &options).unwrap();
+            assert_eq!(transparencies(&gif), vec![None]);
+        }
+        let gif = engiffen_with_options(&transparent_frames(), 10, Quantizer::NeuQuant(1), &options).unwrap();
+        assert_eq!(gif.palette.len(), 256 * 3);
+    }
+
+    #[test]
+    fn test_slow_frame_rate() {
+        let gif = engiffen(&transparent_frames(), 0.5, Quantizer::Naive).unwrap();
+        assert_eq!(delays(&gif), vec![200]);
+    }
+
+    #[test]
+    fn test_high_frame_rate_delay() {
+        let gif = engiffen(&transparent_frames(), 150, Quantizer::Naive).unwrap();
+        assert_eq!(delays(&gif), vec![1]);
+        let gif = engiffen(&transparent_frames(), 1000, Quantizer::Naive).unwrap();
+        assert_eq!(delays(&gif), vec![1]);
+    }
+
+    #[test]
+    fn test_first_frame_delay() {
+        let imgs = vec![transparent_frames().remove(0); 3];
+        let options = Options { first_frame_delay: Some(Duration::from_secs(2)), ..Options::default() };
+        let gif = engiffen_with_options(&imgs, 10, Quantizer::Naive, &options).unwrap();
+        assert_eq!(delays(&gif), vec![200, 10, 10]);
+    }
+
+    #[test]
+    fn test_retime() {
+        let imgs = vec![transparent_frames().remove(0); 2];
+        let mut gif = engiffen(&imgs, 10, Quantizer::Naive).unwrap();
+        gif.retime(Duration::from_millis(500)).unwrap();
+        assert_eq!(delays(&gif), vec![50, 50]);
+        gif.frames[1].delay = 20;
+        gif.change_speed(2.0).unwrap();
+        assert_eq!(delays(&gif), vec![25, 10]);
+        gif.change_speed(0.1).unwrap();
+        assert_eq!(delays(&gif), vec![250, 100]);
+
+        assert!(gif.retime(0).is_err());
+        for &multiplier in &[0.0, -1.0, f32::INFINITY, f32::NAN] {
+            match gif.change_speed(multiplier) {
+                Err(Error::InvalidOption("multiplier", _)) => {},
+                other => panic!("expected a speed of {} to be rejected, got {:?}", multiplier, other),
+            }
+        }
+        assert_eq!(delays(&gif), vec![250, 100]);
+    }
+
+    #[test]
+    fn test_reverse() {
+        let mut imgs = transparent_frames();
+        imgs.push(solid(4, 4, [255, 0, 0, 255]));
+        let mut gif = engiffen(&imgs, 10, Quantizer::Naive).unwrap();
+        gif.frames[0].delay = 50;
+        let forward = gif.clone();
+        gif.reverse();
+        assert_eq!(indices(&gif), vec![forward.frames[1].indices.to_vec(), forward.frames[0].indices.to_vec()]);
+        assert_eq!(transparencies(&gif), vec![None, Some(2)]);
+        assert_eq!(delays(&gif), vec![10, 50]);
+        assert_eq!(gif.palette, forward.palette);
+    }
+
+    #[test]
+    fn test_ping_pong() {
+        let imgs: Vec<_> = (0..4).map(|n| solid(4, 4, [n * 50, 0, 0, 255])).collect();
+        let mut gif = engiffen(&imgs, 10, Quantizer::Naive).unwrap();
+        let forward = indices(&gif);
+        gif.ping_pong();
+        let order: Vec<_> = indices(&gif).iter().map(|img| forward.iter().position(|f| f == img).unwrap()).collect();
+        assert_eq!(order, vec![0, 1, 2, 3, 2, 1]);
+        assert_eq!(delays(&gif).len(), 6);
+        assert_eq!(transparencies(&gif).len(), 6);
+    }
+
+    #[test]
+    fn test_keep_every_nth() {
+        let imgs: Vec<_> = (0..7).map(|n| solid(4, 4, [n * 30, 0, 0, 255])).collect();
+        let options = Options { keep_every_nth: 3, ..Options::default() };
+        let gif = engiffen_with_options(&imgs, 60, Quantizer::Naive, &options).unwrap();
+        assert_eq!(gif.frames.len(), 3);
+        assert_eq!(delays(&gif), vec![5, 5, 2]);
+        let colors: Vec<_> = indices(&gif).iter().map(|img| gif.palette[img[0] as usize * 3]).collect();
+        assert_eq!(colors, vec![0, 90, 180]);
+    }
+
+    #[test]
+    fn test_motion_blur() {
+        let imgs: Vec<_> = (0..4).map(|n| solid(4, 4, [n * 60, 0, 0, 255])).collect();
+        let options = Options { keep_every_nth: 2, motion_blur: true, ..Options::default() };
+        let gif = engiffen_with_options(&imgs, 10, Quantizer::Naive, &options).unwrap();
+        let reds: Vec<_> = indices(&gif).iter().map(|img| gif.palette[img[0] as usize * 3]).collect();
+        assert_eq!(reds, vec![30, 150]);
+    }
+
+    #[test]
+    fn test_loop_crossfade() {
+        let imgs: Vec<_> = (0..6).map(|n| solid(4, 4, [n * 40, 0, 0, 255])).collect();
+        let options = Options { loop_crossfade: 2, ..Options::default() };
+        let gif = engiffen_with_options(&imgs, 10, Quantizer::Naive, &options).unwrap();
+        assert_eq!(gif.frames.len(), 4);
+        assert_eq!(delays(&gif).len(), 4);
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let imgs = vec![
+            solid(4, 4, [0, 0, 0, 255]),
+            solid(4, 4, [200, 0, 0, 255]),
+        ];
+        let options = Options { interpolate: Some(FrameRate::Fps(20.0)), ..Options::default() };
+        let gif = engiffen_with_options(&imgs, 5, Quantizer::Naive, &options).unwrap();
+        assert_eq!(gif.frames.len(), 8);
+        assert_eq!(delays(&gif), vec![5; 8]);
+        let reds: Vec<_> = indices(&gif).iter().map(|img| gif.palette[img[0] as usize * 3]).collect();
+        assert_eq!(reds, vec![0, 50, 100, 150, 200, 200, 200, 200]);
+
+        let options = Options { interpolate: Some(FrameRate::Fps(1e6)), ..Options::default() };
+        let gif = engiffen_with_options(&imgs, 5, Quantizer::Naive, &options).unwrap();
+        assert_eq!(gif.frames.len(), 20);
+        for &rate in &[0.0, -10.0, f32::NAN] {
+            let options = Options { interpolate: Some(FrameRate::Fps(rate)), ..Options::default() };
+            match engiffen_with_options(&imgs, 5, Quantizer::Naive, &options) {
+                Err(Error::InvalidOption("interpolate", _)) => {},
+                other => panic!("expected an invalid interpolate rate, got {:?}", other.map(|gif| gif.frames.len())),
+            }
+        }
+    }
+
+    #[test]
+    fn test_delay_dither() {
+        let imgs = vec![transparent_frames().remove(0); 6];
+        let options = Options { delay_dither: true, ..Options::default() };
+        let gif = engiffen_with_options(&imgs, 30, Quantizer::Naive, &options).unwrap();
+        assert_eq!(delays(&gif), vec![3, 4, 3, 3, 4, 3]);
+    }
+
+    #[test]
+    fn test_no_transparent_index_when_opaque() {
+        let imgs = vec![solid(4, 4, [10, 20, 30, 255])];
+        let gif = engiffen(&imgs, 10, Quantizer::Naive).unwrap();
+        assert_eq!(transparencies(&gif), vec![None]);
+        assert_eq!(gif.palette, vec![10, 20, 30]);
+    }
+
+    #[test]
+    fn test_error_on_size_mismatch() {
+        let imgs = load_images_from_dir("tests/mismatched_size", &[]).unwrap();
+
+        let res = engiffen(&imgs, 30, Quantizer::NeuQuant(1));
 
         assert!(res.is_err());
         match res {
@@ -436,14 +2974,7 @@ mod tests {
     #[test] #[ignore]
     fn test_compress_palette() {
         // This takes a while to run when not in --release
-        let imgs: Vec<_> = read_dir("tests/ball").unwrap()
-            .map(|e| e.unwrap().path())
-            .filter(|path| match path.extension() {
-                Some(ext) if ext == "bmp" => true,
-                _ => false,
-            })
-            .map(|path| load_image(&path).unwrap())
-            .collect();
+        let imgs = load_images_from_dir("tests/ball", &["bmp"]).unwrap();
 
         let mut out = File::create("tests/ball.gif").unwrap();
         let gif = engiffen(&imgs, 10, Quantizer::NeuQuant(2));
@@ -455,14 +2986,7 @@ mod tests {
 
     #[test] #[ignore]
     fn test_simple_paletted_gif() {
-        let imgs: Vec<_> = read_dir("tests/shrug").unwrap()
-            .map(|e| e.unwrap().path())
-            .filter(|path| match path.extension() {
-                Some(ext) if ext == "tga" => true,
-                _ => false,
-            })
-            .map(|path| load_image(&path).unwrap())
-            .collect();
+        let imgs = load_images_from_dir("tests/shrug", &["tga"]).unwrap();
 
         let mut out = File::create("tests/shrug.gif").unwrap();
         let gif = engiffen(&imgs, 30, Quantizer::NeuQuant(2));
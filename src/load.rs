@@ -0,0 +1,898 @@
+//! Loading images and animations from files and readers.
+
+use std::{cmp, io};
+#[cfg(feature = "fs")] use std::fs::{self, File};
+use std::path::Path;
+#[cfg(feature = "fs")] use std::path::PathBuf;
+use std::time::Duration;
+use image;
+#[cfg(feature = "fs")] use image::RgbaImage;
+use animated;
+#[cfg(feature = "archives")] use archive;
+#[cfg(feature = "capture")] use capture;
+#[cfg(feature = "fs")] use config::{self, Recipe};
+use decode;
+use depth;
+use exif;
+#[cfg(feature = "hdr")] use exr;
+#[cfg(feature = "hdr")] use hdr;
+#[cfg(feature = "icc")] use icc;
+use indexed::{self, IndexedImage};
+#[cfg(all(feature = "mmap", unix))] use mmap;
+use natural;
+#[cfg(feature = "fs")] use order::{self, SortOrder};
+use raw::{RawFormat, RawFrames};
+use sprite::{self, SpriteSheet};
+#[cfg(feature = "fs")] use timing::FrameRate;
+#[cfg(feature = "ffmpeg")] use video::VideoFrames;
+#[cfg(feature = "y4m")] use y4m::Y4mFrames;
+#[cfg(feature = "capture")] use super::{engiffen_iter, Crop, Gif, Options, Quantizer};
+use super::{Error, Image, LoadOptions};
+
+/// Loads an image from the given file path.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{load_image, Image, Error};
+/// # use std::path::PathBuf;
+/// # fn foo() -> Result<Image, Error> {
+/// let image = load_image("test/ball/ball01.bmp")?;
+/// # Ok(image)
+/// # }
+/// ```
+///
+/// JPEGs are turned and flipped upright as their EXIF orientation tag says,
+/// since phone cameras record which way up a photo was taken instead of
+/// rotating it. Use `load_image_unoriented` to keep the pixels as they're
+/// stored.
+///
+/// # Errors
+///
+/// Returns an error if the path can't be read or if the image can't be decoded,
+/// and an `Error::Unsupported` for WebP images, which the image crate only
+/// decodes as grayscale, and OpenEXR images without the `hdr` feature. Even
+/// with it, tiled, deep and multi-part OpenEXR images, and ones compressed
+/// with anything but RLE, ZIPS or ZIP, are an `Error::Unsupported`.
+#[cfg(feature = "fs")]
+pub fn load_image<P>(path: P) -> Result<Image, Error>
+    where P: AsRef<Path> {
+    load_image_with(path, &LoadOptions::default())
+}
+
+/// Like `load_image`, ignoring any EXIF orientation.
+///
+/// # Errors
+///
+/// Returns an error if the path can't be read or if the image can't be decoded
+#[cfg(feature = "fs")]
+pub fn load_image_unoriented<P>(path: P) -> Result<Image, Error>
+    where P: AsRef<Path> {
+    load_image_with(path, &LoadOptions { orient: false, ..LoadOptions::default() })
+}
+
+/// Like `load_image`, decoding as `options` say.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{load_image_with, Image, Error, LoadOptions};
+/// # fn foo() -> Result<Image, Error> {
+/// let options = LoadOptions { dither_16_bit: true, ..LoadOptions::default() };
+/// let image = load_image_with("render-0001.png", &options)?;
+/// # Ok(image)
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns an error if the path can't be read or if the image can't be decoded,
+/// and an `Error::Unsupported` for formats `load_image` can't decode.
+#[cfg(feature = "fs")]
+pub fn load_image_with<P>(path: P, options: &LoadOptions) -> Result<Image, Error>
+    where P: AsRef<Path> {
+    let bytes = fs::read(&path).map_err(image::ImageError::IoError)?;
+    decode_image(&bytes, path.as_ref(), options)
+}
+
+/// Like `load_image_with`, decoding an image read from `r` instead of a
+/// file, for frames that never touch the filesystem, like uploads in a
+/// browser. Without a file name, the format is guessed from the contents.
+///
+/// # Examples
+///
+/// ```rust
+/// # use engiffen::{read_image, LoadOptions};
+/// let bmp = include_bytes!("../tests/ball/ball01.bmp");
+/// let image = read_image(&bmp[..], &LoadOptions::default()).unwrap();
+/// assert_eq!((image.width, image.height), (256, 256));
+/// ```
+///
+/// # Errors
+///
+/// Returns an error if `r` can't be read or if the image can't be decoded,
+/// and an `Error::Unsupported` for formats `load_image` can't decode.
+pub fn read_image<R: io::Read>(mut r: R, options: &LoadOptions) -> Result<Image, Error> {
+    let mut bytes = Vec::new();
+    io::Read::read_to_end(&mut r, &mut bytes).map_err(image::ImageError::IoError)?;
+    decode_image(&bytes, Path::new(""), options)
+}
+
+/// Like `load_indexed_image`, decoding an image read from `r` instead of a
+/// file.
+///
+/// # Errors
+///
+/// Returns an error if `r` can't be read or if the image can't be decoded
+pub fn read_indexed_image<R: io::Read>(mut r: R) -> Result<Option<IndexedImage>, Error> {
+    let mut bytes = Vec::new();
+    io::Read::read_to_end(&mut r, &mut bytes).map_err(image::ImageError::IoError)?;
+    indexed::decode(&bytes).map_or(Ok(None), |img| img.map(Some))
+}
+
+/// Decodes a file's contents, in the format its extension names if it has
+/// one, as `options` say.
+fn decode_image(bytes: &[u8], path: &Path, options: &LoadOptions) -> Result<Image, Error> {
+    if let Some(format) = unsupported_format(bytes, path) {
+        return Err(Error::Unsupported(format));
+    }
+    #[cfg(feature = "hdr")]
+    {
+        if let Some(img) = hdr::decode(bytes, options.tone_map).or_else(|| exr::decode(bytes, options.tone_map)) {
+            return img;
+        }
+    }
+    let img = match depth::decode(bytes, options.dither_16_bit) {
+        Some(img) => img?,
+        None => {
+            let img = match image_format(path) {
+                Some(format) => image::load_from_memory_with_format(bytes, format),
+                None => image::load_from_memory(bytes),
+            };
+            Image::from(img?)
+        },
+    };
+    #[cfg(feature = "icc")]
+    let img = {
+        let mut img = img;
+        if let Some(profile) = icc::embedded(bytes).filter(|_| options.color_profiles).and_then(|icc| icc::Profile::parse(&icc)) {
+            profile.to_srgb(&mut img);
+        }
+        img
+    };
+    Ok(match exif::orientation(bytes) {
+        Some(orientation) if options.orient => exif::orient(img, orientation),
+        _ => img,
+    })
+}
+
+/// Reads encode settings from a config file, so that a long recipe of
+/// options can be kept alongside the frames and reused. Images it names,
+/// like a `background` or `watermark_image`, are loaded relative to the
+/// file. See `Recipe` for an example, and the README for every setting.
+///
+/// Recipes are a flat subset of TOML, so TOML tools can still read them:
+///
+/// - `key = value` lines and `#` comments.
+/// - Strings in double quotes, without escapes, or in single quotes.
+/// - Decimal integers and floats.
+/// - `true` and `false`.
+/// - Arrays of numbers, which have to fit on one line.
+///
+/// Tables, escapes, multi-line values and everything else TOML has give an
+/// `Error::Config` for the line they're on rather than being read as
+/// something else.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{load_images, load_recipe, engiffen_with_options, Quantizer};
+/// let recipe = load_recipe("preview.toml").unwrap();
+/// let images = load_images(&["frame01.png", "frame02.png"]);
+/// let quantizer = recipe.quantizer.unwrap_or(Quantizer::NeuQuant(2));
+/// let gif = engiffen_with_options(&images, recipe.fps.unwrap_or(10.into()), quantizer, &recipe.options).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Returns an `Error::Config` for settings that are unknown, set twice or
+/// out of range, and an `Error::ImageLoad` if the file or an image it names
+/// can't be read.
+#[cfg(feature = "fs")]
+pub fn load_recipe<P: AsRef<Path>>(path: P) -> Result<Recipe, Error> {
+    let text = fs::read_to_string(&path).map_err(image::ImageError::IoError)?;
+    let dir = path.as_ref().parent().unwrap_or_else(|| Path::new("."));
+    config::parse(&text, dir)
+}
+
+/// Loads a paletted PNG, or a Gif's first frame, as indices into its own
+/// palette instead of RGBA pixels, for `engiffen_indexed`. Returns `None`
+/// for images that need expanding to RGBA to be re-quantized: ones that
+/// aren't paletted, have partially transparent colors, or are Gifs whose
+/// first frame doesn't cover the whole canvas.
+///
+/// # Errors
+///
+/// Returns an error if the path can't be read or if the image can't be decoded
+#[cfg(feature = "fs")]
+pub fn load_indexed_image<P: AsRef<Path>>(path: P) -> Result<Option<IndexedImage>, Error> {
+    let bytes = fs::read(&path).map_err(image::ImageError::IoError)?;
+    indexed::decode(&bytes).map_or(Ok(None), |img| img.map(Some))
+}
+
+/// Like `load_image`, reading the file through a memory mapping instead of
+/// buffered reads, so that the OS can page it in as it's decoded and evict it
+/// afterwards. This can help with batches of very large files.
+///
+/// # Errors
+///
+/// Returns an error if the path can't be mapped or the image can't be decoded.
+#[cfg(all(feature = "mmap", unix))]
+pub fn load_image_mapped<P: AsRef<Path>>(path: P) -> Result<Image, Error> {
+    let mapped = mmap::Mapped::open(path.as_ref()).map_err(image::ImageError::IoError)?;
+    decode_image(&mapped, path.as_ref(), &LoadOptions::default())
+}
+
+/// Like `load_images`, reading each file through a memory mapping as
+/// `load_image_mapped` does. Errors encountered while loading files are
+/// skipped.
+#[cfg(all(feature = "mmap", unix))]
+pub fn load_images_mapped<P: AsRef<Path>>(paths: &[P]) -> Vec<Image> {
+    paths.iter()
+        .map(load_image_mapped)
+        .filter_map(|img| img.ok())
+        .collect()
+}
+
+/// Decodes an existing animated Gif into frames, plus how long each frame is
+/// shown for, so it can be re-edited, retimed or re-cropped and encoded
+/// again.
+///
+/// Every frame is returned at the full size of the Gif, with the frames
+/// before it composited underneath as the Gif's disposal methods specify.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::load_gif;
+/// let (frames, delays) = load_gif("in.gif").unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Returns an `Error::ImageLoad` if the file can't be read or isn't a Gif.
+#[cfg(feature = "fs")]
+pub fn load_gif<P: AsRef<Path>>(path: P) -> Result<(Vec<Image>, Vec<Duration>), Error> {
+    let file = File::open(path).map_err(image::ImageError::IoError)?;
+    read_gif(io::BufReader::new(file))
+}
+
+/// Like `load_gif`, for Gifs that aren't in a file.
+pub fn read_gif<R: io::Read>(r: R) -> Result<(Vec<Image>, Vec<Duration>), Error> {
+    let (frames, delays) = decode::read(r)?;
+    let delays = delays.into_iter().map(|cs| Duration::from_millis(cs as u64 * 10)).collect();
+    Ok((frames, delays))
+}
+
+/// Decodes an animated PNG into frames, plus how long each frame is shown
+/// for, so existing animations can be converted to Gifs. A PNG that isn't
+/// animated is returned as a single frame.
+///
+/// Like `load_gif`, every frame is returned at the full size of the PNG,
+/// with the frames before it blended and disposed of as its `fcTL` chunks
+/// specify.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::load_apng;
+/// let (frames, delays) = load_apng("in.png").unwrap();
+/// ```
+///
+/// To convert one straight to a Gif, use `reprocess_apng`.
+///
+/// # Errors
+///
+/// Returns an `Error::ImageLoad` if the file can't be read or isn't a PNG.
+///
+/// There's no WebP counterpart: the image crate only decodes still, lossy
+/// WebP images, and only as grayscale, so loading any WebP image returns an
+/// `Error::Unsupported`.
+#[cfg(feature = "fs")]
+pub fn load_apng<P: AsRef<Path>>(path: P) -> Result<(Vec<Image>, Vec<Duration>), Error> {
+    let file = File::open(path).map_err(image::ImageError::IoError)?;
+    read_apng(io::BufReader::new(file))
+}
+
+/// Like `load_apng`, for PNGs that aren't in a file.
+pub fn read_apng<R: io::Read>(r: R) -> Result<(Vec<Image>, Vec<Duration>), Error> {
+    let (frames, delays) = animated::read(r)?;
+    Ok((frames, delays.into_iter().map(Duration::from_millis).collect()))
+}
+
+/// Reads raw frames of `width` x `height` pixels, with no headers or padding
+/// between them, from a stream such as stdin. Raw video from other tools can
+/// be piped straight in, for instance from
+/// `ffmpeg -i in.mp4 -f rawvideo -pix_fmt rgb24 -`.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{read_raw_frames, engiffen_iter, Error, Options, Quantizer, RawFormat};
+/// # use std::io;
+/// # fn foo() -> Result<(), Error> {
+/// let stdin = io::stdin();
+/// let frames = read_raw_frames(stdin.lock(), 640, 480, RawFormat::Rgb24);
+/// let gif = engiffen_iter(frames, 25, Quantizer::NeuQuant(2), &Options::default())?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Each frame is an `Error::ImageLoad` if the stream can't be read, or if it
+/// ends partway through the frame.
+pub fn read_raw_frames<R: io::Read>(r: R, width: u32, height: u32, format: RawFormat) -> RawFrames<R> {
+    RawFrames::new(r, width, height, format)
+}
+
+/// Reads a YUV4MPEG2 stream, converting its frames to RGB as they're
+/// iterated. The stream's header is read right away, so its size and frame
+/// rate are known before any frames are.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{read_y4m, engiffen_iter, Error, Options, Quantizer};
+/// # use std::io;
+/// # fn foo() -> Result<(), Error> {
+/// let stdin = io::stdin();
+/// let frames = read_y4m(stdin.lock())?;
+/// let fps = frames.fps().unwrap_or(30);
+/// let gif = engiffen_iter(frames, fps, Quantizer::NeuQuant(2), &Options::default())?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns an `Error::ImageLoad` if the header can't be read, or uses a
+/// colorspace other than 8-bit 420, 422, 444, 444alpha or mono. Frames that
+/// can't be read are errors too.
+#[cfg(feature = "y4m")]
+pub fn read_y4m<R: io::Read>(r: R) -> Result<Y4mFrames<R>, Error> {
+    Y4mFrames::new(r)
+}
+
+/// Decodes the video at `path` into frames sampled at `fps` frames per
+/// second, by running the `ffmpeg` binary found on the `PATH`. Anything
+/// ffmpeg can read works, such as mp4, webm and mov files.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{load_video, engiffen_iter, Error, Options, Quantizer};
+/// # fn foo() -> Result<(), Error> {
+/// let frames = load_video("clip.mp4", 15)?;
+/// let gif = engiffen_iter(frames, 15, Quantizer::NeuQuant(2), &Options::default())?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns an `Error::ImageLoad` if ffmpeg can't be run or fails to decode
+/// the video. ffmpeg prints its own explanation to stderr.
+#[cfg(feature = "ffmpeg")]
+pub fn load_video<P: AsRef<Path>>(path: P, fps: usize) -> Result<VideoFrames, Error> {
+    VideoFrames::spawn("ffmpeg", path.as_ref(), fps)
+}
+
+/// Records `region` of the screen, or all of it, for `duration` at `fps`
+/// frames per second, by running the `ffmpeg` binary found on the `PATH`
+/// with the screen grabber for the platform: x11grab on Linux and the BSDs
+/// (Wayland sessions need XWayland), gdigrab on Windows and avfoundation on
+/// macOS, which records the main screen. Frames arrive as they're recorded.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{capture_screen, engiffen_iter, Crop, Error, Options, Quantizer};
+/// # use std::time::Duration;
+/// # fn foo() -> Result<(), Error> {
+/// let region = Crop { x: 0, y: 0, width: 800, height: 600 };
+/// let frames = capture_screen(Some(region), 15, Duration::from_secs(5))?;
+/// let gif = engiffen_iter(frames, 15, Quantizer::NeuQuant(2), &Options::default())?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns an `Error::Invalid` for a `duration` of zero, and an
+/// `Error::ImageLoad` if ffmpeg can't be run or can't grab the screen.
+#[cfg(feature = "capture")]
+pub fn capture_screen(region: Option<Crop>, fps: usize, duration: Duration) -> Result<VideoFrames, Error> {
+    capture::spawn("ffmpeg", region, fps, duration)
+}
+
+/// Records the screen like `capture_screen` and encodes the recording into
+/// a `Gif` at the same frame rate.
+///
+/// # Errors
+///
+/// Returns the same errors as `capture_screen` and `engiffen_iter`.
+#[cfg(feature = "capture")]
+pub fn record_screen(region: Option<Crop>, fps: usize, duration: Duration, quantizer: Quantizer, options: &Options) -> Result<Gif, Error> {
+    engiffen_iter(capture_screen(region, fps, duration)?, fps, quantizer, options)
+}
+
+/// Loads images from a list of given paths. Errors encountered while loading files
+/// are skipped.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::load_images;
+/// let paths = vec!["tests/ball/ball06.bmp", "tests/ball/ball07.bmp", "tests/ball/ball08.bmp"];
+/// let images = load_images(&paths);
+/// assert_eq!(images.len(), 2); // The last path doesn't exist. It was silently skipped.
+/// ```
+///
+/// Skips images that fail to load. If all images fail, returns an empty vector.
+#[cfg(feature = "fs")]
+pub fn load_images<P>(paths: &[P]) -> Vec<Image>
+    where P: AsRef<Path> {
+    paths.iter()
+        .map(load_image)
+        .filter_map(|img| img.ok())
+        .collect()
+}
+
+/// Loads images from a list of given paths like `load_images`, but instead of
+/// skipping files that fail to load, reports every one of them along with
+/// why it failed.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::load_images_strict;
+/// match load_images_strict(&["tests/ball/ball01.bmp", "tests/ball/missing.bmp"]) {
+///     Ok(images) => println!("loaded {} images", images.len()),
+///     Err(failures) => for (path, err) in failures {
+///         eprintln!("{}: {}", path.display(), err);
+///     },
+/// }
+/// ```
+///
+/// # Errors
+///
+/// Returns the path and error of each image that failed to load, in order.
+#[cfg(feature = "fs")]
+pub fn load_images_strict<P>(paths: &[P]) -> Result<Vec<Image>, Vec<(PathBuf, Error)>>
+    where P: AsRef<Path> {
+    let mut images = Vec::with_capacity(paths.len());
+    let mut failures = Vec::new();
+    for path in paths {
+        match load_image(path) {
+            Ok(img) => images.push(img),
+            Err(err) => failures.push((path.as_ref().to_path_buf(), err)),
+        }
+    }
+    if failures.is_empty() {
+        Ok(images)
+    } else {
+        Err(failures)
+    }
+}
+
+/// Loads every image in a directory whose extension is one of `extensions`,
+/// ignoring case, in the natural order of their file names (see
+/// `natural_cmp`). An empty list of extensions loads
+/// every file.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{load_images_from_dir, Error};
+/// # fn foo() -> Result<(), Error> {
+/// let images = load_images_from_dir("tests/ball", &["bmp", "png"])?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns an `Error::ImageLoad` if the directory can't be read or one of
+/// the images fails to load.
+#[cfg(feature = "fs")]
+pub fn load_images_from_dir<P: AsRef<Path>>(dir: P, extensions: &[&str]) -> Result<Vec<Image>, Error> {
+    load_images_from_dir_sorted(dir, extensions, SortOrder::Name)
+}
+
+/// Like `load_images_from_dir`, loading the images in `order`, e.g. by when
+/// photos were taken for a timelapse.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{load_images_from_dir_sorted, Error, SortOrder};
+/// # fn foo() -> Result<(), Error> {
+/// let images = load_images_from_dir_sorted("timelapse", &["jpg"], SortOrder::Taken)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns an `Error::ImageLoad` if the directory can't be read or one of
+/// the images fails to load.
+#[cfg(feature = "fs")]
+pub fn load_images_from_dir_sorted<P: AsRef<Path>>(dir: P, extensions: &[&str], order: SortOrder) -> Result<Vec<Image>, Error> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir).map_err(image::ImageError::IoError)? {
+        let path = entry.map_err(image::ImageError::IoError)?.path();
+        if has_extension(&path, extensions) && path.is_file() {
+            paths.push(path);
+        }
+    }
+    sort_paths(&mut paths, order);
+    paths.iter().map(load_image).collect()
+}
+
+/// Cuts a sprite sheet into its frames, as `layout` says, so animations
+/// can be engiffened straight from a game's texture atlas.
+///
+/// # Examples
+///
+/// ```rust
+/// # use engiffen::{slice_sprite_sheet, Image, SpriteSheet};
+/// let sheet = Image { pixels: vec![[255, 0, 0, 255]; 64 * 16], width: 64, height: 16 };
+/// let frames = slice_sprite_sheet(&sheet, &SpriteSheet::new(16, 16)).unwrap();
+/// assert_eq!(frames.len(), 4);
+/// ```
+///
+/// # Errors
+///
+/// Returns an `Error::InvalidOption` if the tiles are empty, or if the
+/// sheet doesn't have room for at least one tile, or for `layout.frames`.
+pub fn slice_sprite_sheet(sheet: &Image, layout: &SpriteSheet) -> Result<Vec<Image>, Error> {
+    sprite::slice(sheet, layout)
+}
+
+/// Loads a sprite sheet and cuts it into frames, like
+/// `slice_sprite_sheet`.
+///
+/// # Errors
+///
+/// Returns an `Error::ImageLoad` if the sheet can't be loaded, or the same
+/// errors as `slice_sprite_sheet`.
+#[cfg(feature = "fs")]
+pub fn load_sprite_sheet<P: AsRef<Path>>(path: P, layout: &SpriteSheet) -> Result<Vec<Image>, Error> {
+    sprite::slice(&load_image(path)?, layout)
+}
+
+/// Lays `frames` out on a sprite sheet, the reverse of
+/// `slice_sprite_sheet`: `columns` tiles wide, row by row, so 1 makes a
+/// vertical strip, `frames.len()` a horizontal one, and 0 the squarest
+/// grid. Returns the sheet and where its frames are.
+///
+/// # Examples
+///
+/// ```rust
+/// # use engiffen::{slice_sprite_sheet, sprite_sheet, Image};
+/// let frames = vec![Image { pixels: vec![[255, 0, 0, 255]; 4], width: 2, height: 2 }; 3];
+/// let (sheet, layout) = sprite_sheet(&frames, 3).unwrap();
+/// assert_eq!((sheet.width, sheet.height), (6, 2));
+/// assert_eq!(slice_sprite_sheet(&sheet, &layout).unwrap(), frames);
+/// ```
+///
+/// # Errors
+///
+/// Returns an `Error::NoImages` if there are no frames, or an
+/// `Error::Mismatch` if their dimensions differ.
+pub fn sprite_sheet(frames: &[Image], columns: usize) -> Result<(Image, SpriteSheet), Error> {
+    sprite::pack(frames, columns)
+}
+
+/// Lays `frames` out on a sprite sheet like `sprite_sheet`, and writes it
+/// to `path` as a PNG, along with a JSON file of the same name describing
+/// each frame's tile and how long it's shown at `fps`, in the format
+/// Aseprite exports, which Phaser, Godot and most other engines import.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{export_sprite_sheet, load_images};
+/// let frames = load_images(&["walk1.png", "walk2.png", "walk3.png"]);
+/// // Writes walk.png and walk.json.
+/// export_sprite_sheet(&frames, 12, 0, "walk.png").unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Returns the same errors as `sprite_sheet`, or an `Error::ImageWrite` if
+/// a file can't be written.
+#[cfg(feature = "fs")]
+pub fn export_sprite_sheet<P: AsRef<Path>, F: Into<FrameRate>>(frames: &[Image], fps: F, columns: usize, path: P) -> Result<SpriteSheet, Error> {
+    let delay = fps.into().delay_ms().round().max(0.0) as u32;
+    write_sprite_sheet(frames, &vec![delay; frames.len()], columns, path.as_ref())
+}
+
+#[cfg(feature = "fs")]
+pub fn write_sprite_sheet(frames: &[Image], delays_ms: &[u32], columns: usize, path: &Path) -> Result<SpriteSheet, Error> {
+    let (sheet, layout) = sprite::pack(frames, columns)?;
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let json = sprite::metadata(&layout, (sheet.width, sheet.height), delays_ms, &name);
+    RgbaImage::from(sheet).save(path)?;
+    fs::write(path.with_extension("json"), json)?;
+    Ok(layout)
+}
+
+/// Sorts image paths in `order`, to pass to `load_images` or
+/// `engiffen_files`. Sorting by time reads each file's modification time,
+/// and for `SortOrder::Taken` the start of each file.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{load_images, sort_paths, SortOrder};
+/// let mut paths = vec!["IMG_0100.JPG", "DSC_0001.JPG"];
+/// sort_paths(&mut paths, SortOrder::Taken);
+/// let images = load_images(&paths);
+/// ```
+#[cfg(feature = "fs")]
+pub fn sort_paths<P: AsRef<Path>>(paths: &mut [P], order: SortOrder) {
+    order::sort(paths, order)
+}
+
+/// Loads every image matching a glob pattern, like `"frames/*.png"`, in the
+/// natural order of their paths (see `natural_cmp`). Needs the `globbing`
+/// feature, which is on by default.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{load_images_glob, Error};
+/// # fn foo() -> Result<(), Error> {
+/// let images = load_images_glob("tests/ball/*.bmp")?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns an `Error::Pattern` if the pattern is malformed, or an
+/// `Error::ImageLoad` if a matching path can't be read or fails to load.
+#[cfg(feature = "globbing")]
+pub fn load_images_glob(pattern: &str) -> Result<Vec<Image>, Error> {
+    let mut paths = Vec::new();
+    for entry in glob::glob(pattern).map_err(Error::Pattern)? {
+        let path = entry.map_err(|e| {
+            let message = format!("{}: {}", e.path().display(), e.error());
+            image::ImageError::IoError(io::Error::new(e.error().kind(), message))
+        })?;
+        if path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort_by(|a, b| natural_cmp(a, b));
+    paths.iter().map(load_image).collect()
+}
+
+/// Whether `path`'s extension is one of `extensions`, ignoring case. Any
+/// path matches an empty list.
+#[cfg(feature = "fs")]
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    extensions.is_empty() || path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| extensions.iter().any(|wanted| wanted.eq_ignore_ascii_case(ext)))
+}
+
+/// Loads every image in a zip or tar archive whose extension is one of
+/// `extensions`, ignoring case, in the natural order of their names, without
+/// extracting the archive first. An empty list of extensions loads every
+/// file. Needs the `archives` feature.
+///
+/// Zip archives can hold stored or deflated files; tar archives must be
+/// uncompressed.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use engiffen::{load_images_from_archive, Error};
+/// # fn foo() -> Result<(), Error> {
+/// let images = load_images_from_archive("renders.zip", &["png"])?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns an `Error::ImageLoad` if the archive can't be read or one of the
+/// images fails to load.
+#[cfg(feature = "archives")]
+pub fn load_images_from_archive<P: AsRef<Path>>(path: P, extensions: &[&str]) -> Result<Vec<Image>, Error> {
+    let mut bytes = Vec::new();
+    File::open(path).and_then(|mut file| io::Read::read_to_end(&mut file, &mut bytes)).map_err(image::ImageError::IoError)?;
+    let mut entries = archive::entries(&bytes, |name| has_extension(Path::new(name), extensions))
+        .map_err(image::ImageError::FormatError)?;
+    entries.sort_by(|a, b| natural_cmp(&a.name, &b.name));
+    entries.iter().map(|entry| decode_image(&entry.data, Path::new(&entry.name), &LoadOptions::default())).collect()
+}
+
+/// The image format a file name's extension stands for, if it's one the
+/// image crate reads.
+fn image_format(path: &Path) -> Option<image::ImageFormat> {
+    let ext = path.extension()?.to_str()?.to_lowercase();
+    Some(match ext.as_str() {
+        "png" => image::ImageFormat::PNG,
+        "jpg" | "jpeg" => image::ImageFormat::JPEG,
+        "gif" => image::ImageFormat::GIF,
+        "ppm" => image::ImageFormat::PPM,
+        "tif" | "tiff" => image::ImageFormat::TIFF,
+        "tga" => image::ImageFormat::TGA,
+        "bmp" => image::ImageFormat::BMP,
+        "ico" => image::ImageFormat::ICO,
+        "hdr" => image::ImageFormat::HDR,
+        _ => return None,
+    })
+}
+
+/// The name of the format of a file this build can't decode, going by its
+/// extension or, without one, its contents.
+fn unsupported_format(bytes: &[u8], path: &Path) -> Option<&'static str> {
+    let ext = path.extension().and_then(|ext| ext.to_str()).map(str::to_lowercase);
+    let is_webp = bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP";
+    if ext.as_deref() == Some("webp") || is_webp {
+        Some("WebP")
+    } else if cfg!(not(feature = "hdr")) && (ext.as_deref() == Some("exr") || bytes.starts_with(&[0x76, 0x2F, 0x31, 0x01])) {
+        Some("OpenEXR")
+    } else {
+        None
+    }
+}
+
+/// Compares paths in natural order, where runs of digits are compared as
+/// numbers, so that `frame2.png` comes before `frame10.png`. Most capture
+/// tools number frames without padding, which plain lexicographic order
+/// scrambles.
+///
+/// # Examples
+///
+/// ```rust
+/// # use engiffen::natural_cmp;
+/// let mut paths = vec!["frame10.png", "frame9.png", "frame1.png"];
+/// paths.sort_by(|a, b| natural_cmp(a, b));
+/// assert_eq!(paths, vec!["frame1.png", "frame9.png", "frame10.png"]);
+/// ```
+pub fn natural_cmp<A: AsRef<Path>, B: AsRef<Path>>(a: A, b: B) -> cmp::Ordering {
+    natural::compare(&a.as_ref().to_string_lossy(), &b.as_ref().to_string_lossy())
+}
+
+// The tests load their frames from `tests/`.
+#[cfg(all(test, feature = "fs"))]
+mod tests {
+    use super::{load_gif, load_image, load_images_from_dir, load_images_strict, read_image, read_indexed_image, slice_sprite_sheet};
+    use super::super::{engiffen, Error, LoadOptions, Quantizer};
+    use std::fs::File;
+    use image;
+    use fixtures::solid;
+
+    #[test]
+    fn test_export_sprite_sheet() {
+        let imgs: Vec<_> = (0..3).map(|n| solid(2, 2, [n * 100, 0, 0, 255])).collect();
+        let gif = engiffen(&imgs, 20, Quantizer::Naive).unwrap();
+        let dir = std::env::temp_dir().join(format!("engiffen-test-sprite-sheet-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let layout = gif.export_sprite_sheet(dir.join("walk.png"), 1).unwrap();
+        let sheet = load_image(dir.join("walk.png")).unwrap();
+        let json = std::fs::read_to_string(dir.join("walk.json")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!((sheet.width, sheet.height), (2, 6));
+        assert_eq!(slice_sprite_sheet(&sheet, &layout).unwrap(), imgs);
+        assert!(json.contains("\"filename\": \"walk.png 2\", \"frame\": { \"x\": 0, \"y\": 4, \"w\": 2, \"h\": 2 }, \"duration\": 50"));
+    }
+
+    #[test]
+    fn test_load_missing_gif() {
+        match load_gif("tests/no-such.gif") {
+            Err(Error::ImageLoad(_)) => (),
+            other => panic!("expected a load error, got {:?}", other.map(|(imgs, _)| imgs.len())),
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "mmap", unix))]
+    fn test_load_image_mapped() {
+        use super::{load_image_mapped, load_images_mapped};
+        let path = "tests/ball/ball01.bmp";
+        assert_eq!(load_image_mapped(path).unwrap(), load_image(path).unwrap());
+        assert!(load_image_mapped("tests/no-such.png").is_err());
+        assert_eq!(load_images_mapped(&[path, "tests/no-such.png"]).len(), 1);
+    }
+
+    #[test]
+    fn test_load_image_exif_orientation() {
+        use super::{exif, load_image_unoriented};
+        let mut jpeg = Vec::new();
+        image::jpeg::JPEGEncoder::new(&mut jpeg).encode(&[0; 16 * 8 * 3], 16, 8, image::ColorType::RGB(8)).unwrap();
+        let mut rotated = jpeg[..2].to_vec();
+        rotated.extend(exif::tests::exif_segment(6, true));
+        rotated.extend_from_slice(&jpeg[2..]);
+        let path = std::env::temp_dir().join(format!("engiffen-exif-{}.jpg", std::process::id()));
+        std::fs::write(&path, &rotated).unwrap();
+
+        let upright = load_image(&path).unwrap();
+        let stored = load_image_unoriented(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((upright.width, upright.height), (8, 16));
+        assert_eq!((stored.width, stored.height), (16, 8));
+    }
+
+    #[test]
+    fn test_read_image() {
+        let path = "tests/ball/ball01.bmp";
+        let file = File::open(path).unwrap();
+        assert_eq!(read_image(file, &LoadOptions::default()).unwrap(), load_image(path).unwrap());
+        assert!(read_image(&b"not an image"[..], &LoadOptions::default()).is_err());
+        match read_image(&b"RIFF\0\0\0\0WEBPVP8 "[..], &LoadOptions::default()) {
+            Err(Error::Unsupported("WebP")) => {},
+            other => panic!("expected WebP to be unsupported, got {:?}", other),
+        }
+        #[cfg(not(feature = "hdr"))]
+        match read_image(&[0x76, 0x2F, 0x31, 0x01, 2, 0, 0, 0][..], &LoadOptions::default()) {
+            Err(Error::Unsupported("OpenEXR")) => {},
+            other => panic!("expected OpenEXR to be unsupported, got {:?}", other),
+        }
+        assert_eq!(read_indexed_image(File::open(path).unwrap()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_load_images_strict() {
+        let loaded = load_images_strict(&["tests/ball/ball01.bmp", "tests/ball/ball02.bmp"]).unwrap();
+        assert_eq!(loaded.len(), 2);
+
+        let failures = load_images_strict(&["tests/no-such.png", "tests/ball/ball01.bmp", "Cargo.toml"]).unwrap_err();
+        let paths: Vec<_> = failures.iter().map(|(path, _)| path.to_str().unwrap()).collect();
+        assert_eq!(paths, vec!["tests/no-such.png", "Cargo.toml"]);
+        assert!(failures.iter().all(|(_, err)| matches!(*err, Error::ImageLoad(_))));
+    }
+
+    #[test]
+    fn test_load_images_from_dir() {
+        let imgs = load_images_from_dir("tests/ball", &["BMP"]).unwrap();
+        assert_eq!(imgs.len(), 7);
+        assert_eq!(imgs[0], load_image("tests/ball/ball01.bmp").unwrap());
+        assert!(load_images_from_dir("tests/ball", &["png"]).unwrap().is_empty());
+        assert!(matches!(load_images_from_dir("tests/no-such-dir", &[]), Err(Error::ImageLoad(_))));
+    }
+
+    #[test]
+    #[cfg(feature = "globbing")]
+    fn test_load_images_glob() {
+        use super::load_images_glob;
+        let imgs = load_images_glob("tests/ball/ball0[1-3].bmp").unwrap();
+        assert_eq!(imgs.len(), 3);
+        assert_eq!(imgs[2], load_image("tests/ball/ball03.bmp").unwrap());
+        assert!(load_images_glob("tests/ball/*.png").unwrap().is_empty());
+        assert!(matches!(load_images_glob("tests/[ball"), Err(Error::Pattern(_))));
+    }
+
+    #[test]
+    #[cfg(feature = "archives")]
+    fn test_load_images_from_archive() {
+        use super::load_images_from_archive;
+        use super::archive::tests::{zip_archive, DEFLATED, PPM};
+        use std::env;
+        use std::io::Write;
+        let path = env::temp_dir().join(format!("engiffen-test-{}.zip", std::process::id()));
+        File::create(&path).unwrap().write_all(&zip_archive(&[("f10.ppm", 0, PPM), ("f9.ppm", 8, &DEFLATED), ("f.txt", 0, b"hi")])).unwrap();
+        let imgs = load_images_from_archive(&path, &["ppm"]);
+        std::fs::remove_file(&path).unwrap();
+        let imgs = imgs.unwrap();
+        assert_eq!(imgs.len(), 2);
+        assert_eq!(imgs[0].pixels, vec![[255, 0, 0, 255], [0, 0, 255, 255]]);
+
+        assert!(matches!(load_images_from_archive("tests/no-such.zip", &[]), Err(Error::ImageLoad(_))));
+        assert!(matches!(load_images_from_archive("Cargo.toml", &[]), Err(Error::ImageLoad(_))));
+    }
+}
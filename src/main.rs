@@ -4,11 +4,13 @@ extern crate getopts;
 extern crate rand;
 #[cfg(feature = "globbing")] extern crate glob;
 
-use std::io::{self, BufWriter};
-use std::{env, fmt, process};
-use std::fs::{read_dir, File};
-use std::path::PathBuf;
-use std::time::{Instant, Duration};
+use std::io::{self, BufRead, BufWriter, Write};
+use std::{env, fmt, process, thread};
+use std::fs::{self, read_dir, File};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, Duration, SystemTime};
+use std::cmp::Ordering;
+use engiffen::{natural_cmp, EncodeStats};
 use parse_args::{parse_args, Args, SourceImages, Modifier};
 
 #[cfg(feature = "globbing")] use self::glob::glob;
@@ -23,6 +25,7 @@ mod parse_args;
 enum RuntimeError {
     Directory(PathBuf),
     Destination(String),
+    Stdin(io::Error),
     Engiffen(engiffen::Error),
 }
 
@@ -37,12 +40,13 @@ impl fmt::Display for RuntimeError {
         match *self {
             RuntimeError::Directory(ref dir) => write!(f, "No such directory {:?}", dir),
             RuntimeError::Destination(ref dst) => write!(f, "Couldn't write to output '{}'", dst),
+            RuntimeError::Stdin(ref e) => write!(f, "Couldn't read file names from stdin: {}", e),
             RuntimeError::Engiffen(ref e) => e.fmt(f,)
         }
     }
 }
 
-fn run_engiffen(args: &Args) -> Result<((Option<String>, Duration)), RuntimeError> {
+fn run_engiffen(args: &Args) -> Result<(Option<String>, Duration), RuntimeError> {
     let mut source_images: Vec<_> = match args.source {
         SourceImages::StartEnd(ref dir, ref start_path, ref end_path) => {
             let start_string = start_path.as_os_str();
@@ -53,51 +57,170 @@ fn run_engiffen(args: &Args) -> Result<((Option<String>, Duration)), RuntimeErro
                 .filter_map(|e| e.ok())
                 .collect();
 
-            // Filesystem probably already sorted by name, but just in case
-            files.sort_by_key(|f| f.file_name());
+            files.sort_by(|a, b| natural_cmp(a.file_name(), b.file_name()));
 
             files.iter()
-            .skip_while(|path| path.file_name() < start_string)
-            .take_while(|path| path.file_name() <= end_string)
+            .skip_while(|path| natural_cmp(path.file_name(), start_string) == Ordering::Less)
+            .take_while(|path| natural_cmp(path.file_name(), end_string) != Ordering::Greater)
             .map(|e| e.path())
             .collect()
         },
-        SourceImages::List(ref list) => list.into_iter().map(PathBuf::from).collect(),
+        SourceImages::List(ref list) => list.iter().map(PathBuf::from).collect(),
         #[cfg(feature = "globbing")]
         SourceImages::Glob(ref string) => {
-            let paths: Vec<_> = glob(string).expect("glob parsing failed :(")
+            let mut paths: Vec<_> = glob(string).expect("glob parsing failed :(")
                 .filter_map(std::result::Result::ok)
                 .collect();
-            #[cfg(feature = "debug-stderr")]
-            eprintln!("Expanded {} into {} files.", string, paths.len());
+            paths.sort_by(|a, b| natural_cmp(a, b));
+            if args.verbose {
+                eprintln!("Expanded {} into {} files", string, paths.len());
+            }
             paths
         },
+        SourceImages::Raw(..) | SourceImages::Sprite(..) => Vec::new(),
+        #[cfg(feature = "ffmpeg")]
+        SourceImages::Video(_) => Vec::new(),
+        SourceImages::Watch(ref dir) => watched_files(dir, args.out_file.as_ref())?
+            .into_iter().map(|(path, _)| path).collect(),
+        SourceImages::Stdin => {
+            let stdin = io::stdin();
+            let lines = stdin.lock().lines().collect::<Result<Vec<_>, _>>()
+                .map_err(RuntimeError::Stdin)?;
+            lines.into_iter()
+                .map(|line| line.trim_end_matches('\r').to_owned())
+                .filter(|line| !line.is_empty())
+                .map(PathBuf::from)
+                .collect()
+        },
     };
 
+    for modifier in &args.modifiers {
+        if let Modifier::Sort(order) = *modifier {
+            engiffen::sort_paths(&mut source_images, order);
+        }
+    }
     modify(&mut source_images, &args.modifiers);
 
-    let imgs = engiffen::load_images(&source_images);
+    // Streamed frames have no paths to decode twice, so they're always held.
+    let low_memory = args.low_memory && !source_images.is_empty();
+    let imgs = match args.source {
+        SourceImages::Raw(width, height, format) => {
+            let stdin = io::stdin();
+            let mut imgs = engiffen::read_raw_frames(stdin.lock(), width, height, format)
+                .collect::<Result<Vec<_>, _>>()?;
+            modify(&mut imgs, &args.modifiers);
+            imgs
+        },
+        SourceImages::Sprite(ref path, ref layout) => {
+            let mut imgs = engiffen::load_sprite_sheet(path, layout)?;
+            modify(&mut imgs, &args.modifiers);
+            imgs
+        },
+        #[cfg(feature = "ffmpeg")]
+        SourceImages::Video(ref path) => {
+            let fps = (1000.0 / args.fps.delay_ms()).round().max(1.0) as usize;
+            let mut imgs = engiffen::load_video(path, fps)?.collect::<Result<Vec<_>, _>>()?;
+            modify(&mut imgs, &args.modifiers);
+            imgs
+        },
+        _ if low_memory => Vec::new(),
+        _ => engiffen::load_images(&source_images),
+    };
 
     let now = Instant::now();
-    let gif = engiffen::engiffen(&imgs, args.fps, args.quantizer)?;
-    match args.out_file {
+    let options = &args.options;
+    let encode = |mut out: &mut dyn Write| if low_memory {
+        engiffen::engiffen_files(&source_images, args.fps, args.quantizer, options, &mut out)
+    } else {
+        engiffen::engiffen_write(&imgs, args.fps, args.quantizer, options, &mut out)
+    };
+    let stats = match args.out_file {
         Some(ref filename) => {
             let mut file = BufWriter::new(
                 File::create(filename)
                 .map_err(|_| RuntimeError::Destination(filename.to_owned()))?
             );
-            gif.write(&mut file)
+            encode(&mut file)
         },
         None => {
             let stdout = io::stdout();
             let mut handle = BufWriter::new(stdout.lock());
-            gif.write(&mut handle)
+            encode(&mut handle)
         }
     }?;
     let duration = now.elapsed();
+    if args.verbose {
+        print_stats(&stats);
+    }
     Ok((args.out_file.clone(), duration))
 }
 
+fn print_stats(stats: &EncodeStats) {
+    eprintln!("Prepared {} frames in {} ms", stats.frames, ms(stats.preparing));
+    eprintln!("Sampled {} colors in {} ms", stats.colors_sampled, ms(stats.sampling));
+    eprintln!("Computed a {} color palette in {} ms", stats.palette_colors, ms(stats.quantizing));
+    eprintln!("Mapped pixels to palette in {} ms", ms(stats.mapping));
+    if let Some(bytes) = stats.bytes_written {
+        eprintln!("Encoded {} bytes", bytes);
+    }
+}
+
+fn ms(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + duration.subsec_nanos() as u64 / 1000000
+}
+
+/// How often a watched directory is checked for changes.
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// When a file was last modified and how big it is, so that frames still
+/// being written count as changes until they're done.
+type Version = (Option<SystemTime>, u64);
+
+/// Every file in a watched directory except the output, in natural order.
+fn watched_files(dir: &Path, out_file: Option<&String>) -> Result<Vec<(PathBuf, Version)>, RuntimeError> {
+    let out_file = out_file.and_then(|out| fs::canonicalize(out).ok());
+    let mut files: Vec<_> = read_dir(dir)
+        .map_err(|_| RuntimeError::Directory(dir.to_owned()))?
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let meta = e.metadata().ok().filter(|meta| meta.is_file())?;
+            let path = e.path();
+            if fs::canonicalize(&path).ok() == out_file && out_file.is_some() {
+                return None;
+            }
+            Some((path, (meta.modified().ok(), meta.len())))
+        })
+        .collect();
+    files.sort_by(|a, b| natural_cmp(&a.0, &b.0));
+    Ok(files)
+}
+
+/// Regenerates the gif whenever the files in `dir` change, until killed.
+/// Errors are reported without stopping, since a frame that's half written
+/// may fail to decode now and succeed on the next change.
+fn watch(args: &Args, dir: &Path) -> ! {
+    let mut last = None;
+    eprintln!("Watching {:?} for new frames", dir);
+    loop {
+        match watched_files(dir, args.out_file.as_ref()) {
+            Ok(ref files) if files.is_empty() => {},
+            Ok(files) => if last.as_ref() != Some(&files) {
+                if let Err(e) = run_engiffen(args).map(report) {
+                    eprintln!("{}", e);
+                }
+                last = Some(files);
+            },
+            Err(e) => eprintln!("{}", e),
+        }
+        thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+fn report((file, duration): (Option<String>, Duration)) {
+    let filename = file.unwrap_or("to stdout".to_owned());
+    eprintln!("Wrote {} in {} ms", filename, ms(duration));
+}
+
 fn main() {
     let arg_strings: Vec<String> = env::args().collect();
     let args = parse_args(&arg_strings).map_err(|e| {
@@ -105,12 +228,11 @@ fn main() {
         process::exit(1);
     }).unwrap();
 
+    if let SourceImages::Watch(ref dir) = args.source {
+        watch(&args, dir);
+    }
     match run_engiffen(&args) {
-        Ok((file, duration)) => {
-            let ms = duration.as_secs() * 1000 + duration.subsec_nanos() as u64 / 1000000;
-            let filename = file.unwrap_or("to stdout".to_owned());
-            eprintln!("Wrote {} in {} ms", filename, ms);
-        },
+        Ok(written) => report(written),
         Err(e) => {
             eprintln!("{}", e);
             process::exit(1);
@@ -123,11 +245,15 @@ fn modify<P>(source_images: &mut [P], modifiers: &[Modifier]) {
         match *modifier {
             Modifier::Reverse => reverse(source_images),
             Modifier::Shuffle => shuffle(source_images),
+            Modifier::Sort(_) => (),
         }
     }
 }
 
 fn reverse<T>(src: &mut [T]) {
+    if src.is_empty() {
+        return;
+    }
     let last_index = src.len()-1;
     for n in 0..(src.len()/2) {
         src.swap(n, last_index-n);
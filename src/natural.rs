@@ -0,0 +1,67 @@
+//! Ordering file names the way people count, so `frame2` sorts before
+//! `frame10`.
+
+use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Compares two strings, treating each run of digits as a number.
+pub fn compare(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        match (a.peek().cloned(), b.peek().cloned()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let ordering = compare_numbers(&digits(&mut a), &digits(&mut b));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            },
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a.next();
+                b.next();
+            },
+        }
+    }
+}
+
+fn digits(chars: &mut Peekable<Chars>) -> String {
+    let mut run = String::new();
+    while let Some(&c) = chars.peek() {
+        if !c.is_ascii_digit() {
+            break;
+        }
+        run.push(c);
+        chars.next();
+    }
+    run
+}
+
+/// Compares runs of digits by value, then puts the one with fewer leading
+/// zeros first, so that the order is still total.
+fn compare_numbers(a: &str, b: &str) -> Ordering {
+    let (x, y) = (a.trim_start_matches('0'), b.trim_start_matches('0'));
+    x.len().cmp(&y.len())
+        .then_with(|| x.cmp(y))
+        .then_with(|| a.len().cmp(&b.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+    use super::compare;
+
+    #[test]
+    fn test_natural_order() {
+        let mut names = vec!["frame10.png", "frame2.png", "frame1.png", "frame02.png", "alpha.png", "frame1a.png"];
+        names.sort_by(|a, b| compare(a, b));
+        assert_eq!(names, vec!["alpha.png", "frame1.png", "frame1a.png", "frame2.png", "frame02.png", "frame10.png"]);
+        assert_eq!(compare("12345678901234567890", "12345678901234567891"), Ordering::Less);
+        assert_eq!(compare("same", "same"), Ordering::Equal);
+    }
+}
@@ -0,0 +1,85 @@
+//! Ordering image files by when they were made rather than by name.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use exif;
+use natural;
+
+/// EXIF data lives in a JPEG's first segments, so there's no need to read
+/// whole photos to find it.
+const HEADER_LEN: u64 = 128 * 1024;
+
+/// How `sort_paths` and `load_images_from_dir_sorted` order files.
+///
+/// `Name` is natural order (see `natural_cmp`). `Modified` is oldest file
+/// modification time first. `Taken` is the time an EXIF timestamp says a
+/// photo was taken, for timelapses whose file names count up from
+/// somewhere unhelpful, falling back to the modification time for files
+/// without one. Files whose times can't be read go last. Ties are broken
+/// by name.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum SortOrder {
+    Name,
+    Modified,
+    Taken,
+}
+
+/// Seconds since the Unix epoch `path` was last modified.
+fn modified(path: &Path) -> Option<i64> {
+    let time = path.metadata().and_then(|meta| meta.modified()).ok()?;
+    Some(match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    })
+}
+
+fn taken(path: &Path) -> Option<i64> {
+    let mut header = Vec::new();
+    File::open(path).and_then(|file| file.take(HEADER_LEN).read_to_end(&mut header)).ok()?;
+    exif::taken(&header)
+}
+
+pub fn sort<P: AsRef<Path>>(paths: &mut [P], order: SortOrder) {
+    paths.sort_by(|a, b| natural::compare(&a.as_ref().to_string_lossy(), &b.as_ref().to_string_lossy()));
+    let time = |path: &Path| match order {
+        SortOrder::Name => Some(0),
+        SortOrder::Modified => modified(path),
+        SortOrder::Taken => taken(path).or_else(|| modified(path)),
+    };
+    // The sort is stable, so ties stay in name order. Missing times would
+    // sort first as `None`, so whether they're missing is compared first.
+    paths.sort_by_cached_key(|path| {
+        let time = time(path.as_ref());
+        (time.is_none(), time)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::env;
+    use std::path::PathBuf;
+    use super::{sort, SortOrder};
+
+    #[test]
+    fn test_sort() {
+        let dir = env::temp_dir().join(format!("engiffen-test-order-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let paths: Vec<PathBuf> = ["b10.txt", "b9.txt", "a.txt"].iter().map(|name| dir.join(name)).collect();
+        for path in &paths {
+            fs::write(path, b"not a photo").unwrap();
+        }
+        let mut sorted = paths.clone();
+        sort(&mut sorted, SortOrder::Name);
+        assert_eq!(sorted, vec![paths[2].clone(), paths[1].clone(), paths[0].clone()]);
+
+        // Files without an EXIF timestamp fall back to their modification
+        // times, and missing files go last.
+        let mut sorted = vec![dir.join("missing.txt"), paths[0].clone(), paths[1].clone()];
+        sort(&mut sorted, SortOrder::Taken);
+        assert_eq!(sorted.last(), Some(&dir.join("missing.txt")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,165 @@
+//! Drawing onto frames.
+
+use std::borrow::Cow;
+use super::Image;
+
+/// Where something drawn onto a frame is placed.
+///
+/// The anchored positions keep a margin between the drawing and the frame
+/// edges they're anchored to, while `At` puts the drawing's top left corner
+/// at an exact pixel.
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash)]
+pub enum Position {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+    At(u32, u32),
+}
+
+impl Position {
+    /// The top left corner of a `size` drawing on a `width` x `height` frame.
+    /// The result may be off the frame if the drawing doesn't fit.
+    pub fn place(self, width: u32, height: u32, size: (u32, u32), margin: u32) -> (i64, i64) {
+        let (width, height, margin) = (width as i64, height as i64, margin as i64);
+        let (w, h) = (size.0 as i64, size.1 as i64);
+        let left = margin;
+        let center = (width - w) / 2;
+        let right = width - w - margin;
+        let top = margin;
+        let middle = (height - h) / 2;
+        let bottom = height - h - margin;
+        match self {
+            Position::TopLeft => (left, top),
+            Position::Top => (center, top),
+            Position::TopRight => (right, top),
+            Position::Left => (left, middle),
+            Position::Center => (center, middle),
+            Position::Right => (right, middle),
+            Position::BottomLeft => (left, bottom),
+            Position::Bottom => (center, bottom),
+            Position::BottomRight => (right, bottom),
+            Position::At(x, y) => (x as i64, y as i64),
+        }
+    }
+}
+
+/// Blends `color` over a pixel with the "over" operator.
+pub fn over(px: &mut [u8; 4], color: [u8; 4]) {
+    let src_a = color[3] as u32;
+    if src_a == 0 {
+        return;
+    }
+    let dst_a = px[3] as u32 * (255 - src_a) / 255;
+    let out_a = src_a + dst_a;
+    for c in 0..3 {
+        px[c] = ((color[c] as u32 * src_a + px[c] as u32 * dst_a + out_a / 2) / out_a) as u8;
+    }
+    px[3] = out_a as u8;
+}
+
+/// Blends `color` over the pixel at `(x, y)`, if it's on the frame.
+pub fn draw(img: &mut Image, x: i64, y: i64, color: [u8; 4]) {
+    if x >= 0 && y >= 0 && x < img.width as i64 && y < img.height as i64 {
+        let idx = y as usize * img.width as usize + x as usize;
+        over(&mut img.pixels[idx], color);
+    }
+}
+
+/// Blends `color` over a `width` x `height` rectangle at `(x, y)`, clipped to
+/// the frame.
+#[cfg(feature = "text")]
+pub fn fill(img: &mut Image, x: i64, y: i64, width: u32, height: u32, color: [u8; 4]) {
+    for dy in 0..height as i64 {
+        for dx in 0..width as i64 {
+            draw(img, x + dx, y + dy, color);
+        }
+    }
+}
+
+/// An image, like a logo, composited onto every frame.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Watermark {
+    /// The overlay. Its alpha channel is respected.
+    pub image: Image,
+    pub position: Position,
+    /// Distance in pixels from the edges the watermark is anchored to.
+    pub margin: u32,
+    /// Scales the overlay's alpha, from 0 (invisible) to 1.
+    pub opacity: f32,
+}
+
+/// Composites `watermark` onto a frame.
+pub fn watermark(img: &mut Image, watermark: &Watermark) {
+    let mark = &watermark.image;
+    let opacity = watermark.opacity.clamp(0.0, 1.0);
+    let (x, y) = watermark.position.place(img.width, img.height, (mark.width, mark.height), watermark.margin);
+    for (n, px) in mark.pixels.iter().enumerate() {
+        let (dx, dy) = ((n % mark.width as usize) as i64, (n / mark.width as usize) as i64);
+        let alpha = (px[3] as f32 * opacity).round() as u8;
+        draw(img, x + dx, y + dy, [px[0], px[1], px[2], alpha]);
+    }
+}
+
+/// Composites `mark` onto every frame.
+pub fn apply<'a>(imgs: Cow<'a, [Image]>, mark: &Watermark) -> Cow<'a, [Image]> {
+    let mut imgs = imgs.into_owned();
+    for img in imgs.iter_mut() {
+        watermark(img, mark);
+    }
+    Cow::Owned(imgs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{over, watermark, Position, Watermark};
+    use super::super::Image;
+    use fixtures::solid;
+
+    #[test]
+    fn test_place() {
+        assert_eq!(Position::TopLeft.place(100, 50, (10, 4), 2), (2, 2));
+        assert_eq!(Position::Center.place(100, 50, (10, 4), 2), (45, 23));
+        assert_eq!(Position::BottomRight.place(100, 50, (10, 4), 2), (88, 44));
+        assert_eq!(Position::At(7, 9).place(100, 50, (10, 4), 2), (7, 9));
+    }
+
+    #[test]
+    fn test_over() {
+        let mut px = [0, 0, 0, 255];
+        over(&mut px, [255, 255, 255, 128]);
+        assert_eq!(px, [128, 128, 128, 255]);
+        let mut px = [10, 20, 30, 0];
+        over(&mut px, [200, 100, 0, 255]);
+        assert_eq!(px, [200, 100, 0, 255]);
+    }
+
+    #[test]
+    #[cfg(feature = "text")]
+    fn test_fill_is_clipped() {
+        use super::fill;
+        let mut img = solid(3, 3, [0, 0, 0, 255]);
+        fill(&mut img, -1, 2, 3, 5, [255, 255, 255, 255]);
+        let lit: Vec<_> = img.pixels.iter().map(|px| px[0] == 255).collect();
+        assert_eq!(lit, vec![false, false, false, false, false, false, true, true, false]);
+    }
+
+    #[test]
+    fn test_watermark() {
+        let mut img = solid(4, 4, [0, 0, 0, 255]);
+        let mark = Watermark {
+            image: Image { pixels: vec![[255, 255, 255, 255], [255, 255, 255, 0]], width: 2, height: 1 },
+            position: Position::BottomRight,
+            margin: 1,
+            opacity: 0.5,
+        };
+        watermark(&mut img, &mark);
+        let reds: Vec<_> = img.pixels.iter().map(|px| px[0]).collect();
+        assert_eq!(reds, vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 128, 0, 0, 0, 0, 0, 0]);
+    }
+}
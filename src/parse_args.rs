@@ -8,28 +8,40 @@ use std::str::FromStr;
 use std;
 
 use self::SourceImages::*;
-use engiffen::Quantizer;
+use engiffen::{load_recipe, Effort, FrameRate, Options as EncodeOptions, Quantizer, RawFormat, Recipe, SortOrder, SpriteSheet};
 
 #[derive(Debug, Eq, PartialEq)]
 pub enum SourceImages {
     StartEnd(PathBuf, PathBuf, PathBuf),
     List(Vec<String>),
     #[cfg(feature = "globbing")] Glob(String),
+    Raw(u32, u32, RawFormat),
+    Sprite(PathBuf, SpriteSheet),
+    #[cfg(feature = "ffmpeg")] Video(PathBuf),
+    Watch(PathBuf),
+    Stdin,
 }
 
 #[derive(Debug, Eq, PartialEq)]
 pub enum Modifier {
     Reverse,
-    Shuffle
+    Shuffle,
+    /// Sorts file names before any other reordering; ignored for frames
+    /// that don't come from files.
+    Sort(SortOrder),
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, PartialEq)]
 pub struct Args {
     pub source: SourceImages,
-    pub fps: usize,
+    pub fps: FrameRate,
     pub out_file: Option<String>,
     pub quantizer: Quantizer,
     pub modifiers: Vec<Modifier>,
+    pub verbose: bool,
+    pub low_memory: bool,
+    /// Settings from the config file, if one was given.
+    pub options: EncodeOptions,
 }
 
 #[derive(Debug, PartialEq)]
@@ -38,6 +50,12 @@ pub enum ArgsError {
     ParseInt(std::num::ParseIntError),
     #[cfg(feature = "globbing")] GlobPattern,
     ImageRange(String),
+    RawSize(String),
+    Sprite(String),
+    #[cfg(feature = "ffmpeg")] Video(String),
+    Watch(String),
+    Config(String),
+    Quality(String),
     DisplayHelp(String),
 }
 
@@ -67,6 +85,12 @@ impl fmt::Display for ArgsError {
             ArgsError::ParseInt(_) => write!(f, "Unable to parse argument as an integer"),
             #[cfg(feature = "globbing")] ArgsError::GlobPattern => write!(f, "Unable to parse glob pattern"),
             ArgsError::ImageRange(ref s) => write!(f, "Bad image range: {}", s),
+            ArgsError::RawSize(ref s) => write!(f, "Bad raw frame size or pixel format: {}", s),
+            ArgsError::Sprite(ref s) => write!(f, "Bad sprite sheet: {}", s),
+            #[cfg(feature = "ffmpeg")] ArgsError::Video(ref s) => write!(f, "Bad video input: {}", s),
+            ArgsError::Watch(ref s) => write!(f, "Can't watch directory: {}", s),
+            ArgsError::Config(ref s) => write!(f, "Can't use config file: {}", s),
+            ArgsError::Quality(ref s) => write!(f, "Unknown quality {}, expected draft, balanced or best", s),
             ArgsError::DisplayHelp(ref msg) => write!(f, "{}", msg),
         }
     }
@@ -75,20 +99,32 @@ impl fmt::Display for ArgsError {
 impl error::Error for ArgsError {
     fn description(&self) -> &str {
         match *self {
-            ArgsError::Parse(ref err) => err.description(),
-            ArgsError::ParseInt(ref err) => err.description(),
+            ArgsError::Parse(_) => "Unable to parse options",
+            ArgsError::ParseInt(_) => "Unable to parse argument as an integer",
             #[cfg(feature = "globbing")] ArgsError::GlobPattern => "Bad glob pattern",
             ArgsError::ImageRange(_) => "Bad image range",
+            ArgsError::RawSize(_) => "Bad raw frame size or pixel format",
+            ArgsError::Sprite(_) => "Bad sprite sheet",
+            #[cfg(feature = "ffmpeg")] ArgsError::Video(_) => "Bad video input",
+            ArgsError::Watch(_) => "Can't watch directory",
+            ArgsError::Config(_) => "Can't use config file",
+            ArgsError::Quality(_) => "Unknown quality",
             ArgsError::DisplayHelp(_) => "Display help message"
         }
     }
 
-    fn cause(&self) -> Option<&error::Error> {
+    fn cause(&self) -> Option<&dyn error::Error> {
         match *self {
             ArgsError::Parse(ref err) => Some(err),
             ArgsError::ParseInt(ref err) => Some(err),
             #[cfg(feature = "globbing")] ArgsError::GlobPattern => None,
             ArgsError::ImageRange(_) => None,
+            ArgsError::RawSize(_) => None,
+            ArgsError::Sprite(_) => None,
+            #[cfg(feature = "ffmpeg")] ArgsError::Video(_) => None,
+            ArgsError::Watch(_) => None,
+            ArgsError::Config(_) => None,
+            ArgsError::Quality(_) => None,
             ArgsError::DisplayHelp(_) => None,
         }
     }
@@ -98,38 +134,71 @@ pub fn parse_args(args: &[String]) -> Result<Args, ArgsError> {
     let program = args[0].clone();
 
     let mut opts = Options::new();
-    opts.optopt("o", "outfile", "engiffen to this filename", "FILE");
+    opts.optopt("o", "outfile", "engiffen to this filename, or to stdout if it's - (the default)", "FILE");
     opts.optopt("f", "framerate", "frames per second", "30");
     opts.optopt("s", "sample-rate", "reduces how many pixels are analyzed when generating palette, higher means faster", "2");
-    opts.optopt("q", "quantizer", "pick quantizer algorithm (default: neuquant)", "naive");
+    opts.optopt("", "quality", "trade speed for quality: draft, balanced or best; -q and -s override its quantizer", "draft");
+    opts.optopt("q", "quantizer", "pick quantizer algorithm: neuquant, naive or grayscale (default: neuquant)", "naive");
     opts.optflag("r", "range", "arguments specify start and end images");
-    opts.optmulti("n", "reorder", "reorder frames before processing", "reverse");
+    opts.optmulti("n", "reorder", "reorder frames before processing: reverse, shuffle, or sort files by mtime or by taken (EXIF time)", "reverse");
+    opts.optflag("v", "verbose", "print how long each stage took");
+    opts.optflag("m", "low-memory", "decode each image twice instead of holding every frame in memory");
+    opts.optopt("", "raw", "read raw frames of this size from stdin instead of files", "WxH");
+    opts.optopt("", "sprite", "cut the one file given into frames of this size, like a game's sprite sheet", "WxH");
+    opts.optopt("", "sprite-frames", "how many tiles of the sprite sheet are frames (default: every whole tile)", "N");
+    opts.optopt("", "pix-fmt", "pixel format of raw frames: rgb24 or rgba (default: rgb24)", "rgba");
+    #[cfg(feature = "ffmpeg")]
+    opts.optflag("", "video", "decode a video file with ffmpeg, at the frame rate given by -f");
+    opts.optopt("w", "watch", "regenerate the gif from every file in this directory whenever they change", "DIR");
+    opts.optopt("c", "config", "read settings from this TOML file; -f, -q and -s override it", "FILE");
     opts.optflag("h", "help", "display this help");
 
     let matches = opts.parse(&args[1..])?;
     if matches.opt_present("h") {
-        let brief = format!("Usage: {} <files ...>", program);
+        let brief = format!("Usage: {} <files ...>\n       {} - (to read file names from stdin)", program, program);
         return Err(ArgsError::DisplayHelp(opts.usage(&brief)));
     }
 
+    let recipe = match matches.opt_str("c") {
+        Some(path) => load_recipe(&path).map_err(|e| ArgsError::Config(format!("{}: {}", path, e)))?,
+        None => Recipe::default(),
+    };
+
     let sample_rate = if let Some(sample_rate_str) = matches.opt_str("s") {
-        u32::from_str(&sample_rate_str)?
+        Some(u32::from_str(&sample_rate_str)?)
     } else {
-        1
+        None
     };
 
-    let quantizer = match matches.opt_str("q").map(|s| s.to_lowercase()) {
-        Some(ref s) if s == "naive" => Quantizer::Naive,
-        Some(_) => {
-            Quantizer::NeuQuant(sample_rate)
+    let effort = match matches.opt_str("quality").map(|s| s.to_lowercase()) {
+        None => None,
+        Some(ref s) if s == "draft" => Some(Effort::Draft),
+        Some(ref s) if s == "balanced" => Some(Effort::Balanced),
+        Some(ref s) if s == "best" => Some(Effort::Best),
+        Some(s) => return Err(ArgsError::Quality(s)),
+    };
+    let recipe = match effort {
+        Some(effort) if matches.opt_str("q").is_none() && sample_rate.is_none() => Recipe {
+            quantizer: Some(effort.quantizer()),
+            options: effort.apply(recipe.options),
+            ..recipe
         },
-        None => Quantizer::NeuQuant(sample_rate),
+        Some(effort) => Recipe { options: effort.apply(recipe.options), ..recipe },
+        None => recipe,
+    };
+
+    let quantizer = match (matches.opt_str("q").map(|s| s.to_lowercase()), recipe.quantizer) {
+        (Some(ref s), _) if s == "naive" => Quantizer::Naive,
+        (Some(ref s), _) if s == "grayscale" || s == "greyscale" => Quantizer::Grayscale,
+        (Some(_), _) | (None, None) => Quantizer::NeuQuant(sample_rate.unwrap_or(1)),
+        (None, Some(Quantizer::NeuQuant(rate))) => Quantizer::NeuQuant(sample_rate.unwrap_or(rate)),
+        (None, Some(quantizer)) => quantizer,
     };
 
-    let fps: usize = if let Some(fps_str) = matches.opt_str("f") {
-        usize::from_str(&fps_str)?
+    let fps = if let Some(fps_str) = matches.opt_str("f") {
+        FrameRate::from(usize::from_str(&fps_str)?)
     } else {
-        30
+        recipe.fps.unwrap_or(FrameRate::from(30))
     };
 
     let mut modifiers = vec![];
@@ -137,12 +206,47 @@ pub fn parse_args(args: &[String]) -> Result<Args, ArgsError> {
         match opt_str.as_str() {
             "reverse" | "rev" => modifiers.push(Modifier::Reverse),
             "shuffle" => modifiers.push(Modifier::Shuffle),
-            m @ _ => eprintln!("Ignoring unknown modifier `{}`", m),
+            "mtime" | "modified" => modifiers.push(Modifier::Sort(SortOrder::Modified)),
+            "taken" | "exif" => modifiers.push(Modifier::Sort(SortOrder::Taken)),
+            m => eprintln!("Ignoring unknown modifier `{}`", m),
         }
     }
 
-    let out_file = matches.opt_str("o").map(|f| f.clone());
-    let source = if matches.opt_present("r") {
+    let out_file = matches.opt_str("o").filter(|file| file != "-");
+    let verbose = matches.opt_present("v");
+    let low_memory = matches.opt_present("m");
+    let source = if let Some(size) = matches.opt_str("raw") {
+        let (width, height) = parse_size(&size)
+            .ok_or_else(|| ArgsError::RawSize(format!("expected a size like 640x480, got {}", size)))?;
+        let format = match matches.opt_str("pix-fmt").map(|s| s.to_lowercase()) {
+            None => RawFormat::Rgb24,
+            Some(ref s) if s == "rgb24" => RawFormat::Rgb24,
+            Some(ref s) if s == "rgba" => RawFormat::Rgba32,
+            Some(s) => return Err(ArgsError::RawSize(format!("unknown pixel format {}", s))),
+        };
+        Raw(width, height, format)
+    } else if let Some(size) = matches.opt_str("sprite") {
+        let (width, height) = parse_size(&size)
+            .ok_or_else(|| ArgsError::Sprite(format!("expected a tile size like 32x32, got {}", size)))?;
+        let frames = match matches.opt_str("sprite-frames") {
+            Some(frames) => Some(usize::from_str(&frames)?),
+            None => None,
+        };
+        if matches.free.len() != 1 {
+            return Err(ArgsError::Sprite(format!("expected one sprite sheet, got {}", matches.free.len())));
+        }
+        Sprite(PathBuf::from(&matches.free[0]), SpriteSheet { frames, ..SpriteSheet::new(width, height) })
+    } else if let Some(video) = video_source(&matches)? {
+        video
+    } else if let Some(dir) = matches.opt_str("w") {
+        if out_file.is_none() {
+            return Err(ArgsError::Watch("an output file is needed to rewrite".to_string()));
+        }
+        if !matches.free.is_empty() {
+            return Err(ArgsError::Watch("frames come from the directory, not file arguments".to_string()));
+        }
+        Watch(PathBuf::from(dir))
+    } else if matches.opt_present("r") {
         if matches.free.len() >= 2 {
             let (path_start, filename_start) = path_and_filename(&matches.free[0])?;
             let (path_end, filename_end) = path_and_filename(&matches.free[1])?;
@@ -155,6 +259,8 @@ pub fn parse_args(args: &[String]) -> Result<Args, ArgsError> {
         } else {
             return Err(ArgsError::ImageRange("missing start and end filenames".to_string()));
         }
+    } else if matches.free == ["-"] {
+        Stdin
     } else {
         if matches.free.len() == 1 {
             #[cfg(feature = "globbing")]
@@ -169,14 +275,40 @@ pub fn parse_args(args: &[String]) -> Result<Args, ArgsError> {
     };
 
     Ok(Args {
-        source: source,
-        fps: fps,
-        out_file: out_file,
-        quantizer: quantizer,
-        modifiers: modifiers,
+        source,
+        fps,
+        out_file,
+        quantizer,
+        modifiers,
+        verbose,
+        low_memory,
+        options: recipe.options,
     })
 }
 
+#[cfg(feature = "ffmpeg")]
+fn video_source(matches: &getopts::Matches) -> Result<Option<SourceImages>, ArgsError> {
+    if !matches.opt_present("video") {
+        return Ok(None);
+    }
+    match matches.free.len() {
+        1 => Ok(Some(Video(PathBuf::from(&matches.free[0])))),
+        n => Err(ArgsError::Video(format!("expected one video file, got {}", n))),
+    }
+}
+
+#[cfg(not(feature = "ffmpeg"))]
+fn video_source(_: &getopts::Matches) -> Result<Option<SourceImages>, ArgsError> {
+    Ok(None)
+}
+
+/// A size like `640x480`, neither side 0.
+fn parse_size(size: &str) -> Option<(u32, u32)> {
+    size.split_once('x')
+        .and_then(|(w, h)| Some((u32::from_str(w).ok()?, u32::from_str(h).ok()?)))
+        .filter(|&(w, h)| w > 0 && h > 0)
+}
+
 fn path_and_filename(input: &str) -> Result<(PathBuf, PathBuf), ArgsError> {
     let p = Path::new(&input);
     let parent = match p.parent() {
@@ -199,7 +331,7 @@ fn path_and_filename(input: &str) -> Result<(PathBuf, PathBuf), ArgsError> {
 #[cfg(test)]
 #[allow(unused_must_use)]
 mod tests {
-    use super::{parse_args, SourceImages, ArgsError, Args, Quantizer};
+    use super::{parse_args, SourceImages, ArgsError, Args, FrameRate, Modifier, Quantizer, RawFormat, SortOrder, SpriteSheet};
     use std::path::PathBuf;
     use std::str::FromStr;
 
@@ -223,7 +355,7 @@ mod tests {
     fn test_fps() {
         let args = parse_args(&make_args("engiffen -f 45"));
         assert!(args.is_ok());
-        assert_eq!(args.unwrap().fps, 45);
+        assert_eq!(args.unwrap().fps, FrameRate::from(45));
     }
 
     #[test]
@@ -249,6 +381,12 @@ mod tests {
         assert_err_eq(args, ArgsError::ParseInt(parse_error));
     }
 
+    #[test]
+    fn test_grayscale_quantizer() {
+        let args = parse_args(&make_args("engiffen -q grayscale"));
+        assert_eq!(args.unwrap().quantizer, Quantizer::Grayscale);
+    }
+
     #[test]
     fn test_file_list() {
         let args = parse_args(&make_args("engiffen this.jpg that.jpg other.jpg"));
@@ -263,6 +401,12 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_reorder() {
+        let args = parse_args(&make_args("engiffen -n taken -n reverse -n mtime a.jpg b.jpg")).unwrap();
+        assert_eq!(args.modifiers, vec![Modifier::Sort(SortOrder::Taken), Modifier::Reverse, Modifier::Sort(SortOrder::Modified)]);
+    }
+
     #[test]
     fn test_file_range() {
         let args = parse_args(&make_args("engiffen -r thing001.jpg thing010.jpg"));
@@ -318,13 +462,95 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_sprite() {
+        let args = parse_args(&make_args("engiffen --sprite 32x16 --sprite-frames 6 walk.png"));
+        let layout = SpriteSheet { frames: Some(6), ..SpriteSheet::new(32, 16) };
+        assert_eq!(args.unwrap().source, SourceImages::Sprite(PathBuf::from("walk.png"), layout));
+        let args = parse_args(&make_args("engiffen --sprite 32x16 walk.png run.png"));
+        assert_err_eq(args, ArgsError::Sprite("expected one sprite sheet, got 2".to_string()));
+        let args = parse_args(&make_args("engiffen --sprite 32 walk.png"));
+        assert_err_eq(args, ArgsError::Sprite("expected a tile size like 32x32, got 32".to_string()));
+    }
+
+    #[test]
+    fn test_raw() {
+        let args = parse_args(&make_args("engiffen --raw 640x480"));
+        assert_eq!(args.unwrap().source, SourceImages::Raw(640, 480, RawFormat::Rgb24));
+        let args = parse_args(&make_args("engiffen --raw 2x3 --pix-fmt rgba"));
+        assert_eq!(args.unwrap().source, SourceImages::Raw(2, 3, RawFormat::Rgba32));
+        let args = parse_args(&make_args("engiffen --raw 640"));
+        assert_err_eq(args, ArgsError::RawSize("expected a size like 640x480, got 640".to_string()));
+        let args = parse_args(&make_args("engiffen --raw 1x1 --pix-fmt yuv420p"));
+        assert_err_eq(args, ArgsError::RawSize("unknown pixel format yuv420p".to_string()));
+    }
+
+    #[cfg(feature = "ffmpeg")]
+    #[test]
+    fn test_video() {
+        let args = parse_args(&make_args("engiffen --video clip.mp4 -f 12"));
+        assert_eq!(args.unwrap().source, SourceImages::Video(PathBuf::from("clip.mp4")));
+        let args = parse_args(&make_args("engiffen --video a.mp4 b.mp4"));
+        assert_err_eq(args, ArgsError::Video("expected one video file, got 2".to_string()));
+    }
+
+    #[test]
+    fn test_stdin_and_stdout() {
+        let args = parse_args(&make_args("engiffen - -o -")).unwrap();
+        assert_eq!(args.source, SourceImages::Stdin);
+        assert_eq!(args.out_file, None);
+        let args = parse_args(&make_args("engiffen --watch renders -o -"));
+        assert_err_eq(args, ArgsError::Watch("an output file is needed to rewrite".to_string()));
+    }
+
+    #[test]
+    fn test_watch() {
+        let args = parse_args(&make_args("engiffen --watch renders -o preview.gif"));
+        assert_eq!(args.unwrap().source, SourceImages::Watch(PathBuf::from("renders")));
+        let args = parse_args(&make_args("engiffen -w renders"));
+        assert_err_eq(args, ArgsError::Watch("an output file is needed to rewrite".to_string()));
+    }
+
+    #[test]
+    fn test_config() {
+        use std::fs;
+        use std::env;
+
+        let path = env::temp_dir().join(format!("engiffen-test-config-{}.toml", std::process::id()));
+        fs::write(&path, "fps = 12\nsample_rate = 4\nopaque = true\n").unwrap();
+        let args = parse_args(&make_args(&format!("engiffen -c {}", path.display()))).unwrap();
+        assert_eq!((args.fps, args.quantizer), (FrameRate::from(12), Quantizer::NeuQuant(4)));
+        assert!(args.options.opaque);
+        let args = parse_args(&make_args(&format!("engiffen -c {} -f 20 -s 2", path.display()))).unwrap();
+        assert_eq!((args.fps, args.quantizer), (FrameRate::from(20), Quantizer::NeuQuant(2)));
+        fs::remove_file(&path).unwrap();
+
+        match parse_args(&make_args("engiffen -c no-such-config.toml")) {
+            Err(ArgsError::Config(_)) => (),
+            other => panic!("expected a config error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_quality() {
+        let args = parse_args(&make_args("engiffen --quality draft")).unwrap();
+        assert_eq!(args.quantizer, Quantizer::NeuQuant(10));
+        assert!(!args.options.delay_dither);
+        let args = parse_args(&make_args("engiffen --quality Best -s 2")).unwrap();
+        assert_eq!(args.quantizer, Quantizer::NeuQuant(2));
+        assert!(args.options.alpha_dither);
+        let args = parse_args(&make_args("engiffen --quality balanced -q naive")).unwrap();
+        assert_eq!(args.quantizer, Quantizer::Naive);
+        assert_err_eq(parse_args(&make_args("engiffen --quality great")), ArgsError::Quality("great".to_string()));
+    }
+
     #[test]
     fn test_help() {
         let args = parse_args(&make_args("engiffen -h"));
         // Such a long DisplayHelp message that will probably change as more
         // options get added. Just check the error's type instead.
         match args {
-            Err(ArgsError::DisplayHelp(_)) => assert!(true),
+            Err(ArgsError::DisplayHelp(_)) => (),
             Err(_) => panic!("Wrong error type returned"),
             Ok(_) => panic!("Should not have returned an Ok args result"),
         }
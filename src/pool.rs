@@ -0,0 +1,105 @@
+//! Running an encode's parallel work on a thread pool of the caller's
+//! choosing instead of rayon's global one.
+
+use std::fmt;
+use std::sync::Arc;
+use rayon;
+use super::{Error, Options};
+
+/// A thread pool for `Options::thread_pool`, so that an encode only uses as
+/// many threads as it's given, for example in a server with a CPU budget.
+/// Without one, encoding runs on rayon's global pool, with a thread per
+/// core. Clones share the same pool.
+#[derive(Clone)]
+pub struct ThreadPool(Arc<rayon::ThreadPool>);
+
+impl ThreadPool {
+    /// Starts a pool of `threads` threads, or one per core for 0.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error::ThreadPool` if the threads can't be started.
+    #[allow(deprecated)]
+    pub fn new(threads: usize) -> Result<ThreadPool, Error> {
+        let config = rayon::Configuration::new().num_threads(threads);
+        match rayon::ThreadPool::new(config) {
+            Ok(pool) => Ok(ThreadPool(Arc::new(pool))),
+            Err(e) => Err(Error::ThreadPool(e.to_string())),
+        }
+    }
+
+    /// The number of threads in the pool.
+    pub fn threads(&self) -> usize {
+        self.0.current_num_threads()
+    }
+
+    /// Runs `op` on the pool, so that parallel work inside it that has no
+    /// `Options` to take a pool from, like `Gif::write` with the
+    /// `parallel-write` feature, uses the pool's threads.
+    ///
+    /// ```rust
+    /// # use engiffen::{engiffen, Image, Quantizer, ThreadPool};
+    /// let frames = vec![Image { pixels: vec![[0, 0, 0, 255]; 4], width: 2, height: 2 }];
+    /// let gif = engiffen(&frames, 10, Quantizer::Naive).unwrap();
+    /// let pool = ThreadPool::new(2).unwrap();
+    /// let bytes = pool.install(|| gif.to_bytes()).unwrap();
+    /// ```
+    pub fn install<R, F>(&self, op: F) -> R
+        where R: Send, F: FnOnce() -> R + Send
+    {
+        self.0.install(op)
+    }
+}
+
+/// Shares a pool the caller already runs other work on.
+impl From<Arc<rayon::ThreadPool>> for ThreadPool {
+    fn from(pool: Arc<rayon::ThreadPool>) -> ThreadPool {
+        ThreadPool(pool)
+    }
+}
+
+impl fmt::Debug for ThreadPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ThreadPool {{ threads: {} }}", self.threads())
+    }
+}
+
+/// Pools are equal when they're the same pool.
+impl PartialEq for ThreadPool {
+    fn eq(&self, other: &ThreadPool) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Runs `op` on `options.thread_pool`, or on the current pool if it has
+/// none, so that the parallel iterators inside it use that pool's threads.
+pub fn install<R, F>(options: &Options, op: F) -> R
+    where R: Send, F: FnOnce() -> R + Send
+{
+    match options.thread_pool {
+        Some(ref pool) => pool.install(op),
+        None => op(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rayon::{self, prelude::*};
+    use super::{install, ThreadPool};
+    use super::super::Options;
+
+    #[test]
+    fn test_install() {
+        let pool = ThreadPool::new(2).unwrap();
+        assert_eq!(pool.threads(), 2);
+        assert_eq!(pool, pool.clone());
+        assert!(pool != ThreadPool::new(2).unwrap());
+
+        let options = Options { thread_pool: Some(pool), ..Options::default() };
+        let threads = install(&options, rayon::current_num_threads);
+        assert_eq!(threads, 2);
+        let sum: u32 = install(&options, || (0..100u32).into_par_iter().sum());
+        assert_eq!(sum, 4950);
+        assert_eq!(options.thread_pool.unwrap().install(rayon::current_num_threads), 2);
+    }
+}
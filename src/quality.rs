@@ -0,0 +1,203 @@
+//! Measuring how closely an encoded Gif matches its source frames.
+
+use image::{ImageBuffer, Rgba, RgbaImage};
+use super::{Error, Gif, Image, RGBA};
+
+/// The side of the square windows SSIM is computed over.
+const WINDOW: usize = 8;
+const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+/// The color distance drawn at the hot end of an error map.
+const HEAT_SCALE: f64 = 64.0;
+
+/// How closely one encoded frame matches its source.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Quality {
+    /// Peak signal to noise ratio over the color channels, in decibels.
+    /// Infinite for identical frames; above about 30 is hard to tell apart.
+    pub psnr: f64,
+    /// Structural similarity of the frames' brightness, from 1 (identical)
+    /// down towards 0.
+    pub ssim: f64,
+}
+
+/// The quality of every frame of a Gif.
+#[derive(Debug, PartialEq, Clone)]
+pub struct QualityReport {
+    pub frames: Vec<Quality>,
+}
+
+impl QualityReport {
+    /// The PSNR of the worst frame.
+    pub fn min_psnr(&self) -> f64 {
+        self.frames.iter().map(|q| q.psnr).fold(f64::INFINITY, f64::min)
+    }
+
+    /// The SSIM of the worst frame.
+    pub fn min_ssim(&self) -> f64 {
+        self.frames.iter().map(|q| q.ssim).fold(1.0, f64::min)
+    }
+}
+
+/// Compares each frame of `gif`, as it would be shown, with the frame in
+/// `sources` it was made from. Both are composited onto black first, so
+/// transparency counts too.
+pub fn report(gif: &Gif, sources: &[Image]) -> Result<QualityReport, Error> {
+    let frames = compare(gif, sources, |a, b, width, _| {
+        Quality { psnr: psnr(a, b), ssim: ssim(a, b, width as usize) }
+    })?;
+    Ok(QualityReport { frames })
+}
+
+/// Draws how far each pixel of each frame of `gif` is from its source,
+/// compared like `report`: black where they match, through blue and red to
+/// yellow, and white for colors `HEAT_SCALE` or more apart.
+pub fn error_maps(gif: &Gif, sources: &[Image]) -> Result<Vec<RgbaImage>, Error> {
+    compare(gif, sources, |a, b, width, height| {
+        ImageBuffer::from_fn(width, height, |x, y| {
+            let i = (y * width + x) as usize;
+            let distance = (0..3).map(|c| (a[i][c] - b[i][c]).powi(2)).sum::<f64>().sqrt();
+            heat(distance / HEAT_SCALE)
+        })
+    })
+}
+
+/// How far `pixels` are from the palette colors they were mapped to, as a
+/// root mean square over the color channels, both composited onto black.
+/// The `transparent` index counts as transparent black.
+pub fn mapping_error(pixels: &[RGBA], indices: &[u8], palette: &[u8], transparent: Option<u8>) -> f64 {
+    let squared: f64 = pixels.iter().zip(indices).map(|(px, &idx)| {
+        let mapped = match transparent {
+            Some(t) if t == idx => [0.0; 3],
+            _ => {
+                let color = idx as usize * 3;
+                [palette[color] as f64, palette[color + 1] as f64, palette[color + 2] as f64]
+            },
+        };
+        let source = on_black(px);
+        (0..3).map(|c| (source[c] - mapped[c]).powi(2)).sum::<f64>()
+    }).sum();
+    (squared / (pixels.len().max(1) * 3) as f64).sqrt()
+}
+
+/// Runs `measure` on the pixels of each source and the frame made from it,
+/// composited onto black, along with the frames' width and height.
+fn compare<T, M>(gif: &Gif, sources: &[Image], measure: M) -> Result<Vec<T>, Error>
+    where M: Fn(&[[f64; 3]], &[[f64; 3]], u32, u32) -> T
+{
+    if sources.len() != gif.frame_count() {
+        return Err(Error::Length(sources.len(), gif.frame_count()));
+    }
+    gif.rgba_frames().zip(sources).map(|(encoded, source)| {
+        if (source.width, source.height) != (encoded.width(), encoded.height()) {
+            return Err(Error::Mismatch((source.width, source.height), (encoded.width(), encoded.height())));
+        }
+        let a: Vec<[f64; 3]> = source.pixels.iter().map(|px| on_black(px)).collect();
+        let b: Vec<[f64; 3]> = encoded.into_raw().chunks(4).map(on_black).collect();
+        Ok(measure(&a, &b, source.width, source.height))
+    }).collect()
+}
+
+/// A color on the error map's scale, from black at 0 through blue, red and
+/// yellow to white at 1 and above.
+fn heat(t: f64) -> Rgba<u8> {
+    const STOPS: [[f64; 3]; 5] = [[0.0, 0.0, 0.0], [0.0, 0.0, 255.0], [255.0, 0.0, 0.0], [255.0, 255.0, 0.0], [255.0, 255.0, 255.0]];
+    let t = t.clamp(0.0, 1.0) * (STOPS.len() - 1) as f64;
+    let n = (t as usize).min(STOPS.len() - 2);
+    let f = t - n as f64;
+    let channel = |c: usize| (STOPS[n][c] + (STOPS[n + 1][c] - STOPS[n][c]) * f).round() as u8;
+    Rgba([channel(0), channel(1), channel(2), 255])
+}
+
+fn on_black(px: &[u8]) -> [f64; 3] {
+    let alpha = px[3] as f64 / 255.0;
+    [px[0] as f64 * alpha, px[1] as f64 * alpha, px[2] as f64 * alpha]
+}
+
+fn psnr(a: &[[f64; 3]], b: &[[f64; 3]]) -> f64 {
+    let squared: f64 = a.iter().zip(b).map(|(pa, pb)| (0..3).map(|c| (pa[c] - pb[c]).powi(2)).sum::<f64>()).sum();
+    let mse = squared / (a.len().max(1) * 3) as f64;
+    if mse == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (255.0 * 255.0 / mse).log10()
+    }
+}
+
+/// The mean SSIM of the frames' luma over non-overlapping windows.
+fn ssim(a: &[[f64; 3]], b: &[[f64; 3]], width: usize) -> f64 {
+    let luma = |px: &[f64; 3]| px[0] * 0.299 + px[1] * 0.587 + px[2] * 0.114;
+    let (a, b): (Vec<f64>, Vec<f64>) = (a.iter().map(luma).collect(), b.iter().map(luma).collect());
+    let height = a.len() / width.max(1);
+    let mut total = 0.0;
+    let mut windows = 0;
+    for wy in (0..height).step_by(WINDOW) {
+        for wx in (0..width).step_by(WINDOW) {
+            let idxs: Vec<usize> = (wy..(wy + WINDOW).min(height))
+                .flat_map(|y| (wx..(wx + WINDOW).min(width)).map(move |x| y * width + x))
+                .collect();
+            let n = idxs.len() as f64;
+            let mean_a = idxs.iter().map(|&i| a[i]).sum::<f64>() / n;
+            let mean_b = idxs.iter().map(|&i| b[i]).sum::<f64>() / n;
+            let var_a = idxs.iter().map(|&i| (a[i] - mean_a).powi(2)).sum::<f64>() / n;
+            let var_b = idxs.iter().map(|&i| (b[i] - mean_b).powi(2)).sum::<f64>() / n;
+            let cov = idxs.iter().map(|&i| (a[i] - mean_a) * (b[i] - mean_b)).sum::<f64>() / n;
+            total += ((2.0 * mean_a * mean_b + C1) * (2.0 * cov + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2));
+            windows += 1;
+        }
+    }
+    if windows == 0 { 1.0 } else { total / windows as f64 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{error_maps, heat, mapping_error, report};
+    use super::super::{engiffen, Image, Quantizer};
+    use fixtures::solid;
+
+    #[test]
+    fn test_identical() {
+        let imgs = vec![Image { pixels: vec![[255, 0, 0, 255], [0, 0, 0, 0], [0, 0, 255, 255], [9, 9, 9, 255]], width: 2, height: 2 }];
+        let gif = engiffen(&imgs, 10, Quantizer::Naive).unwrap();
+        let report = report(&gif, &imgs).unwrap();
+        assert_eq!(report.min_psnr(), f64::INFINITY);
+        assert!((report.min_ssim() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_lossy() {
+        let pixels = (0..256).map(|n| [n as u8, (n * 7 % 256) as u8, 0, 255]).collect();
+        let imgs = vec![Image { pixels, width: 16, height: 16 }];
+        let gif = engiffen(&imgs, 10, Quantizer::Grayscale).unwrap();
+        let report = report(&gif, &imgs).unwrap();
+        assert!(report.min_psnr().is_finite() && report.min_psnr() > 0.0);
+        assert!(report.min_ssim() < 1.0);
+        assert!(super::report(&gif, &[]).is_err());
+    }
+
+    #[test]
+    fn test_mapping_error() {
+        let pixels = [[10, 20, 30, 255], [0, 0, 0, 0]];
+        assert_eq!(mapping_error(&pixels, &[0, 1], &[10, 20, 30, 99, 99, 99], Some(1)), 0.0);
+        // Off by 6 in each channel, then a transparent pixel drawn opaque.
+        let squared = 3.0 * 36.0 + (16.0 * 16.0 + 26.0 * 26.0 + 36.0 * 36.0);
+        assert_eq!(mapping_error(&pixels, &[1, 1], &[10, 20, 30, 16, 26, 36], None), (squared / 6.0f64).sqrt());
+    }
+
+    #[test]
+    fn test_error_maps() {
+        let imgs = vec![Image { pixels: vec![[255, 0, 0, 255], [0, 0, 0, 0], [0, 0, 255, 255], [9, 9, 9, 255]], width: 2, height: 2 }];
+        let gif = engiffen(&imgs, 10, Quantizer::Naive).unwrap();
+        let maps = error_maps(&gif, &imgs).unwrap();
+        assert_eq!(maps.len(), 1);
+        assert_eq!(maps[0].dimensions(), (2, 2));
+        assert!(maps[0].pixels().all(|px| px.data == [0, 0, 0, 255]));
+
+        let gray = vec![solid(2, 2, [255, 0, 0, 255])];
+        let gif = engiffen(&gray, 10, Quantizer::Grayscale).unwrap();
+        assert_eq!(error_maps(&gif, &gray).unwrap()[0].get_pixel(0, 0).data, [255, 255, 255, 255]);
+        assert_eq!(heat(0.5).data, [255, 0, 0, 255]);
+        assert!(error_maps(&gif, &[]).is_err());
+    }
+}
@@ -0,0 +1,112 @@
+//! Reading raw, headerless frames from a stream, like the output of
+//! `ffmpeg -f rawvideo`.
+
+use std::io::{self, Read};
+use image;
+use super::{Error, Image};
+
+/// How pixels are laid out in a raw frame.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum RawFormat {
+    /// Three bytes per pixel: red, green and blue. ffmpeg's `rgb24`.
+    Rgb24,
+    /// Four bytes per pixel: red, green, blue and alpha. ffmpeg's `rgba`.
+    Rgba32,
+}
+
+impl RawFormat {
+    /// The number of bytes each pixel takes.
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            RawFormat::Rgb24 => 3,
+            RawFormat::Rgba32 => 4,
+        }
+    }
+}
+
+/// An iterator over raw frames read from a stream, created by
+/// `read_raw_frames`. It ends when the stream does, between two frames.
+#[derive(Debug)]
+pub struct RawFrames<R> {
+    reader: R,
+    width: u32,
+    height: u32,
+    format: RawFormat,
+    done: bool,
+}
+
+impl<R: Read> RawFrames<R> {
+    pub fn new(reader: R, width: u32, height: u32, format: RawFormat) -> RawFrames<R> {
+        RawFrames { reader, width, height, format, done: false }
+    }
+}
+
+/// Fills `buf` from `reader`, returning false if the stream ended before any
+/// of it was read.
+pub fn fill<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) if read == 0 => return Ok(false),
+            Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                format!("stream ended {} bytes into a {} byte frame", read, buf.len()))),
+            Ok(n) => read += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+impl<R: Read> Iterator for RawFrames<R> {
+    type Item = Result<Image, Error>;
+
+    fn next(&mut self) -> Option<Result<Image, Error>> {
+        if self.done {
+            return None;
+        }
+        let bpp = self.format.bytes_per_pixel();
+        let mut buf = vec![0; self.width as usize * self.height as usize * bpp];
+        match fill(&mut self.reader, &mut buf) {
+            Ok(true) => {},
+            Ok(false) => {
+                self.done = true;
+                return None;
+            },
+            Err(e) => {
+                self.done = true;
+                return Some(Err(Error::ImageLoad(image::ImageError::IoError(e))));
+            },
+        }
+        let pixels = buf.chunks(bpp)
+            .map(|px| [px[0], px[1], px[2], if bpp == 4 { px[3] } else { 255 }])
+            .collect();
+        Some(Ok(Image { pixels, width: self.width, height: self.height }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RawFormat, RawFrames};
+    use super::super::Error;
+
+    #[test]
+    fn test_raw_frames() {
+        let bytes: &[u8] = &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+        let frames: Vec<_> = RawFrames::new(bytes, 2, 1, RawFormat::Rgb24).map(Result::unwrap).collect();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].pixels, vec![[7, 8, 9, 255], [10, 11, 12, 255]]);
+
+        let frames: Vec<_> = RawFrames::new(bytes, 1, 1, RawFormat::Rgba32).map(Result::unwrap).collect();
+        assert_eq!(frames[2].pixels, vec![[9, 10, 11, 12]]);
+    }
+
+    #[test]
+    fn test_raw_frames_truncated() {
+        let bytes: &[u8] = &[1, 2, 3, 4, 5];
+        let mut frames = RawFrames::new(bytes, 1, 1, RawFormat::Rgba32);
+        assert!(frames.next().unwrap().is_ok());
+        assert!(matches!(frames.next(), Some(Err(Error::ImageLoad(_)))));
+        assert!(frames.next().is_none());
+    }
+}
@@ -0,0 +1,81 @@
+//! Writing a Gif in a way that can pick up where an interrupted run left
+//! off.
+
+use std::cell::RefCell;
+use std::io;
+use gif::{Encoder, Repeat, SetParameter};
+use stats::Counter;
+
+/// Everything needed to carry on with an interrupted encode: the palette
+/// the finished frames were mapped onto, and how far the output got.
+#[derive(Debug, Eq, PartialEq, Clone, Hash)]
+pub struct Checkpoint {
+    pub palette: Vec<u8>,
+    pub transparency: Option<u8>,
+    /// The number of frames completely written.
+    pub frames_written: usize,
+    /// The length of the output up to and including the last of those
+    /// frames.
+    pub bytes_written: u64,
+}
+
+/// The number of bytes an encoder writes before the first frame.
+pub fn header_len(width: u16, height: u16, palette: &[u8]) -> io::Result<u64> {
+    let mut header = Vec::new();
+    {
+        let mut encoder = Encoder::new(&mut header, width, height, palette)?;
+        encoder.set(Repeat::Infinite)?;
+    }
+    // Dropping the encoder wrote a one byte trailer.
+    Ok(header.len() as u64 - 1)
+}
+
+/// Lets the encoder write to the output while the caller still counts and
+/// flushes it between frames.
+pub struct Shared<'a, W: 'a>(pub &'a RefCell<Counter<W>>);
+
+impl<'a, W: io::Write> io::Write for Shared<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+/// Discards the first `skip` bytes written, for leaving out the header
+/// when resuming into output that already has one.
+pub struct Skip<W> {
+    pub inner: W,
+    pub skip: u64,
+}
+
+impl<W: io::Write> io::Write for Skip<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let skipped = (self.skip.min(buf.len() as u64)) as usize;
+        self.skip -= skipped as u64;
+        if skipped == buf.len() {
+            return Ok(skipped);
+        }
+        Ok(skipped + self.inner.write(&buf[skipped..])?)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use super::Skip;
+
+    #[test]
+    fn test_skip() {
+        let mut out = Skip { inner: Vec::new(), skip: 3 };
+        out.write_all(b"ab").unwrap();
+        out.write_all(b"cdef").unwrap();
+        assert_eq!(out.inner, b"def");
+    }
+}
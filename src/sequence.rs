@@ -0,0 +1,332 @@
+//! Operations on the sequence of frames, run before frames are quantized.
+
+use std::borrow::Cow;
+use super::{Error, Image};
+use overlay;
+
+/// How consecutive segments are joined by `engiffen_segments`.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Transition {
+    /// Cut straight from one segment to the next.
+    Cut,
+    /// Overlap the last N frames of a segment with the first N frames of the
+    /// next, fading from one into the other. The result is N frames shorter.
+    Crossfade(usize),
+    /// Fade the last N frames of a segment out to black, and the first N
+    /// frames of the next in from black.
+    DipToBlack(usize),
+}
+
+/// Keeps every `n`th frame, adding the delays of the dropped frames onto the
+/// kept frame before them. With `blur`, each kept frame is instead the
+/// average of itself and the frames dropped after it, weighted by delay.
+pub fn decimate<'a>(imgs: Cow<'a, [Image]>, delays: Vec<f64>, n: usize, blur: bool) -> (Cow<'a, [Image]>, Vec<f64>) {
+    if n <= 1 {
+        return (imgs, delays);
+    }
+    let kept = if blur {
+        imgs.chunks(n).zip(delays.chunks(n)).map(|(group, weights)| average(group, weights)).collect()
+    } else {
+        imgs.iter().step_by(n).cloned().collect()
+    };
+    let delays = delays.chunks(n).map(|chunk| chunk.iter().sum()).collect();
+    (Cow::Owned(kept), delays)
+}
+
+/// Merges runs of consecutive frames shown for less than `min_ms` each
+/// into one frame shown for the whole run, so that every frame is shown for
+/// at least `min_ms`. The first frame of each run is kept, or with `blur`,
+/// the average of the run weighted by delay. A run too short at the end is
+/// merged into the frame before it.
+pub fn merge_fast<'a>(imgs: Cow<'a, [Image]>, delays: Vec<f64>, min_ms: f64, blur: bool) -> (Cow<'a, [Image]>, Vec<f64>) {
+    if delays.iter().all(|&delay| delay >= min_ms) {
+        return (imgs, delays);
+    }
+    // The start, end and total delay of each run.
+    let mut runs: Vec<(usize, usize, f64)> = Vec::new();
+    let (mut start, mut total) = (0, 0.0);
+    for (n, &delay) in delays.iter().enumerate() {
+        total += delay;
+        if total >= min_ms {
+            runs.push((start, n + 1, total));
+            start = n + 1;
+            total = 0.0;
+        }
+    }
+    if start < delays.len() {
+        match runs.last_mut() {
+            Some(last) => {
+                last.1 = delays.len();
+                last.2 += total;
+            },
+            None => runs.push((start, delays.len(), total)),
+        }
+    }
+    let kept = runs.iter().map(|&(start, end, _)| {
+        if blur { average(&imgs[start..end], &delays[start..end]) } else { imgs[start].clone() }
+    }).collect();
+    (Cow::Owned(kept), runs.iter().map(|run| run.2).collect())
+}
+
+/// Averages frames of the same dimensions, weighting each by its delay.
+fn average(imgs: &[Image], weights: &[f64]) -> Image {
+    let total: f64 = weights.iter().sum();
+    if imgs.len() == 1 || total <= 0.0 {
+        return imgs[0].clone();
+    }
+    let mut sums = vec![[0f64; 4]; imgs[0].pixels.len()];
+    for (img, &weight) in imgs.iter().zip(weights) {
+        for (sum, px) in sums.iter_mut().zip(&img.pixels) {
+            for c in 0..4 {
+                sum[c] += px[c] as f64 * weight;
+            }
+        }
+    }
+    let pixels = sums.iter().map(|sum| {
+        let mut px = [0u8; 4];
+        for c in 0..4 {
+            px[c] = (sum[c] / total).round() as u8;
+        }
+        px
+    }).collect();
+    Image { pixels, width: imgs[0].width, height: imgs[0].height }
+}
+
+/// Linearly blends two frames of the same dimensions, `t` of the way from
+/// `a` to `b`.
+pub fn blend(a: &Image, b: &Image, t: f64) -> Image {
+    let pixels = a.pixels.iter().zip(&b.pixels).map(|(pa, pb)| {
+        let mut px = [0u8; 4];
+        for c in 0..4 {
+            px[c] = (pa[c] as f64 + (pb[c] as f64 - pa[c] as f64) * t).round() as u8;
+        }
+        px
+    }).collect();
+    Image { pixels, width: a.width, height: a.height }
+}
+
+/// Resamples the sequence to one frame every `delay` milliseconds, blending
+/// between the two source frames that are on screen on either side of each
+/// output frame. The final source frame is held rather than blended into the
+/// first, since the sequence may not be meant to loop.
+pub fn interpolate<'a>(imgs: &[Image], delays: &[f64], delay: f64) -> (Cow<'a, [Image]>, Vec<f64>) {
+    let total: f64 = delays.iter().sum();
+    let count = (total / delay).ceil().max(1.0) as usize;
+    let mut frames = Vec::with_capacity(count);
+    let mut out_delays = Vec::with_capacity(count);
+    let mut src = 0;
+    let mut src_start = 0.0;
+    for n in 0..count {
+        let time = n as f64 * delay;
+        while src + 1 < imgs.len() && time >= src_start + delays[src] {
+            src_start += delays[src];
+            src += 1;
+        }
+        let frame = if src + 1 < imgs.len() && delays[src] > 0.0 {
+            blend(&imgs[src], &imgs[src + 1], (time - src_start) / delays[src])
+        } else {
+            imgs[src].clone()
+        };
+        frames.push(frame);
+        out_delays.push(delay.min(total - time));
+    }
+    (Cow::Owned(frames), out_delays)
+}
+
+/// Blends the last `count` frames into the first `count` frames and drops
+/// them from the end. The first frame is then mostly the frame that would
+/// have followed the new last frame, fading into the original start, so the
+/// loop has no visible seam.
+pub fn loop_crossfade<'a>(imgs: Cow<'a, [Image]>, mut delays: Vec<f64>, count: usize) -> (Cow<'a, [Image]>, Vec<f64>) {
+    let count = count.min(imgs.len() / 2);
+    if count == 0 {
+        return (imgs, delays);
+    }
+    let mut imgs = imgs.into_owned();
+    let tail = imgs.split_off(imgs.len() - count);
+    for (n, end) in tail.iter().enumerate() {
+        let t = (n + 1) as f64 / (count + 1) as f64;
+        imgs[n] = blend(end, &imgs[n], t);
+    }
+    delays.truncate(imgs.len());
+    (Cow::Owned(imgs), delays)
+}
+
+/// Concatenates segments, joining them with `transition`. A transition never
+/// uses more frames than either side of it has.
+pub fn join(segments: &[&[Image]], transition: Transition) -> Result<Vec<Image>, Error> {
+    let mut out: Vec<Image> = Vec::with_capacity(segments.iter().map(|s| s.len()).sum());
+    for segment in segments {
+        if let (Some(last), Some(first)) = (out.last(), segment.first()) {
+            if (last.width, last.height) != (first.width, first.height) {
+                return Err(Error::Mismatch((last.width, last.height), (first.width, first.height)));
+            }
+        }
+        let skip = match transition {
+            _ if out.is_empty() => 0,
+            Transition::Cut => 0,
+            Transition::Crossfade(count) => {
+                let count = count.min(out.len()).min(segment.len());
+                let start = out.len() - count;
+                for (n, img) in segment[..count].iter().enumerate() {
+                    let t = (n + 1) as f64 / (count + 1) as f64;
+                    out[start + n] = blend(&out[start + n], img, t);
+                }
+                count
+            },
+            Transition::DipToBlack(count) => {
+                let count = count.min(out.len());
+                let start = out.len() - count;
+                for (n, img) in out[start..].iter_mut().enumerate() {
+                    let t = (n + 1) as f64 / (count + 1) as f64;
+                    *img = blend(img, &black(img), t);
+                }
+                0
+            },
+        };
+        let fade_in = match transition {
+            Transition::DipToBlack(count) if !out.is_empty() => count.min(segment.len()),
+            _ => 0,
+        };
+        for (n, img) in segment.iter().enumerate().skip(skip) {
+            if n < fade_in {
+                let t = (n + 1) as f64 / (fade_in + 1) as f64;
+                out.push(blend(&black(img), img, t));
+            } else {
+                out.push(img.clone());
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// An opaque black frame with the same dimensions as `img`.
+/// Turns the frames of an animation, each drawn at an `(x, y)` offset over
+/// what came before it, into full frames. The canvas is large enough to
+/// hold every frame and starts out transparent.
+pub fn flatten_layers(layers: &[(Image, u32, u32)]) -> Vec<Image> {
+    let width = layers.iter().map(|l| l.1 + l.0.width).max().unwrap_or(0);
+    let height = layers.iter().map(|l| l.2 + l.0.height).max().unwrap_or(0);
+    let mut canvas = Image { pixels: vec![[0, 0, 0, 0]; (width * height) as usize], width, height };
+    layers.iter().map(|&(ref layer, left, top)| {
+        for (n, px) in layer.pixels.iter().enumerate() {
+            let (x, y) = (left + n as u32 % layer.width, top + n as u32 / layer.width);
+            overlay::draw(&mut canvas, x as i64, y as i64, *px);
+        }
+        canvas.clone()
+    }).collect()
+}
+
+fn black(img: &Image) -> Image {
+    Image { pixels: vec![[0, 0, 0, 255]; img.pixels.len()], width: img.width, height: img.height }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{flatten_layers, blend, decimate, interpolate, join, loop_crossfade, merge_fast, Transition};
+    use super::super::Image;
+    use std::borrow::Cow;
+    use fixtures::solid;
+
+    fn frames(n: u8) -> Vec<Image> {
+        (0..n).map(|n| Image { pixels: vec![[n, n, n, 255]], width: 1, height: 1 }).collect()
+    }
+
+    #[test]
+    fn test_decimate() {
+        let imgs = frames(5);
+        let (kept, delays) = decimate(Cow::Borrowed(&imgs), vec![10.0; 5], 2, false);
+        let values: Vec<_> = kept.iter().map(|img| img.pixels[0][0]).collect();
+        assert_eq!(values, vec![0, 2, 4]);
+        assert_eq!(delays, vec![20.0, 20.0, 10.0]);
+    }
+
+    #[test]
+    fn test_decimate_blur() {
+        let imgs = frames(5);
+        let (kept, delays) = decimate(Cow::Borrowed(&imgs), vec![10.0, 30.0, 10.0, 10.0, 10.0], 2, true);
+        let values: Vec<_> = kept.iter().map(|img| img.pixels[0][0]).collect();
+        assert_eq!(values, vec![1, 3, 4]);
+        assert_eq!(delays, vec![40.0, 20.0, 10.0]);
+    }
+
+    #[test]
+    fn test_decimate_noop() {
+        let imgs = frames(3);
+        let (kept, delays) = decimate(Cow::Borrowed(&imgs), vec![10.0; 3], 1, true);
+        assert!(match kept { Cow::Borrowed(_) => true, Cow::Owned(_) => false });
+        assert_eq!(delays.len(), 3);
+    }
+
+    #[test]
+    fn test_merge_fast() {
+        let imgs = frames(7);
+        let (kept, delays) = merge_fast(Cow::Borrowed(&imgs), vec![8.0, 8.0, 8.0, 30.0, 8.0, 8.0, 8.0], 20.0, false);
+        let values: Vec<_> = kept.iter().map(|img| img.pixels[0][0]).collect();
+        assert_eq!(values, vec![0, 3, 4]);
+        assert_eq!(delays, vec![24.0, 30.0, 24.0]);
+
+        // The leftover frame joins the run before it.
+        let (kept, delays) = merge_fast(Cow::Borrowed(&imgs[..3]), vec![10.0; 3], 20.0, true);
+        assert_eq!(kept.iter().map(|img| img.pixels[0][0]).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(delays, vec![30.0]);
+
+        let (kept, _) = merge_fast(Cow::Borrowed(&imgs), vec![20.0; 7], 20.0, false);
+        assert!(match kept { Cow::Borrowed(_) => true, Cow::Owned(_) => false });
+    }
+
+    #[test]
+    fn test_blend() {
+        let a = Image { pixels: vec![[0, 100, 200, 255]], width: 1, height: 1 };
+        let b = Image { pixels: vec![[100, 100, 0, 0]], width: 1, height: 1 };
+        assert_eq!(blend(&a, &b, 0.25).pixels, vec![[25, 100, 150, 191]]);
+    }
+
+    #[test]
+    fn test_interpolate_uneven() {
+        let imgs = frames(3);
+        let (out, delays) = interpolate(&imgs, &[20.0, 40.0, 15.0], 10.0);
+        let values: Vec<_> = out.iter().map(|img| img.pixels[0][0]).collect();
+        assert_eq!(values, vec![0, 1, 1, 1, 2, 2, 2, 2]);
+        assert_eq!(delays, vec![10.0, 10.0, 10.0, 10.0, 10.0, 10.0, 10.0, 5.0]);
+    }
+
+    #[test]
+    fn test_loop_crossfade() {
+        let imgs: Vec<_> = (0..5).map(|n| Image { pixels: vec![[n * 30, 0, 0, 255]], width: 1, height: 1 }).collect();
+        let (out, delays) = loop_crossfade(Cow::Borrowed(&imgs), vec![10.0; 5], 2);
+        let values: Vec<_> = out.iter().map(|img| img.pixels[0][0]).collect();
+        // Frame 0 is 2/3 of frame 3 and 1/3 of frame 0, frame 1 is 1/3 of
+        // frame 4 and 2/3 of frame 1.
+        assert_eq!(values, vec![60, 60, 60]);
+        assert_eq!(delays, vec![10.0; 3]);
+    }
+
+    #[test]
+    fn test_join_crossfade() {
+        let a = frames(3);
+        let b: Vec<_> = (0..3).map(|_| Image { pixels: vec![[90, 90, 90, 255]], width: 1, height: 1 }).collect();
+        let joined = join(&[&a, &b], Transition::Crossfade(2)).unwrap();
+        let values: Vec<_> = joined.iter().map(|img| img.pixels[0][0]).collect();
+        assert_eq!(values, vec![0, 31, 61, 90]);
+        let cut = join(&[&a, &b], Transition::Cut).unwrap();
+        assert_eq!(cut.len(), 6);
+    }
+
+    #[test]
+    fn test_join_mismatch() {
+        let a = frames(2);
+        let b = vec![solid(2, 2, [0, 0, 0, 255])];
+        assert!(join(&[&a, &b], Transition::Cut).is_err());
+    }
+
+    #[test]
+    fn test_flatten_layers() {
+        let base = solid(2, 2, [1, 1, 1, 255]);
+        let patch = Image { pixels: vec![[9, 9, 9, 255]], width: 1, height: 1 };
+        let out = flatten_layers(&[(base, 0, 0), (patch.clone(), 1, 1), (patch, 2, 0)]);
+        assert_eq!((out[2].width, out[2].height), (3, 2));
+        let values: Vec<_> = out.iter().map(|img| img.pixels.iter().map(|px| px[0]).collect::<Vec<_>>()).collect();
+        assert_eq!(values, vec![vec![1, 1, 0, 1, 1, 0], vec![1, 1, 0, 1, 9, 0], vec![1, 1, 9, 1, 9, 0]]);
+    }
+}
@@ -0,0 +1,88 @@
+//! Keeping frames in a temporary file instead of in memory.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use super::Image;
+
+static SPILLS: AtomicUsize = AtomicUsize::new(0);
+
+/// Frames written to a file as raw RGBA, which is removed when it's dropped.
+#[derive(Debug)]
+pub struct Spill {
+    path: PathBuf,
+    file: File,
+    /// Each frame's offset in the file, width and height.
+    frames: Vec<(u64, u32, u32)>,
+    len: u64,
+}
+
+impl Spill {
+    /// Creates an empty spill file in `dir`.
+    pub fn create(dir: &Path) -> io::Result<Spill> {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+        let name = format!("engiffen-{}-{}-{}.spill", process::id(), SPILLS.fetch_add(1, Ordering::Relaxed), nanos);
+        let path = dir.join(name);
+        let file = OpenOptions::new().read(true).write(true).create_new(true).open(&path)?;
+        Ok(Spill { path, file, frames: Vec::new(), len: 0 })
+    }
+
+    /// Appends a frame to the file.
+    pub fn push(&mut self, img: &Image) -> io::Result<()> {
+        let bytes: Vec<u8> = img.pixels.iter().flat_map(|px| px.iter().cloned()).collect();
+        (&self.file).seek(SeekFrom::End(0))?;
+        (&self.file).write_all(&bytes)?;
+        self.frames.push((self.len, img.width, img.height));
+        self.len += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Reads frame `n` back from the file.
+    pub fn read(&self, n: usize) -> io::Result<Image> {
+        let (offset, width, height) = self.frames[n];
+        let mut bytes = vec![0; width as usize * height as usize * 4];
+        (&self.file).seek(SeekFrom::Start(offset))?;
+        (&self.file).read_exact(&mut bytes)?;
+        let pixels = bytes.chunks(4).map(|px| [px[0], px[1], px[2], px[3]]).collect();
+        Ok(Image { pixels, width, height })
+    }
+
+    /// The number of frames in the file.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use super::Spill;
+    use super::super::Image;
+    use fixtures::solid;
+
+    #[test]
+    fn test_spill_round_trip() {
+        let a = Image { pixels: vec![[1, 2, 3, 4], [5, 6, 7, 8]], width: 2, height: 1 };
+        let b = solid(2, 3, [9, 9, 9, 255]);
+        let mut spill = Spill::create(&env::temp_dir()).unwrap();
+        spill.push(&a).unwrap();
+        spill.push(&b).unwrap();
+        assert_eq!(spill.len(), 2);
+        assert_eq!(spill.read(1).unwrap(), b);
+        assert_eq!(spill.read(0).unwrap(), a);
+
+        let path = spill.path.clone();
+        assert!(path.exists());
+        drop(spill);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,190 @@
+//! Slicing sprite sheets, the grids of animation frames games pack into
+//! one texture, into frames, and packing frames into them.
+
+use transform::crop;
+use super::{Crop, Error, Image};
+
+/// Where the frames are on a sprite sheet, for `slice_sprite_sheet` and
+/// `load_sprite_sheet`.
+///
+/// ```rust
+/// # use engiffen::SpriteSheet;
+/// // 32x32 tiles, the last row only partly full.
+/// let layout = SpriteSheet { frames: Some(14), ..SpriteSheet::new(32, 32) };
+/// ```
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash)]
+pub struct SpriteSheet {
+    pub tile_width: u32,
+    pub tile_height: u32,
+    /// How many tiles are frames, for sheets whose last row or column isn't
+    /// full. `None` takes every whole tile.
+    pub frames: Option<usize>,
+    /// Take tiles down each column, then across, instead of along each
+    /// row, then down.
+    pub column_major: bool,
+    /// Pixels around the edge of the sheet, before the first tile.
+    pub margin: u32,
+    /// Pixels between neighbouring tiles.
+    pub spacing: u32,
+}
+
+impl SpriteSheet {
+    /// A sheet of `tile_width` by `tile_height` tiles, packed edge to edge,
+    /// taken row by row.
+    pub fn new(tile_width: u32, tile_height: u32) -> SpriteSheet {
+        SpriteSheet { tile_width, tile_height, frames: None, column_major: false, margin: 0, spacing: 0 }
+    }
+
+    /// How many whole tiles fit across and down a sheet this size.
+    fn grid(&self, width: u32, height: u32) -> (u32, u32) {
+        let fit = |size: u32, tile: u32| (size.saturating_sub(2 * self.margin) + self.spacing) / (tile + self.spacing);
+        (fit(width, self.tile_width), fit(height, self.tile_height))
+    }
+
+    /// The top left corner of the nth tile on a sheet this many tiles
+    /// wide and tall.
+    fn tile(&self, n: u32, (columns, rows): (u32, u32)) -> (u32, u32) {
+        let (column, row) = if self.column_major { (n / rows.max(1), n % rows.max(1)) } else { (n % columns.max(1), n / columns.max(1)) };
+        (self.margin + column * (self.tile_width + self.spacing), self.margin + row * (self.tile_height + self.spacing))
+    }
+}
+
+pub fn slice(sheet: &Image, layout: &SpriteSheet) -> Result<Vec<Image>, Error> {
+    if layout.tile_width == 0 || layout.tile_height == 0 {
+        return Err(Error::InvalidOption("tile_width", "sprite sheet tiles can't be empty".to_string()));
+    }
+    let grid = layout.grid(sheet.width, sheet.height);
+    let tiles = (grid.0 * grid.1) as usize;
+    let frames = layout.frames.unwrap_or(tiles);
+    if frames > tiles || frames == 0 {
+        return Err(Error::InvalidOption("frames", format!("{} frames asked for from a sheet of {} whole tiles", frames, tiles)));
+    }
+    Ok((0..frames as u32).map(|n| {
+        let (x, y) = layout.tile(n, grid);
+        crop(sheet, Crop { x, y, width: layout.tile_width, height: layout.tile_height })
+    }).collect())
+}
+
+/// Lays `frames` out on a sheet `columns` tiles wide, row by row, with
+/// transparent tiles after the last frame. A column count of 0 picks the
+/// squarest grid.
+pub fn pack(frames: &[Image], columns: usize) -> Result<(Image, SpriteSheet), Error> {
+    let first = frames.first().ok_or(Error::NoImages)?;
+    let (tile_width, tile_height) = (first.width, first.height);
+    if let Some(img) = frames.iter().find(|img| (img.width, img.height) != (tile_width, tile_height)) {
+        return Err(Error::Mismatch((tile_width, tile_height), (img.width, img.height)));
+    }
+    let columns = match columns {
+        0 => (1..=frames.len()).find(|&n| n * n >= frames.len()).unwrap_or(1),
+        n => n.min(frames.len()),
+    };
+    let rows = frames.len().div_ceil(columns);
+    let (width, height) = (tile_width * columns as u32, tile_height * rows as u32);
+    let layout = SpriteSheet { frames: Some(frames.len()), ..SpriteSheet::new(tile_width, tile_height) };
+    let mut pixels = vec![[0, 0, 0, 0]; width as usize * height as usize];
+    for (n, frame) in frames.iter().enumerate() {
+        let (left, top) = layout.tile(n as u32, (columns as u32, rows as u32));
+        for (y, row) in frame.pixels.chunks(tile_width.max(1) as usize).enumerate() {
+            let start = ((top + y as u32) * width + left) as usize;
+            pixels[start..start + row.len()].copy_from_slice(row);
+        }
+    }
+    Ok((Image { pixels, width, height }, layout))
+}
+
+/// Describes each frame's tile on a sheet of `width` by `height` pixels,
+/// and how long it's shown, in the JSON array format Aseprite exports and
+/// most game engines import. `image` is the sheet's file name.
+#[cfg(feature = "fs")]
+pub fn metadata(layout: &SpriteSheet, (width, height): (u32, u32), delays_ms: &[u32], image: &str) -> String {
+    let escaped: String = image.chars().flat_map(|c| match c {
+        '"' | '\\' => vec!['\\', c],
+        c if (c as u32) < 0x20 => format!("\\u{:04x}", c as u32).chars().collect(),
+        c => vec![c],
+    }).collect();
+    let grid = layout.grid(width, height);
+    let frames: Vec<String> = delays_ms.iter().enumerate().map(|(n, delay)| {
+        let (x, y) = layout.tile(n as u32, grid);
+        format!("    {{ \"filename\": \"{} {}\", \"frame\": {{ \"x\": {}, \"y\": {}, \"w\": {}, \"h\": {} }}, \"duration\": {} }}",
+            escaped, n, x, y, layout.tile_width, layout.tile_height, delay)
+    }).collect();
+    format!("{{\n  \"frames\": [\n{}\n  ],\n  \"meta\": {{ \"image\": \"{}\", \"size\": {{ \"w\": {}, \"h\": {} }} }}\n}}\n",
+        frames.join(",\n"), escaped, width, height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pack, slice, SpriteSheet};
+    use super::super::{Error, Image};
+    use fixtures::solid;
+
+    /// A sheet whose pixels are numbered from 0, row by row.
+    fn numbered(width: u32, height: u32) -> Image {
+        Image { pixels: (0..width * height).map(|n| [n as u8, 0, 0, 255]).collect(), width, height }
+    }
+
+    #[test]
+    fn test_slice() {
+        // 3x2 tiles of 2x1 pixels.
+        let sheet = numbered(6, 2);
+        let frames = slice(&sheet, &SpriteSheet::new(2, 1)).unwrap();
+        let firsts: Vec<u8> = frames.iter().map(|img| img.pixels[0][0]).collect();
+        assert_eq!(firsts, vec![0, 2, 4, 6, 8, 10]);
+        assert_eq!(frames[1].pixels, vec![[2, 0, 0, 255], [3, 0, 0, 255]]);
+
+        let layout = SpriteSheet { frames: Some(3), column_major: true, ..SpriteSheet::new(2, 1) };
+        let firsts: Vec<u8> = slice(&sheet, &layout).unwrap().iter().map(|img| img.pixels[0][0]).collect();
+        assert_eq!(firsts, vec![0, 6, 2]);
+    }
+
+    #[test]
+    fn test_margin_and_spacing() {
+        // A 1 pixel margin, then 2x2 tiles 1 pixel apart, and a partial
+        // tile's worth of pixels left over on the right.
+        let sheet = numbered(9, 5);
+        let layout = SpriteSheet { margin: 1, spacing: 1, ..SpriteSheet::new(2, 2) };
+        let frames = slice(&sheet, &layout).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].pixels[0][0], 9 + 4);
+        assert_eq!(frames[1].pixels[3][0], 18 + 5);
+    }
+
+    #[test]
+    fn test_pack() {
+        let frames: Vec<Image> = (0..5).map(|n| solid(3, 2, [n, 0, 0, 255])).collect();
+        let (sheet, layout) = pack(&frames, 0).unwrap();
+        // The squarest grid for 5 frames is 3 by 2.
+        assert_eq!((sheet.width, sheet.height), (9, 4));
+        assert_eq!(sheet.pixels[sheet.pixels.len() - 1], [0, 0, 0, 0]);
+        assert_eq!(slice(&sheet, &layout).unwrap(), frames);
+
+        let (strip, layout) = pack(&frames, 100).unwrap();
+        assert_eq!((strip.width, strip.height), (15, 2));
+        assert_eq!(slice(&strip, &layout).unwrap(), frames);
+        assert!(pack(&[], 1).is_err());
+        assert!(pack(&[frames[0].clone(), Image { pixels: vec![], width: 0, height: 0 }], 1).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn test_metadata() {
+        use super::metadata;
+        let layout = SpriteSheet { frames: Some(3), ..SpriteSheet::new(16, 8) };
+        let json = metadata(&layout, (32, 16), &[100, 100, 250], "walk \"a\".png");
+        assert!(json.contains("{ \"filename\": \"walk \\\"a\\\".png 2\", \"frame\": { \"x\": 0, \"y\": 8, \"w\": 16, \"h\": 8 }, \"duration\": 250 }"));
+        assert!(json.contains("\"meta\": { \"image\": \"walk \\\"a\\\".png\", \"size\": { \"w\": 32, \"h\": 16 } }"));
+        assert_eq!(json.matches("\"filename\"").count(), 3);
+    }
+
+    #[test]
+    fn test_bad_layout() {
+        let sheet = numbered(4, 4);
+        match slice(&sheet, &SpriteSheet::new(0, 2)) {
+            Err(Error::InvalidOption("tile_width", _)) => {},
+            other => panic!("expected an invalid tile size, got {:?}", other),
+        }
+        let layout = SpriteSheet { frames: Some(5), ..SpriteSheet::new(2, 2) };
+        assert!(slice(&sheet, &layout).is_err());
+        assert!(slice(&sheet, &SpriteSheet::new(8, 8)).is_err());
+    }
+}
@@ -0,0 +1,51 @@
+//! Timings and counts from an encode.
+
+use std::io;
+use std::time::{Duration, Instant};
+
+/// What an encode spent its time on, for applications that want to show
+/// performance data.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct EncodeStats {
+    /// The number of frames encoded, after any retiming.
+    pub frames: usize,
+    /// Time spent transforming and adjusting frames before quantization.
+    pub preparing: Duration,
+    /// Time spent collecting the colors the palette is computed from.
+    pub sampling: Duration,
+    /// Time spent computing the palette.
+    pub quantizing: Duration,
+    /// Time spent mapping every pixel onto the palette.
+    pub mapping: Duration,
+    /// How many colors the quantizer looked at: sampled pixels for NeuQuant,
+    /// distinct colors for Naive.
+    pub colors_sampled: usize,
+    /// The number of colors in the palette, including transparency.
+    pub palette_colors: usize,
+    /// The size of the encoded Gif, when it was written as part of the
+    /// encode.
+    pub bytes_written: Option<u64>,
+}
+
+/// Adds the time since `start` to `total`.
+pub fn add_since(total: &mut Duration, start: Instant) {
+    *total += start.elapsed();
+}
+
+/// Counts the bytes written through it.
+pub struct Counter<W> {
+    pub inner: W,
+    pub count: u64,
+}
+
+impl<W: io::Write> io::Write for Counter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,279 @@
+//! Captions drawn onto frames with a built-in 5x8 bitmap font.
+
+use std::borrow::Cow;
+use std::ops::Range;
+use super::Image;
+use overlay::{self, Position};
+
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 8;
+/// Glyphs are drawn with a blank column and row between them.
+const ADVANCE: u32 = GLYPH_WIDTH + 1;
+const LINE_HEIGHT: u32 = GLYPH_HEIGHT + 1;
+
+/// Printable ASCII from ' ' to '~'. Each glyph is five columns, left to
+/// right, with the least significant bit at the top.
+const FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], [0x00, 0x00, 0x5F, 0x00, 0x00], [0x00, 0x07, 0x00, 0x07, 0x00],
+    [0x14, 0x7F, 0x14, 0x7F, 0x14], [0x24, 0x2A, 0x7F, 0x2A, 0x12], [0x23, 0x13, 0x08, 0x64, 0x62],
+    [0x36, 0x49, 0x56, 0x20, 0x50], [0x00, 0x08, 0x07, 0x03, 0x00], [0x00, 0x1C, 0x22, 0x41, 0x00],
+    [0x00, 0x41, 0x22, 0x1C, 0x00], [0x2A, 0x1C, 0x7F, 0x1C, 0x2A], [0x08, 0x08, 0x3E, 0x08, 0x08],
+    [0x00, 0x80, 0x70, 0x30, 0x00], [0x08, 0x08, 0x08, 0x08, 0x08], [0x00, 0x00, 0x60, 0x60, 0x00],
+    [0x20, 0x10, 0x08, 0x04, 0x02], [0x3E, 0x51, 0x49, 0x45, 0x3E], [0x00, 0x42, 0x7F, 0x40, 0x00],
+    [0x72, 0x49, 0x49, 0x49, 0x46], [0x21, 0x41, 0x49, 0x4D, 0x33], [0x18, 0x14, 0x12, 0x7F, 0x10],
+    [0x27, 0x45, 0x45, 0x45, 0x39], [0x3C, 0x4A, 0x49, 0x49, 0x31], [0x41, 0x21, 0x11, 0x09, 0x07],
+    [0x36, 0x49, 0x49, 0x49, 0x36], [0x46, 0x49, 0x49, 0x29, 0x1E], [0x00, 0x00, 0x14, 0x00, 0x00],
+    [0x00, 0x40, 0x34, 0x00, 0x00], [0x00, 0x08, 0x14, 0x22, 0x41], [0x14, 0x14, 0x14, 0x14, 0x14],
+    [0x00, 0x41, 0x22, 0x14, 0x08], [0x02, 0x01, 0x59, 0x09, 0x06], [0x3E, 0x41, 0x5D, 0x59, 0x4E],
+    [0x7C, 0x12, 0x11, 0x12, 0x7C], [0x7F, 0x49, 0x49, 0x49, 0x36], [0x3E, 0x41, 0x41, 0x41, 0x22],
+    [0x7F, 0x41, 0x41, 0x41, 0x3E], [0x7F, 0x49, 0x49, 0x49, 0x41], [0x7F, 0x09, 0x09, 0x09, 0x01],
+    [0x3E, 0x41, 0x41, 0x51, 0x73], [0x7F, 0x08, 0x08, 0x08, 0x7F], [0x00, 0x41, 0x7F, 0x41, 0x00],
+    [0x20, 0x40, 0x41, 0x3F, 0x01], [0x7F, 0x08, 0x14, 0x22, 0x41], [0x7F, 0x40, 0x40, 0x40, 0x40],
+    [0x7F, 0x02, 0x1C, 0x02, 0x7F], [0x7F, 0x04, 0x08, 0x10, 0x7F], [0x3E, 0x41, 0x41, 0x41, 0x3E],
+    [0x7F, 0x09, 0x09, 0x09, 0x06], [0x3E, 0x41, 0x51, 0x21, 0x5E], [0x7F, 0x09, 0x19, 0x29, 0x46],
+    [0x26, 0x49, 0x49, 0x49, 0x32], [0x03, 0x01, 0x7F, 0x01, 0x03], [0x3F, 0x40, 0x40, 0x40, 0x3F],
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], [0x3F, 0x40, 0x38, 0x40, 0x3F], [0x63, 0x14, 0x08, 0x14, 0x63],
+    [0x03, 0x04, 0x78, 0x04, 0x03], [0x61, 0x59, 0x49, 0x4D, 0x43], [0x00, 0x7F, 0x41, 0x41, 0x41],
+    [0x02, 0x04, 0x08, 0x10, 0x20], [0x00, 0x41, 0x41, 0x41, 0x7F], [0x04, 0x02, 0x01, 0x02, 0x04],
+    [0x40, 0x40, 0x40, 0x40, 0x40], [0x00, 0x03, 0x07, 0x08, 0x00], [0x20, 0x54, 0x54, 0x78, 0x40],
+    [0x7F, 0x28, 0x44, 0x44, 0x38], [0x38, 0x44, 0x44, 0x44, 0x28], [0x38, 0x44, 0x44, 0x28, 0x7F],
+    [0x38, 0x54, 0x54, 0x54, 0x18], [0x00, 0x08, 0x7E, 0x09, 0x02], [0x18, 0xA4, 0xA4, 0x9C, 0x78],
+    [0x7F, 0x08, 0x04, 0x04, 0x78], [0x00, 0x44, 0x7D, 0x40, 0x00], [0x20, 0x40, 0x40, 0x3D, 0x00],
+    [0x7F, 0x10, 0x28, 0x44, 0x00], [0x00, 0x41, 0x7F, 0x40, 0x00], [0x7C, 0x04, 0x78, 0x04, 0x78],
+    [0x7C, 0x08, 0x04, 0x04, 0x78], [0x38, 0x44, 0x44, 0x44, 0x38], [0xFC, 0x18, 0x24, 0x24, 0x18],
+    [0x18, 0x24, 0x24, 0x18, 0xFC], [0x7C, 0x08, 0x04, 0x04, 0x08], [0x48, 0x54, 0x54, 0x54, 0x24],
+    [0x04, 0x04, 0x3F, 0x44, 0x24], [0x3C, 0x40, 0x40, 0x20, 0x7C], [0x1C, 0x20, 0x40, 0x20, 0x1C],
+    [0x3C, 0x40, 0x30, 0x40, 0x3C], [0x44, 0x28, 0x10, 0x28, 0x44], [0x4C, 0x90, 0x90, 0x90, 0x7C],
+    [0x44, 0x64, 0x54, 0x4C, 0x44], [0x00, 0x08, 0x36, 0x41, 0x00], [0x00, 0x00, 0x77, 0x00, 0x00],
+    [0x00, 0x41, 0x36, 0x08, 0x00], [0x02, 0x01, 0x02, 0x04, 0x02],
+];
+
+/// Text drawn onto a range of frames, like a subtitle or a step in a
+/// tutorial.
+///
+/// The text may span several lines. Characters outside of printable ASCII
+/// are drawn as `?`.
+///
+/// ```rust
+/// # use engiffen::{Caption, Position};
+/// let caption = Caption {
+///     text: "step 1".to_string(),
+///     frames: 10..40,
+///     ..Caption::default()
+/// };
+/// assert_eq!(caption.position, Position::Bottom);
+/// ```
+#[derive(Debug, Eq, PartialEq, Clone, Hash)]
+pub struct Caption {
+    pub text: String,
+    /// Indices of the source frames the caption is drawn on.
+    pub frames: Range<usize>,
+    pub position: Position,
+    /// Distance in pixels from the edges the caption is anchored to.
+    pub margin: u32,
+    pub color: [u8; 4],
+    /// A box drawn behind the text, so it stays readable on busy footage.
+    pub background: Option<[u8; 4]>,
+    /// Each font pixel is drawn as a `scale` x `scale` block.
+    pub scale: u32,
+}
+
+impl Default for Caption {
+    fn default() -> Caption {
+        Caption {
+            text: String::new(),
+            frames: 0..usize::MAX,
+            position: Position::Bottom,
+            margin: 4,
+            color: [255, 255, 255, 255],
+            background: Some([0, 0, 0, 160]),
+            scale: 1,
+        }
+    }
+}
+
+/// What `BurnIn` stamps onto each frame.
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash)]
+pub enum Stamp {
+    /// The index of the source frame, counting from 0.
+    FrameNumber,
+    /// When the source frame is shown, as `minutes:seconds.milliseconds`.
+    Elapsed,
+}
+
+/// Stamps the frame number or elapsed time onto every frame, for debugging
+/// simulations and reviewing capture timing.
+///
+/// `style` controls how and where the stamp is drawn, and on which frames.
+/// Its text is replaced by the stamp.
+#[derive(Debug, Eq, PartialEq, Clone, Hash)]
+pub struct BurnIn {
+    pub stamp: Stamp,
+    pub style: Caption,
+}
+
+impl Default for BurnIn {
+    fn default() -> BurnIn {
+        BurnIn {
+            stamp: Stamp::FrameNumber,
+            style: Caption { position: Position::TopLeft, ..Caption::default() },
+        }
+    }
+}
+
+fn glyph(c: char) -> &'static [u8; 5] {
+    match c {
+        ' '..='~' => &FONT[c as usize - ' ' as usize],
+        _ => &FONT['?' as usize - ' ' as usize],
+    }
+}
+
+/// The width and height in pixels of `text` when drawn at `scale`.
+pub fn measure(text: &str, scale: u32) -> (u32, u32) {
+    let lines = text.lines().count().max(1) as u32;
+    let columns = text.lines().map(|line| line.chars().count()).max().unwrap_or(0) as u32;
+    let width = (columns * ADVANCE).saturating_sub(1);
+    let height = lines * LINE_HEIGHT - 1;
+    (width * scale, height * scale)
+}
+
+/// Draws `text` with its top left corner at `(x, y)`.
+pub fn draw_text(img: &mut Image, text: &str, x: i64, y: i64, color: [u8; 4], scale: u32) {
+    let scale = scale.max(1);
+    for (row, line) in text.lines().enumerate() {
+        let top = y + (row as u32 * LINE_HEIGHT * scale) as i64;
+        for (col, c) in line.chars().enumerate() {
+            let left = x + (col as u32 * ADVANCE * scale) as i64;
+            for (gx, bits) in glyph(c).iter().enumerate() {
+                for gy in 0..GLYPH_HEIGHT {
+                    if bits & (1 << gy) != 0 {
+                        let px = left + (gx as u32 * scale) as i64;
+                        let py = top + (gy * scale) as i64;
+                        overlay::fill(img, px, py, scale, scale, color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Draws a caption onto a frame, whether or not the frame is in its range.
+pub fn caption(img: &mut Image, caption: &Caption) {
+    let scale = caption.scale.max(1);
+    let (width, height) = measure(&caption.text, scale);
+    // The background box extends one font pixel past the text on each side.
+    let pad = scale;
+    let size = (width + pad * 2, height + pad * 2);
+    let (x, y) = caption.position.place(img.width, img.height, size, caption.margin);
+    if let Some(background) = caption.background {
+        overlay::fill(img, x, y, size.0, size.1, background);
+    }
+    draw_text(img, &caption.text, x + pad as i64, y + pad as i64, caption.color, scale);
+}
+
+/// Draws each caption onto the frames in its range.
+pub fn apply<'a>(imgs: Cow<'a, [Image]>, captions: &[Caption]) -> Cow<'a, [Image]> {
+    if captions.is_empty() {
+        return imgs;
+    }
+    let mut imgs = imgs.into_owned();
+    for (index, img) in imgs.iter_mut().enumerate() {
+        for c in captions.iter().filter(|c| c.frames.contains(&index)) {
+            caption(img, c);
+        }
+    }
+    Cow::Owned(imgs)
+}
+
+/// Stamps frames according to `burn_in`. `delays_ms` are the source frame
+/// delays, used to work out elapsed times.
+pub fn burn_in<'a>(imgs: Cow<'a, [Image]>, delays_ms: &[f64], burn_in: &BurnIn) -> Cow<'a, [Image]> {
+    let mut imgs = imgs.into_owned();
+    let mut elapsed = 0f64;
+    for (index, (img, delay)) in imgs.iter_mut().zip(delays_ms).enumerate() {
+        if burn_in.style.frames.contains(&index) {
+            let text = match burn_in.stamp {
+                Stamp::FrameNumber => index.to_string(),
+                Stamp::Elapsed => {
+                    let ms = elapsed.round() as u64;
+                    format!("{}:{:02}.{:03}", ms / 60_000, ms / 1000 % 60, ms % 1000)
+                },
+            };
+            caption(img, &Caption { text, ..burn_in.style.clone() });
+        }
+        elapsed += delay;
+    }
+    Cow::Owned(imgs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply, burn_in, caption, draw_text, measure, BurnIn, Caption, Stamp};
+    use super::super::Image;
+    use overlay::Position;
+    use std::borrow::Cow;
+    use fixtures::solid;
+
+    fn lit(img: &Image) -> Vec<String> {
+        img.pixels.chunks(img.width as usize)
+            .map(|row| row.iter().map(|px| if px[0] > 0 { '#' } else { '.' }).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_measure() {
+        assert_eq!(measure("ab", 1), (11, 8));
+        assert_eq!(measure("abc\nd", 2), (34, 34));
+        assert_eq!(measure("", 1), (0, 8));
+    }
+
+    #[test]
+    fn test_draw_text() {
+        let mut img = solid(5, 8, [0, 0, 0, 255]);
+        draw_text(&mut img, "L", 0, 0, [255, 255, 255, 255], 1);
+        assert_eq!(lit(&img), vec![
+            "#....", "#....", "#....", "#....", "#....", "#....", "#####", ".....",
+        ]);
+    }
+
+    #[test]
+    fn test_caption_position() {
+        let mut img = solid(20, 20, [0, 0, 0, 255]);
+        caption(&mut img, &Caption {
+            text: "-".to_string(),
+            position: Position::BottomRight,
+            margin: 0,
+            background: None,
+            ..Caption::default()
+        });
+        let rows = lit(&img);
+        assert_eq!(rows[14], "..............#####.");
+        assert!(rows.iter().enumerate().all(|(y, row)| y == 14 || !row.contains('#')));
+    }
+
+    #[test]
+    fn test_apply_to_range() {
+        let imgs = vec![solid(8, 10, [0, 0, 0, 255]); 3];
+        let captioned = apply(Cow::Owned(imgs), &[Caption { text: "x".to_string(), frames: 1..2, ..Caption::default() }]);
+        assert_eq!(captioned[0], captioned[2]);
+        assert!(captioned[0] != captioned[1]);
+    }
+
+    #[test]
+    fn test_burn_in() {
+        let imgs = vec![solid(40, 12, [0, 0, 0, 255]); 3];
+        let delays = [40000.0, 25000.0, 1.0];
+        let style = Caption { position: Position::At(0, 0), background: None, ..Caption::default() };
+        let elapsed = burn_in(Cow::Borrowed(&imgs), &delays, &BurnIn { stamp: Stamp::Elapsed, style: style.clone() });
+        for (img, text) in elapsed.iter().zip(&["0:00.000", "0:40.000", "1:05.000"]) {
+            let mut expected = solid(40, 12, [0, 0, 0, 255]);
+            draw_text(&mut expected, text, 1, 1, [255, 255, 255, 255], 1);
+            assert_eq!(img, &expected);
+        }
+
+        let numbered = burn_in(Cow::Borrowed(&imgs), &delays, &BurnIn { stamp: Stamp::FrameNumber, style });
+        let mut expected = solid(40, 12, [0, 0, 0, 255]);
+        draw_text(&mut expected, "2", 1, 1, [255, 255, 255, 255], 1);
+        assert_eq!(numbered[2], expected);
+    }
+}
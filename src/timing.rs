@@ -0,0 +1,220 @@
+//! Frame timing.
+
+use std::time::{Duration, Instant};
+use super::Error;
+
+/// The shortest delay browsers show frames for, in milliseconds. Frames
+/// with shorter delays, which a Gif asks for above 50 frames per second,
+/// are shown for 100 ms instead.
+pub const MIN_DELAY_MS: f64 = 20.0;
+
+/// How long each frame of a Gif is displayed.
+///
+/// Anything that converts into a `FrameRate` can be passed as the frame rate
+/// argument of `engiffen`: integers and floats are read as frames per second,
+/// and a `Duration` as the delay between frames.
+///
+/// ```rust
+/// # use engiffen::FrameRate;
+/// # use std::time::{Duration, Instant};
+/// assert_eq!(FrameRate::from(12.5).delay_ms(), 80.0);
+/// assert_eq!(FrameRate::from(Duration::from_millis(2500)).delay_ms(), 2500.0);
+/// ```
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum FrameRate {
+    /// Frames per second. Fractional rates such as 12.5 or 0.5 are allowed.
+    Fps(f32),
+    /// The time each frame is shown for.
+    Delay(Duration),
+}
+
+impl FrameRate {
+    /// The time each frame is shown for, in milliseconds.
+    pub fn delay_ms(&self) -> f64 {
+        match *self {
+            FrameRate::Fps(fps) => 1000.0 / fps as f64,
+            FrameRate::Delay(duration) => as_ms(duration),
+        }
+    }
+}
+
+impl From<f32> for FrameRate {
+    fn from(fps: f32) -> FrameRate {
+        FrameRate::Fps(fps)
+    }
+}
+
+impl From<f64> for FrameRate {
+    fn from(fps: f64) -> FrameRate {
+        FrameRate::Fps(fps as f32)
+    }
+}
+
+impl From<usize> for FrameRate {
+    fn from(fps: usize) -> FrameRate {
+        FrameRate::Fps(fps as f32)
+    }
+}
+
+impl From<u32> for FrameRate {
+    fn from(fps: u32) -> FrameRate {
+        FrameRate::Fps(fps as f32)
+    }
+}
+
+// Integer literals default to i32, so this keeps `engiffen(&imgs, 10, ...)`
+// compiling.
+impl From<i32> for FrameRate {
+    fn from(fps: i32) -> FrameRate {
+        FrameRate::Fps(fps as f32)
+    }
+}
+
+impl From<Duration> for FrameRate {
+    fn from(delay: Duration) -> FrameRate {
+        FrameRate::Delay(delay)
+    }
+}
+
+/// A point in time at which a frame was captured, used by
+/// `engiffen_timestamped` to work out how long each frame is displayed.
+pub trait Timestamp: Copy {
+    /// The time elapsed between `earlier` and `self`. Returns a zero duration
+    /// if `earlier` is actually later.
+    fn since(self, earlier: Self) -> Duration;
+}
+
+impl Timestamp for Duration {
+    fn since(self, earlier: Duration) -> Duration {
+        self.checked_sub(earlier).unwrap_or_default()
+    }
+}
+
+impl Timestamp for Instant {
+    fn since(self, earlier: Instant) -> Duration {
+        self.checked_duration_since(earlier).unwrap_or_default()
+    }
+}
+
+fn as_ms(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1_000_000.0
+}
+
+/// How long the only frame of a timestamped sequence is shown for, in
+/// milliseconds, since there's no later timestamp to measure it against.
+pub const DEFAULT_DELAY_MS: f64 = 100.0;
+
+/// Converts frame timestamps into per-frame delays in milliseconds. The last
+/// frame has nothing to measure against, so it gets the median of the other
+/// frames' delays, or `DEFAULT_DELAY_MS` if it's the only frame.
+///
+/// Timestamps that don't strictly increase are an `Error::InvalidOption`.
+pub fn delays_from_timestamps<T: Timestamp>(timestamps: &[T]) -> Result<Vec<f64>, Error> {
+    let mut delays = Vec::with_capacity(timestamps.len());
+    for (n, pair) in timestamps.windows(2).enumerate() {
+        let delay = pair[1].since(pair[0]);
+        if delay == Duration::from_secs(0) {
+            return Err(Error::InvalidOption("timestamps", format!("frame {}'s timestamp isn't later than frame {}'s", n + 1, n)));
+        }
+        delays.push(as_ms(delay));
+    }
+    if !timestamps.is_empty() {
+        let mut sorted = delays.clone();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        delays.push(sorted.get(sorted.len() / 2).cloned().unwrap_or(DEFAULT_DELAY_MS));
+    }
+    Ok(delays)
+}
+
+/// Checks that every delay is a real, non-negative time, which it isn't for
+/// a frame rate of zero, below zero or NaN.
+pub fn check(delays_ms: &[f64]) -> Result<(), Error> {
+    match delays_ms.iter().find(|&&ms| !(ms.is_finite() && ms >= 0.0)) {
+        Some(&ms) => Err(Error::InvalidOption("fps", format!("{} frames per second isn't a positive rate", 1000.0 / ms))),
+        None => Ok(()),
+    }
+}
+
+/// Converts exact per-frame delays in milliseconds into Gif delays, which are
+/// in hundredths of a second.
+///
+/// Each delay is rounded to the nearest hundredth and clamped to `1..=65535`,
+/// so very high frame rates never turn into a zero delay. With `dither`,
+/// rounding error is instead carried over into the following frames, so that
+/// every frame starts at the representable time closest to when it should,
+/// and the total duration doesn't drift. Dithered delays may be zero.
+pub fn frame_delays(delays_ms: &[f64], dither: bool) -> Vec<u16> {
+    if !dither {
+        return delays_ms.iter()
+            .map(|&ms| (ms / 10.0).round().max(1.0).min(u16::MAX as f64) as u16)
+            .collect();
+    }
+    let mut elapsed = 0.0;
+    let mut written = 0.0;
+    delays_ms.iter().map(|&ms| {
+        elapsed += ms / 10.0;
+        let delay = (elapsed.round() - written).max(0.0).min(u16::MAX as f64);
+        written += delay;
+        delay as u16
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, delays_from_timestamps, frame_delays, FrameRate, DEFAULT_DELAY_MS};
+    use super::super::Error;
+    use std::time::Duration;
+
+    #[test]
+    fn test_fractional_fps() {
+        assert_eq!(FrameRate::from(0.5).delay_ms(), 2000.0);
+        assert_eq!(FrameRate::from(12.5f32).delay_ms(), 80.0);
+    }
+
+    #[test]
+    fn test_duration_delay() {
+        assert_eq!(FrameRate::from(Duration::new(1, 500_000_000)).delay_ms(), 1500.0);
+    }
+
+    #[test]
+    fn test_delays_from_timestamps() {
+        let timestamps = [Duration::from_millis(500), Duration::from_millis(520), Duration::from_millis(600), Duration::from_millis(700)];
+        assert_eq!(delays_from_timestamps(&timestamps).unwrap(), vec![20.0, 80.0, 100.0, 80.0]);
+        assert!(delays_from_timestamps::<Duration>(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_single_timestamp() {
+        assert_eq!(delays_from_timestamps(&[Duration::from_millis(500)]).unwrap(), vec![DEFAULT_DELAY_MS]);
+    }
+
+    #[test]
+    fn test_timestamps_out_of_order() {
+        for timestamps in &[[Duration::from_millis(40), Duration::from_millis(20)], [Duration::from_millis(40), Duration::from_millis(40)]] {
+            match delays_from_timestamps(timestamps) {
+                Err(Error::InvalidOption("timestamps", _)) => {},
+                other => panic!("expected out of order timestamps to be rejected, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(check(&[100.0, 0.0, 5.0]).is_ok());
+        assert!(check(&[FrameRate::from(0).delay_ms()]).is_err());
+        assert!(check(&[FrameRate::from(-5.0).delay_ms()]).is_err());
+        assert!(check(&[FrameRate::from(f64::NAN).delay_ms()]).is_err());
+    }
+
+    #[test]
+    fn test_frame_delays_rounding() {
+        assert_eq!(frame_delays(&[1000.0 / 30.0, 35.0, 4.0, 1_000_000.0], false), vec![3, 4, 1, 65535]);
+    }
+
+    #[test]
+    fn test_frame_delays_dither() {
+        let delays = frame_delays(&[1000.0 / 30.0; 9], true);
+        assert_eq!(delays, vec![3, 4, 3, 3, 4, 3, 3, 4, 3]);
+        assert_eq!(delays.iter().map(|&d| d as u32).sum::<u32>(), 30);
+    }
+}
@@ -0,0 +1,492 @@
+//! Geometric transforms applied to every frame before quantization.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use rayon::prelude::*;
+use image::{self, RgbaImage};
+use pool;
+use super::{Error, Image, Options};
+
+/// A resampling filter used when frames are resized.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Filter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+impl Filter {
+    fn filter_type(self) -> image::FilterType {
+        match self {
+            Filter::Nearest => image::FilterType::Nearest,
+            Filter::Triangle => image::FilterType::Triangle,
+            Filter::CatmullRom => image::FilterType::CatmullRom,
+            Filter::Gaussian => image::FilterType::Gaussian,
+            Filter::Lanczos3 => image::FilterType::Lanczos3,
+        }
+    }
+}
+
+/// What to do when frames don't all have the same dimensions.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Mismatch {
+    /// Fail with an `Error::Mismatch`.
+    Error,
+    /// Resize every frame to the given width and height, or to the first
+    /// frame's dimensions if `None`, using the given filter.
+    Resize(Option<(u32, u32)>, Filter),
+    /// Center every frame on a canvas as large as the widest and tallest
+    /// frames, filling the rest with the given RGBA color. Use an alpha of 0
+    /// to pad with transparency. Aspect ratios are preserved.
+    Pad([u8; 4]),
+}
+
+/// A target size for scaling frames. Sizes are rounded to whole pixels, and
+/// never drop below 1.
+///
+/// Factors must be finite and above zero, and sizes at least 1 pixel, or
+/// encoding returns an `Error::InvalidOption`.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Scale {
+    /// Multiply both dimensions by a factor.
+    Factor(f32),
+    /// Scale to this width, keeping the aspect ratio.
+    Width(u32),
+    /// Scale to this height, keeping the aspect ratio.
+    Height(u32),
+    /// Scale to exactly this width and height.
+    Size(u32, u32),
+}
+
+impl Scale {
+    /// The dimensions an image of `width` x `height` is scaled to.
+    pub fn dimensions(&self, width: u32, height: u32) -> (u32, u32) {
+        let scaled = |n: u32, factor: f64| ((n as f64 * factor).round() as u32).max(1);
+        match *self {
+            Scale::Factor(factor) => (scaled(width, factor as f64), scaled(height, factor as f64)),
+            Scale::Width(w) => (w.max(1), scaled(height, w as f64 / width as f64)),
+            Scale::Height(h) => (scaled(width, h as f64 / height as f64), h.max(1)),
+            Scale::Size(w, h) => (w.max(1), h.max(1)),
+        }
+    }
+}
+
+/// A function run on every frame before it's quantized, with the frame's
+/// index in the output.
+///
+/// The function may change the frame's pixels but not its size.
+///
+/// ```rust
+/// # extern crate engiffen;
+/// # extern crate image;
+/// # use engiffen::{FrameHook, Options};
+/// # fn main() {
+/// let options = Options {
+///     // Mark the top left corner of every other frame.
+///     map_frames: Some(FrameHook::new(|index, frame| if index % 2 == 0 {
+///         frame.put_pixel(0, 0, image::Rgba([255, 0, 0, 255]));
+///     })),
+///     ..Options::default()
+/// };
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct FrameHook(Arc<Mutex<HookFn>>);
+
+type HookFn = dyn FnMut(usize, &mut RgbaImage) + Send;
+
+impl FrameHook {
+    pub fn new<F>(hook: F) -> FrameHook
+        where F: FnMut(usize, &mut RgbaImage) + Send + 'static
+    {
+        FrameHook(Arc::new(Mutex::new(hook)))
+    }
+
+    /// Runs the hook on each frame in order.
+    pub fn run<'a>(&self, imgs: Cow<'a, [Image]>) -> Result<Cow<'a, [Image]>, Error> {
+        let mut hook = self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut mapped = Vec::with_capacity(imgs.len());
+        for (index, img) in imgs.iter().enumerate() {
+            let mut buf = img.to_rgba_image();
+            (*hook)(index, &mut buf);
+            let dimensions = buf.dimensions();
+            if dimensions != (img.width, img.height) {
+                return Err(Error::Mismatch((img.width, img.height), dimensions));
+            }
+            mapped.push(Image::from(buf));
+        }
+        Ok(Cow::Owned(mapped))
+    }
+}
+
+impl fmt::Debug for FrameHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "FrameHook")
+    }
+}
+
+// Hooks can't be compared, so two are equal only if they're the same one.
+impl PartialEq for FrameHook {
+    fn eq(&self, other: &FrameHook) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Resizes a frame to exactly `width` x `height`.
+pub fn resize(img: &Image, width: u32, height: u32, filter: Filter) -> Image {
+    if (img.width, img.height) == (width, height) {
+        return img.clone();
+    }
+    if filter == Filter::Nearest {
+        // The image crate's box kernel divides by zero when upscaling, and
+        // sampling pixel centers directly is faster anyway.
+        return nearest(img, width, height);
+    }
+    Image::from(image::imageops::resize(&img.to_rgba_image(), width, height, filter.filter_type()))
+}
+
+fn nearest(img: &Image, width: u32, height: u32) -> Image {
+    let mut pixels = Vec::with_capacity((width * height) as usize);
+    for y in 0..height as u64 {
+        let src_y = (y * 2 + 1) * img.height as u64 / (height as u64 * 2);
+        let row = src_y as usize * img.width as usize;
+        for x in 0..width as u64 {
+            let src_x = (x * 2 + 1) * img.width as u64 / (width as u64 * 2);
+            pixels.push(img.pixels[row + src_x as usize]);
+        }
+    }
+    Image { pixels, width, height }
+}
+
+/// A clockwise rotation.
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash)]
+pub enum Rotation {
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+/// A rectangular region of a frame, in pixels from the top left corner.
+///
+/// The region has to be at least one pixel in size and lie within the
+/// frames, or encoding returns an `Error::InvalidOption`.
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash)]
+pub struct Crop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Crop {
+    /// Clips the region to a `width` x `height` frame.
+    fn clip(self, width: u32, height: u32) -> Crop {
+        let x = self.x.min(width - 1);
+        let y = self.y.min(height - 1);
+        Crop { x, y, width: self.width.min(width - x).max(1), height: self.height.min(height - y).max(1) }
+    }
+}
+
+/// Cuts `region` out of a frame.
+pub fn crop(img: &Image, region: Crop) -> Image {
+    let Crop { x, y, width, height } = region.clip(img.width, img.height);
+    let mut pixels = Vec::with_capacity((width * height) as usize);
+    for row in img.pixels.chunks(img.width as usize).skip(y as usize).take(height as usize) {
+        pixels.extend_from_slice(&row[x as usize..(x + width) as usize]);
+    }
+    Image { pixels, width, height }
+}
+
+/// Shrinks `within` to exclude the rows and columns along its edges whose
+/// pixels, in every frame, all match the color of its top left corner in the
+/// first frame. At least one pixel is always kept.
+pub fn trim(imgs: &[Image], within: Crop) -> Crop {
+    let first = &imgs[0];
+    let within = within.clip(first.width, first.height);
+    let border = first.pixels[(within.y * first.width + within.x) as usize];
+    let plain = |x0: u32, y0: u32, x1: u32, y1: u32| imgs.iter().all(|img| {
+        (y0..y1).all(|y| (x0..x1).all(|x| img.pixels[(y * img.width + x) as usize] == border))
+    });
+
+    let (mut left, mut top) = (within.x, within.y);
+    let (mut right, mut bottom) = (within.x + within.width, within.y + within.height);
+    while bottom - top > 1 && plain(left, top, right, top + 1) {
+        top += 1;
+    }
+    while bottom - top > 1 && plain(left, bottom - 1, right, bottom) {
+        bottom -= 1;
+    }
+    while right - left > 1 && plain(left, top, left + 1, bottom) {
+        left += 1;
+    }
+    while right - left > 1 && plain(right - 1, top, right, bottom) {
+        right -= 1;
+    }
+    Crop { x: left, y: top, width: right - left, height: bottom - top }
+}
+
+/// Works out the region of the frames to keep from `crop` and `auto_trim`.
+pub fn region(imgs: &[Image], options: &Options) -> Option<Crop> {
+    if !options.auto_trim {
+        return options.crop;
+    }
+    let whole = Crop { x: 0, y: 0, width: imgs[0].width, height: imgs[0].height };
+    Some(trim(imgs, options.crop.unwrap_or(whole)))
+}
+
+/// Turns a frame clockwise.
+pub fn rotate(img: &Image, rotation: Rotation) -> Image {
+    let (w, h) = (img.width as usize, img.height as usize);
+    let (width, height) = match rotation {
+        Rotation::Rotate180 => (w, h),
+        Rotation::Rotate90 | Rotation::Rotate270 => (h, w),
+    };
+    let mut pixels = Vec::with_capacity(w * h);
+    for y in 0..height {
+        for x in 0..width {
+            let (sx, sy) = match rotation {
+                Rotation::Rotate90 => (y, h - 1 - x),
+                Rotation::Rotate180 => (w - 1 - x, h - 1 - y),
+                Rotation::Rotate270 => (w - 1 - y, x),
+            };
+            pixels.push(img.pixels[sy * w + sx]);
+        }
+    }
+    Image { pixels, width: width as u32, height: height as u32 }
+}
+
+/// Mirrors a frame left to right.
+pub fn flip_horizontal(img: &Image) -> Image {
+    let mut pixels = img.pixels.clone();
+    for row in pixels.chunks_mut(img.width as usize) {
+        row.reverse();
+    }
+    Image { pixels, width: img.width, height: img.height }
+}
+
+/// Mirrors a frame top to bottom.
+pub fn flip_vertical(img: &Image) -> Image {
+    let pixels = img.pixels.chunks(img.width as usize).rev().flat_map(|row| row.iter().cloned()).collect();
+    Image { pixels, width: img.width, height: img.height }
+}
+
+/// Scales a frame up by a whole `factor`, turning each pixel into a block.
+pub fn upscale(img: &Image, factor: u32) -> Image {
+    let factor = factor as usize;
+    let width = img.width as usize * factor;
+    let mut pixels = Vec::with_capacity(width * img.height as usize * factor);
+    for row in img.pixels.chunks(img.width as usize) {
+        let start = pixels.len();
+        for px in row {
+            for _ in 0..factor {
+                pixels.push(*px);
+            }
+        }
+        for _ in 1..factor {
+            pixels.extend_from_within(start..start + width);
+        }
+    }
+    Image { pixels, width: img.width * factor as u32, height: img.height * factor as u32 }
+}
+
+/// Makes all frames the same size according to `policy`, or returns the
+/// dimensions that conflict.
+pub fn conform<'a>(imgs: Cow<'a, [Image]>, policy: Mismatch) -> Result<Cow<'a, [Image]>, Error> {
+    let first = (imgs[0].width, imgs[0].height);
+    let mismatched = imgs.iter().map(|img| (img.width, img.height)).find(|&dims| dims != first);
+    match policy {
+        Mismatch::Error => match mismatched {
+            Some(other) => Err(Error::Mismatch(first, other)),
+            None => Ok(imgs),
+        },
+        Mismatch::Resize(target, filter) => {
+            let (width, height) = target.unwrap_or(first);
+            if mismatched.is_none() && first == (width, height) {
+                return Ok(imgs);
+            }
+            Ok(Cow::Owned(imgs.iter().map(|img| resize(img, width, height, filter)).collect()))
+        },
+        Mismatch::Pad(color) => {
+            if mismatched.is_none() {
+                return Ok(imgs);
+            }
+            let width = imgs.iter().map(|img| img.width).max().unwrap_or(0);
+            let height = imgs.iter().map(|img| img.height).max().unwrap_or(0);
+            Ok(Cow::Owned(imgs.iter().map(|img| pad(img, width, height, color)).collect()))
+        },
+    }
+}
+
+/// Centers a frame on a `width` x `height` canvas of `color`. The canvas must
+/// be at least as large as the frame.
+pub fn pad(img: &Image, width: u32, height: u32, color: [u8; 4]) -> Image {
+    let left = ((width - img.width) / 2) as usize;
+    let top = ((height - img.height) / 2) as usize;
+    let mut pixels = vec![color; (width * height) as usize];
+    for (y, row) in img.pixels.chunks(img.width as usize).enumerate() {
+        let start = (top + y) * width as usize + left;
+        pixels[start..start + row.len()].copy_from_slice(row);
+    }
+    Image { pixels, width, height }
+}
+
+/// Cuts `region` out of a single image, then applies the rest of the
+/// geometric transforms in `options` to it.
+pub fn apply_to<'a>(img: &'a Image, region: Option<Crop>, options: &Options) -> Cow<'a, Image> {
+    let mut img = Cow::Borrowed(img);
+    if let Some(region) = region {
+        img = Cow::Owned(crop(&img, region));
+    }
+    if let Some(rotation) = options.rotation {
+        img = Cow::Owned(rotate(&img, rotation));
+    }
+    if options.flip_horizontal {
+        img = Cow::Owned(flip_horizontal(&img));
+    }
+    if options.flip_vertical {
+        img = Cow::Owned(flip_vertical(&img));
+    }
+    if let Some(scale) = options.scale {
+        let (width, height) = scale.dimensions(img.width, img.height);
+        img = Cow::Owned(resize(&img, width, height, options.scale_filter));
+    }
+    if options.integer_scale > 1 {
+        img = Cow::Owned(upscale(&img, options.integer_scale));
+    }
+    img
+}
+
+/// Cuts `region` out of every frame, then applies the rest of the geometric
+/// transforms in `options` to them.
+pub fn apply<'a>(imgs: Cow<'a, [Image]>, region: Option<Crop>, options: &Options) -> Cow<'a, [Image]> {
+    let reoriented = options.rotation.is_some() || options.flip_horizontal || options.flip_vertical;
+    if region.is_none() && !reoriented && options.scale.is_none() && options.integer_scale <= 1 {
+        return imgs;
+    }
+    Cow::Owned(pool::install(options, || imgs.par_iter().map(|img| apply_to(img, region, options).into_owned()).collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{conform, crop, flip_horizontal, flip_vertical, pad, resize, rotate, trim, upscale, Crop, Filter, Mismatch, Rotation, Scale};
+    use super::super::Image;
+    use std::borrow::Cow;
+    use fixtures::solid;
+
+    #[test]
+    fn test_resize() {
+        let img = resize(&solid(4, 2, [10, 20, 30, 255]), 2, 1, Filter::Triangle);
+        assert_eq!((img.width, img.height), (2, 1));
+        assert_eq!(img.pixels, vec![[10, 20, 30, 255]; 2]);
+    }
+
+    #[test]
+    fn test_resize_nearest() {
+        let img = Image { pixels: vec![[1; 4], [2; 4], [3; 4], [4; 4]], width: 2, height: 2 };
+        let up = resize(&img, 4, 4, Filter::Nearest);
+        let values: Vec<_> = up.pixels.iter().map(|px| px[0]).collect();
+        assert_eq!(values, vec![1, 1, 2, 2, 1, 1, 2, 2, 3, 3, 4, 4, 3, 3, 4, 4]);
+        let down = resize(&up, 2, 1, Filter::Nearest);
+        assert_eq!(down.pixels, vec![[3; 4], [4; 4]]);
+    }
+
+    #[test]
+    fn test_conform_resize_to_first() {
+        let imgs = vec![solid(4, 4, [0; 4]), solid(8, 2, [0; 4])];
+        let out = conform(Cow::Borrowed(&imgs), Mismatch::Resize(None, Filter::Nearest)).unwrap();
+        assert!(out.iter().all(|img| (img.width, img.height) == (4, 4)));
+    }
+
+    #[test]
+    fn test_conform_resize_to_target() {
+        let imgs = vec![solid(4, 4, [0; 4]), solid(4, 4, [0; 4])];
+        let out = conform(Cow::Borrowed(&imgs), Mismatch::Resize(Some((2, 3)), Filter::Lanczos3)).unwrap();
+        assert!(out.iter().all(|img| (img.width, img.height) == (2, 3) && img.pixels.len() == 6));
+    }
+
+    #[test]
+    fn test_pad() {
+        let img = solid(1, 1, [9, 9, 9, 255]);
+        let padded = pad(&img, 3, 2, [0, 0, 0, 0]);
+        assert_eq!(padded.pixels, vec![[0; 4], [9, 9, 9, 255], [0; 4], [0; 4], [0; 4], [0; 4]]);
+    }
+
+    #[test]
+    fn test_conform_pad() {
+        let imgs = vec![solid(4, 2, [1; 4]), solid(2, 4, [2; 4])];
+        let out = conform(Cow::Borrowed(&imgs), Mismatch::Pad([0; 4])).unwrap();
+        assert!(out.iter().all(|img| (img.width, img.height) == (4, 4)));
+        assert_eq!(out[0].pixels[4..8].to_vec(), vec![[1; 4]; 4]);
+        assert_eq!(out[1].pixels[0..4].to_vec(), vec![[0; 4], [2; 4], [2; 4], [0; 4]]);
+    }
+
+    #[test]
+    fn test_scale_dimensions() {
+        assert_eq!(Scale::Factor(0.5).dimensions(3840, 2160), (1920, 1080));
+        assert_eq!(Scale::Width(800).dimensions(3840, 2160), (800, 450));
+        assert_eq!(Scale::Height(100).dimensions(50, 200), (25, 100));
+        assert_eq!(Scale::Size(10, 20).dimensions(50, 200), (10, 20));
+        assert_eq!(Scale::Factor(0.001).dimensions(50, 200), (1, 1));
+    }
+
+    #[test]
+    fn test_upscale() {
+        let img = Image { pixels: vec![[1; 4], [2; 4]], width: 2, height: 1 };
+        let up = upscale(&img, 3);
+        assert_eq!((up.width, up.height), (6, 3));
+        let values: Vec<_> = up.pixels.iter().map(|px| px[0]).collect();
+        assert_eq!(values, vec![1, 1, 1, 2, 2, 2, 1, 1, 1, 2, 2, 2, 1, 1, 1, 2, 2, 2]);
+    }
+
+    #[test]
+    fn test_crop() {
+        let img = Image { pixels: (0..12).map(|n| [n; 4]).collect(), width: 4, height: 3 };
+        let cropped = crop(&img, Crop { x: 1, y: 1, width: 2, height: 2 });
+        assert_eq!((cropped.width, cropped.height), (2, 2));
+        let values: Vec<_> = cropped.pixels.iter().map(|px| px[0]).collect();
+        assert_eq!(values, vec![5, 6, 9, 10]);
+
+        let clipped = crop(&img, Crop { x: 3, y: 2, width: 10, height: 10 });
+        assert_eq!((clipped.width, clipped.height), (1, 1));
+        assert_eq!(clipped.pixels[0][0], 11);
+    }
+
+    #[test]
+    fn test_trim() {
+        let mut a = solid(6, 4, [9; 4]);
+        let mut b = solid(6, 4, [9; 4]);
+        a.pixels[6 + 2] = [1; 4];
+        b.pixels[2 * 6 + 4] = [1; 4];
+        let whole = Crop { x: 0, y: 0, width: 6, height: 4 };
+        assert_eq!(trim(&[a, b.clone()], whole), Crop { x: 2, y: 1, width: 3, height: 2 });
+
+        let blank = solid(6, 4, [9; 4]);
+        assert_eq!(trim(&[blank], whole), Crop { x: 5, y: 3, width: 1, height: 1 });
+        let within = Crop { x: 3, y: 0, width: 3, height: 4 };
+        assert_eq!(trim(&[b], within), Crop { x: 4, y: 2, width: 1, height: 1 });
+    }
+
+    #[test]
+    fn test_rotate() {
+        // 0 1 2
+        // 3 4 5
+        let img = Image { pixels: (0..6).map(|n| [n; 4]).collect(), width: 3, height: 2 };
+        let values = |img: &Image| img.pixels.iter().map(|px| px[0]).collect::<Vec<_>>();
+        let turned = rotate(&img, Rotation::Rotate90);
+        assert_eq!((turned.width, turned.height), (2, 3));
+        assert_eq!(values(&turned), vec![3, 0, 4, 1, 5, 2]);
+        assert_eq!(values(&rotate(&img, Rotation::Rotate180)), vec![5, 4, 3, 2, 1, 0]);
+        assert_eq!(values(&rotate(&img, Rotation::Rotate270)), vec![2, 5, 1, 4, 0, 3]);
+    }
+
+    #[test]
+    fn test_flip() {
+        let img = Image { pixels: (0..6).map(|n| [n; 4]).collect(), width: 3, height: 2 };
+        let values = |img: &Image| img.pixels.iter().map(|px| px[0]).collect::<Vec<_>>();
+        assert_eq!(values(&flip_horizontal(&img)), vec![2, 1, 0, 5, 4, 3]);
+        assert_eq!(values(&flip_vertical(&img)), vec![3, 4, 5, 0, 1, 2]);
+    }
+}
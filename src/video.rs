@@ -0,0 +1,111 @@
+//! Decoding video files by running the `ffmpeg` binary and reading its output
+//! as a y4m stream.
+
+use std::ffi::{OsStr, OsString};
+use std::path::Path;
+use std::process::{Child, ChildStdout, Command, Stdio};
+use image;
+use y4m::Y4mFrames;
+use super::{Error, Image};
+
+/// An iterator over the frames of a video, created by `load_video` or
+/// `capture_screen`. The ffmpeg process is killed if this is dropped before
+/// the video ends.
+#[derive(Debug)]
+pub struct VideoFrames {
+    child: Child,
+    frames: Y4mFrames<ChildStdout>,
+    finished: bool,
+}
+
+/// An error for an ffmpeg that exited unsuccessfully, or `None` if it
+/// didn't.
+fn exit_error(child: &mut Child) -> Option<Error> {
+    match child.wait() {
+        Ok(status) if status.success() => None,
+        Ok(status) => Some(Error::ImageLoad(image::ImageError::FormatError(format!("ffmpeg failed with {}", status)))),
+        Err(e) => Some(Error::ImageLoad(image::ImageError::IoError(e))),
+    }
+}
+
+impl VideoFrames {
+    /// Runs `program` to decode `path` at `fps` frames per second.
+    pub fn spawn<S: AsRef<OsStr>>(program: S, path: &Path, fps: usize) -> Result<VideoFrames, Error> {
+        let input = vec![OsString::from("-i"), path.as_os_str().to_os_string()];
+        VideoFrames::spawn_with(program, &input, &format!("fps={}", fps.max(1)))
+    }
+
+    /// Runs `program` to decode the video `input` arguments select, passing
+    /// it through the ffmpeg `filters`.
+    pub fn spawn_with<S: AsRef<OsStr>>(program: S, input: &[OsString], filters: &str) -> Result<VideoFrames, Error> {
+        let mut child = Command::new(program)
+            .args(["-v", "error", "-nostdin"])
+            .args(input)
+            .args(["-an", "-vf", filters, "-pix_fmt", "yuva444p", "-f", "yuv4mpegpipe", "-"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(image::ImageError::IoError)?;
+        let stdout = child.stdout.take().expect("ffmpeg's stdout is piped");
+        match Y4mFrames::new(stdout) {
+            Ok(frames) => Ok(VideoFrames { child, frames, finished: false }),
+            // ffmpeg's exit status explains more than the missing header does.
+            Err(e) => Err(exit_error(&mut child).unwrap_or(e)),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.frames.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.frames.height()
+    }
+}
+
+impl Iterator for VideoFrames {
+    type Item = Result<Image, Error>;
+
+    fn next(&mut self) -> Option<Result<Image, Error>> {
+        if self.finished {
+            return None;
+        }
+        match self.frames.next() {
+            Some(Ok(img)) => Some(Ok(img)),
+            Some(Err(e)) => {
+                self.finished = true;
+                let _ = self.child.kill();
+                let _ = self.child.wait();
+                Some(Err(e))
+            },
+            None => {
+                self.finished = true;
+                exit_error(&mut self.child).map(Err)
+            },
+        }
+    }
+}
+
+impl Drop for VideoFrames {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use super::VideoFrames;
+    use super::super::Error;
+
+    #[test]
+    fn test_missing_ffmpeg() {
+        match VideoFrames::spawn("engiffen-no-such-ffmpeg", Path::new("in.mp4"), 10) {
+            Err(Error::ImageLoad(_)) => {},
+            other => panic!("expected a load error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Reading YUV4MPEG2 (y4m) streams, the uncompressed format video tools like
+//! ffmpeg and x264 pass between each other.
+
+use std::io::Read;
+use std::str::FromStr;
+use image;
+use raw::fill;
+use super::{Error, Image};
+
+/// Longer headers than this are treated as corrupt rather than read forever.
+const MAX_HEADER_LEN: usize = 4096;
+
+/// How the chroma planes are subsampled.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+enum Chroma {
+    C420,
+    C422,
+    C444,
+    C444Alpha,
+    Mono,
+}
+
+impl Chroma {
+    /// The size of each chroma plane for a `width` x `height` frame.
+    fn plane_size(self, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            Chroma::C420 => (width.div_ceil(2), height.div_ceil(2)),
+            Chroma::C422 => (width.div_ceil(2), height),
+            Chroma::C444 | Chroma::C444Alpha => (width, height),
+            Chroma::Mono => (0, 0),
+        }
+    }
+}
+
+/// An iterator over the frames of a y4m stream, converted to RGB, created by
+/// `read_y4m`.
+#[derive(Debug)]
+pub struct Y4mFrames<R> {
+    reader: R,
+    width: u32,
+    height: u32,
+    frame_rate: Option<(u32, u32)>,
+    chroma: Chroma,
+    full_range: bool,
+    done: bool,
+}
+
+fn format_error<S: Into<String>>(msg: S) -> Error {
+    Error::ImageLoad(image::ImageError::FormatError(msg.into()))
+}
+
+/// Reads up to the end of a header line. Returns `None` if the stream ended
+/// before the line started.
+fn read_line<R: Read>(reader: &mut R) -> Result<Option<String>, Error> {
+    let mut line = Vec::new();
+    let mut byte = [0];
+    loop {
+        if !fill(reader, &mut byte).map_err(image::ImageError::IoError)? {
+            if line.is_empty() {
+                return Ok(None);
+            }
+            return Err(format_error("y4m stream ended in the middle of a header"));
+        }
+        if byte[0] == b'\n' {
+            return Ok(Some(String::from_utf8_lossy(&line).into_owned()));
+        }
+        if line.len() == MAX_HEADER_LEN {
+            return Err(format_error("y4m header is too long"));
+        }
+        line.push(byte[0]);
+    }
+}
+
+impl<R: Read> Y4mFrames<R> {
+    pub fn new(mut reader: R) -> Result<Y4mFrames<R>, Error> {
+        let header = read_line(&mut reader)?.unwrap_or_default();
+        let mut params = header.split(' ');
+        if params.next() != Some("YUV4MPEG2") {
+            return Err(format_error("not a y4m stream"));
+        }
+        let (mut width, mut height, mut frame_rate) = (0, 0, None);
+        let (mut chroma, mut full_range) = (Chroma::C420, false);
+        for param in params.filter(|p| !p.is_empty()) {
+            let (tag, value) = param.split_at(param.chars().next().map_or(0, char::len_utf8));
+            match tag {
+                "W" => width = u32::from_str(value).map_err(|_| format_error(format!("bad y4m width {}", value)))?,
+                "H" => height = u32::from_str(value).map_err(|_| format_error(format!("bad y4m height {}", value)))?,
+                "F" => {
+                    frame_rate = value.split_once(':')
+                        .and_then(|(n, d)| Some((u32::from_str(n).ok()?, u32::from_str(d).ok()?)))
+                        .filter(|&(n, d)| n > 0 && d > 0);
+                },
+                "C" => {
+                    chroma = match value {
+                        "420" | "420jpeg" | "420paldv" | "420mpeg2" => Chroma::C420,
+                        "422" => Chroma::C422,
+                        "444" => Chroma::C444,
+                        "444alpha" => Chroma::C444Alpha,
+                        "mono" => Chroma::Mono,
+                        _ => return Err(format_error(format!("unsupported y4m colorspace {}", value))),
+                    };
+                },
+                "X" => full_range = full_range || value == "COLORRANGE=FULL",
+                _ => {},
+            }
+        }
+        if width == 0 || height == 0 {
+            return Err(format_error("y4m header has no frame size"));
+        }
+        Ok(Y4mFrames { reader, width, height, frame_rate, chroma, full_range, done: false })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The stream's frame rate as a fraction, such as `(30000, 1001)` for
+    /// NTSC video, if the header has one.
+    pub fn frame_rate(&self) -> Option<(u32, u32)> {
+        self.frame_rate
+    }
+
+    /// The frame rate rounded to whole frames per second, to pass to
+    /// `engiffen`.
+    pub fn fps(&self) -> Option<usize> {
+        self.frame_rate.map(|(n, d)| ((n as f64 / d as f64).round() as usize).max(1))
+    }
+
+    fn read_frame(&mut self) -> Result<Option<Image>, Error> {
+        match read_line(&mut self.reader)? {
+            None => return Ok(None),
+            Some(ref line) if line == "FRAME" || line.starts_with("FRAME ") => {},
+            Some(_) => return Err(format_error("y4m frame header is missing")),
+        }
+        let (width, height) = (self.width as usize, self.height as usize);
+        let (cw, ch) = self.chroma.plane_size(width, height);
+        let alpha = if self.chroma == Chroma::C444Alpha { width * height } else { 0 };
+        let mut buf = vec![0; width * height + cw * ch * 2 + alpha];
+        if !fill(&mut self.reader, &mut buf).map_err(image::ImageError::IoError)? {
+            return Err(format_error("y4m stream ended after a frame header"));
+        }
+        let (luma, rest) = buf.split_at(width * height);
+        let (u, rest) = rest.split_at(cw * ch);
+        let (v, a) = rest.split_at(cw * ch);
+
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let luma = luma[y * width + x];
+                let (u, v) = if cw == 0 {
+                    (128, 128)
+                } else {
+                    let at = (y * ch / height) * cw + x * cw / width;
+                    (u[at], v[at])
+                };
+                let [r, g, b] = to_rgb(luma, u, v, self.full_range);
+                let alpha = if a.is_empty() { 255 } else { a[y * width + x] };
+                pixels.push([r, g, b, alpha]);
+            }
+        }
+        Ok(Some(Image { pixels, width: self.width, height: self.height }))
+    }
+}
+
+/// Converts a BT.601 pixel to RGB, either from the video range of 16-235 or
+/// the full range of 0-255.
+fn to_rgb(y: u8, u: u8, v: u8, full_range: bool) -> [u8; 3] {
+    let (u, v) = (u as f32 - 128.0, v as f32 - 128.0);
+    let (y, u, v) = if full_range {
+        (y as f32, u, v)
+    } else {
+        ((y as f32 - 16.0) * 255.0 / 219.0, u * 255.0 / 224.0, v * 255.0 / 224.0)
+    };
+    let channel = |c: f32| c.round().clamp(0.0, 255.0) as u8;
+    [
+        channel(y + 1.402 * v),
+        channel(y - 0.344_136 * u - 0.714_136 * v),
+        channel(y + 1.772 * u),
+    ]
+}
+
+impl<R: Read> Iterator for Y4mFrames<R> {
+    type Item = Result<Image, Error>;
+
+    fn next(&mut self) -> Option<Result<Image, Error>> {
+        if self.done {
+            return None;
+        }
+        match self.read_frame() {
+            Ok(Some(img)) => Some(Ok(img)),
+            Ok(None) => {
+                self.done = true;
+                None
+            },
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_rgb, Y4mFrames};
+    use super::super::Error;
+
+    #[test]
+    fn test_y4m_frames() {
+        let mut stream = b"YUV4MPEG2 W2 H2 F30000:1001 Ip A1:1 C420jpeg XCOLORRANGE=FULL\n".to_vec();
+        stream.extend_from_slice(b"FRAME\n\x00\x40\x80\xff\x80\x80");
+        stream.extend_from_slice(b"FRAME Ixyz\n\xff\xff\xff\xff\x80\x80");
+        let frames = Y4mFrames::new(&stream[..]).unwrap();
+        assert_eq!((frames.width(), frames.height()), (2, 2));
+        assert_eq!(frames.frame_rate(), Some((30000, 1001)));
+        assert_eq!(frames.fps(), Some(30));
+
+        let imgs: Vec<_> = frames.map(Result::unwrap).collect();
+        assert_eq!(imgs.len(), 2);
+        assert_eq!(imgs[0].pixels, vec![[0, 0, 0, 255], [64, 64, 64, 255], [128, 128, 128, 255], [255, 255, 255, 255]]);
+        assert_eq!(imgs[1].pixels, vec![[255, 255, 255, 255]; 4]);
+    }
+
+    #[test]
+    fn test_y4m_errors() {
+        assert!(Y4mFrames::new(&b"P6\n"[..]).is_err());
+        assert!(Y4mFrames::new(&b"YUV4MPEG2 W2 H2 C420p10\n"[..]).is_err());
+
+        let frames = Y4mFrames::new(&b"YUV4MPEG2 W2 H2 Cmono\nFRAME\n\x00\x00"[..]).unwrap();
+        let results: Vec<_> = frames.collect();
+        assert_eq!(results.len(), 1);
+        assert!(matches!(results[0], Err(Error::ImageLoad(_))));
+    }
+
+    #[test]
+    fn test_video_range() {
+        assert_eq!(to_rgb(16, 128, 128, false), [0, 0, 0]);
+        assert_eq!(to_rgb(235, 128, 128, false), [255, 255, 255]);
+        assert_eq!(to_rgb(81, 90, 240, false), [254, 0, 0]);
+    }
+}